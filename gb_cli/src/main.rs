@@ -0,0 +1,538 @@
+//! Runs ROMs without a window, for use in scripts and CI pipelines
+
+use std::{collections::BTreeMap, path::PathBuf, process::exit};
+
+use clap::{Args, Parser, Subcommand};
+use gb_core::gameboy::{
+    cart::{
+        header::{fix_checksums, CartHeader, Checksums},
+        LoadMode,
+    },
+    debug::{BankedAddr, MapFile, RegionSpec, TraceError, Violation},
+    models::DMG,
+    ppu::PPU,
+    serial::{test_verdict, TestVerdict},
+    Gameboy, IllegalOpcodePolicy,
+};
+use gb_frontend::paths::{DataKind, Paths};
+
+mod png;
+mod server;
+
+/// Exit status when the frame limit is reached before the ROM reported a result
+const EXIT_NO_VERDICT: i32 = 2;
+
+#[derive(Parser)]
+#[clap(about = "Runs Gameboy ROMs headlessly")]
+struct Cli {
+    #[clap(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Run a ROM until it reports a result over the serial port, or until the frame limit is reached.
+    ///
+    /// Exits with 0 if the ROM passed, 1 if it failed, and 2 if it never reported a result or stopped at a
+    /// breakpoint.
+    Run {
+        rom: PathBuf,
+        /// The maximum number of frames to run for
+        #[clap(long, default_value_t = 3600)]
+        frames: u32,
+        /// Treat the ROM as passing if the last frame has this hash, rather than waiting for serial output
+        #[clap(long, parse(try_from_str = parse_hash))]
+        expect_hash: Option<u64>,
+        /// Refuse to run ROMs with bad checksums, a wrong size or an unsupported mapper
+        #[clap(long)]
+        strict: bool,
+        /// Warn about writes to ROM that don't go to a mapper register, which usually come from a bug in the game
+        #[clap(long)]
+        warn_rom_writes: bool,
+        /// Write a JSON report of the opcodes that ran and how much of the ROM they covered
+        #[clap(long, value_name = "FILE")]
+        coverage: Option<PathBuf>,
+        #[clap(flatten)]
+        debug: DebugArgs,
+    },
+    /// Print the decoded cartridge header of a ROM and check its checksums.
+    ///
+    /// Exits with 1 if a checksum doesn't match, unless --fix was given.
+    Header {
+        rom: PathBuf,
+        /// Write the correct checksums into the ROM file
+        #[clap(long)]
+        fix: bool,
+    },
+    /// Run a ROM at normal speed, controlled over HTTP. See `server.rs` for the requests it accepts.
+    Serve {
+        /// A ROM to load at startup. Others can be loaded with `POST /load`
+        rom: Option<PathBuf>,
+        #[clap(long, default_value = "127.0.0.1:7878")]
+        addr: String,
+    },
+    /// Run a ROM in lockstep with a Gameboy Doctor trace from another emulator, and show the first instruction where
+    /// they disagree.
+    ///
+    /// Exits with 0 if the whole trace matched, 1 if it diverged, and 2 if the ROM or trace couldn't be read.
+    Compare {
+        rom: PathBuf,
+        trace: PathBuf,
+        /// How many of the matching lines before a divergence to show
+        #[clap(long, default_value_t = 5)]
+        context: usize,
+    },
+    /// Print the sections of a homebrew ROM, bank by bank, from the .map file RGBDS's linker writes with -m
+    Map { map: PathBuf },
+    /// Print where config, saves, states, screenshots and movies are kept
+    Paths {
+        #[clap(flatten)]
+        paths: PathArgs,
+    },
+}
+
+/// Debugging aids for homebrew ROMs
+#[derive(Args)]
+struct DebugArgs {
+    /// Named memory regions to protect, one `START-END NAME [read-only] [no-exec]` per line. The ROM fails as
+    /// soon as one is violated.
+    #[clap(long, value_name = "FILE")]
+    regions: Option<PathBuf>,
+    /// Stop before running the instruction at `BANK:ADDR`, `ADDR` in any bank, or a symbol from --map. May be
+    /// given more than once.
+    #[clap(long = "break", value_name = "LOCATION")]
+    breakpoints: Vec<String>,
+    /// The ROM's RGBDS .map file, to name the sections breakpoints are in and warn about ones that can't be hit
+    #[clap(long, value_name = "FILE")]
+    map: Option<PathBuf>,
+}
+
+/// Where to keep data, instead of the platform's usual locations
+#[derive(Args)]
+struct PathArgs {
+    /// Keep everything in folders under this one
+    #[clap(long, value_name = "DIR")]
+    data_dir: Option<PathBuf>,
+    /// Keep everything in a gb-emu-data folder next to the executable, e.g. to run from a USB stick
+    #[clap(long, conflicts_with = "data-dir")]
+    portable: bool,
+    /// Keep one kind of data somewhere else, e.g. `saves=/mnt/usb/saves`. May be given more than once.
+    #[clap(long = "dir", value_name = "KIND=DIR")]
+    dirs: Vec<String>,
+}
+
+impl PathArgs {
+    fn resolve(&self) -> Result<Paths, String> {
+        let mut paths = match (&self.data_dir, self.portable) {
+            (Some(dir), _) => Ok(Paths::in_dir(dir)),
+            (None, true) => Paths::portable(),
+            (None, false) => Paths::platform(),
+        }
+        .map_err(|e| format!("Couldn't find the data folders: {}", e))?;
+        for spec in &self.dirs {
+            paths
+                .parse_override(spec)
+                .map_err(|e| format!("Bad --dir {}: {}", spec, e))?;
+        }
+        Ok(paths)
+    }
+}
+
+fn parse_hash(s: &str) -> Result<u64, std::num::ParseIntError> {
+    u64::from_str_radix(s.trim_start_matches("0x"), 16)
+}
+
+fn main() {
+    match Cli::parse().command {
+        CliCommand::Run {
+            rom,
+            frames,
+            expect_hash,
+            strict,
+            warn_rom_writes,
+            coverage,
+            debug,
+        } => {
+            let mode = if strict {
+                LoadMode::Strict
+            } else {
+                LoadMode::Lenient
+            };
+            exit(run(
+                rom,
+                frames,
+                expect_hash,
+                mode,
+                warn_rom_writes,
+                coverage,
+                debug,
+            ))
+        }
+        CliCommand::Header { rom, fix } => exit(header(rom, fix)),
+        CliCommand::Serve { rom, addr } => {
+            let gameboy = rom
+                .as_deref()
+                .map(|rom| load_gameboy(rom, LoadMode::Lenient));
+            if let Err(e) = server::serve(&addr, gameboy) {
+                eprintln!("Couldn't serve on {}: {}", addr, e);
+                exit(1)
+            }
+        }
+        CliCommand::Compare {
+            rom,
+            trace,
+            context,
+        } => exit(compare(rom, trace, context)),
+        CliCommand::Map { map } => match load_map(&map) {
+            Ok(map) => print_map(&map),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1)
+            }
+        },
+        CliCommand::Paths { paths } => match paths.resolve() {
+            Ok(paths) => {
+                for kind in DataKind::ALL {
+                    println!("{:<12} {}", kind.name(), paths.dir(kind).display());
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1)
+            }
+        },
+    }
+}
+
+fn load_gameboy(path: &std::path::Path, mode: LoadMode) -> Box<Gameboy<DMG>> {
+    let rom = std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Couldn't read {}: {}", path.display(), e);
+        exit(EXIT_NO_VERDICT)
+    });
+    let mut gameboy = Gameboy::with_load_mode(rom, mode).unwrap_or_else(|e| {
+        eprintln!("Couldn't load {}: {}", path.display(), e);
+        exit(EXIT_NO_VERDICT)
+    });
+    for diagnostic in gameboy.cart.diagnostics() {
+        eprintln!("warning: {}", diagnostic);
+    }
+    gameboy.reset();
+    gameboy
+}
+
+fn load_regions(path: &std::path::Path) -> Vec<RegionSpec> {
+    let config = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Couldn't read {}: {}", path.display(), e);
+        exit(EXIT_NO_VERDICT)
+    });
+    RegionSpec::parse_config(&config).unwrap_or_else(|e| {
+        eprintln!("Couldn't parse {}: {}", path.display(), e);
+        exit(EXIT_NO_VERDICT)
+    })
+}
+
+/// Read a breakpoint written as `BANK:ADDR`, `ADDR`, or the name of a symbol in `map`
+fn parse_breakpoint(location: &str, map: Option<&MapFile>) -> Result<BankedAddr, &'static str> {
+    if let Some((bank, addr)) = map.and_then(|map| map.symbol(location)) {
+        return Ok(BankedAddr {
+            bank: Some(bank),
+            addr,
+        });
+    }
+    location.parse()
+}
+
+fn load_map(path: &std::path::Path) -> Result<MapFile, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+    MapFile::parse(&text).map_err(|e| format!("Couldn't parse {}: {}", path.display(), e))
+}
+
+fn print_map(map: &MapFile) {
+    for section in &map.sections {
+        let end = section.start as u32 + section.size as u32;
+        println!(
+            "{:<5} {:02X} ${:04X}-${:04X} {:>6} {}",
+            section.kind,
+            section.bank,
+            section.start,
+            end.saturating_sub(1).max(section.start as u32),
+            section.size,
+            section.name
+        );
+    }
+}
+
+/// Returns the exit status
+fn compare(rom: PathBuf, trace: PathBuf, context: usize) -> i32 {
+    let mut gameboy = load_gameboy(&rom, LoadMode::Lenient);
+    let text = std::fs::read_to_string(&trace).unwrap_or_else(|e| {
+        eprintln!("Couldn't read {}: {}", trace.display(), e);
+        exit(EXIT_NO_VERDICT)
+    });
+
+    match gameboy.compare_trace(&text, context) {
+        Ok(lines) => {
+            println!("All {} lines matched", lines);
+            0
+        }
+        Err(TraceError::Parse { line, message }) => {
+            eprintln!("{}:{}: {}", trace.display(), line, message);
+            EXIT_NO_VERDICT
+        }
+        Err(TraceError::Diverged(divergence)) => {
+            println!("Diverged at line {}:", divergence.line);
+            for line in &divergence.history {
+                println!("           {}", line);
+            }
+            println!("  expected {}", divergence.expected);
+            println!("  actual   {}", divergence.actual);
+            println!("Differs in {}", divergence.fields.join(", "));
+            1
+        }
+    }
+}
+
+/// Returns the exit status
+fn header(path: PathBuf, fix: bool) -> i32 {
+    let mut rom = std::fs::read(&path).unwrap_or_else(|e| {
+        eprintln!("Couldn't read {}: {}", path.display(), e);
+        exit(1)
+    });
+    let (header, checksums) = match (CartHeader::parse(&rom), Checksums::compute(&rom)) {
+        (Some(header), Some(checksums)) => (header, checksums),
+        _ => {
+            eprintln!("{} is too small to have a header", path.display());
+            return 1;
+        }
+    };
+
+    let or_unknown = |size: Option<usize>| match size {
+        Some(size) => format!("{} KiB", size / 1024),
+        None => "unknown".to_owned(),
+    };
+    println!("title:           {}", header.title);
+    println!(
+        "type:            {:02X} ({})",
+        header.cart_type,
+        header.cart_type_name().unwrap_or("unknown")
+    );
+    println!(
+        "ROM size:        {:02X} ({})",
+        header.rom_size,
+        or_unknown(header.rom_size_bytes())
+    );
+    println!(
+        "RAM size:        {:02X} ({})",
+        header.ram_size,
+        or_unknown(header.ram_size_bytes())
+    );
+    println!("CGB flag:        {:02X}", header.cgb_flag);
+    println!("SGB flag:        {:02X}", header.sgb_flag);
+    println!("version:         {:02X}", header.version);
+
+    let mut ok = true;
+    let mut check = |name: &str, stored: u16, computed: u16, width: usize| {
+        if stored == computed {
+            println!("{:<16} {:02$X} (ok)", name, stored, width);
+        } else {
+            println!(
+                "{:<16} {:03$X} (MISMATCH, should be {:03$X})",
+                name, stored, computed, width
+            );
+            ok = false;
+        }
+    };
+    check(
+        "header checksum:",
+        header.header_checksum as u16,
+        checksums.header as u16,
+        2,
+    );
+    check(
+        "global checksum:",
+        header.global_checksum,
+        checksums.global,
+        4,
+    );
+    if let Some(size) = header.rom_size_bytes() {
+        if size != rom.len() {
+            println!(
+                "the header declares {} bytes of ROM, but the file has {}",
+                size,
+                rom.len()
+            );
+        }
+    }
+
+    if fix && !ok {
+        fix_checksums(&mut rom);
+        if let Err(e) = std::fs::write(&path, &rom) {
+            eprintln!("Couldn't write {}: {}", path.display(), e);
+            return 1;
+        }
+        println!("fixed the checksums in {}", path.display());
+        ok = true;
+    }
+
+    if ok {
+        0
+    } else {
+        1
+    }
+}
+
+/// Returns the exit status
+fn run(
+    rom: PathBuf,
+    frames: u32,
+    expect_hash: Option<u64>,
+    mode: LoadMode,
+    warn_rom_writes: bool,
+    coverage: Option<PathBuf>,
+    debug: DebugArgs,
+) -> i32 {
+    let mut gameboy = load_gameboy(&rom, mode);
+    // Nothing is shown while running headlessly, so there's no reason to spend time on a halted CPU
+    gameboy.set_fast_idle(true);
+    gameboy.set_stray_rom_write_detection(warn_rom_writes);
+    gameboy.set_coverage(coverage.is_some());
+    gameboy.set_illegal_opcode_policy(IllegalOpcodePolicy::Break);
+    if let Some(path) = debug.regions {
+        for spec in load_regions(&path) {
+            gameboy.add_region(spec);
+        }
+    }
+    let map = debug.map.map(|path| {
+        load_map(&path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(EXIT_NO_VERDICT)
+        })
+    });
+    for location in &debug.breakpoints {
+        let addr = parse_breakpoint(location, map.as_ref()).unwrap_or_else(|e| {
+            eprintln!("Bad --break {}: {}", location, e);
+            exit(EXIT_NO_VERDICT)
+        });
+        if let (Some(map), Some(bank)) = (&map, addr.bank) {
+            if !map.is_mapped(bank, addr.addr) {
+                eprintln!(
+                    "warning: nothing is mapped at {}, so the breakpoint can't be hit",
+                    addr
+                );
+            }
+        }
+        gameboy.add_breakpoint(addr);
+    }
+
+    let status = run_frames(&mut gameboy, frames, expect_hash, map.as_ref());
+    // The report is written however the run ended, as stopping at a breakpoint is a common way to end one
+    if let (Some(path), Some(coverage)) = (coverage, gameboy.coverage()) {
+        if let Err(e) = std::fs::write(&path, coverage.to_json()) {
+            eprintln!("Couldn't write {}: {}", path.display(), e);
+        }
+    }
+    status
+}
+
+/// Run until the ROM reports a result, breaks a rule or stops at a breakpoint, and return the exit status
+fn run_frames(
+    gameboy: &mut Gameboy<DMG>,
+    frames: u32,
+    expect_hash: Option<u64>,
+    map: Option<&MapFile>,
+) -> i32 {
+    let mut verdict = None;
+    // Taken from the serial port every frame, so none of it is dropped from the port's capped buffer
+    let mut serial = Vec::new();
+    // A bad write in a loop would repeat every frame, so each one is counted and only reported once
+    let mut stray_writes = BTreeMap::new();
+    for _ in 0..frames {
+        gameboy.run_frame();
+        serial.extend(gameboy.serial.take_output());
+        for write in gameboy.take_stray_rom_writes() {
+            *stray_writes.entry((write.pc, write.addr)).or_insert(0) += 1;
+        }
+        if let Some(violation) = gameboy.take_break() {
+            let region = &gameboy.region(violation.region).unwrap().name;
+            match violation.violation {
+                Violation::Write { addr, data } => println!(
+                    "the instruction at {:04X} wrote {:02X} to {:04X} in read-only region {}",
+                    violation.pc, data, addr, region
+                ),
+                Violation::Execute { addr } => {
+                    println!("executed {:04X} in no-exec region {}", addr, region)
+                }
+            }
+            println!("failed after {} frames", gameboy.perf_stats().frames);
+            return 1;
+        }
+        if let Some(illegal) = gameboy.take_illegal_opcode() {
+            println!(
+                "the CPU locked up on illegal opcode {:02X} at {}",
+                illegal.opcode,
+                gameboy.instruction_location()
+            );
+            println!("failed after {} frames", gameboy.perf_stats().frames);
+            return 1;
+        }
+        if let Some(hit) = gameboy.take_breakpoint_hit() {
+            let location = hit.location;
+            let section =
+                map.and_then(|map| map.section_at(location.bank.unwrap_or(0), location.addr));
+            match section {
+                Some(section) => println!("stopped at {} in {}", location, section.name),
+                None => println!("stopped at {}", location),
+            }
+            // PC has already moved past the opcode of the instruction that's about to run
+            let mut registers = gameboy.cpu.cpu.registers;
+            registers.pc = location.addr;
+            println!("{:?}", registers);
+            println!("after {} frames", gameboy.perf_stats().frames);
+            return EXIT_NO_VERDICT;
+        }
+        if expect_hash.is_none() {
+            verdict = test_verdict(&serial);
+            if verdict.is_some() {
+                break;
+            }
+        }
+    }
+
+    for ((pc, addr), count) in stray_writes {
+        eprintln!(
+            "warning: the instruction at {:04X} wrote to ROM at {:04X} {} times",
+            pc, addr, count
+        );
+    }
+
+    let hash = gameboy.ppu.get_frame().hash();
+    if let Some(expected) = expect_hash {
+        verdict = Some(if hash == expected {
+            TestVerdict::Passed
+        } else {
+            TestVerdict::Failed
+        });
+    }
+
+    let serial = String::from_utf8_lossy(&serial);
+    if !serial.is_empty() {
+        println!("{}", serial.trim_end());
+    }
+    println!("frame hash: {:016x}", hash);
+
+    match verdict {
+        Some(TestVerdict::Passed) => {
+            println!("passed after {} frames", gameboy.perf_stats().frames);
+            0
+        }
+        Some(TestVerdict::Failed) => {
+            println!("failed after {} frames", gameboy.perf_stats().frames);
+            1
+        }
+        None => {
+            println!("no result after {} frames", frames);
+            EXIT_NO_VERDICT
+        }
+    }
+}
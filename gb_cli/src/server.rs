@@ -0,0 +1,364 @@
+//! An HTTP server for driving the emulator from other programs, e.g. bots or integration tests.
+//!
+//! | Request                    | Effect                                                       |
+//! |----------------------------|--------------------------------------------------------------|
+//! | `GET /status`              | JSON with whether a ROM is loaded, whether it's paused, etc. |
+//! | `POST /load`               | Load the ROM in the request body                             |
+//! | `POST /pause`, `/resume`   | Pause or resume emulation                                    |
+//! | `POST /step?frames=N`      | Run N frames (default 1, at most 600), even while paused     |
+//! | `POST /press/<button>`     | Hold a button: `a`, `b`, `start`, `select`, `up`, ...        |
+//! | `POST /release/<button>`   | Release a button                                             |
+//! | `POST /input/<mask>`       | Set every button at once from a hex `ButtonState`            |
+//! | `GET /frame.png`           | The last finished frame                                      |
+//! | `POST /map/start`          | Start stitching the scrolling background into a level map    |
+//! | `POST /map/stop`           | Stop stitching, and forget the map                           |
+//! | `GET /map.png`             | The level map stitched so far                                |
+//! | `GET /memory/<addr>?len=N` | N bytes (default 1) from a hex address                       |
+//!
+//! Memory is read without side effects. Requests are handled between frames, one at a time, so a response always
+//! reflects a consistent state.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use gb_core::gameboy::{
+    debug::MapCapture,
+    joypad::{Button, ButtonState},
+    models::DMG,
+    ppu::PPU,
+    Gameboy,
+};
+
+use crate::png;
+
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The biggest request body accepted, the size of the biggest cartridge ROMs (8 MiB, on an MBC5)
+const MAX_BODY_SIZE: usize = 0x80_0000;
+
+/// The most frames one `/step` request runs, so a single request can't hold up the server for long
+const MAX_STEP_FRAMES: u32 = 600;
+
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Looks up `key` in the query string
+    fn param(&self, key: &str) -> Option<&str> {
+        self.query
+            .as_deref()?
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Response::text("200 OK", "ok")
+    }
+
+    fn text(status: &'static str, text: &str) -> Self {
+        Response {
+            status,
+            content_type: "text/plain",
+            body: text.as_bytes().to_vec(),
+        }
+    }
+
+    fn bad_request(text: &str) -> Self {
+        Response::text("400 Bad Request", text)
+    }
+}
+
+struct Server {
+    gameboy: Option<Box<Gameboy<DMG>>>,
+    paused: bool,
+    map_capture: Option<MapCapture>,
+}
+
+/// Serve requests on `addr` until the process is killed, emulating at normal speed in between
+pub fn serve(addr: &str, gameboy: Option<Box<Gameboy<DMG>>>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    println!("listening on {}", listener.local_addr()?);
+
+    let mut server = Server {
+        gameboy,
+        paused: false,
+        map_capture: None,
+    };
+    let mut next_frame = Instant::now();
+    loop {
+        while let Ok((stream, _)) = listener.accept() {
+            if let Err(e) = server.handle_connection(stream) {
+                eprintln!("request failed: {}", e);
+            }
+        }
+
+        if let (Some(gameboy), false) = (&mut server.gameboy, server.paused) {
+            run_frame(gameboy, &mut server.map_capture);
+        }
+
+        next_frame += FRAME_TIME;
+        match next_frame.checked_duration_since(Instant::now()) {
+            Some(wait) => thread::sleep(wait),
+            // Don't try to catch up after falling behind
+            None => next_frame = Instant::now(),
+        }
+    }
+}
+
+impl Server {
+    fn handle_connection(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream);
+        let response = match read_request(&mut reader)? {
+            Ok(request) => self.handle(&request),
+            Err(response) => response,
+        };
+
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status,
+            response.content_type,
+            response.body.len()
+        )?;
+        stream.write_all(&response.body)
+    }
+
+    fn handle(&mut self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["status"]) => self.status(),
+            ("POST", ["load"]) => match Gameboy::new(request.body.clone()) {
+                Ok(mut gameboy) => {
+                    gameboy.reset();
+                    self.gameboy = Some(gameboy);
+                    Response::ok()
+                }
+                Err(e) => Response::bad_request(e),
+            },
+            ("POST", ["pause"]) => {
+                self.paused = true;
+                Response::ok()
+            }
+            ("POST", ["resume"]) => {
+                self.paused = false;
+                Response::ok()
+            }
+            ("POST", ["map", "start"]) => {
+                self.map_capture = Some(MapCapture::new());
+                Response::ok()
+            }
+            ("POST", ["map", "stop"]) => {
+                self.map_capture = None;
+                Response::ok()
+            }
+            ("GET", ["map.png"]) => match &self.map_capture {
+                Some(capture) if capture.tile_count() > 0 => {
+                    let (pixels, width, height) = capture.image();
+                    Response {
+                        status: "200 OK",
+                        content_type: "image/png",
+                        body: png::encode(&pixels, width, height),
+                    }
+                }
+                Some(_) => Response::text("409 Conflict", "nothing has been captured yet"),
+                None => Response::text("409 Conflict", "no map is being captured"),
+            },
+            ("POST", ["state", "save"]) | ("POST", ["state", "load"]) => {
+                Response::text("501 Not Implemented", "save states aren't supported yet")
+            }
+            (method, path) => match &mut self.gameboy {
+                Some(gameboy) => {
+                    handle_with_gameboy(gameboy, &mut self.map_capture, method, path, request)
+                }
+                None => Response::text("409 Conflict", "no ROM is loaded"),
+            },
+        }
+    }
+
+    fn status(&self) -> Response {
+        let json = match &self.gameboy {
+            Some(gameboy) => format!(
+                "{{\"loaded\":true,\"paused\":{},\"frames\":{},\"title\":{:?}}}",
+                self.paused,
+                gameboy.perf_stats().frames,
+                gameboy.cart.header().title
+            ),
+            None => format!("{{\"loaded\":false,\"paused\":{}}}", self.paused),
+        };
+        Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: json.into_bytes(),
+        }
+    }
+}
+
+/// Run a frame, and add it to the map being captured, if any
+fn run_frame(gameboy: &mut Gameboy<DMG>, map_capture: &mut Option<MapCapture>) {
+    gameboy.run_frame();
+    if let Some(capture) = map_capture {
+        capture.capture(&gameboy.ppu);
+    }
+}
+
+fn handle_with_gameboy(
+    gameboy: &mut Gameboy<DMG>,
+    map_capture: &mut Option<MapCapture>,
+    method: &str,
+    path: &[&str],
+    request: &Request,
+) -> Response {
+    match (method, path) {
+        ("POST", ["step"]) => match request.param("frames").unwrap_or("1").parse::<u32>() {
+            Ok(frames) => {
+                for _ in 0..frames.min(MAX_STEP_FRAMES) {
+                    run_frame(gameboy, map_capture);
+                }
+                Response::ok()
+            }
+            Err(_) => Response::bad_request("frames must be a number"),
+        },
+        ("POST", ["press", button]) => match parse_button(button) {
+            Some(button) => {
+                gameboy.joypad.press(button);
+                Response::ok()
+            }
+            None => Response::bad_request("unknown button"),
+        },
+        ("POST", ["release", button]) => match parse_button(button) {
+            Some(button) => {
+                gameboy.joypad.release(button);
+                Response::ok()
+            }
+            None => Response::bad_request("unknown button"),
+        },
+        ("POST", ["input", mask]) => match u8::from_str_radix(mask, 16) {
+            Ok(mask) => {
+                gameboy.set_input(ButtonState::from_bits_truncate(mask));
+                Response::ok()
+            }
+            Err(_) => Response::bad_request("the input must be a hex bitmask"),
+        },
+        ("GET", ["frame.png"]) => {
+            let (pixels, width, height) = gameboy.ppu.get_frame().scaled(1);
+            Response {
+                status: "200 OK",
+                content_type: "image/png",
+                body: png::encode(&pixels, width, height),
+            }
+        }
+        ("GET", ["memory", addr]) => {
+            let addr = u16::from_str_radix(addr.trim_start_matches("0x"), 16);
+            let len = request.param("len").unwrap_or("1").parse::<u32>();
+            let end = match (addr, len) {
+                (Ok(addr), Ok(len)) => (addr as u32).checked_add(len).map(|end| (addr, end)),
+                _ => None,
+            };
+            match end {
+                Some((addr, end)) if end <= 0x10000 => Response {
+                    status: "200 OK",
+                    content_type: "application/octet-stream",
+                    body: (addr as u32..end)
+                        .map(|addr| gameboy.debug_read(addr as u16))
+                        .collect(),
+                },
+                _ => Response::bad_request(
+                    "expected a hex address and a length within the address space",
+                ),
+            }
+        }
+        _ => Response::text("404 Not Found", "unknown request"),
+    }
+}
+
+fn parse_button(name: &str) -> Option<Button> {
+    Some(match name {
+        "a" => Button::A,
+        "b" => Button::B,
+        "start" => Button::Start,
+        "select" => Button::Select,
+        "up" => Button::Up,
+        "down" => Button::Down,
+        "left" => Button::Left,
+        "right" => Button::Right,
+        _ => return None,
+    })
+}
+
+/// Returns the response to send instead if the request couldn't be parsed, or its body is bigger than any ROM
+fn read_request(reader: &mut impl BufRead) -> io::Result<Result<Request, Response>> {
+    let malformed = || Err(Response::bad_request("malformed request"));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_owned(), target),
+        _ => return Ok(malformed()),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_owned(), Some(query.to_owned())),
+        None => (target.to_owned(), None),
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(malformed());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                match value.trim().parse() {
+                    Ok(len) => content_length = len,
+                    Err(_) => return Ok(malformed()),
+                }
+            }
+        }
+    }
+
+    // Checked before allocating, since the client can claim any length
+    if content_length > MAX_BODY_SIZE {
+        return Ok(Err(Response::text(
+            "413 Payload Too Large",
+            "the body is bigger than any ROM",
+        )));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok(Request {
+        method,
+        path,
+        query,
+        body,
+    }))
+}
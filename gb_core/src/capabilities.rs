@@ -0,0 +1,43 @@
+//! What this build of the core can do, so frontends can hide options it doesn't support instead of failing when
+//! they're used
+
+/// The features compiled into this build. Fields are only ever added, and a field that's `false` now may become
+/// `true` in a later version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The version of gb_core
+    pub version: &'static str,
+    /// Sound emulation
+    pub apu: bool,
+    /// Gameboy Color emulation
+    pub cgb: bool,
+    /// Watches, RAM search, I/O snapshots and input latency (the `debugger` feature)
+    pub debugger: bool,
+    /// Bus tracing and conflict detection (the `trace` feature)
+    pub trace: bool,
+    /// Streaming a game to spectators (the `spectate` feature)
+    pub spectate: bool,
+    /// Playing over a network with input from both sides
+    pub netplay: bool,
+    /// Drawing scanlines on a worker thread, trading raster effect accuracy for speed
+    pub threaded_rendering: bool,
+    /// Frame buffers and mappers are stored inline, in the one allocation for the `Gameboy`, and the ROM size is capped
+    /// (the `static-alloc` feature). The ROM itself is still allocated when it's loaded.
+    pub static_alloc: bool,
+}
+
+/// Describe this build of the core
+pub const fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        apu: false,
+        cgb: false,
+        debugger: cfg!(feature = "debugger"),
+        trace: cfg!(feature = "trace"),
+        spectate: cfg!(feature = "spectate"),
+        netplay: false,
+        threaded_rendering: cfg!(feature = "std"),
+        static_alloc: cfg!(feature = "static-alloc"),
+    }
+}
@@ -0,0 +1,183 @@
+//! The state the boot ROM leaves the Gameboy in when it hands over to the cartridge at $0100
+//!
+//! The boot ROM isn't emulated, so [`Gameboy::reset`] sets the registers it would have left behind instead. Each
+//! revision of the hardware leaves slightly different values, which games use to tell them apart: A is $11 on a CGB
+//! and $FF on an MGB. The values are the ones listed in Pan Docs' "Power Up Sequence".
+
+use crate::{
+    cpu::{Cpu, CpuInputPins, Registers},
+    gameboy::{models::DMG, ppu::registers::LCDC, Gameboy},
+};
+
+/// A revision of the hardware, by the boot ROM it runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Revision {
+    /// The first DMG boot ROM, only found in early Japanese units
+    Dmg0,
+    Dmg,
+    /// The Gameboy Pocket
+    Mgb,
+    /// The Gameboy Color, running a game that supports it
+    Cgb,
+}
+
+impl Revision {
+    pub const ALL: [Revision; 4] = [Revision::Dmg0, Revision::Dmg, Revision::Mgb, Revision::Cgb];
+
+    pub fn post_boot_state(self) -> &'static PostBootState {
+        match self {
+            Revision::Dmg0 => &DMG0_STATE,
+            Revision::Dmg => &DMG_STATE,
+            Revision::Mgb => &MGB_STATE,
+            Revision::Cgb => &CGB_STATE,
+        }
+    }
+}
+
+/// The registers a revision's boot ROM leaves set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PostBootState {
+    pub a: u8,
+    /// F for a cartridge whose header checksum isn't 0, see `flags_from_checksum`
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    /// The DMG and MGB boot ROMs leave H and C set only if the header checksum ($014D) isn't 0
+    pub flags_from_checksum: bool,
+    /// IO registers as `(address, value)`, with unused bits set like they read on hardware. Registers the boot ROM
+    /// doesn't touch (OBP0 and OBP1) or that follow the PPU (LY and STAT) are left out, and so are the APU's and the
+    /// CGB's, which aren't emulated. The CGB's DIV depends on how long its logo animation ran, so it's left out too.
+    pub io: &'static [(u16, u8)],
+}
+
+impl PostBootState {
+    /// The CPU registers, ready to run the cartridge at $0100
+    pub fn registers(&self, header_checksum: u8) -> Registers {
+        let f = if self.flags_from_checksum && header_checksum == 0 {
+            self.f & !0x30
+        } else {
+            self.f
+        };
+        Registers {
+            a: self.a,
+            f: f.into(),
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+}
+
+/// The IO registers, which only differ in DIV between revisions
+macro_rules! io_registers {
+    ($($div:expr)?) => {
+        &[
+            $((0xFF04, $div),)?
+            (0xFF05, 0x00), // TIMA
+            (0xFF06, 0x00), // TMA
+            (0xFF07, 0xF8), // TAC
+            (0xFF0F, 0xE1), // IF, with VBlank requested
+            (0xFF40, 0x91), // LCDC
+            (0xFF42, 0x00), // SCY
+            (0xFF43, 0x00), // SCX
+            (0xFF45, 0x00), // LYC
+            (0xFF47, 0xFC), // BGP
+            (0xFF4A, 0x00), // WY
+            (0xFF4B, 0x00), // WX
+            (0xFFFF, 0x00), // IE
+        ]
+    };
+}
+
+const DMG0_STATE: PostBootState = PostBootState {
+    a: 0x01,
+    f: 0x00,
+    b: 0xFF,
+    c: 0x13,
+    d: 0x00,
+    e: 0xC1,
+    h: 0x84,
+    l: 0x03,
+    flags_from_checksum: false,
+    io: io_registers!(0x18),
+};
+
+const DMG_STATE: PostBootState = PostBootState {
+    a: 0x01,
+    f: 0xB0,
+    b: 0x00,
+    c: 0x13,
+    d: 0x00,
+    e: 0xD8,
+    h: 0x01,
+    l: 0x4D,
+    flags_from_checksum: true,
+    io: io_registers!(0xAB),
+};
+
+const MGB_STATE: PostBootState = PostBootState {
+    a: 0xFF,
+    ..DMG_STATE
+};
+
+const CGB_STATE: PostBootState = PostBootState {
+    a: 0x11,
+    f: 0x80,
+    b: 0x00,
+    c: 0x00,
+    d: 0xFF,
+    e: 0x56,
+    h: 0x00,
+    l: 0x0D,
+    flags_from_checksum: false,
+    io: io_registers!(),
+};
+
+impl Gameboy<DMG> {
+    /// Like [`Gameboy::reset`], but with the registers another revision's boot ROM leaves, e.g. to test how a game
+    /// detects the model. Only the registers change: the hardware emulated is still a DMG.
+    ///
+    /// The CPU is restarted from scratch, so a reset also recovers from a lock-up or HALT.
+    pub fn reset_as(&mut self, revision: Revision) {
+        let state = revision.post_boot_state();
+        self.cpu = Cpu {
+            registers: state.registers(self.cart.header().header_checksum),
+            ime: false,
+        }
+        .runner();
+        self.cpu_input = CpuInputPins::default();
+        self.cpu_halted = false;
+        self.cpu_locked_up = false;
+        self.illegal_opcode = None;
+        for &(addr, value) in state.io {
+            self.set_io_register(addr, value);
+        }
+    }
+
+    /// Set an IO register directly, without the side effects a CPU write would have
+    fn set_io_register(&mut self, addr: u16, value: u8) {
+        let ppu = &mut self.ppu;
+        match addr {
+            0xFF04..=0xFF07 => self.timer.set_register(addr, value),
+            0xFF0F => self.interrupt_request = value & 0x1F,
+            0xFF40 => ppu.set_lcdc(LCDC::from_bits_truncate(value)),
+            0xFF42 => ppu.set_scy(value),
+            0xFF43 => ppu.set_scx(value),
+            0xFF45 => ppu.set_lyc(value),
+            0xFF47 => ppu.set_bgp(value),
+            0xFF4A => ppu.set_wy(value),
+            0xFF4B => ppu.set_wx(value),
+            0xFFFF => self.interrupt_enable = value & 0x1F,
+            _ => unreachable!("${:04X} isn't in any post-boot state", addr),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Parsing of the cartridge header at $0100-$014F
+//!
+//! See https://gbdev.io/pandocs/The_Cartridge_Header.html
+
+use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+
+/// The Nintendo logo at $0104-$0133, which the boot ROM checks
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CartHeader {
+    /// The game's title, with padding removed
+    pub title: String,
+    /// $0143, which overlaps the last byte of the title on older cartridges
+    pub cgb_flag: u8,
+    pub sgb_flag: u8,
+    pub cart_type: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartHeader {
+    /// Parse the header out of a ROM image. Returns `None` if the image is too small to contain a header.
+    pub fn parse(rom: &[u8]) -> Option<Self> {
+        let header = rom.get(0x100..0x150)?;
+        let byte = |addr: usize| header[addr - 0x100];
+
+        let cgb_flag = byte(0x143);
+        // CGB-aware cartridges use the last byte of the title for the CGB flag
+        let title_end = if cgb_flag & 0x80 != 0 { 0x143 } else { 0x144 };
+        let title = header[0x134 - 0x100..title_end - 0x100]
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| if c.is_ascii_graphic() { c as char } else { ' ' })
+            .collect::<String>()
+            .trim_end()
+            .to_owned();
+
+        Some(CartHeader {
+            title,
+            cgb_flag,
+            sgb_flag: byte(0x146),
+            cart_type: byte(0x147),
+            rom_size: byte(0x148),
+            ram_size: byte(0x149),
+            version: byte(0x14C),
+            header_checksum: byte(0x14D),
+            global_checksum: u16::from_be_bytes([byte(0x14E), byte(0x14F)]),
+        })
+    }
+}
+
+impl CartHeader {
+    /// The checksums stored in the header. Compare with `Checksums::compute` to verify them.
+    pub fn checksums(&self) -> Checksums {
+        Checksums {
+            header: self.header_checksum,
+            global: self.global_checksum,
+        }
+    }
+
+    /// A description of the cartridge hardware in `cart_type`, or `None` for unknown types
+    pub fn cart_type_name(&self) -> Option<&'static str> {
+        Some(match self.cart_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => return None,
+        })
+    }
+
+    /// The ROM size in bytes that `rom_size` declares
+    pub fn rom_size_bytes(&self) -> Option<usize> {
+        match self.rom_size {
+            0x00..=0x08 => Some(0x8000 << self.rom_size),
+            _ => None,
+        }
+    }
+
+    /// The external RAM size in bytes that `ram_size` declares
+    pub fn ram_size_bytes(&self) -> Option<usize> {
+        match self.ram_size {
+            0x00 => Some(0),
+            0x01 => Some(0x800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        }
+    }
+}
+
+/// The header checksum ($014D) and global checksum ($014E-$014F) of a ROM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checksums {
+    /// Checked by the boot ROM, which locks up if it doesn't match
+    pub header: u8,
+    /// Not checked by any hardware, but useful for telling ROMs apart
+    pub global: u16,
+}
+
+impl Checksums {
+    /// Compute the checksums a ROM image should have. Returns `None` if the image is too small to contain a header.
+    pub fn compute(rom: &[u8]) -> Option<Self> {
+        let header = rom
+            .get(0x134..=0x14C)?
+            .iter()
+            .fold(0u8, |x, &byte| x.wrapping_sub(byte).wrapping_sub(1));
+
+        // The global checksum covers every byte except itself, including the header checksum
+        let global = rom
+            .iter()
+            .enumerate()
+            .filter(|&(addr, _)| addr != 0x14E && addr != 0x14F)
+            .fold(0u16, |sum, (addr, &byte)| {
+                let byte = if addr == 0x14D { header } else { byte };
+                sum.wrapping_add(byte as u16)
+            });
+
+        Some(Checksums { header, global })
+    }
+}
+
+/// Write the correct checksums into a ROM image, e.g. after assembling it. Returns the new checksums, or `None` if
+/// the image is too small to contain a header.
+pub fn fix_checksums(rom: &mut [u8]) -> Option<Checksums> {
+    let checksums = Checksums::compute(rom)?;
+    rom[0x14D] = checksums.header;
+    rom[0x14E..0x150].copy_from_slice(&checksums.global.to_be_bytes());
+    Some(checksums)
+}
+
+/// Build a 32 KiB ROM-only image with a valid header, which jumps straight to `code` at $0150. Handy for examples and
+/// tests.
+///
+/// ```
+/// use gb_core::gameboy::cart::{header::rom_with_code, Cart, LoadMode};
+///
+/// let rom = rom_with_code(&[0x18, 0xFE]); // JR -2
+/// let cart = Cart::with_mode(rom, LoadMode::Strict).unwrap();
+/// assert!(cart.diagnostics().is_empty());
+/// ```
+pub fn rom_with_code(code: &[u8]) -> Vec<u8> {
+    assert!(
+        code.len() <= 0x8000 - 0x150,
+        "the code doesn't fit in 32 KiB"
+    );
+    let mut rom = vec![0; 0x8000];
+    // NOP; JP $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    rom[0x150..0x150 + code.len()].copy_from_slice(code);
+    fix_checksums(&mut rom);
+    rom
+}
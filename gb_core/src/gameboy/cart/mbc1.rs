@@ -1,92 +1,95 @@
-use crate::{cpu::CpuOutputPins, gameboy::Chip};
+use alloc::sync::Arc;
 
-use super::Mapper;
+use crate::{cpu::CpuOutputPins, gameboy::Chip};
 
-type Bank = [u8; 0x4000];
+use super::{header::NINTENDO_LOGO, Mapper};
 
 pub type Mbc1 = Mbc1Generic<ram::NullRam>;
 pub type Mbc1WithRam = Mbc1Generic<ram::BasicRam>;
 // TODO: Implement save files
 pub type Mbc1WithBatteryRam = Mbc1Generic<ram::BasicRam>;
 
-// TODO: ROM Bank mirroring
 pub struct Mbc1Generic<R: ram::Ram> {
-    data: Vec<Bank>,
+    data: Arc<[u8]>,
     ram: R,
 
     ram_enable: bool,
     rom_bank_lower: u8,
     rom_bank_upper: u8,
     mode_select: bool,
+    /// MBC1M wiring, used by multi-game compilations: the upper bank bits select one of four 256 KiB games, so
+    /// they're shifted in at bit 4 instead of bit 5 and the lower bank register only has 4 bits
+    multicart: bool,
 }
 
 impl<R: ram::Ram> Mbc1Generic<R> {
-    pub fn new(data: Vec<u8>) -> Self {
-        let mut banks = data.array_chunks::<0x4000>();
-        let mut data = vec![];
-        while let Some(bank) = banks.next() {
-            data.push(bank.clone());
-        }
-        let remainder = {
-            let mut buf = [0; 0x4000];
-            buf[..banks.remainder().len()].copy_from_slice(banks.remainder());
-            buf
-        };
-
-        data.push(remainder);
-
-        while data.len() < 0x80 {
-            data.push([0; 0x4000]);
-        }
-
-        assert_eq!(data.len(), 0x80);
-
+    /// `ram_size` is the size of external RAM the header declares, in bytes
+    pub fn new(data: Arc<[u8]>, ram_size: usize) -> Self {
+        let multicart = is_multicart(&data);
         Mbc1Generic {
             data,
-            ram: Default::default(),
+            ram: R::with_size(ram_size),
             ram_enable: false,
             rom_bank_lower: 1,
             rom_bank_upper: 0,
             mode_select: false,
+            multicart,
         }
     }
 
-    fn bank_0(&mut self) -> &mut [u8; 0x4000] {
-        let bank_idx = if self.mode_select {
+    fn upper_bits(&self) -> u8 {
+        if self.multicart {
+            self.rom_bank_upper << 4
+        } else {
             self.rom_bank_upper << 5
+        }
+    }
+
+    /// The offset into the ROM of a byte in a 16 KiB bank. Banks past the end of the ROM mirror the ones before
+    /// them, as the unused bank bits aren't connected.
+    fn bank_offset(&self, bank: u8, offset: u16) -> usize {
+        let bank_count = self.data.len().div_ceil(0x4000);
+        let bank = bank as usize % bank_count;
+        bank * 0x4000 + offset as usize
+    }
+
+    /// The bank mapped at $0000-$3FFF
+    fn bank_0(&self) -> u8 {
+        if self.mode_select {
+            self.upper_bits()
         } else {
             0
-        };
-        &mut self.data[bank_idx as usize]
+        }
     }
 
-    fn bank_1(&mut self) -> &mut [u8; 0x4000] {
+    /// The bank mapped at $4000-$7FFF
+    fn bank_1(&self) -> u8 {
         let lower = if self.rom_bank_lower == 0 {
             1
         } else {
             self.rom_bank_lower
         };
-        let bank_idx = (self.rom_bank_upper << 5) + lower;
-        &mut self.data[bank_idx as usize]
+        // The check for bank 0 uses all 5 bits, even on multicarts which then ignore bit 4
+        let lower = if self.multicart { lower & 0x0F } else { lower };
+        self.upper_bits() | lower
+    }
+
+    /// The offset into external RAM of `addr` ($A000-$BFFF). In mode 1 the upper bank bits select the RAM bank;
+    /// carts with fewer banks don't connect those lines, so the banks past the end mirror the ones before them.
+    fn ram_offset(&self, addr: u16) -> u16 {
+        let bank = if self.mode_select {
+            self.rom_bank_upper as usize % self.ram.bank_count()
+        } else {
+            0
+        };
+        (bank * 0x2000) as u16 + (addr - 0xA000)
     }
 }
 
 impl<R: ram::Ram> Chip for Mbc1Generic<R> {
     fn clock(&mut self, input: CpuOutputPins, data: &mut u8, _interrupt_request: &mut u8) {
         match input {
-            CpuOutputPins::Read { addr } => match addr {
-                0x0000..=0x3FFF => *data = self.bank_0()[addr as usize],
-                0x4000..=0x7FFF => *data = self.bank_1()[(addr - 0x4000) as usize],
-
-                0xA000..=0xBFFF => {
-                    *data = if self.ram_enable {
-                        self.ram[addr - 0xA000]
-                    } else {
-                        0
-                    }
-                }
-                0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
-            },
+            CpuOutputPins::Read { addr } => self.debug_read(addr, data),
             CpuOutputPins::Write { addr, data } => {
                 match addr {
                     0x0000..=0x1FFF => {
@@ -101,55 +104,161 @@ impl<R: ram::Ram> Chip for Mbc1Generic<R> {
                     0x6000..=0x7FFF => self.mode_select = !(data == 0),
                     0xA000..=0xBFFF => {
                         if self.ram_enable {
-                            self.ram[addr - 0xA000] = data
+                            self.ram.write(self.ram_offset(addr), data)
                         }
                     }
                     0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
                 };
                 Default::default()
             }
+            CpuOutputPins::Idle => (),
+        }
+    }
+
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        match addr {
+            // A partial last bank reads as 0 past the end of the data
+            0x0000..=0x7FFF => *data = self.data.get(self.rom_offset(addr)).copied().unwrap_or(0),
+
+            // Disabled or missing RAM doesn't drive the bus, so it reads as open bus
+            0xA000..=0xBFFF => {
+                if let Some(byte) = self
+                    .ram
+                    .read(self.ram_offset(addr))
+                    .filter(|_| self.ram_enable)
+                {
+                    *data = byte
+                }
+            }
+            0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
         }
     }
 }
 
-impl<R: ram::Ram> Mapper for Mbc1Generic<R> {}
+impl<R: ram::Ram> Mapper for Mbc1Generic<R> {
+    fn rom(&self) -> &[u8] {
+        &self.data
+    }
 
-mod ram {
-    pub trait Ram: std::ops::IndexMut<u16, Output = u8> + Default {}
+    fn rom_mut(&mut self) -> &mut Arc<[u8]> {
+        &mut self.data
+    }
 
-    #[derive(Default)]
-    pub struct NullRam(u8);
-    impl std::ops::Index<u16> for NullRam {
-        type Output = u8;
-        fn index(&self, _index: u16) -> &u8 {
-            &0
+    fn rom_offset(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => self.bank_offset(self.bank_0(), addr),
+            _ => self.bank_offset(self.bank_1(), addr - 0x4000),
         }
     }
-    impl std::ops::IndexMut<u16> for NullRam {
-        fn index_mut(&mut self, _index: u16) -> &mut u8 {
-            &mut self.0
-        }
+
+    fn is_register(&self, _addr: u16) -> bool {
+        // The four registers cover the whole of ROM
+        true
     }
 
-    impl Ram for NullRam {}
+    fn ram(&self) -> &[u8] {
+        self.ram.as_slice()
+    }
 
-    pub struct BasicRam([u8; 0x2000]);
-    impl Default for BasicRam {
-        fn default() -> Self {
-            BasicRam([0u8; 0x2000])
-        }
+    fn ram_mut(&mut self) -> &mut [u8] {
+        self.ram.as_mut_slice()
     }
-    impl std::ops::Index<u16> for BasicRam {
-        type Output = u8;
-        fn index(&self, index: u16) -> &u8 {
-            &self.0[index as usize]
-        }
+}
+
+/// Multicarts have the menu in the first 256 KiB game, and another game with its own header in bank $10.
+///
+/// MBC1M is the only multicart wiring emulated. The unlicensed BBD and Hitek compilations scramble the bank number and
+/// the data on top of MBC5, which isn't emulated, so they load as ROM only with `Diagnostic::UnknownMapper`.
+fn is_multicart(data: &[u8]) -> bool {
+    const SECOND_GAME_LOGO: usize = 0x10 * 0x4000 + 0x104;
+    data.get(SECOND_GAME_LOGO..SECOND_GAME_LOGO + NINTENDO_LOGO.len()) == Some(&NINTENDO_LOGO[..])
+}
+
+mod ram {
+    /// External RAM, addressed by the offset from the start of bank 0
+    pub trait Ram {
+        /// RAM for a cartridge whose header declares `bytes` of it
+        fn with_size(bytes: usize) -> Self;
+
+        /// The number of 8 KiB banks, which is at least 1 so there's always a bank to mirror
+        fn bank_count(&self) -> usize;
+
+        /// `None` past the end of the RAM, where nothing answers the read
+        fn read(&self, offset: u16) -> Option<u8>;
+
+        /// Writes past the end of the RAM are ignored
+        fn write(&mut self, offset: u16, data: u8);
+
+        fn as_slice(&self) -> &[u8];
+
+        fn as_mut_slice(&mut self) -> &mut [u8];
     }
-    impl std::ops::IndexMut<u16> for BasicRam {
-        fn index_mut(&mut self, index: u16) -> &mut u8 {
-            &mut self.0[index as usize]
+
+    pub struct NullRam;
+
+    impl Ram for NullRam {
+        fn with_size(_bytes: usize) -> Self {
+            NullRam
+        }
+
+        fn bank_count(&self) -> usize {
+            1
+        }
+
+        fn read(&self, _offset: u16) -> Option<u8> {
+            None
+        }
+
+        fn write(&mut self, _offset: u16, _data: u8) {}
+
+        fn as_slice(&self) -> &[u8] {
+            &[]
         }
+
+        fn as_mut_slice(&mut self) -> &mut [u8] {
+            &mut []
+        }
+    }
+
+    /// The most RAM MBC1 can bank in: four 8 KiB banks
+    const MAX_SIZE: usize = 4 * 0x2000;
+
+    /// Stored inline at the largest size, so the mapper doesn't need another allocation
+    pub struct BasicRam {
+        data: [u8; MAX_SIZE],
+        /// In bytes: 0, 2 KiB, or a whole number of banks. Bigger sizes in the header are cut down to what can be
+        /// banked in.
+        size: usize,
     }
 
-    impl Ram for BasicRam {}
+    impl Ram for BasicRam {
+        fn with_size(bytes: usize) -> Self {
+            BasicRam {
+                data: [0; MAX_SIZE],
+                size: bytes.min(MAX_SIZE),
+            }
+        }
+
+        fn bank_count(&self) -> usize {
+            (self.size / 0x2000).max(1)
+        }
+
+        fn read(&self, offset: u16) -> Option<u8> {
+            self.as_slice().get(offset as usize).copied()
+        }
+
+        fn write(&mut self, offset: u16, data: u8) {
+            if let Some(byte) = self.as_mut_slice().get_mut(offset as usize) {
+                *byte = data
+            }
+        }
+
+        fn as_slice(&self) -> &[u8] {
+            &self.data[..self.size]
+        }
+
+        fn as_mut_slice(&mut self) -> &mut [u8] {
+            &mut self.data[..self.size]
+        }
+    }
 }
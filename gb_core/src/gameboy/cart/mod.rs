@@ -1,36 +1,499 @@
+pub mod header;
 mod mbc1;
 mod rom;
+mod wisdom_tree;
+
+#[cfg(not(feature = "static-alloc"))]
+use alloc::boxed::Box;
+use alloc::{sync::Arc, vec::Vec};
+use core::fmt;
 
 use super::Chip;
 use crate::cpu::CpuOutputPins;
+use header::{CartHeader, Checksums, NINTENDO_LOGO};
 use mbc1::{Mbc1, Mbc1WithBatteryRam, Mbc1WithRam};
+use wisdom_tree::WisdomTree;
+
+/// The biggest ROM that can be loaded with the `static-alloc` feature, which is enough for every MBC1 game
+#[cfg(feature = "static-alloc")]
+pub const MAX_ROM_SIZE: usize = 0x20_0000;
+
+/// The size of the external RAM banks that are switched in at $A000-$BFFF
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+trait Mapper: Chip {
+    fn rom(&self) -> &[u8];
+
+    fn rom_mut(&mut self) -> &mut Arc<[u8]>;
+
+    /// How far into the ROM image the byte read at `addr` ($0000-$7FFF) is, with the banks currently selected
+    fn rom_offset(&self, addr: u16) -> usize;
+
+    /// Whether writes to `addr` ($0000-$7FFF) go to one of the mapper's registers
+    fn is_register(&self, _addr: u16) -> bool {
+        false
+    }
+
+    /// All of the external RAM, whichever bank is mapped. It's empty if the cartridge has none.
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
 
-trait Mapper: Chip {}
+    /// External RAM, which is empty if the cartridge has none
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+}
+
+/// How to treat problems with a ROM image when loading it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Refuse to load ROMs with any problem
+    Strict,
+    /// Load anything that has a header, making a best guess for anything wrong with it. Problems are listed by
+    /// `Cart::diagnostics`.
+    Lenient,
+}
+
+/// A problem found while loading a ROM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The Nintendo logo is wrong, so a real Gameboy would refuse to boot it
+    BadLogo,
+    HeaderChecksum {
+        stored: u8,
+        computed: u8,
+    },
+    GlobalChecksum {
+        stored: u16,
+        computed: u16,
+    },
+    /// The size the header declares doesn't match the size of the image
+    RomSize {
+        declared: Option<usize>,
+        actual: usize,
+    },
+    /// A mapper that isn't emulated. The cartridge is treated as ROM only.
+    UnknownMapper(u8),
+}
+
+impl Diagnostic {
+    /// A short description, used as the error when loading strictly
+    pub fn summary(&self) -> &'static str {
+        match self {
+            Diagnostic::BadLogo => "The Nintendo logo in the header is wrong",
+            Diagnostic::HeaderChecksum { .. } => "The header checksum is wrong",
+            Diagnostic::GlobalChecksum { .. } => "The global checksum is wrong",
+            Diagnostic::RomSize { .. } => "The ROM size doesn't match the header",
+            Diagnostic::UnknownMapper(_) => "The cartridge type isn't supported",
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Diagnostic::HeaderChecksum { stored, computed } => {
+                write!(
+                    f,
+                    "header checksum is {:02X}, should be {:02X}",
+                    stored, computed
+                )
+            }
+            Diagnostic::GlobalChecksum { stored, computed } => {
+                write!(
+                    f,
+                    "global checksum is {:04X}, should be {:04X}",
+                    stored, computed
+                )
+            }
+            Diagnostic::RomSize {
+                declared: Some(declared),
+                actual,
+            } => write!(
+                f,
+                "header declares {} bytes of ROM, but there are {}",
+                declared, actual
+            ),
+            Diagnostic::RomSize {
+                declared: None,
+                actual,
+            } => write!(
+                f,
+                "header declares an unknown ROM size, and there are {} bytes",
+                actual
+            ),
+            Diagnostic::UnknownMapper(id) => {
+                write!(
+                    f,
+                    "unsupported cartridge type {:02X}, treating it as ROM only",
+                    id
+                )
+            }
+            Diagnostic::BadLogo => f.write_str("the Nintendo logo in the header is wrong"),
+        }
+    }
+}
+
+/// Identifies a patch made with [`Cart::patch_rom`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PatchId(usize);
+
+struct RomPatch {
+    id: PatchId,
+    /// How far into the ROM image the patched byte is
+    offset: usize,
+    original: u8,
+    replacement: u8,
+}
 
 pub struct Cart {
-    mapper: Box<dyn Mapper + Send>,
+    header: CartHeader,
+    mapper: AnyMapper,
+    diagnostics: Vec<Diagnostic>,
+    patches: Vec<RomPatch>,
+    next_patch_id: usize,
 }
 
 impl Chip for Cart {
     fn clock(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8) {
-        self.mapper.clock(input, data, interrupt_request)
+        self.mapper.clock(input, data, interrupt_request);
+        if let CpuOutputPins::Read { addr } = input {
+            self.apply_patches(addr, data);
+        }
+    }
+
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        self.mapper.debug_read(addr, data);
+        self.apply_patches(addr, data);
     }
 }
 
 impl Cart {
-    pub fn new(data: Vec<u8>) -> Result<Self, &'static str> {
-        let id = data.get(0x147).ok_or("Invalid ROM file")?;
-        let mapper = mapper_from_id(*id, data);
-        Ok(Cart { mapper })
+    /// Load a ROM leniently
+    pub fn new(data: impl Into<Arc<[u8]>>) -> Result<Self, &'static str> {
+        Self::with_mode(data, LoadMode::Lenient)
+    }
+
+    /// Load a ROM. The mapper reads straight from `data`, so an `Arc` can be shared between several carts without
+    /// copying the ROM.
+    ///
+    /// Even with the `static-alloc` feature the ROM stays in `data` rather than being copied inline, and the
+    /// diagnostics are allocated here. Nothing else in the cart allocates except `patch_rom`, and `write_rom` when the
+    /// ROM is shared.
+    pub fn with_mode(data: impl Into<Arc<[u8]>>, mode: LoadMode) -> Result<Self, &'static str> {
+        let data = data.into();
+        #[cfg(feature = "static-alloc")]
+        if data.len() > MAX_ROM_SIZE {
+            return Err("The ROM is bigger than MAX_ROM_SIZE");
+        }
+        let header = CartHeader::parse(&data).ok_or("Invalid ROM file")?;
+        let diagnostics = diagnose(&header, &data);
+        if let (LoadMode::Strict, Some(diagnostic)) = (mode, diagnostics.first()) {
+            return Err(diagnostic.summary());
+        }
+
+        let mapper = mapper_from_header(&header, data);
+        Ok(Cart {
+            header,
+            mapper,
+            diagnostics,
+            patches: Vec::new(),
+            next_patch_id: 0,
+        })
+    }
+
+    /// Load a ROM leniently, with the external RAM starting out as `ram`, e.g. a save file or a test fixture
+    pub fn from_parts(rom: impl Into<Arc<[u8]>>, ram: &[u8]) -> Result<Self, &'static str> {
+        let mut cart = Self::new(rom)?;
+        cart.write_ram(0, ram)?;
+        Ok(cart)
+    }
+
+    pub fn header(&self) -> &CartHeader {
+        &self.header
+    }
+
+    /// The whole ROM image, as loaded or changed by `write_rom`. Patches aren't applied.
+    pub fn rom(&self) -> &[u8] {
+        self.mapper.rom()
+    }
+
+    /// Overwrite part of the ROM image, starting at `offset` bytes into it. If the ROM is shared with other carts it's
+    /// copied first, so they aren't affected.
+    ///
+    /// This is meant for tests and tools: the header isn't parsed again.
+    pub fn write_rom(&mut self, offset: usize, bytes: &[u8]) -> Result<(), &'static str> {
+        let rom = self.mapper.rom_mut();
+        let end = match offset.checked_add(bytes.len()) {
+            Some(end) if end <= rom.len() => end,
+            _ => return Err("Write past the end of the ROM"),
+        };
+        if Arc::get_mut(rom).is_none() {
+            *rom = rom.to_vec().into();
+        }
+        Arc::get_mut(rom).unwrap()[offset..end].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Overwrite part of the external RAM, starting at `offset` bytes into it. This ignores whether the RAM is enabled.
+    pub fn write_ram(&mut self, offset: usize, bytes: &[u8]) -> Result<(), &'static str> {
+        let ram = self.mapper.ram_mut();
+        let end = match offset.checked_add(bytes.len()) {
+            Some(end) if end <= ram.len() => end,
+            _ => return Err("Write past the end of the cartridge RAM"),
+        };
+        ram[offset..end].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// All of the external RAM, whichever bank is mapped. It's empty if the cartridge has none.
+    pub fn ram(&self) -> &[u8] {
+        self.mapper.ram()
+    }
+
+    /// The external RAM split into the 8 KiB banks the mapper switches between, for save editors and debuggers. A
+    /// cartridge with only 2 KiB has one short bank.
+    pub fn ram_banks(&self) -> impl Iterator<Item = &[u8]> {
+        self.mapper.ram().chunks(RAM_BANK_SIZE)
+    }
+
+    /// One bank of external RAM, or `None` if the cartridge doesn't have that many
+    pub fn ram_bank(&self, bank: usize) -> Option<&[u8]> {
+        self.ram_banks().nth(bank)
+    }
+
+    /// Problems found when the ROM was loaded
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The 16 KiB bank of the ROM image mapped at `addr`, or `None` if `addr` isn't in ROM ($0000-$7FFF)
+    pub fn rom_bank(&self, addr: u16) -> Option<usize> {
+        self.rom_offset(addr).map(|offset| offset / 0x4000)
+    }
+
+    /// How far into the ROM image the byte mapped at `addr` is, or `None` if `addr` isn't in ROM ($0000-$7FFF)
+    pub fn rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x0000..=0x7FFF => Some(self.mapper.rom_offset(addr)),
+            _ => None,
+        }
+    }
+
+    /// Whether writing to `addr` does anything. Writes to ROM ($0000-$7FFF) only do something if they're to one of the
+    /// mapper's registers; anything else is usually a bug in the game.
+    pub fn is_writable(&self, addr: u16) -> bool {
+        match addr {
+            0x0000..=0x7FFF => self.mapper.is_register(addr),
+            _ => true,
+        }
+    }
+
+    /// Make reads of `addr` ($0000-$7FFF) return `replacement` while 16 KiB ROM bank `bank` is mapped there, like a
+    /// Game Genie. The ROM image itself isn't changed, so reverting the patch puts the original byte back.
+    ///
+    /// Fails if the byte isn't `original`, which guards against patching the wrong version of a game. Reads only
+    /// return `replacement` while the byte is still `original`, so patches stop applying if `write_rom` changes it.
+    pub fn patch_rom(
+        &mut self,
+        bank: usize,
+        addr: u16,
+        original: u8,
+        replacement: u8,
+    ) -> Result<PatchId, &'static str> {
+        if addr > 0x7FFF {
+            return Err("Patches have to be in ROM ($0000-$7FFF)");
+        }
+        let offset = bank * 0x4000 + (addr & 0x3FFF) as usize;
+        match self.mapper.rom_mut().get(offset) {
+            None => return Err("The ROM has no such bank"),
+            Some(&byte) if byte != original => {
+                return Err("The ROM doesn't contain the original byte")
+            }
+            Some(_) => (),
+        }
+
+        let id = PatchId(self.next_patch_id);
+        self.next_patch_id += 1;
+        self.patches.push(RomPatch {
+            id,
+            offset,
+            original,
+            replacement,
+        });
+        Ok(id)
+    }
+
+    /// Remove a patch, so the original byte is read again. Returns false if it was already reverted.
+    pub fn revert_patch(&mut self, patch: PatchId) -> bool {
+        let count = self.patches.len();
+        self.patches.retain(|p| p.id != patch);
+        self.patches.len() != count
+    }
+
+    /// Remove every patch
+    pub fn revert_all_patches(&mut self) {
+        self.patches.clear();
+    }
+
+    #[inline]
+    fn apply_patches(&self, addr: u16, data: &mut u8) {
+        if self.patches.is_empty() || addr > 0x7FFF {
+            return;
+        }
+        let offset = self.mapper.rom_offset(addr);
+        if let Some(patch) = self
+            .patches
+            .iter()
+            .find(|patch| patch.offset == offset && patch.original == *data)
+        {
+            *data = patch.replacement;
+        }
     }
 }
 
-fn mapper_from_id(id: u8, data: Vec<u8>) -> Box<dyn Mapper + Send> {
-    match id {
+fn diagnose(header: &CartHeader, data: &[u8]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    if data[0x104..0x134] != NINTENDO_LOGO {
+        diagnostics.push(Diagnostic::BadLogo);
+    }
+
+    // The header was parsed, so the image is big enough to have checksums
+    let computed = Checksums::compute(data).unwrap();
+    let stored = header.checksums();
+    if stored.header != computed.header {
+        diagnostics.push(Diagnostic::HeaderChecksum {
+            stored: stored.header,
+            computed: computed.header,
+        });
+    }
+    if stored.global != computed.global {
+        diagnostics.push(Diagnostic::GlobalChecksum {
+            stored: stored.global,
+            computed: computed.global,
+        });
+    }
+
+    let declared = header.rom_size_bytes();
+    // Wisdom Tree games are bigger than their ROM only header says
+    let wisdom_tree = header.cart_type == 0 && data.len() > 0x8000;
+    if declared != Some(data.len()) && !wisdom_tree {
+        diagnostics.push(Diagnostic::RomSize {
+            declared,
+            actual: data.len(),
+        });
+    }
+
+    if !is_supported(header.cart_type) {
+        diagnostics.push(Diagnostic::UnknownMapper(header.cart_type));
+    }
+
+    diagnostics
+}
+
+fn is_supported(id: u8) -> bool {
+    matches!(id, 0..=3)
+}
+
+#[cfg(not(feature = "static-alloc"))]
+type AnyMapper = Box<dyn Mapper + Send>;
+
+#[cfg(not(feature = "static-alloc"))]
+fn mapper_from_header(header: &CartHeader, data: Arc<[u8]>) -> AnyMapper {
+    let ram_size = header.ram_size_bytes().unwrap_or(0);
+    match header.cart_type {
+        // Wisdom Tree games claim to be ROM only, but are too big for that
+        0 if data.len() > 0x8000 => Box::new(WisdomTree::new(data)),
         0 => Box::new(rom::Rom::new(data)),
-        1 => Box::new(Mbc1::new(data)),
-        2 => Box::new(Mbc1WithRam::new(data)),
-        3 => Box::new(Mbc1WithBatteryRam::new(data)),
-        _ => panic!("Mapper unimplemented: {:#02X}", id),
+        1 => Box::new(Mbc1::new(data, ram_size)),
+        2 => Box::new(Mbc1WithRam::new(data, ram_size)),
+        3 => Box::new(Mbc1WithBatteryRam::new(data, ram_size)),
+        // Best effort for mappers that aren't emulated: only the first 32 KiB is mapped, with no banking
+        _ => Box::new(rom::Rom::new(data)),
+    }
+}
+
+/// Every mapper, so the cart can hold one without boxing it. Mappers with RAM are much bigger than the rest, but boxing
+/// them is what this is avoiding; the whole `Gameboy` is boxed once instead.
+#[cfg(feature = "static-alloc")]
+#[allow(clippy::large_enum_variant)]
+enum AnyMapper {
+    Rom(rom::Rom),
+    WisdomTree(WisdomTree),
+    Mbc1(Mbc1),
+    Mbc1WithRam(Mbc1WithRam),
+}
+
+#[cfg(feature = "static-alloc")]
+impl AnyMapper {
+    fn get(&self) -> &dyn Mapper {
+        match self {
+            AnyMapper::Rom(mapper) => mapper,
+            AnyMapper::WisdomTree(mapper) => mapper,
+            AnyMapper::Mbc1(mapper) => mapper,
+            AnyMapper::Mbc1WithRam(mapper) => mapper,
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut dyn Mapper {
+        match self {
+            AnyMapper::Rom(mapper) => mapper,
+            AnyMapper::WisdomTree(mapper) => mapper,
+            AnyMapper::Mbc1(mapper) => mapper,
+            AnyMapper::Mbc1WithRam(mapper) => mapper,
+        }
+    }
+}
+
+#[cfg(feature = "static-alloc")]
+impl Chip for AnyMapper {
+    fn clock(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8) {
+        self.get_mut().clock(input, data, interrupt_request)
+    }
+
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        self.get().debug_read(addr, data)
+    }
+}
+
+#[cfg(feature = "static-alloc")]
+impl Mapper for AnyMapper {
+    fn rom(&self) -> &[u8] {
+        self.get().rom()
+    }
+
+    fn rom_mut(&mut self) -> &mut Arc<[u8]> {
+        self.get_mut().rom_mut()
+    }
+
+    fn rom_offset(&self, addr: u16) -> usize {
+        self.get().rom_offset(addr)
+    }
+
+    fn is_register(&self, addr: u16) -> bool {
+        self.get().is_register(addr)
+    }
+
+    fn ram(&self) -> &[u8] {
+        self.get().ram()
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        self.get_mut().ram_mut()
+    }
+}
+
+#[cfg(feature = "static-alloc")]
+fn mapper_from_header(header: &CartHeader, data: Arc<[u8]>) -> AnyMapper {
+    let ram_size = header.ram_size_bytes().unwrap_or(0);
+    match header.cart_type {
+        0 if data.len() > 0x8000 => AnyMapper::WisdomTree(WisdomTree::new(data)),
+        0 => AnyMapper::Rom(rom::Rom::new(data)),
+        1 => AnyMapper::Mbc1(Mbc1::new(data, ram_size)),
+        2 => AnyMapper::Mbc1WithRam(Mbc1WithRam::new(data, ram_size)),
+        3 => AnyMapper::Mbc1WithRam(Mbc1WithBatteryRam::new(data, ram_size)),
+        _ => AnyMapper::Rom(rom::Rom::new(data)),
     }
 }
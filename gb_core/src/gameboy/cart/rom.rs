@@ -1,26 +1,41 @@
+use alloc::sync::Arc;
+
 use super::*;
 
 pub struct Rom {
-    pub data: [u8; 0x8000],
+    /// Only the first 32 KiB is mapped. Anything missing from a smaller ROM reads as 0
+    pub data: Arc<[u8]>,
 }
 
 impl Rom {
-    pub fn new(data: Vec<u8>) -> Self {
-        let mut buf = [0; 0x8000];
-        let len = usize::max(data.len(), 0x8000);
-        buf[..len].copy_from_slice(&data[..len]);
-        Self { data: buf }
+    pub fn new(data: Arc<[u8]>) -> Self {
+        Self { data }
     }
 }
 
 impl Chip for Rom {
     fn clock(&mut self, input: CpuOutputPins, data: &mut u8, _interrupt_request: &mut u8) {
-        if let CpuOutputPins::Read {
-            addr: addr @ (0x0000..=0x7FFF),
-        } = input
-        {
-            *data = self.data[addr as usize]
+        if let CpuOutputPins::Read { addr } = input {
+            self.debug_read(addr, data)
         }
     }
+
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        if let 0x0000..=0x7FFF = addr {
+            *data = self.data.get(addr as usize).copied().unwrap_or(0)
+        }
+    }
+}
+impl Mapper for Rom {
+    fn rom(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn rom_mut(&mut self) -> &mut Arc<[u8]> {
+        &mut self.data
+    }
+
+    fn rom_offset(&self, addr: u16) -> usize {
+        addr as usize
+    }
 }
-impl Mapper for Rom {}
@@ -0,0 +1,59 @@
+//! The Wisdom Tree mapper, used by unlicensed games whose headers claim to be ROM only despite being bigger than
+//! 32 KiB. Writing anywhere in $0000-$3FFF switches the whole 32 KiB address space to the bank given by the low byte
+//! of the address; the data written is ignored.
+
+use alloc::sync::Arc;
+
+use super::*;
+
+pub struct WisdomTree {
+    data: Arc<[u8]>,
+    bank: usize,
+}
+
+impl WisdomTree {
+    pub fn new(data: Arc<[u8]>) -> Self {
+        WisdomTree { data, bank: 0 }
+    }
+
+    fn bank_count(&self) -> usize {
+        self.data.len().div_ceil(0x8000)
+    }
+}
+
+impl Chip for WisdomTree {
+    fn clock(&mut self, input: CpuOutputPins, data: &mut u8, _interrupt_request: &mut u8) {
+        match input {
+            CpuOutputPins::Read { addr } => self.debug_read(addr, data),
+            CpuOutputPins::Write {
+                addr: addr @ 0x0000..=0x3FFF,
+                ..
+            } => self.bank = (addr & 0xFF) as usize % self.bank_count(),
+            _ => (),
+        }
+    }
+
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        if let 0x0000..=0x7FFF = addr {
+            // A partial last bank reads as 0 past the end of the data
+            *data = self.data.get(self.rom_offset(addr)).copied().unwrap_or(0)
+        }
+    }
+}
+impl Mapper for WisdomTree {
+    fn rom(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn rom_mut(&mut self) -> &mut Arc<[u8]> {
+        &mut self.data
+    }
+
+    fn rom_offset(&self, addr: u16) -> usize {
+        self.bank * 0x8000 + addr as usize
+    }
+
+    fn is_register(&self, addr: u16) -> bool {
+        addr <= 0x3FFF
+    }
+}
@@ -0,0 +1,135 @@
+//! Breakpoints on instructions, which know which ROM bank they're in
+//!
+//! In banked ROM the same address holds different code depending on the bank the mapper has switched in, so an
+//! address alone isn't enough to say which instruction is meant. Breakpoints are written `bank:addr` (e.g. `05:4123`)
+//! and only trigger while that bank is mapped there, as well as plain `addr` for any bank. Like broken memory region
+//! rules, hitting one makes [`Gameboy::run_frame`] return early, and the hit is taken with
+//! [`Gameboy::take_breakpoint_hit`].
+
+use alloc::vec::Vec;
+use core::{fmt, str::FromStr};
+
+use crate::gameboy::{models::GbModel, Gameboy};
+
+/// An address, and the ROM bank it's in if that matters. Banks are the 16 KiB banks of the ROM image, numbered the
+/// same way as RGBDS's ROMX banks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BankedAddr {
+    /// Ignored outside of ROM ($0000-$7FFF)
+    pub bank: Option<u16>,
+    pub addr: u16,
+}
+
+impl BankedAddr {
+    /// Whether `self` refers to `addr` while `bank` is mapped there
+    pub fn matches(&self, bank: Option<u16>, addr: u16) -> bool {
+        self.addr == addr && (addr > 0x7FFF || self.bank.is_none() || self.bank == bank)
+    }
+}
+
+/// Reads `bank:addr` or `addr`, in hex with or without `$`
+impl FromStr for BankedAddr {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = |value: &str| {
+            u16::from_str_radix(value.trim().trim_start_matches('$'), 16)
+                .map_err(|_| "Expected an address like 4123 or 05:4123")
+        };
+        match s.split_once(':') {
+            Some((bank, addr)) => Ok(BankedAddr {
+                bank: Some(hex(bank)?),
+                addr: hex(addr)?,
+            }),
+            None => Ok(BankedAddr {
+                bank: None,
+                addr: hex(s)?,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for BankedAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.addr),
+            None => write!(f, "{:04X}", self.addr),
+        }
+    }
+}
+
+/// Identifies a breakpoint added with [`Gameboy::add_breakpoint`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BreakpointId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BreakpointHit {
+    pub breakpoint: BreakpointId,
+    /// Where the instruction about to run is, with the bank it was fetched from
+    pub location: BankedAddr,
+}
+
+#[derive(Default)]
+pub(crate) struct Breakpoints {
+    next_id: usize,
+    breakpoints: Vec<(BreakpointId, BankedAddr)>,
+    hit: Option<BreakpointHit>,
+}
+
+impl Breakpoints {
+    #[inline]
+    pub(crate) fn hit_pending(&self) -> bool {
+        self.hit.is_some()
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.breakpoints.is_empty()
+    }
+
+    /// Check the instruction being fetched from `addr`, which is in `bank` if it's in ROM
+    pub(crate) fn check(&mut self, bank: Option<u16>, addr: u16) {
+        if self.hit.is_some() {
+            return;
+        }
+        self.hit = self
+            .breakpoints
+            .iter()
+            .find(|(_, breakpoint)| breakpoint.matches(bank, addr))
+            .map(|(id, _)| BreakpointHit {
+                breakpoint: *id,
+                location: BankedAddr { bank, addr },
+            });
+    }
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    /// Break before running the instruction at `addr`
+    pub fn add_breakpoint(&mut self, addr: BankedAddr) -> BreakpointId {
+        let id = BreakpointId(self.breakpoints.next_id);
+        self.breakpoints.next_id += 1;
+        self.breakpoints.breakpoints.push((id, addr));
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: BreakpointId) {
+        self.breakpoints
+            .breakpoints
+            .retain(|(id, _)| *id != breakpoint);
+    }
+
+    /// Take the breakpoint that was hit, letting the emulator run again. The CPU is stopped just before running the
+    /// instruction, so continuing runs it.
+    pub fn take_breakpoint_hit(&mut self) -> Option<BreakpointHit> {
+        self.breakpoints.hit.take()
+    }
+
+    /// Where the instruction being run is, with the ROM bank mapped there if it's in ROM
+    pub fn instruction_location(&self) -> BankedAddr {
+        let addr = self.instruction_pc;
+        BankedAddr {
+            bank: self.cart.rom_bank(addr).map(|bank| bank as u16),
+            addr,
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Lockstep comparison against a trace from another emulator, for finding the first instruction the CPU gets wrong
+//!
+//! Traces are in [Gameboy Doctor](https://github.com/robert/gameboy-doctor)'s format, with one line per instruction
+//! holding the CPU's state before it runs and the four bytes at PC:
+//!
+//! ```text
+//! A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+//! ```
+//!
+//! Gameboy Doctor's traces are recorded with LY always reading $90, so [`Gameboy::compare_trace`] does the same. The
+//! comparison starts from the registers on the trace's first line, so it doesn't depend on the boot ROM's state.
+
+use alloc::{collections::VecDeque, vec::Vec};
+use core::fmt;
+
+use crate::{
+    cpu::Registers,
+    gameboy::{models::GbModel, Gameboy},
+};
+
+/// The CPU's state before running an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceLine {
+    pub registers: Registers,
+    /// The bytes at PC
+    pub pcmem: [u8; 4],
+}
+
+impl TraceLine {
+    pub fn parse(line: &str) -> Result<Self, &'static str> {
+        let mut registers = Registers::default();
+        let mut pcmem = None;
+        let mut seen = 0u16;
+        for field in line.split_whitespace() {
+            let (name, value) = field.split_once(':').ok_or("Expected fields like A:01")?;
+            let byte = || u8::from_str_radix(value, 16).map_err(|_| "Expected a hex byte");
+            let word = || u16::from_str_radix(value, 16).map_err(|_| "Expected a hex word");
+            let bit = match name {
+                "A" => {
+                    registers.a = byte()?;
+                    0
+                }
+                "F" => {
+                    registers.f = byte()?.into();
+                    1
+                }
+                "B" => {
+                    registers.b = byte()?;
+                    2
+                }
+                "C" => {
+                    registers.c = byte()?;
+                    3
+                }
+                "D" => {
+                    registers.d = byte()?;
+                    4
+                }
+                "E" => {
+                    registers.e = byte()?;
+                    5
+                }
+                "H" => {
+                    registers.h = byte()?;
+                    6
+                }
+                "L" => {
+                    registers.l = byte()?;
+                    7
+                }
+                "SP" => {
+                    registers.sp = word()?;
+                    8
+                }
+                "PC" => {
+                    registers.pc = word()?;
+                    9
+                }
+                "PCMEM" => {
+                    let mut bytes = [0; 4];
+                    let mut values = value.split(',');
+                    for byte in &mut bytes {
+                        let value = values.next().ok_or("PCMEM needs four bytes")?;
+                        *byte = u8::from_str_radix(value, 16).map_err(|_| "Expected a hex byte")?;
+                    }
+                    if values.next().is_some() {
+                        return Err("PCMEM needs four bytes");
+                    }
+                    pcmem = Some(bytes);
+                    10
+                }
+                _ => return Err("Unknown field"),
+            };
+            seen |= 1 << bit;
+        }
+
+        match pcmem {
+            Some(pcmem) if seen == 0x7FF => Ok(TraceLine { registers, pcmem }),
+            _ => Err("Every register and PCMEM must be given"),
+        }
+    }
+
+    /// The names of the fields that differ between the two lines
+    pub fn differences(&self, other: &TraceLine) -> Vec<&'static str> {
+        let (a, b) = (&self.registers, &other.registers);
+        [
+            ("A", a.a != b.a),
+            ("F", u8::from(a.f) != u8::from(b.f)),
+            ("B", a.b != b.b),
+            ("C", a.c != b.c),
+            ("D", a.d != b.d),
+            ("E", a.e != b.e),
+            ("H", a.h != b.h),
+            ("L", a.l != b.l),
+            ("SP", a.sp != b.sp),
+            ("PC", a.pc != b.pc),
+            ("PCMEM", self.pcmem != other.pcmem),
+        ]
+        .iter()
+        .filter(|(_, differs)| *differs)
+        .map(|(name, _)| *name)
+        .collect()
+    }
+}
+
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.registers;
+        write!(
+            f,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} \
+             PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            r.a,
+            u8::from(r.f),
+            r.b,
+            r.c,
+            r.d,
+            r.e,
+            r.h,
+            r.l,
+            r.sp,
+            r.pc,
+            self.pcmem[0],
+            self.pcmem[1],
+            self.pcmem[2],
+            self.pcmem[3],
+        )
+    }
+}
+
+/// The first instruction where the emulator and the trace disagree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The line of the trace, counting from 1
+    pub line: usize,
+    pub expected: TraceLine,
+    pub actual: TraceLine,
+    /// The fields that differ
+    pub fields: Vec<&'static str>,
+    /// The lines that matched just before, oldest first
+    pub history: Vec<TraceLine>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceError {
+    /// A line of the trace couldn't be parsed
+    Parse {
+        line: usize,
+        message: &'static str,
+    },
+    Diverged(Divergence),
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    /// Run one instruction per line of `trace`, a Gameboy Doctor log, and stop at the first line the CPU's state
+    /// doesn't match. The registers are set from the first line, so this should be run on a freshly loaded ROM.
+    ///
+    /// Returns the number of lines that matched. A [`Divergence`] includes up to `context` of the lines before it.
+    pub fn compare_trace(&mut self, trace: &str, context: usize) -> Result<usize, TraceError> {
+        self.ly_override = Some(0x90);
+        let result = self.run_trace(trace, context);
+        self.ly_override = None;
+        result
+    }
+
+    fn run_trace(&mut self, trace: &str, context: usize) -> Result<usize, TraceError> {
+        let lines = trace
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let mut history = VecDeque::with_capacity(context);
+        let mut matched = 0;
+        for (line, text) in lines {
+            let expected =
+                TraceLine::parse(text).map_err(|message| TraceError::Parse { line, message })?;
+            if matched == 0 {
+                self.cpu.cpu.registers = expected.registers;
+            }
+
+            // Stepping stops on the next instruction's fetch, when the one before it has finished
+            self.step_instruction();
+            let actual = self.trace_line();
+            if actual != expected {
+                return Err(TraceError::Diverged(Divergence {
+                    line,
+                    expected,
+                    fields: actual.differences(&expected),
+                    actual,
+                    history: history.into_iter().collect(),
+                }));
+            }
+
+            if context > 0 {
+                if history.len() == context {
+                    history.pop_front();
+                }
+                history.push_back(actual);
+            }
+            matched += 1;
+        }
+        Ok(matched)
+    }
+
+    /// The state of the CPU at an instruction fetch, in the same form as a trace
+    fn trace_line(&self) -> TraceLine {
+        let mut registers = self.cpu.cpu.registers;
+        // PC has already moved past the opcode being fetched
+        registers.pc = self.instruction_pc;
+        let pc = self.instruction_pc;
+        TraceLine {
+            registers,
+            pcmem: [0, 1, 2, 3].map(|offset| self.debug_read(pc.wrapping_add(offset))),
+        }
+    }
+}
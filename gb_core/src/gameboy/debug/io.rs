@@ -0,0 +1,176 @@
+//! A structured view of the interrupt and IO registers
+
+use alloc::{vec, vec::Vec};
+
+use bitflags::bitflags;
+
+use crate::gameboy::{
+    dma::OamDma,
+    models::DMG,
+    ppu::registers::{LCDC, STAT},
+    timer::Timer,
+    Gameboy,
+};
+
+bitflags! {
+    /// The bit layout shared by the IE ($FFFF) and IF ($FF0F) registers
+    #[derive(Default)]
+    pub struct Interrupts: u8 {
+        const VBLANK = 0x01;
+        const STAT = 0x02;
+        const TIMER = 0x04;
+        const SERIAL = 0x08;
+        const JOYPAD = 0x10;
+    }
+}
+
+/// The timer registers along with the values decoded from TAC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerSnapshot {
+    pub div: u8,
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8,
+    /// TAC bit 2
+    pub enabled: bool,
+    /// The frequency TIMA is incremented at, as selected by TAC bits 0-1
+    pub frequency_hz: u32,
+}
+
+impl TimerSnapshot {
+    fn new(timer: &Timer) -> Self {
+        TimerSnapshot {
+            div: timer.div(),
+            tima: timer.tima(),
+            tma: timer.tma(),
+            tac: timer.tac(),
+            enabled: timer.enabled(),
+            frequency_hz: timer.frequency_hz(),
+        }
+    }
+}
+
+/// The LCD registers along with the PPU mode decoded from STAT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LcdSnapshot {
+    pub lcdc: LCDC,
+    pub stat: STAT,
+    /// STAT bits 0-1
+    pub mode: u8,
+    pub scy: u8,
+    pub scx: u8,
+    pub ly: u8,
+    pub lyc: u8,
+    pub wy: u8,
+    pub wx: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+}
+
+/// The OAM DMA register along with whether a transfer is running
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmaSnapshot {
+    /// The last value written to $FF46, the high byte of the address copied from
+    pub source: u8,
+    /// Whether a transfer is copying bytes
+    pub active: bool,
+}
+
+impl DmaSnapshot {
+    fn new(dma: &OamDma) -> Self {
+        DmaSnapshot {
+            source: dma.register(),
+            active: dma.active(),
+        }
+    }
+}
+
+/// A copy of every interrupt and IO register that is currently emulated.
+///
+/// This is much cheaper for a debugger UI to work with than issuing a bus read for each
+/// register and decoding the bits itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoSnapshot {
+    pub interrupt_enable: Interrupts,
+    pub interrupt_request: Interrupts,
+    pub ime: bool,
+    /// The joypad register, with the buttons selected by bits 4-5 in the low nibble
+    pub p1: u8,
+    /// The serial data register
+    pub sb: u8,
+    /// The serial control register
+    pub sc: u8,
+    pub timer: TimerSnapshot,
+    pub lcd: LcdSnapshot,
+    pub dma: DmaSnapshot,
+}
+
+/// A single raw IO register, as listed by [`IoSnapshot::registers`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoRegister {
+    pub name: &'static str,
+    pub addr: u16,
+    pub value: u8,
+}
+
+impl IoSnapshot {
+    /// Lists every register in the snapshot with its conventional name and address, in address order.
+    pub fn registers(&self) -> Vec<IoRegister> {
+        let reg = |name, addr, value| IoRegister { name, addr, value };
+        vec![
+            reg("P1", 0xFF00, self.p1),
+            reg("SB", 0xFF01, self.sb),
+            reg("SC", 0xFF02, self.sc),
+            reg("DIV", 0xFF04, self.timer.div),
+            reg("TIMA", 0xFF05, self.timer.tima),
+            reg("TMA", 0xFF06, self.timer.tma),
+            reg("TAC", 0xFF07, self.timer.tac),
+            reg("IF", 0xFF0F, self.interrupt_request.bits()),
+            reg("LCDC", 0xFF40, self.lcd.lcdc.bits()),
+            reg("STAT", 0xFF41, self.lcd.stat.bits()),
+            reg("SCY", 0xFF42, self.lcd.scy),
+            reg("SCX", 0xFF43, self.lcd.scx),
+            reg("LY", 0xFF44, self.lcd.ly),
+            reg("LYC", 0xFF45, self.lcd.lyc),
+            reg("DMA", 0xFF46, self.dma.source),
+            reg("BGP", 0xFF47, self.lcd.bgp),
+            reg("OBP0", 0xFF48, self.lcd.obp0),
+            reg("OBP1", 0xFF49, self.lcd.obp1),
+            reg("WY", 0xFF4A, self.lcd.wy),
+            reg("WX", 0xFF4B, self.lcd.wx),
+            reg("IE", 0xFFFF, self.interrupt_enable.bits()),
+        ]
+    }
+}
+
+impl Gameboy<DMG> {
+    /// Take a snapshot of the interrupt and IO registers
+    pub fn io_snapshot(&self) -> IoSnapshot {
+        let ppu = &self.ppu.state;
+        IoSnapshot {
+            interrupt_enable: Interrupts::from_bits_truncate(self.interrupt_enable),
+            interrupt_request: Interrupts::from_bits_truncate(self.interrupt_request),
+            ime: self.cpu.cpu.ime,
+            p1: self.debug_read(0xFF00),
+            sb: self.debug_read(0xFF01),
+            sc: self.debug_read(0xFF02),
+            timer: TimerSnapshot::new(&self.timer),
+            lcd: LcdSnapshot {
+                lcdc: ppu.lcdc,
+                stat: ppu.stat,
+                mode: (ppu.stat & !STAT::MODE_BITMASK).bits(),
+                scy: ppu.scy,
+                scx: ppu.scx,
+                ly: ppu.ly,
+                lyc: ppu.lyc,
+                wy: ppu.wy,
+                wx: ppu.wx,
+                bgp: ppu.bgp,
+                obp0: ppu.obp0,
+                obp1: ppu.obp1,
+            },
+            dma: DmaSnapshot::new(&self.oam_dma),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Input latency, from the frontend receiving an input event to the frame that shows it being presented
+//!
+//! The frontend calls [`Gameboy::input_event`] when it passes on an event from the host, and
+//! [`Gameboy::frame_presented`] after a frame reaches the screen. In between, the game latches the input the next time
+//! it reads the joypad register, and the frame it latched it in has to be finished before it can be shown.
+
+use alloc::collections::VecDeque;
+use core::time::Duration;
+
+use super::perf::Instant;
+use crate::gameboy::{models::GbModel, ppu::PPU, Gameboy};
+
+/// The number of samples [`LatencyStats`] are calculated over
+const SAMPLE_WINDOW: usize = 60;
+
+/// The journey of one input event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LatencySample {
+    /// The frame the game was drawing when it read the joypad register
+    pub latched_frame: u64,
+    /// Host time from the input event to the game reading the joypad register
+    pub to_latch: Duration,
+    /// Host time from the input event to the first frame that could show it being presented
+    pub to_present: Duration,
+}
+
+/// Input latency over the last 60 inputs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub average_to_latch: Duration,
+    pub average_to_present: Duration,
+    pub min_to_present: Duration,
+    pub max_to_present: Duration,
+}
+
+impl LatencyStats {
+    fn from_samples<'a>(samples: impl Iterator<Item = &'a LatencySample> + Clone) -> Self {
+        let count = samples.clone().count();
+        if count == 0 {
+            return LatencyStats::default();
+        }
+        let to_present = samples.clone().map(|sample| sample.to_present);
+        LatencyStats {
+            samples: count,
+            average_to_latch: samples.map(|sample| sample.to_latch).sum::<Duration>()
+                / count as u32,
+            average_to_present: to_present.clone().sum::<Duration>() / count as u32,
+            min_to_present: to_present.clone().min().unwrap(),
+            max_to_present: to_present.max().unwrap(),
+        }
+    }
+}
+
+struct Latched {
+    event: Instant,
+    frame: u64,
+    to_latch: Duration,
+}
+
+#[derive(Default)]
+pub(crate) struct LatencyTracker {
+    enabled: bool,
+    /// Input events the game hasn't read yet
+    unlatched: VecDeque<Instant>,
+    /// Input events the game has read, which haven't been presented yet
+    latched: VecDeque<Latched>,
+    samples: VecDeque<LatencySample>,
+}
+
+impl LatencyTracker {
+    /// Called when the CPU reads the joypad register while drawing `frame`
+    pub(crate) fn joypad_read(&mut self, frame: u64) {
+        for event in self.unlatched.drain(..) {
+            self.latched.push_back(Latched {
+                event,
+                frame,
+                to_latch: event.elapsed(),
+            });
+        }
+    }
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    /// Record input latency from now on. Only events passed to `input_event` after this are measured.
+    pub fn set_latency_tracking(&mut self, enabled: bool) {
+        self.latency = LatencyTracker {
+            enabled,
+            ..Default::default()
+        };
+    }
+
+    /// Tell the latency tracker the frontend just received an input event from the host and passed it on
+    pub fn input_event(&mut self) {
+        if self.latency.enabled {
+            self.latency.unlatched.push_back(Instant::now());
+        }
+    }
+
+    /// Tell the latency tracker the last finished frame was just presented. Returns a sample for each input that
+    /// frame was the first to show.
+    pub fn frame_presented(&mut self) -> impl Iterator<Item = LatencySample> + '_ {
+        // The frame being drawn when an input was latched is the first one that can show it
+        let finished = self.ppu.frame_count();
+        let latency = &mut self.latency;
+        let shown = latency
+            .latched
+            .iter()
+            .take_while(|latched| latched.frame < finished)
+            .count();
+        for latched in latency.latched.drain(..shown) {
+            if latency.samples.len() == SAMPLE_WINDOW {
+                latency.samples.pop_front();
+            }
+            latency.samples.push_back(LatencySample {
+                latched_frame: latched.frame,
+                to_latch: latched.to_latch,
+                to_present: latched.event.elapsed(),
+            });
+        }
+        let new = latency.samples.len() - shown.min(latency.samples.len());
+        latency.samples.range(new..).copied()
+    }
+
+    /// Statistics over the most recent samples
+    pub fn latency_stats(&self) -> LatencyStats {
+        LatencyStats::from_samples(self.latency.samples.iter())
+    }
+}
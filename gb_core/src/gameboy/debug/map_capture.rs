@@ -0,0 +1,154 @@
+//! Stitching a scrolling background together into one big image, for exporting a game's level maps
+//!
+//! The tile maps only hold 32x32 tiles, so games that scroll further than that keep writing new tiles in just ahead
+//! of the screen. A [`MapCapture`] follows SCX and SCY from frame to frame to work out where the screen is in the
+//! level, and keeps every tile it sees at its position in the level rather than in the tile map.
+//!
+//! The captured area is capped at [`MapCapture::MAX_TILES`], so a game that keeps scrolling the same way (or a
+//! capture left running for hours) can't use up all the memory.
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+use crate::gameboy::ppu::{
+    monochrome::{color::Palette, MonochromePpu},
+    registers::LCDC,
+};
+
+/// The width and height of a tile, in pixels
+const TILE_SIZE: i32 = 8;
+
+pub struct MapCapture {
+    /// The colors of each captured tile, keyed by its position in the level in tiles
+    tiles: BTreeMap<(i32, i32), [[u32; 8]; 8]>,
+    /// The smallest and largest tile positions captured, as (min x, max x, min y, max y)
+    bounds: Option<(i32, i32, i32, i32)>,
+    /// The position in the level of the top left of the screen, in pixels
+    camera: (i32, i32),
+    /// SCX and SCY from the last capture
+    scroll: Option<(u8, u8)>,
+}
+
+impl MapCapture {
+    /// The most tiles the captured area can cover, e.g. 256x256 tiles, which makes a 16 MiB image. Tiles that would
+    /// make it any bigger are left out.
+    pub const MAX_TILES: usize = 0x1_0000;
+
+    pub fn new() -> Self {
+        MapCapture {
+            tiles: BTreeMap::new(),
+            bounds: None,
+            camera: (0, 0),
+            scroll: None,
+        }
+    }
+
+    /// Follow the scroll registers, and keep any tiles that are fully on screen and haven't been seen before, as long
+    /// as the captured area stays within [`Self::MAX_TILES`]. Call this once a frame, e.g. after
+    /// `Gameboy::run_frame`.
+    ///
+    /// The scrolling between two calls is taken to be the shortest way round the tile map, so jumps of more than 127
+    /// pixels at once (e.g. cutting to another room) put the rest of the level in the wrong place. Games that change
+    /// the scroll registers partway down the screen are followed using the last values they were set to.
+    pub fn capture(&mut self, ppu: &MonochromePpu) {
+        let (scx, scy) = (ppu.scx(), ppu.scy());
+        self.camera = match self.scroll {
+            Some((old_scx, old_scy)) => (
+                self.camera.0 + scx.wrapping_sub(old_scx) as i8 as i32,
+                self.camera.1 + scy.wrapping_sub(old_scy) as i8 as i32,
+            ),
+            // Starting at the scroll position keeps the level's tile grid lined up with the tile map's
+            None => (scx as i32, scy as i32),
+        };
+        self.scroll = Some((scx, scy));
+
+        let lcdc = ppu.lcdc();
+        if !lcdc.contains(LCDC::LCD_ENABLE | LCDC::BG_ENABLE) {
+            return;
+        }
+        // The window usually holds a status bar, so the background underneath it isn't part of the level
+        let window = (lcdc.contains(LCDC::WINDOW_ENABLE) && ppu.wx() <= 166 && ppu.wy() <= 143)
+            .then(|| (ppu.wx() as i32 - 7, ppu.wy() as i32));
+
+        let (left, top) = self.camera;
+        let first = (div_ceil(left), div_ceil(top));
+        let last = (
+            (left + 160).div_euclid(TILE_SIZE) - 1,
+            (top + 144).div_euclid(TILE_SIZE) - 1,
+        );
+        for tile_y in first.1..=last.1 {
+            for tile_x in first.0..=last.0 {
+                let screen_right = tile_x * TILE_SIZE - left + TILE_SIZE - 1;
+                let screen_bottom = tile_y * TILE_SIZE - top + TILE_SIZE - 1;
+                if let Some((window_x, window_y)) = window {
+                    if screen_right >= window_x && screen_bottom >= window_y {
+                        continue;
+                    }
+                }
+
+                if self.tiles.contains_key(&(tile_x, tile_y)) {
+                    continue;
+                }
+                let bounds = match self.bounds {
+                    Some((min_x, max_x, min_y, max_y)) => (
+                        min_x.min(tile_x),
+                        max_x.max(tile_x),
+                        min_y.min(tile_y),
+                        max_y.max(tile_y),
+                    ),
+                    None => (tile_x, tile_x, tile_y, tile_y),
+                };
+                let (min_x, max_x, min_y, max_y) = bounds;
+                let area = (max_x - min_x + 1) as usize * (max_y - min_y + 1) as usize;
+                if area > Self::MAX_TILES {
+                    continue;
+                }
+                self.bounds = Some(bounds);
+
+                let colors = ppu.palette_colors(Palette::Bg);
+                let tile = ppu.bg_tile(tile_x.rem_euclid(32) as u8, tile_y.rem_euclid(32) as u8);
+                let pixels = tile
+                    .pixels
+                    .map(|row| row.map(|color| colors[color as usize]));
+                self.tiles.insert((tile_x, tile_y), pixels);
+            }
+        }
+    }
+
+    /// The number of tiles captured so far
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Create an image of every tile captured so far, width, and height. Parts of the level that were never on
+    /// screen are left as 0.
+    pub fn image(&self) -> (Vec<u32>, usize, usize) {
+        let (min_x, max_x, min_y, max_y) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return (Vec::new(), 0, 0),
+        };
+
+        let width = ((max_x - min_x + 1) * TILE_SIZE) as usize;
+        let height = ((max_y - min_y + 1) * TILE_SIZE) as usize;
+        let mut image = vec![0; width * height];
+        for (&(tile_x, tile_y), pixels) in &self.tiles {
+            let left = ((tile_x - min_x) * TILE_SIZE) as usize;
+            let top = ((tile_y - min_y) * TILE_SIZE) as usize;
+            for (y, row) in pixels.iter().enumerate() {
+                let offset = (top + y) * width + left;
+                image[offset..offset + 8].copy_from_slice(row);
+            }
+        }
+        (image, width, height)
+    }
+}
+
+impl Default for MapCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The first tile starting at or after pixel `pixel`
+fn div_ceil(pixel: i32) -> i32 {
+    (pixel + TILE_SIZE - 1).div_euclid(TILE_SIZE)
+}
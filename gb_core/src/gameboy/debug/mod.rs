@@ -0,0 +1,61 @@
+//! Inspection helpers intended for debuggers and other frontend tooling.
+//!
+//! Apart from the performance counters, nothing in here is required to run the emulator; these types only read (or
+//! record) state that the rest of the `gameboy` module already keeps track of. The debugger helpers are behind the
+//! `debugger` feature and bus tracing is behind `trace`, both on by default.
+
+#[cfg(feature = "debugger")]
+pub mod breakpoints;
+#[cfg(feature = "debugger")]
+pub mod doctor;
+#[cfg(feature = "debugger")]
+pub mod io;
+#[cfg(feature = "debugger")]
+pub mod latency;
+#[cfg(feature = "debugger")]
+pub mod map_capture;
+pub mod perf;
+#[cfg(feature = "debugger")]
+pub mod ram_search;
+#[cfg(feature = "debugger")]
+pub mod regions;
+#[cfg(feature = "debugger")]
+pub mod rgbds;
+#[cfg(feature = "debugger")]
+pub mod sram_view;
+#[cfg(feature = "debugger")]
+pub mod stack;
+#[cfg(feature = "debugger")]
+pub mod state_diff;
+#[cfg(feature = "trace")]
+pub mod trace;
+#[cfg(feature = "debugger")]
+pub mod watch;
+
+#[cfg(feature = "debugger")]
+pub use breakpoints::{BankedAddr, BreakpointHit, BreakpointId};
+#[cfg(feature = "debugger")]
+pub use doctor::{Divergence, TraceError, TraceLine};
+#[cfg(feature = "debugger")]
+pub use io::{DmaSnapshot, Interrupts, IoRegister, IoSnapshot, LcdSnapshot, TimerSnapshot};
+#[cfg(feature = "debugger")]
+pub use latency::{LatencySample, LatencyStats};
+#[cfg(feature = "debugger")]
+pub use map_capture::MapCapture;
+pub use perf::{Coverage, PerfStats};
+#[cfg(feature = "debugger")]
+pub use ram_search::{RamSearch, SearchFilter};
+#[cfg(feature = "debugger")]
+pub use regions::{ConfigError, Protection, RegionId, RegionSpec, RegionViolation, Violation};
+#[cfg(feature = "debugger")]
+pub use rgbds::{MapFile, Section, SectionType, Symbol};
+#[cfg(feature = "debugger")]
+pub use sram_view::{SramChange, SramView, SramViewId};
+#[cfg(feature = "debugger")]
+pub use stack::{StackProtectionId, StackWarning};
+#[cfg(feature = "debugger")]
+pub use state_diff::{RangeDiff, RegisterDiff, Snapshot, StateDiff};
+#[cfg(feature = "trace")]
+pub use trace::{BusAccess, BusConflict, BusEvent, Responders, StrayRomWrite};
+#[cfg(feature = "debugger")]
+pub use watch::{WatchHit, WatchId};
@@ -0,0 +1,279 @@
+//! Emulation performance statistics, meant for drawing a performance HUD, and coverage reports of the instructions a
+//! run executed
+
+use alloc::{collections::VecDeque, string::String, vec, vec::Vec};
+use core::{fmt::Write, ops::Range, time::Duration};
+
+use crate::gameboy::{models::GbModel, Gameboy};
+
+#[cfg(feature = "std")]
+pub(crate) use std::time::Instant;
+
+/// Without `std` there's no clock to read, so every duration measures as zero
+#[cfg(not(feature = "std"))]
+#[derive(Clone, Copy)]
+pub(crate) struct Instant;
+
+#[cfg(not(feature = "std"))]
+impl Instant {
+    pub(crate) fn now() -> Self {
+        Instant
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// The number of frames `average_frame_time` is calculated over
+const AVERAGE_WINDOW: usize = 60;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PerfStats {
+    /// Frames completed by [`Gameboy::run_frame`]
+    pub frames: u64,
+    /// M-cycles executed in total
+    pub cycles: u64,
+    /// Host time spent emulating the last frame
+    pub frame_time: Duration,
+    /// Host time spent emulating a frame, averaged over the last 60 frames
+    pub average_frame_time: Duration,
+    /// Host time spent clocking the CPU during the last frame. Only measured while instrumentation is enabled.
+    pub cpu_time: Option<Duration>,
+    /// Host time spent clocking the PPU during the last frame. Only measured while instrumentation is enabled.
+    pub ppu_time: Option<Duration>,
+}
+
+impl PerfStats {
+    /// Host milliseconds spent emulating the last frame
+    pub fn frame_ms(&self) -> f64 {
+        self.frame_time.as_secs_f64() * 1000.0
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct PerfCounters {
+    pub(crate) stats: PerfStats,
+    pub(crate) instrumented: bool,
+    pub(crate) cpu_time: Duration,
+    pub(crate) ppu_time: Duration,
+    pub(crate) coverage: Option<Coverage>,
+    /// Where the instruction being run for coverage was fetched from, and its opcode. Its operands are only known once
+    /// it's finished.
+    coverage_instruction: Option<(u16, u8)>,
+    recent: VecDeque<Duration>,
+}
+
+impl PerfCounters {
+    /// Returns a timestamp if instrumentation is enabled
+    #[inline]
+    pub(crate) fn start(&self) -> Option<Instant> {
+        if self.instrumented {
+            Some(Instant::now())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn finish_frame(&mut self, frame_time: Duration) {
+        if self.recent.len() == AVERAGE_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(frame_time);
+
+        let stats = &mut self.stats;
+        stats.frames += 1;
+        stats.frame_time = frame_time;
+        stats.average_frame_time = self.recent.iter().sum::<Duration>() / self.recent.len() as u32;
+        if self.instrumented {
+            stats.cpu_time = Some(core::mem::take(&mut self.cpu_time));
+            stats.ppu_time = Some(core::mem::take(&mut self.ppu_time));
+        } else {
+            stats.cpu_time = None;
+            stats.ppu_time = None;
+        }
+    }
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    pub fn perf_stats(&self) -> &PerfStats {
+        &self.perf.stats
+    }
+
+    /// Measure the time spent in the CPU and PPU separately. This makes emulation noticeably slower.
+    pub fn set_perf_instrumentation(&mut self, enabled: bool) {
+        self.perf.instrumented = enabled;
+        self.perf.cpu_time = Duration::ZERO;
+        self.perf.ppu_time = Duration::ZERO;
+    }
+
+    /// Start collecting a new [`Coverage`] report, or stop and drop the current one. This makes emulation slightly
+    /// slower.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.perf.coverage = if enabled {
+            Some(Coverage::new(self.cart.rom().len()))
+        } else {
+            None
+        };
+        self.perf.coverage_instruction = None;
+    }
+
+    /// The report collected since coverage was enabled
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.perf.coverage.as_ref()
+    }
+
+    /// Count the instruction whose opcode is being fetched from `addr`
+    pub(crate) fn record_coverage(&mut self, addr: u16, opcode: u8) {
+        let cart = &self.cart;
+        if let Some(coverage) = &mut self.perf.coverage {
+            coverage.opcodes[opcode as usize] += 1;
+            coverage.mark_executed(cart.rom_offset(addr));
+            self.perf.coverage_instruction = Some((addr, opcode));
+        }
+    }
+
+    /// Count the operands of the instruction that just finished, as reported by the CPU on the first cycle after it
+    pub(crate) fn finish_coverage(&mut self, operands: &[u8]) {
+        let cart = &self.cart;
+        if let (Some(coverage), Some((addr, opcode))) = (
+            &mut self.perf.coverage,
+            self.perf.coverage_instruction.take(),
+        ) {
+            // The second byte of CB-prefixed instructions is read like an operand
+            if let (0xCB, Some(&cb_opcode)) = (opcode, operands.first()) {
+                coverage.cb_opcodes[cb_opcode as usize] += 1;
+            }
+            for i in 1..=operands.len() as u16 {
+                coverage.mark_executed(cart.rom_offset(addr.wrapping_add(i)));
+            }
+        }
+    }
+}
+
+/// Which instructions a run executed, and which bytes of the ROM image they came from, for measuring how much of the
+/// instruction set a test ROM exercises or how much of a game was reached. Collected while enabled with
+/// [`Gameboy::set_coverage`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Coverage {
+    opcodes: [u64; 256],
+    cb_opcodes: [u64; 256],
+    /// Whether each byte of the ROM image was part of an instruction that ran
+    executed: Vec<bool>,
+    executed_bytes: usize,
+}
+
+impl Coverage {
+    fn new(rom_size: usize) -> Self {
+        Coverage {
+            opcodes: [0; 256],
+            cb_opcodes: [0; 256],
+            executed: vec![false; rom_size],
+            executed_bytes: 0,
+        }
+    }
+
+    /// Mark the byte `offset` bytes into the ROM image as executed. `None` is for bytes that aren't in ROM.
+    fn mark_executed(&mut self, offset: Option<usize>) {
+        if let Some(executed) = offset.and_then(|offset| self.executed.get_mut(offset)) {
+            if !*executed {
+                *executed = true;
+                self.executed_bytes += 1;
+            }
+        }
+    }
+
+    /// How many times an opcode ran. CB-prefixed instructions are counted as $CB here, as well as by
+    /// `cb_opcode_count`.
+    pub fn opcode_count(&self, opcode: u8) -> u64 {
+        self.opcodes[opcode as usize]
+    }
+
+    /// How many times a CB-prefixed instruction ran, by the byte after $CB
+    pub fn cb_opcode_count(&self, opcode: u8) -> u64 {
+        self.cb_opcodes[opcode as usize]
+    }
+
+    /// Instructions run in total, wherever they were in memory
+    pub fn instructions(&self) -> u64 {
+        self.opcodes.iter().sum()
+    }
+
+    /// Whether the byte `offset` bytes into the ROM image was part of an instruction that ran
+    pub fn is_executed(&self, offset: usize) -> bool {
+        self.executed.get(offset).copied().unwrap_or(false)
+    }
+
+    pub fn executed_bytes(&self) -> usize {
+        self.executed_bytes
+    }
+
+    pub fn rom_size(&self) -> usize {
+        self.executed.len()
+    }
+
+    /// How much of the ROM image was executed, from 0 to 100
+    pub fn percent(&self) -> f64 {
+        if self.executed.is_empty() {
+            0.0
+        } else {
+            self.executed_bytes as f64 * 100.0 / self.executed.len() as f64
+        }
+    }
+
+    /// The runs of executed bytes, as offsets into the ROM image
+    pub fn executed_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for (offset, _) in self.executed.iter().enumerate().filter(|(_, &e)| e) {
+            match ranges.last_mut() {
+                Some(range) if range.end == offset => range.end += 1,
+                _ => ranges.push(offset..offset + 1),
+            }
+        }
+        ranges
+    }
+
+    /// The report as a JSON object. Opcodes are keyed by their hex value and only listed if they ran, and
+    /// `executed_ranges` holds `[start, end)` pairs of offsets into the ROM image:
+    ///
+    /// ```text
+    /// {"instructions":3,"opcodes":{"00":2,"CB":1},"cb_opcodes":{"37":1},"rom_size":32768,"executed_bytes":4,
+    ///  "percent":0.01,"executed_ranges":[[256,260]]}
+    /// ```
+    pub fn to_json(&self) -> String {
+        fn histogram(json: &mut String, counts: &[u64; 256]) {
+            json.push('{');
+            let ran = counts.iter().enumerate().filter(|(_, &count)| count > 0);
+            for (i, (opcode, count)) in ran.enumerate() {
+                let comma = if i == 0 { "" } else { "," };
+                write!(json, "{}\"{:02X}\":{}", comma, opcode, count).unwrap();
+            }
+            json.push('}');
+        }
+
+        let mut json = String::new();
+        write!(
+            json,
+            "{{\"instructions\":{},\"opcodes\":",
+            self.instructions()
+        )
+        .unwrap();
+        histogram(&mut json, &self.opcodes);
+        json.push_str(",\"cb_opcodes\":");
+        histogram(&mut json, &self.cb_opcodes);
+        write!(
+            json,
+            ",\"rom_size\":{},\"executed_bytes\":{},\"percent\":{:.2},\"executed_ranges\":[",
+            self.rom_size(),
+            self.executed_bytes,
+            self.percent()
+        )
+        .unwrap();
+        for (i, range) in self.executed_ranges().iter().enumerate() {
+            let comma = if i == 0 { "" } else { "," };
+            write!(json, "{}[{},{}]", comma, range.start, range.end).unwrap();
+        }
+        json.push_str("]}");
+        json
+    }
+}
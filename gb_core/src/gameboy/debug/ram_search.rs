@@ -0,0 +1,88 @@
+//! Iterative searching of work RAM, used to find the addresses behind in-game values (health, score, ...)
+//!
+//! The usual workflow is to create a [`RamSearch`], play until the value of interest changes, then
+//! narrow down the candidates with [`RamSearch::filter`], repeating until only a few addresses remain.
+
+use alloc::vec::Vec;
+
+use crate::gameboy::memory::Memory;
+
+const WORK_RAM_START: u16 = 0xC000;
+const WORK_RAM_END: u16 = 0xDFFF;
+
+/// How a candidate's current value must relate to its value in the previous snapshot to be kept
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchFilter {
+    /// The value is equal to a constant
+    EqualTo(u8),
+    /// The value did not change
+    Unchanged,
+    /// The value changed
+    Changed,
+    /// The value increased
+    Greater,
+    /// The value decreased
+    Less,
+    /// The value changed by exactly this amount (without wrapping)
+    ChangedBy(i16),
+}
+
+impl SearchFilter {
+    fn matches(&self, old: u8, new: u8) -> bool {
+        match *self {
+            SearchFilter::EqualTo(v) => new == v,
+            SearchFilter::Unchanged => new == old,
+            SearchFilter::Changed => new != old,
+            SearchFilter::Greater => new > old,
+            SearchFilter::Less => new < old,
+            SearchFilter::ChangedBy(delta) => new as i16 - old as i16 == delta,
+        }
+    }
+}
+
+pub struct RamSearch {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    /// Start a new search with every work RAM address as a candidate
+    pub fn new(memory: &Memory) -> Self {
+        RamSearch {
+            snapshot: Self::take_snapshot(memory),
+            candidates: (WORK_RAM_START..=WORK_RAM_END).collect(),
+        }
+    }
+
+    fn take_snapshot(memory: &Memory) -> Vec<u8> {
+        (WORK_RAM_START..=WORK_RAM_END)
+            .map(|addr| memory[addr])
+            .collect()
+    }
+
+    /// Drop every candidate that doesn't match `filter` when comparing the previous snapshot against the
+    /// current contents of `memory`, then take a new snapshot. Returns the number of remaining candidates.
+    pub fn filter(&mut self, memory: &Memory, filter: SearchFilter) -> usize {
+        let snapshot = Self::take_snapshot(memory);
+        let old = &self.snapshot;
+        self.candidates.retain(|&addr| {
+            let i = (addr - WORK_RAM_START) as usize;
+            filter.matches(old[i], snapshot[i])
+        });
+        self.snapshot = snapshot;
+        self.candidates.len()
+    }
+
+    /// The addresses that have matched every filter so far
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// The value of a work RAM address when the last snapshot was taken
+    pub fn snapshot_value(&self, addr: u16) -> Option<u8> {
+        match addr {
+            WORK_RAM_START..=WORK_RAM_END => Some(self.snapshot[(addr - WORK_RAM_START) as usize]),
+            _ => None,
+        }
+    }
+}
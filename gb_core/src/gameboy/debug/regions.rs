@@ -0,0 +1,199 @@
+//! Named address ranges with access rules, for catching homebrew bugs as they happen
+//!
+//! Regions can be added one at a time with [`Gameboy::add_region`], or loaded from a config file parsed by
+//! [`RegionSpec::parse_config`]. A region without any protection is just a name for the debugger to show. Accesses
+//! that break a region's rules still happen, like they would on the real hardware, but the emulator breaks straight
+//! after them: [`Gameboy::run_frame`] returns early, and the violation can be taken with [`Gameboy::take_break`].
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt, ops::RangeInclusive};
+
+use bitflags::bitflags;
+
+use crate::{
+    cpu::CpuOutputPins,
+    gameboy::{models::GbModel, Gameboy},
+};
+
+bitflags! {
+    /// The accesses a region doesn't allow
+    pub struct Protection: u8 {
+        /// Break when the CPU writes to the region
+        const READ_ONLY = 0x01;
+        /// Break when the CPU fetches an instruction from the region
+        const NO_EXEC = 0x02;
+    }
+}
+
+/// Identifies a region added with [`Gameboy::add_region`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RegionId(usize);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionSpec {
+    pub name: String,
+    pub range: RangeInclusive<u16>,
+    pub protection: Protection,
+}
+
+/// A line of a region config or RGBDS map file that couldn't be parsed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Counting from 1
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl RegionSpec {
+    /// Parse a region config, which has one region per line:
+    ///
+    /// ```text
+    /// # Comments and blank lines are ignored
+    /// C000-C0FF   player
+    /// 0000-7FFF   rom        read-only
+    /// C100-C1FF   level_data read-only no-exec
+    /// ```
+    pub fn parse_config(config: &str) -> Result<Vec<RegionSpec>, ConfigError> {
+        config
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.split('#').next().unwrap().trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(line, text)| {
+                Self::parse_line(text).map_err(|message| ConfigError { line, message })
+            })
+            .collect()
+    }
+
+    fn parse_line(line: &str) -> Result<RegionSpec, &'static str> {
+        let mut words = line.split_whitespace();
+        let (start, end) = words
+            .next()
+            .and_then(|range| range.split_once('-'))
+            .ok_or("Expected an address range like C000-C0FF")?;
+        let parse_addr = |addr: &str| u16::from_str_radix(addr.trim_start_matches('$'), 16);
+        let (start, end) = match (parse_addr(start), parse_addr(end)) {
+            (Ok(start), Ok(end)) if start <= end => (start, end),
+            _ => return Err("Expected an address range like C000-C0FF"),
+        };
+        let name = words
+            .next()
+            .ok_or("Expected a name after the address range")?;
+
+        let mut protection = Protection::empty();
+        for word in words {
+            protection |= match word {
+                "read-only" => Protection::READ_ONLY,
+                "no-exec" => Protection::NO_EXEC,
+                _ => return Err("Unknown protection, expected read-only or no-exec"),
+            };
+        }
+
+        Ok(RegionSpec {
+            name: name.into(),
+            range: start..=end,
+            protection,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    Write { addr: u16, data: u8 },
+    Execute { addr: u16 },
+}
+
+/// An access that broke a region's rules
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegionViolation {
+    pub region: RegionId,
+    pub violation: Violation,
+    /// The address of the instruction that made the access
+    pub pc: u16,
+}
+
+#[derive(Default)]
+pub(crate) struct Regions {
+    next_id: usize,
+    regions: Vec<(RegionId, RegionSpec)>,
+    pending_break: Option<RegionViolation>,
+}
+
+impl Regions {
+    #[inline]
+    pub(crate) fn break_pending(&self) -> bool {
+        self.pending_break.is_some()
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    #[inline]
+    pub(crate) fn check(&mut self, pins: CpuOutputPins, is_fetch_cycle: bool, pc: u16) {
+        if self.regions.is_empty() || self.pending_break.is_some() {
+            return;
+        }
+
+        let (addr, violation, rule) = match pins {
+            CpuOutputPins::Write { addr, data } => {
+                (addr, Violation::Write { addr, data }, Protection::READ_ONLY)
+            }
+            CpuOutputPins::Read { addr } if is_fetch_cycle => {
+                (addr, Violation::Execute { addr }, Protection::NO_EXEC)
+            }
+            _ => return,
+        };
+        self.pending_break = self
+            .regions
+            .iter()
+            .find(|(_, spec)| spec.protection.contains(rule) && spec.range.contains(&addr))
+            .map(|(region, _)| RegionViolation {
+                region: *region,
+                violation,
+                pc,
+            });
+    }
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    pub fn add_region(&mut self, spec: RegionSpec) -> RegionId {
+        let id = RegionId(self.regions.next_id);
+        self.regions.next_id += 1;
+        self.regions.regions.push((id, spec));
+        id
+    }
+
+    pub fn remove_region(&mut self, region: RegionId) {
+        self.regions.regions.retain(|(id, _)| *id != region);
+    }
+
+    pub fn region(&self, region: RegionId) -> Option<&RegionSpec> {
+        self.regions
+            .regions
+            .iter()
+            .find(|(id, _)| *id == region)
+            .map(|(_, spec)| spec)
+    }
+
+    /// The regions `addr` is in, in the order they were added
+    pub fn regions_at(&self, addr: u16) -> impl Iterator<Item = &RegionSpec> + '_ {
+        self.regions
+            .regions
+            .iter()
+            .map(|(_, spec)| spec)
+            .filter(move |spec| spec.range.contains(&addr))
+    }
+
+    /// Take the violation that caused a break, letting the emulator run again
+    pub fn take_break(&mut self) -> Option<RegionViolation> {
+        self.regions.pending_break.take()
+    }
+}
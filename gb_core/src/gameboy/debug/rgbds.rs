@@ -0,0 +1,239 @@
+//! Reading the `.map` files RGBDS's linker writes with `rgblink -m`, which lay out every section of a homebrew ROM by
+//! bank:
+//!
+//! ```text
+//! ROM0 bank #0:
+//!     SECTION: $0100-$014f ($0050 bytes) ["Header"]
+//!              $0100 = EntryPoint
+//!     EMPTY: $0150-$3fff ($3eb0 bytes)
+//!
+//! ROMX bank #2:
+//!     SECTION: $4000-$47ff ($0800 bytes) ["Level data"]
+//! ```
+//!
+//! The same address can hold a different section in each bank, so lookups take the bank as well. The format of
+//! older versions, with headers like `ROM Bank #1:`, is read too. Lines other than bank headers, sections and symbols
+//! (the summary, empty space and slack) are skipped.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use super::regions::ConfigError;
+
+/// The kinds of memory sections can be placed in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SectionType {
+    Rom0,
+    RomX,
+    Vram,
+    Sram,
+    Wram0,
+    WramX,
+    Oam,
+    Hram,
+}
+
+impl SectionType {
+    /// The name RGBDS uses for the type
+    pub fn name(self) -> &'static str {
+        match self {
+            SectionType::Rom0 => "ROM0",
+            SectionType::RomX => "ROMX",
+            SectionType::Vram => "VRAM",
+            SectionType::Sram => "SRAM",
+            SectionType::Wram0 => "WRAM0",
+            SectionType::WramX => "WRAMX",
+            SectionType::Oam => "OAM",
+            SectionType::Hram => "HRAM",
+        }
+    }
+
+    /// The type of memory at `addr`, if sections can be placed there
+    pub fn at(addr: u16) -> Option<Self> {
+        Some(match addr {
+            0x0000..=0x3FFF => SectionType::Rom0,
+            0x4000..=0x7FFF => SectionType::RomX,
+            0x8000..=0x9FFF => SectionType::Vram,
+            0xA000..=0xBFFF => SectionType::Sram,
+            0xC000..=0xCFFF => SectionType::Wram0,
+            0xD000..=0xDFFF => SectionType::WramX,
+            0xFE00..=0xFE9F => SectionType::Oam,
+            0xFF80..=0xFFFE => SectionType::Hram,
+            _ => return None,
+        })
+    }
+
+    /// Whether the memory is switched between several banks. On a DMG only ROMX and SRAM are, but RGBDS also banks
+    /// VRAM and WRAMX for the Gameboy Color.
+    pub fn is_banked(self) -> bool {
+        matches!(
+            self,
+            SectionType::RomX | SectionType::Vram | SectionType::Sram | SectionType::WramX
+        )
+    }
+
+    /// Read a bank header's type, which older versions of RGBDS write as `ROM`/`WRAM` for both the fixed and the
+    /// switchable banks
+    fn parse(name: &str, bank: u16) -> Option<Self> {
+        Some(match (name.to_ascii_uppercase().as_str(), bank) {
+            ("ROM0", _) | ("ROM", 0) => SectionType::Rom0,
+            ("ROMX", _) | ("ROM", _) => SectionType::RomX,
+            ("VRAM", _) => SectionType::Vram,
+            ("SRAM", _) => SectionType::Sram,
+            ("WRAM0", _) | ("WRAM", 0) => SectionType::Wram0,
+            ("WRAMX", _) | ("WRAM", _) => SectionType::WramX,
+            ("OAM", _) => SectionType::Oam,
+            ("HRAM", _) => SectionType::Hram,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for SectionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub kind: SectionType,
+    pub bank: u16,
+    pub start: u16,
+    /// In bytes, which may be 0
+    pub size: u16,
+    pub symbols: Vec<Symbol>,
+}
+
+impl Section {
+    pub fn contains(&self, addr: u16) -> bool {
+        addr >= self.start && ((addr - self.start) as u32) < self.size as u32
+    }
+}
+
+/// Every section in a ROM's `.map` file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MapFile {
+    /// In the order they appear in the file, which is by bank and then by address
+    pub sections: Vec<Section>,
+}
+
+impl MapFile {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut map = MapFile::default();
+        let mut bank = None;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let error = |message| ConfigError {
+                line: i + 1,
+                message,
+            };
+
+            // Older versions write headers like `ROM Bank #0 (HOME):`
+            let header = line
+                .to_ascii_lowercase()
+                .find(" bank #")
+                .filter(|_| line.ends_with(':'));
+            if let Some(at) = header {
+                let number: String = line[at + 7..]
+                    .chars()
+                    .take_while(char::is_ascii_digit)
+                    .collect();
+                let number = number
+                    .parse()
+                    .map_err(|_| error("Expected a bank number"))?;
+                let kind = SectionType::parse(&line[..at], number)
+                    .ok_or_else(|| error("Unknown section type"))?;
+                bank = Some((kind, number));
+                continue;
+            }
+
+            if let Some(section) = line.strip_prefix("SECTION:") {
+                let (kind, number) =
+                    bank.ok_or_else(|| error("A section comes before any bank"))?;
+                let (start, size, name) = parse_section(section).map_err(error)?;
+                map.sections.push(Section {
+                    name,
+                    kind,
+                    bank: number,
+                    start,
+                    size,
+                    symbols: Vec::new(),
+                });
+            } else if let Some((addr, name)) = line
+                .split_once(" = ")
+                .filter(|(addr, _)| addr.starts_with('$'))
+            {
+                let section = map
+                    .sections
+                    .last_mut()
+                    .ok_or_else(|| error("A symbol comes before any section"))?;
+                section.symbols.push(Symbol {
+                    name: name.trim().into(),
+                    addr: parse_hex(addr).map_err(error)?,
+                });
+            }
+        }
+        Ok(map)
+    }
+
+    /// The section `addr` is in while `bank` is mapped there. The bank is ignored in memory that isn't banked.
+    pub fn section_at(&self, bank: u16, addr: u16) -> Option<&Section> {
+        self.sections.iter().find(|section| {
+            (!section.kind.is_banked() || section.bank == bank) && section.contains(addr)
+        })
+    }
+
+    /// Whether the ROM puts anything at `addr` in `bank`, e.g. to warn about a breakpoint that can never be hit
+    pub fn is_mapped(&self, bank: u16, addr: u16) -> bool {
+        self.section_at(bank, addr).is_some()
+    }
+
+    /// The bank and address of a symbol
+    pub fn symbol(&self, name: &str) -> Option<(u16, u16)> {
+        self.sections.iter().find_map(|section| {
+            let symbol = section.symbols.iter().find(|symbol| symbol.name == name)?;
+            Some((section.bank, symbol.addr))
+        })
+    }
+}
+
+/// Parse the rest of a `SECTION:` line, e.g. ` $4000-$47ff ($0800 bytes) ["Level data"]`. Empty sections only have a
+/// start address.
+fn parse_section(line: &str) -> Result<(u16, u16, String), &'static str> {
+    const EXPECTED: &str = "Expected a section like $4000-$47ff ($0800 bytes) [\"name\"]";
+    let (range, rest) = line.trim().split_once(' ').ok_or(EXPECTED)?;
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse_hex(start)?, Some(parse_hex(end)?)),
+        None => (parse_hex(range)?, None),
+    };
+    let size = match end {
+        Some(end) if end >= start => end - start + 1,
+        // Older versions write empty sections as ending just before they start
+        Some(end) if end == start.wrapping_sub(1) => 0,
+        Some(_) => return Err("The section ends before it starts"),
+        None => 0,
+    };
+
+    let name = rest
+        .split_once("[\"")
+        .and_then(|(_, name)| name.rsplit_once("\"]"))
+        .map(|(name, _)| name)
+        .ok_or(EXPECTED)?;
+    Ok((start, size, name.into()))
+}
+
+fn parse_hex(value: &str) -> Result<u16, &'static str> {
+    let value = value
+        .trim()
+        .strip_prefix('$')
+        .ok_or("Expected an address like $4000")?;
+    u16::from_str_radix(value, 16).map_err(|_| "Expected an address like $4000")
+}
@@ -0,0 +1,103 @@
+//! Named views over cartridge RAM, for save editors and game-specific debugger panels
+//!
+//! A view is a byte range of external RAM, counted from the start of bank 0 like [`Cart::ram`], so it doesn't depend
+//! on which bank is mapped. Tools register one for each structure they understand (a party, an inventory, a
+//! checksum...) and decode its bytes themselves. Changes are found by comparing each view against its bytes when
+//! they were last taken, which also catches RAM replaced from outside the game, e.g. by loading a save.
+//!
+//! [`Cart::ram`]: crate::gameboy::cart::Cart::ram
+
+use alloc::{string::String, vec::Vec};
+use core::ops::Range;
+
+use crate::gameboy::{models::GbModel, Gameboy};
+
+/// Identifies a view added with [`Gameboy::add_sram_view`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SramViewId(usize);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SramView {
+    pub name: String,
+    /// Offsets into external RAM
+    pub range: Range<usize>,
+}
+
+/// A byte in a view that changed since changes were last taken
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SramChange {
+    pub view: SramViewId,
+    /// The offset into external RAM
+    pub offset: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+#[derive(Default)]
+pub(crate) struct SramViews {
+    next_id: usize,
+    /// Each view with its bytes when changes were last taken
+    views: Vec<(SramViewId, SramView, Vec<u8>)>,
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    /// Fails if the range is empty or goes past the end of the cartridge's RAM.
+    pub fn add_sram_view(&mut self, view: SramView) -> Result<SramViewId, &'static str> {
+        if view.range.is_empty() {
+            return Err("The view is empty");
+        }
+        let bytes = self
+            .cart
+            .ram()
+            .get(view.range.clone())
+            .ok_or("The view goes past the end of the cartridge RAM")?
+            .to_vec();
+
+        let views = &mut self.sram_views;
+        let id = SramViewId(views.next_id);
+        views.next_id += 1;
+        views.views.push((id, view, bytes));
+        Ok(id)
+    }
+
+    pub fn remove_sram_view(&mut self, view: SramViewId) {
+        self.sram_views.views.retain(|(id, ..)| *id != view);
+    }
+
+    pub fn sram_views(&self) -> impl Iterator<Item = (SramViewId, &SramView)> {
+        self.sram_views
+            .views
+            .iter()
+            .map(|(id, view, _)| (*id, view))
+    }
+
+    /// The current bytes of a view
+    pub fn read_sram_view(&self, view: SramViewId) -> Option<&[u8]> {
+        let (_, view, _) = self.sram_views.views.iter().find(|(id, ..)| *id == view)?;
+        self.cart.ram().get(view.range.clone())
+    }
+
+    /// Returns every byte that changed in a view since the last call, in view then address order. A byte that was
+    /// changed and then changed back in between isn't reported.
+    ///
+    /// Frontends will typically call this once per frame.
+    pub fn take_sram_changes(&mut self) -> Vec<SramChange> {
+        let ram = self.cart.ram();
+        let mut changes = Vec::new();
+        for (id, view, last) in &mut self.sram_views.views {
+            let current = &ram[view.range.clone()];
+            for (i, (old, &new)) in last.iter_mut().zip(current).enumerate() {
+                if *old != new {
+                    changes.push(SramChange {
+                        view: *id,
+                        offset: view.range.start + i,
+                        old: *old,
+                        new,
+                    });
+                    *old = new;
+                }
+            }
+        }
+        changes
+    }
+}
@@ -0,0 +1,120 @@
+//! Heuristics for catching stack bugs, which are easy to write in assembly and usually crash a long way from the
+//! cause
+//!
+//! Two things are checked: the stack pointer moving somewhere a stack can't be (a stack overflow or underflow
+//! usually runs off the end of RAM into ROM or the I/O registers), and pushes overwriting RAM that has been marked as
+//! off limits to the stack with [`Gameboy::protect_from_stack`].
+
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use crate::{
+    cpu::CpuOutputPins,
+    gameboy::{models::GbModel, Gameboy},
+};
+
+/// Identifies a range registered with [`Gameboy::protect_from_stack`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StackProtectionId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackWarning {
+    /// SP moved somewhere a push would write to ROM, echo RAM, OAM, the I/O registers or IE
+    UnusualSp {
+        sp: u16,
+        /// The address of the instruction that moved it there
+        pc: u16,
+    },
+    /// A push wrote to a protected range
+    ProtectedWrite {
+        protection: StackProtectionId,
+        addr: u16,
+        data: u8,
+        /// The address of the instruction that performed the push
+        pc: u16,
+    },
+}
+
+/// Whether a stack at `sp` is almost certainly a bug. Stacks normally live in work RAM or HRAM.
+///
+/// What matters is where the next push writes, one below SP, so the common `LD SP, $E000` is fine: its first push
+/// lands at $DFFF.
+fn is_unusual_sp(sp: u16) -> bool {
+    matches!(sp.wrapping_sub(1), 0x0000..=0x7FFF | 0xE000..=0xFF7F | 0xFFFF)
+}
+
+#[derive(Default)]
+pub(crate) struct StackChecks {
+    enabled: bool,
+    next_id: usize,
+    protected: Vec<(StackProtectionId, RangeInclusive<u16>)>,
+    /// Whether SP was somewhere unusual last cycle, so moving around in an unusual region is only warned about once
+    unusual: bool,
+    /// The instruction being executed last cycle. A new SP only shows up once the next instruction is being fetched,
+    /// so this is the instruction that set it.
+    last_pc: u16,
+    warnings: Vec<StackWarning>,
+}
+
+impl StackChecks {
+    #[inline]
+    pub(crate) fn check(&mut self, pins: CpuOutputPins, sp: u16, pc: u16) {
+        if !self.enabled {
+            return;
+        }
+
+        let unusual = is_unusual_sp(sp);
+        if unusual && !self.unusual {
+            self.warnings.push(StackWarning::UnusualSp {
+                sp,
+                pc: self.last_pc,
+            });
+        }
+        self.unusual = unusual;
+        self.last_pc = pc;
+
+        // The CPU decrements SP before each byte it pushes, so a push writes to the address SP points at
+        if let CpuOutputPins::Write { addr, data } = pins {
+            if addr == sp {
+                for (protection, range) in &self.protected {
+                    if range.contains(&addr) {
+                        self.warnings.push(StackWarning::ProtectedWrite {
+                            protection: *protection,
+                            addr,
+                            data,
+                            pc,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    /// Start or stop checking the stack. Warnings that were already recorded are kept until they are taken.
+    pub fn set_stack_checks(&mut self, enabled: bool) {
+        self.stack_checks.enabled = enabled;
+        self.stack_checks.unusual = false;
+    }
+
+    /// Warn about pushes that write to `range`, e.g. variables that sit just below the stack. Only takes effect
+    /// while stack checks are enabled.
+    pub fn protect_from_stack(&mut self, range: RangeInclusive<u16>) -> StackProtectionId {
+        let id = StackProtectionId(self.stack_checks.next_id);
+        self.stack_checks.next_id += 1;
+        self.stack_checks.protected.push((id, range));
+        id
+    }
+
+    pub fn unprotect_from_stack(&mut self, protection: StackProtectionId) {
+        self.stack_checks
+            .protected
+            .retain(|(id, _)| *id != protection);
+    }
+
+    /// Returns every warning recorded since the last call, in the order they happened
+    pub fn take_stack_warnings(&mut self) -> Vec<StackWarning> {
+        core::mem::take(&mut self.stack_checks.warnings)
+    }
+}
@@ -0,0 +1,191 @@
+//! Comparing the whole state of two Gameboys, for tracking down where two runs that should be identical drift apart
+//!
+//! The emulator doesn't have save states yet, so a [`Snapshot`] holds what the CPU can see: its registers and
+//! everything on the memory map, read the same way a debugger reads it. Take one from each run at the same point (e.g.
+//! after the same frame) and [`StateDiff::compare`] lists what differs.
+
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    cpu::Registers,
+    gameboy::{models::GbModel, Gameboy},
+};
+
+/// The state of a Gameboy as the CPU sees it
+#[derive(Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub registers: Registers,
+    pub ime: bool,
+    /// Every address from $0000 to $FFFF
+    memory: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn read(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+}
+
+impl fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("registers", &self.registers)
+            .field("ime", &self.ime)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.cpu.cpu.registers,
+            ime: self.cpu.cpu.ime,
+            memory: (0..=0xFFFF).map(|addr| self.debug_read(addr)).collect(),
+        }
+    }
+}
+
+/// A register that differs, with its value in each snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterDiff {
+    pub name: &'static str,
+    pub a: u16,
+    pub b: u16,
+}
+
+/// A run of consecutive addresses that differ, all in the same area of the memory map
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeDiff {
+    pub start: u16,
+    /// Inclusive
+    pub end: u16,
+    pub a: Vec<u8>,
+    pub b: Vec<u8>,
+}
+
+impl RangeDiff {
+    /// The name of the area of the memory map the range is in, e.g. "work RAM"
+    pub fn area(&self) -> &'static str {
+        area(self.start)
+    }
+}
+
+/// Ranges longer than this are summarized instead of listing their bytes
+const MAX_LISTED_BYTES: usize = 8;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub registers: Vec<RegisterDiff>,
+    pub ranges: Vec<RangeDiff>,
+}
+
+impl StateDiff {
+    pub fn compare(a: &Snapshot, b: &Snapshot) -> Self {
+        let (ra, rb) = (&a.registers, &b.registers);
+        let registers = [
+            ("A", ra.a as u16, rb.a as u16),
+            ("F", u8::from(ra.f) as u16, u8::from(rb.f) as u16),
+            ("B", ra.b as u16, rb.b as u16),
+            ("C", ra.c as u16, rb.c as u16),
+            ("D", ra.d as u16, rb.d as u16),
+            ("E", ra.e as u16, rb.e as u16),
+            ("H", ra.h as u16, rb.h as u16),
+            ("L", ra.l as u16, rb.l as u16),
+            ("SP", ra.sp, rb.sp),
+            ("PC", ra.pc, rb.pc),
+            ("IME", a.ime as u16, b.ime as u16),
+        ]
+        .iter()
+        .filter(|(_, a, b)| a != b)
+        .map(|&(name, a, b)| RegisterDiff { name, a, b })
+        .collect();
+
+        let mut ranges: Vec<RangeDiff> = Vec::new();
+        // Echo RAM mirrors work RAM, so it would only repeat the same differences
+        let addrs = (0x0000..0xE000).chain(0xFE00..=0xFFFF);
+        for addr in addrs {
+            let (x, y) = (a.read(addr), b.read(addr));
+            if x == y {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(range) if range.end + 1 == addr && area(range.start) == area(addr) => {
+                    range.end = addr;
+                    range.a.push(x);
+                    range.b.push(y);
+                }
+                _ => ranges.push(RangeDiff {
+                    start: addr,
+                    end: addr,
+                    a: vec![x],
+                    b: vec![y],
+                }),
+            }
+        }
+
+        StateDiff { registers, ranges }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.ranges.is_empty()
+    }
+}
+
+/// One line per register or range that differs
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "The states are identical");
+        }
+        for register in &self.registers {
+            match register.name {
+                "SP" | "PC" => writeln!(
+                    f,
+                    "{}: ${:04X} -> ${:04X}",
+                    register.name, register.a, register.b
+                )?,
+                _ => writeln!(
+                    f,
+                    "{}: ${:02X} -> ${:02X}",
+                    register.name, register.a, register.b
+                )?,
+            }
+        }
+        for range in &self.ranges {
+            write!(f, "${:04X}", range.start)?;
+            if range.end != range.start {
+                write!(f, "-${:04X}", range.end)?;
+            }
+            write!(f, " ({}):", range.area())?;
+            if range.a.len() > MAX_LISTED_BYTES {
+                writeln!(f, " {} bytes differ", range.a.len())?;
+            } else {
+                write_bytes(f, &range.a)?;
+                write!(f, " ->")?;
+                write_bytes(f, &range.b)?;
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_bytes(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|byte| write!(f, " {:02X}", byte))
+}
+
+fn area(addr: u16) -> &'static str {
+    match addr {
+        0x0000..=0x7FFF => "ROM",
+        0x8000..=0x9FFF => "VRAM",
+        0xA000..=0xBFFF => "cartridge RAM",
+        0xC000..=0xDFFF => "work RAM",
+        0xE000..=0xFDFF => "echo RAM",
+        0xFE00..=0xFE9F => "OAM",
+        0xFEA0..=0xFEFF => "unusable",
+        0xFF00..=0xFF7F => "IO registers",
+        0xFF80..=0xFFFE => "HRAM",
+        0xFFFF => "IE",
+    }
+}
@@ -0,0 +1,238 @@
+//! A cycle by cycle record of the bus
+//!
+//! While tracing is enabled, every M-cycle is recorded with the address and data on the bus and the chips that
+//! respond to the address. Only the most recent cycles are kept, so tracing can be left on while waiting for a bug to
+//! happen. [`write_vcd`] exports a trace for viewing in a logic analyzer such as GTKWave.
+//!
+//! On the real hardware only one chip drives the data bus at a time, but here every chip gets to modify the byte on
+//! the bus, so a mistake in a chip's address decoding silently corrupts what the CPU reads. Conflict detection checks
+//! every cycle for addresses that more than one chip responds to.
+//!
+//! Stray ROM write detection catches the opposite kind of bug, in the game rather than the emulator: writes to ROM
+//! that don't hit any of the mapper's registers, which usually come from a bad pointer.
+
+use alloc::{collections::VecDeque, vec::Vec};
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use bitflags::bitflags;
+
+use crate::{
+    cpu::CpuOutputPins,
+    gameboy::{models::GbModel, Chip, Gameboy},
+};
+
+bitflags! {
+    /// The chips that respond to an address
+    pub struct Responders: u8 {
+        const PPU = 0x01;
+        const MEMORY = 0x02;
+        const CART = 0x04;
+        const TIMER = 0x08;
+        const JOYPAD = 0x10;
+        const SERIAL = 0x20;
+        /// IE and IF, which are handled by the Gameboy itself rather than a chip
+        const INTERRUPTS = 0x40;
+    }
+}
+
+impl Responders {
+    /// Whether more than one chip responded
+    pub fn is_conflict(self) -> bool {
+        self.bits().count_ones() > 1
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusAccess {
+    Read,
+    Write,
+    /// The CPU didn't use the bus
+    Idle,
+}
+
+/// One M-cycle on the bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusEvent {
+    /// M-cycles since the Gameboy was created, including this one
+    pub cycle: u64,
+    pub access: BusAccess,
+    pub addr: u16,
+    /// The byte written by the CPU, or the byte returned to it
+    pub data: u8,
+    pub responders: Responders,
+}
+
+/// A cycle where more than one chip responded to the address on the bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusConflict {
+    pub event: BusEvent,
+    /// The address of the instruction that caused the conflict
+    pub pc: u16,
+}
+
+/// A write to ROM that didn't go to one of the mapper's registers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StrayRomWrite {
+    /// M-cycles since the Gameboy was created, including the one with the write
+    pub cycle: u64,
+    pub addr: u16,
+    pub data: u8,
+    /// The address of the instruction that performed the write
+    pub pc: u16,
+}
+
+pub(crate) struct BusTrace {
+    capacity: usize,
+    events: VecDeque<BusEvent>,
+}
+
+impl BusTrace {
+    fn push(&mut self, event: BusEvent) {
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    /// Start recording bus cycles, keeping the most recent `capacity` of them. Any existing trace is discarded. A
+    /// capacity of 0 turns tracing off, like `disable_bus_trace`.
+    pub fn enable_bus_trace(&mut self, capacity: usize) {
+        self.bus_trace = (capacity > 0).then(|| BusTrace {
+            capacity,
+            events: VecDeque::with_capacity(capacity),
+        });
+    }
+
+    pub fn disable_bus_trace(&mut self) {
+        self.bus_trace = None;
+    }
+
+    /// Returns the recorded cycles, oldest first, and clears the trace. Tracing stays enabled.
+    pub fn take_bus_trace(&mut self) -> Vec<BusEvent> {
+        match &mut self.bus_trace {
+            Some(trace) => trace.events.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Start or stop checking every bus cycle for conflicts. Conflicts that were already found are kept until taken.
+    pub fn set_conflict_detection(&mut self, enabled: bool) {
+        self.detect_conflicts = enabled;
+    }
+
+    /// Returns every conflict found since the last call
+    pub fn take_bus_conflicts(&mut self) -> Vec<BusConflict> {
+        core::mem::take(&mut self.bus_conflicts)
+    }
+
+    /// Start or stop recording writes to ROM that don't go to a mapper register. Writes that were already recorded
+    /// are kept until taken.
+    pub fn set_stray_rom_write_detection(&mut self, enabled: bool) {
+        self.detect_stray_rom_writes = enabled;
+    }
+
+    /// Returns every stray ROM write recorded since the last call, in the order they happened
+    pub fn take_stray_rom_writes(&mut self) -> Vec<StrayRomWrite> {
+        core::mem::take(&mut self.stray_rom_writes)
+    }
+
+    /// Find the chips that respond to reads of `addr`
+    pub fn responders(&self, addr: u16) -> Responders {
+        if addr == 0xFF0F || addr == 0xFFFF {
+            return Responders::INTERRUPTS;
+        }
+
+        let chips: [(Responders, &dyn Chip); 6] = [
+            (Responders::PPU, &self.ppu),
+            (Responders::MEMORY, &self.memory),
+            (Responders::CART, &self.cart),
+            (Responders::TIMER, &self.timer),
+            (Responders::JOYPAD, &self.joypad),
+            (Responders::SERIAL, &self.serial),
+        ];
+
+        let mut responders = Responders::empty();
+        for (flag, chip) in chips {
+            // A chip that doesn't respond leaves the bus alone, whatever was on it
+            let (mut low, mut high) = (0x00, 0xFF);
+            chip.debug_read(addr, &mut low);
+            chip.debug_read(addr, &mut high);
+            if low != 0x00 || high != 0xFF {
+                responders |= flag;
+            }
+        }
+        responders
+    }
+
+    pub(crate) fn trace_bus(&mut self, pins: CpuOutputPins, data: u8) {
+        if let (true, CpuOutputPins::Write { addr, data }) = (self.detect_stray_rom_writes, pins) {
+            if !self.cart.is_writable(addr) {
+                self.stray_rom_writes.push(StrayRomWrite {
+                    cycle: self.perf.stats.cycles,
+                    addr,
+                    data,
+                    pc: self.instruction_pc,
+                });
+            }
+        }
+
+        if self.bus_trace.is_none() && !self.detect_conflicts {
+            return;
+        }
+
+        let (access, addr, data) = match pins {
+            CpuOutputPins::Read { addr } => (BusAccess::Read, addr, data),
+            CpuOutputPins::Write { addr, data } => (BusAccess::Write, addr, data),
+            CpuOutputPins::Idle => (BusAccess::Idle, 0, 0),
+        };
+        let responders = match access {
+            BusAccess::Idle => Responders::empty(),
+            _ => self.responders(addr),
+        };
+        let event = BusEvent {
+            cycle: self.perf.stats.cycles,
+            access,
+            addr,
+            data,
+            responders,
+        };
+
+        if let Some(trace) = &mut self.bus_trace {
+            trace.push(event);
+        }
+        if self.detect_conflicts && responders.is_conflict() {
+            self.bus_conflicts.push(BusConflict {
+                event,
+                pc: self.instruction_pc,
+            });
+        }
+    }
+}
+
+/// Write a trace as a Value Change Dump, with one signal each for the address, data, read and write lines
+#[cfg(feature = "std")]
+pub fn write_vcd(events: &[BusEvent], out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "$version gb_core bus trace $end")?;
+    writeln!(out, "$timescale 1ns $end")?;
+    writeln!(out, "$scope module bus $end")?;
+    writeln!(out, "$var wire 16 a addr $end")?;
+    writeln!(out, "$var wire 8 d data $end")?;
+    writeln!(out, "$var wire 1 r read $end")?;
+    writeln!(out, "$var wire 1 w write $end")?;
+    writeln!(out, "$upscope $end")?;
+    writeln!(out, "$enddefinitions $end")?;
+
+    for event in events {
+        // An M-cycle is 4 periods of the 4.194304 MHz clock
+        let time = event.cycle * 4 * 1_000_000_000 / 4_194_304;
+        writeln!(out, "#{}", time)?;
+        writeln!(out, "b{:016b} a", event.addr)?;
+        writeln!(out, "b{:08b} d", event.data)?;
+        writeln!(out, "{}r", (event.access == BusAccess::Read) as u8)?;
+        writeln!(out, "{}w", (event.access == BusAccess::Write) as u8)?;
+    }
+    Ok(())
+}
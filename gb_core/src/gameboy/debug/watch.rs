@@ -0,0 +1,78 @@
+//! Change notifications for watched address ranges
+//!
+//! Watches are a lighter-weight alternative to breaking on every write: the emulator keeps running and each
+//! change to a watched address is recorded, so a RAM-watch tool can collect them once per frame.
+
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use crate::gameboy::{models::GbModel, Gameboy};
+
+/// Identifies a watch registered with [`Gameboy::add_watch`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WatchId(usize);
+
+/// A write that changed the value at a watched address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub watch: WatchId,
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+    /// The address of the instruction that performed the write
+    pub pc: u16,
+}
+
+#[derive(Default)]
+pub(crate) struct Watches {
+    next_id: usize,
+    ranges: Vec<(WatchId, RangeInclusive<u16>)>,
+    hits: Vec<WatchHit>,
+}
+
+impl Watches {
+    #[inline]
+    pub(crate) fn is_watched(&self, addr: u16) -> bool {
+        self.ranges.iter().any(|(_, range)| range.contains(&addr))
+    }
+
+    pub(crate) fn record(&mut self, addr: u16, old: u8, new: u8, pc: u16) {
+        if old == new {
+            return;
+        }
+
+        for (watch, range) in self.ranges.iter() {
+            if range.contains(&addr) {
+                self.hits.push(WatchHit {
+                    watch: *watch,
+                    addr,
+                    old,
+                    new,
+                    pc,
+                });
+            }
+        }
+    }
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    /// Start recording every write that changes a value in `range`.
+    pub fn add_watch(&mut self, range: RangeInclusive<u16>) -> WatchId {
+        let id = WatchId(self.watches.next_id);
+        self.watches.next_id += 1;
+        self.watches.ranges.push((id, range));
+        id
+    }
+
+    /// Stop recording writes for a watch. Hits that were already recorded are kept until they are taken.
+    pub fn remove_watch(&mut self, watch: WatchId) {
+        self.watches.ranges.retain(|(id, _)| *id != watch);
+    }
+
+    /// Returns every hit recorded since the last call, in the order the writes happened.
+    ///
+    /// Frontends will typically call this once per frame.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        core::mem::take(&mut self.watches.hits)
+    }
+}
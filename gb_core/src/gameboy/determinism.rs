@@ -0,0 +1,79 @@
+//! Settings for what's left to chance on real hardware
+//!
+//! A real DMG's RAM powers on holding semi-random garbage, which differs between units and even between power cycles.
+//! Games shouldn't read it before writing it, but some do, e.g. to seed a random number generator. By default RAM is
+//! zeroed so every run is the same; [`DeterminismConfig`] picks other patterns, including a seeded random one that's
+//! still reproducible.
+
+use super::{models::DMG, Gameboy};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeterminismConfig {
+    /// Work RAM, $C000-$DFFF
+    pub wram: RamFill,
+    /// Video RAM, $8000-$9FFF
+    pub vram: RamFill,
+    /// High RAM, $FF80-$FFFE
+    pub hram: RamFill,
+}
+
+impl DeterminismConfig {
+    /// Every region filled the same way
+    pub fn uniform(fill: RamFill) -> Self {
+        DeterminismConfig {
+            wram: fill,
+            vram: fill,
+            hram: fill,
+        }
+    }
+}
+
+/// What a region of RAM holds at power-on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RamFill {
+    #[default]
+    Zero,
+    Ones,
+    /// Alternating runs of eight $0F and eight $F0 bytes, a simple stand-in for the striped patterns DMG work RAM
+    /// tends to power on with
+    Nibbles,
+    /// Pseudo-random bytes, the same for the same seed on every platform
+    Random {
+        seed: u64,
+    },
+}
+
+impl RamFill {
+    /// The byte at `addr` at power-on. Each address is independent, so regions filled with the same seed still differ.
+    pub fn byte_at(self, addr: u16) -> u8 {
+        match self {
+            RamFill::Zero => 0x00,
+            RamFill::Ones => 0xFF,
+            RamFill::Nibbles if addr & 0x08 == 0 => 0x0F,
+            RamFill::Nibbles => 0xF0,
+            RamFill::Random { seed } => (splitmix64(seed ^ addr as u64) >> 56) as u8,
+        }
+    }
+}
+
+/// The SplitMix64 finalizer, which spreads every bit of `x` over the whole result
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+impl Gameboy<DMG> {
+    /// Fill RAM the way `config` says it powers on, overwriting anything already in it. Meant to be called before
+    /// running anything, since real RAM only gets its garbage at power-on.
+    pub fn set_determinism_config(&mut self, config: DeterminismConfig) {
+        self.determinism = config;
+        self.memory.fill(config.wram, config.hram);
+        self.ppu.fill_vram(config.vram);
+    }
+
+    pub fn determinism_config(&self) -> DeterminismConfig {
+        self.determinism
+    }
+}
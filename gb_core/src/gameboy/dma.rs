@@ -0,0 +1,74 @@
+//! OAM DMA, which copies 160 bytes to OAM, one per M-cycle.
+//!
+//! While a transfer is running the CPU can only reach HRAM and the I/O registers, which sit on their own bus. Reads
+//! from anywhere else return the byte being copied, and writes are dropped. That includes instruction fetches, which is
+//! why games start a transfer from a routine in HRAM and wait there until it's done.
+
+/// The number of bytes copied by one transfer
+pub const OAM_DMA_LENGTH: u8 = 160;
+
+#[derive(Clone, Copy, Debug)]
+struct Transfer {
+    source: u16,
+    /// The next byte to copy
+    index: u8,
+    /// The transfer starts copying on the M-cycle after the one it was started in
+    starting: bool,
+}
+
+#[derive(Default, Debug)]
+pub struct OamDma {
+    /// The last value written to $FF46
+    register: u8,
+    transfer: Option<Transfer>,
+}
+
+impl OamDma {
+    /// The value of $FF46, which reads back as the last value written
+    pub fn register(&self) -> u8 {
+        self.register
+    }
+
+    /// Whether a transfer is copying bytes, and so blocking the CPU's access to the bus
+    pub fn active(&self) -> bool {
+        matches!(
+            self.transfer,
+            Some(Transfer {
+                starting: false,
+                ..
+            })
+        )
+    }
+
+    /// Start a transfer from `value` * $100, replacing any transfer already running
+    pub(crate) fn start(&mut self, value: u8) {
+        self.register = value;
+        self.transfer = Some(Transfer {
+            source: (value as u16) << 8,
+            index: 0,
+            starting: true,
+        });
+    }
+
+    /// Advance by one M-cycle, returning the address to copy from and the OAM index to copy to on this cycle, if any
+    pub(crate) fn next_copy(&mut self) -> Option<(u16, u8)> {
+        let transfer = self.transfer.as_mut()?;
+        if transfer.starting {
+            transfer.starting = false;
+            return None;
+        }
+
+        let mut source = transfer.source + transfer.index as u16;
+        // Above $DFFF the DMA sees the WRAM echo, all the way up to $FFFF
+        if source >= 0xE000 {
+            source -= 0x2000;
+        }
+        let copy = (source, transfer.index);
+
+        transfer.index += 1;
+        if transfer.index == OAM_DMA_LENGTH {
+            self.transfer = None;
+        }
+        Some(copy)
+    }
+}
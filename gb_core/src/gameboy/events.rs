@@ -0,0 +1,63 @@
+//! When the next scheduled events happen
+//!
+//! Runners can use [`Gameboy::next_events`] to know how long they can run before something they care about happens,
+//! e.g. to run straight to the end of a frame, and tests can check the timing against it. The counts assume the game
+//! doesn't write to any of the registers involved in the meantime, which would move the events.
+//!
+//! Fast idle (see [`Gameboy::set_fast_idle`]) uses the same counts to skip ahead while the CPU is halted or polling,
+//! since nothing can write to those registers then.
+
+use super::{
+    models::{GbModel, DMG},
+    ppu::PPU,
+    Gameboy,
+};
+
+/// Counts are in M-cycles, i.e. calls to [`Gameboy::clock`], up to and including the one that sets the interrupt's
+/// bit in IF
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NextEvents {
+    /// `None` while the LCD is off
+    pub vblank: Option<u32>,
+    /// `None` while the timer is disabled
+    pub timer_overflow: Option<u32>,
+    /// The PPU's current mode, as in STAT
+    pub ppu_mode: u8,
+    /// The line the PPU is on, as in LY
+    pub ly: u8,
+}
+
+impl NextEvents {
+    /// The number of M-cycles until the first of the interrupts, if any are coming
+    pub fn next_interrupt(&self) -> Option<u32> {
+        Self::first(self.vblank, self.timer_overflow)
+    }
+
+    fn first(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+impl<Model: GbModel> Gameboy<Model> {
+    /// The number of M-cycles until VBlank or the timer next requests an interrupt, which fast idle skips ahead to
+    pub(crate) fn cycles_until_interrupt(&self) -> Option<u32> {
+        NextEvents::first(
+            self.ppu.cycles_until_vblank(),
+            self.timer.cycles_until_overflow(),
+        )
+    }
+}
+
+impl Gameboy<DMG> {
+    pub fn next_events(&self) -> NextEvents {
+        NextEvents {
+            vblank: self.ppu.cycles_until_vblank(),
+            timer_overflow: self.timer.cycles_until_overflow(),
+            ppu_mode: self.ppu.mode(),
+            ly: self.ppu.ly(),
+        }
+    }
+}
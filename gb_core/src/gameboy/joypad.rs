@@ -1,3 +1,7 @@
+use alloc::vec::Vec;
+
+use bitflags::bitflags;
+
 use super::Chip;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -12,6 +16,32 @@ pub enum Button {
     Down,
 }
 
+bitflags! {
+    /// A snapshot of every button, e.g. one frame of a movie. Set bits are held buttons.
+    #[derive(Default)]
+    pub struct ButtonState: u8 {
+        const RIGHT = 0x01;
+        const LEFT = 0x02;
+        const UP = 0x04;
+        const DOWN = 0x08;
+        const A = 0x10;
+        const B = 0x20;
+        const SELECT = 0x40;
+        const START = 0x80;
+    }
+}
+
+/// A button press queued with `Joypad::schedule_press`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduledPress {
+    pub button: Button,
+    /// The button is pressed once the PPU has finished this many frames (see `PPU::frame_count`)
+    pub frame: u64,
+    /// How many frames the button is held for
+    pub frames: u64,
+    started: bool,
+}
+
 #[derive(Debug, Default)]
 pub struct Joypad {
     pub start: bool,
@@ -23,7 +53,13 @@ pub struct Joypad {
     pub left: bool,
     pub right: bool,
 
+    /// Treat Left+Right and Up+Down as if neither were held, since a real D-pad can't press both at once. Some games
+    /// misbehave when they see opposing directions.
+    pub forbid_opposing_directions: bool,
+
     p1: u8,
+    pending_input: Option<ButtonState>,
+    schedule: Vec<ScheduledPress>,
 }
 
 impl Joypad {
@@ -41,6 +77,78 @@ impl Joypad {
         }
     }
 
+    /// Replace the state of every button at the end of the current frame, so a whole frame sees the same input.
+    /// While the LCD is off no frames end, so the input isn't applied until it is switched back on.
+    pub fn set_input(&mut self, input: ButtonState) {
+        self.pending_input = Some(input);
+    }
+
+    /// The buttons that are currently held
+    pub fn input(&self) -> ButtonState {
+        let mut input = ButtonState::empty();
+        input.set(ButtonState::RIGHT, self.right);
+        input.set(ButtonState::LEFT, self.left);
+        input.set(ButtonState::UP, self.up);
+        input.set(ButtonState::DOWN, self.down);
+        input.set(ButtonState::A, self.a);
+        input.set(ButtonState::B, self.b);
+        input.set(ButtonState::SELECT, self.select);
+        input.set(ButtonState::START, self.start);
+        input
+    }
+
+    /// Press `button` at the start of `frame` and release it `frames` frames later, e.g. "press A at frame 1234 for 3
+    /// frames". Frames that have already started are pressed at the start of the next one.
+    pub fn schedule_press(&mut self, button: Button, frame: u64, frames: u64) {
+        self.schedule.push(ScheduledPress {
+            button,
+            frame,
+            frames,
+            started: false,
+        });
+    }
+
+    /// Presses that haven't finished yet
+    pub fn scheduled_presses(&self) -> &[ScheduledPress] {
+        &self.schedule
+    }
+
+    pub fn clear_scheduled_presses(&mut self) {
+        self.schedule.clear();
+    }
+
+    /// Apply the input given to `set_input` and any scheduled presses. Called when `frame` starts.
+    pub(crate) fn start_frame(&mut self, frame: u64) {
+        self.latch_input();
+
+        let mut schedule = core::mem::take(&mut self.schedule);
+        schedule.retain_mut(|press| {
+            if !press.started && frame >= press.frame {
+                self.press(press.button);
+                press.started = true;
+            }
+            if press.started && frame >= press.frame.saturating_add(press.frames) {
+                self.release(press.button);
+                return false;
+            }
+            true
+        });
+        self.schedule = schedule;
+    }
+
+    fn latch_input(&mut self) {
+        if let Some(input) = self.pending_input.take() {
+            self.right = input.contains(ButtonState::RIGHT);
+            self.left = input.contains(ButtonState::LEFT);
+            self.up = input.contains(ButtonState::UP);
+            self.down = input.contains(ButtonState::DOWN);
+            self.a = input.contains(ButtonState::A);
+            self.b = input.contains(ButtonState::B);
+            self.select = input.contains(ButtonState::SELECT);
+            self.start = input.contains(ButtonState::START);
+        }
+    }
+
     pub fn release(&mut self, button: Button) {
         use Button::*;
         match button {
@@ -88,10 +196,13 @@ impl Chip for Joypad {
         };
 
         let direction_buttons = if self.p1 & 0b00010000 == 0 {
-            let down = !bool_to_bit(self.down, 3);
-            let up = !bool_to_bit(self.up, 2);
-            let left = !bool_to_bit(self.left, 1);
-            let right = !bool_to_bit(self.right, 0);
+            let forbid_vertical = self.forbid_opposing_directions && self.up && self.down;
+            let forbid_horizontal = self.forbid_opposing_directions && self.left && self.right;
+
+            let down = !bool_to_bit(self.down && !forbid_vertical, 3);
+            let up = !bool_to_bit(self.up && !forbid_vertical, 2);
+            let left = !bool_to_bit(self.left && !forbid_horizontal, 1);
+            let right = !bool_to_bit(self.right && !forbid_horizontal, 0);
 
             0x0F & down & up & left & right
         } else {
@@ -101,11 +212,18 @@ impl Chip for Joypad {
         let old_p1 = self.p1;
         self.p1 = (old_p1 & 0xF0) | (action_buttons & direction_buttons);
 
-        let interrupt = old_p1 & 0x0F == 0x0F && self.p1 & 0x0F != 0x0F;
+        // Any of P10-P13 going from high to low requests an interrupt, even if another line was already low
+        let interrupt = old_p1 & !self.p1 & 0x0F != 0;
         if interrupt {
             *interrupt_request |= 1 << 4;
         }
     }
+
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        if addr == 0xFF00 {
+            *data = self.p1;
+        }
+    }
 }
 
 fn bool_to_bit(b: bool, bit: usize) -> u8 {
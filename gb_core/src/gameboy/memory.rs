@@ -1,4 +1,4 @@
-use crate::cpu::CpuOutputPins;
+use crate::{cpu::CpuOutputPins, gameboy::determinism::RamFill};
 
 pub struct Memory {
     work_ram_1: [u8; 0x1000],
@@ -15,6 +15,16 @@ impl Memory {
         }
     }
 
+    /// Overwrite work RAM and high RAM with what they hold at power-on
+    pub(crate) fn fill(&mut self, wram: RamFill, hram: RamFill) {
+        for addr in 0xC000..=0xDFFF {
+            self[addr] = wram.byte_at(addr);
+        }
+        for addr in 0xFF80..=0xFFFE {
+            self[addr] = hram.byte_at(addr);
+        }
+    }
+
     fn address_is_in_range(addr: u16) -> bool {
         match addr {
             0xC000..=0xDFFF => true,
@@ -24,7 +34,7 @@ impl Memory {
     }
 }
 
-impl std::ops::Index<u16> for Memory {
+impl core::ops::Index<u16> for Memory {
     type Output = u8;
     fn index(&self, index: u16) -> &Self::Output {
         match index {
@@ -36,7 +46,7 @@ impl std::ops::Index<u16> for Memory {
     }
 }
 
-impl std::ops::IndexMut<u16> for Memory {
+impl core::ops::IndexMut<u16> for Memory {
     fn index_mut(&mut self, index: u16) -> &mut Self::Output {
         match index {
             0xC000..=0xCFFF => &mut self.work_ram_1[(index - 0xC000) as usize],
@@ -49,15 +59,20 @@ impl std::ops::IndexMut<u16> for Memory {
 
 impl super::Chip for Memory {
     fn clock(&mut self, input: CpuOutputPins, data: &mut u8, _interrupt_request: &mut u8) {
-        if Self::address_is_in_range(input.addr()) {
-            match input {
-                CpuOutputPins::Read { addr } => {
-                    *data = self[addr];
-                }
-                CpuOutputPins::Write { addr, data } => {
-                    self[addr] = data;
-                }
+        match input {
+            CpuOutputPins::Read { addr } if Self::address_is_in_range(addr) => {
+                *data = self[addr];
             }
+            CpuOutputPins::Write { addr, data } if Self::address_is_in_range(addr) => {
+                self[addr] = data;
+            }
+            _ => (),
+        }
+    }
+
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        if Self::address_is_in_range(addr) {
+            *data = self[addr];
         }
     }
 }
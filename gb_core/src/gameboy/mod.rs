@@ -1,15 +1,48 @@
+pub mod boot;
 pub mod cart;
+pub mod debug;
+pub mod determinism;
+pub mod dma;
+pub mod events;
 pub mod joypad;
 pub mod memory;
+mod polling;
 pub mod ppu;
+pub mod serial;
+pub mod timeline;
 pub mod timer;
 
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+
 use crate::cpu::{CpuInputPins, CpuOutputPins, CpuRunner, CpuRunnerYield};
 use memory::Memory;
 use ppu::PPU;
 
-use self::{cart::Cart, models::DMG};
+use self::{
+    cart::Cart,
+    models::{GbModel, DMG},
+};
 
+/// A whole Gameboy: the CPU, the PPU, and everything else on the bus.
+///
+/// ```
+/// use gb_core::gameboy::{cart::header::rom_with_code, ppu::monochrome::color::COLOR_BLACK, Gameboy};
+///
+/// let rom = rom_with_code(&[
+///     0x3E, 0xFF, // LD A, $FF
+///     0xE0, 0x47, // LDH (BGP), A ; every background color is black
+///     0x18, 0xFE, // JR -2
+/// ]);
+/// let mut gameboy = Gameboy::new(rom).unwrap();
+/// gameboy.reset();
+///
+/// // The first frame was mostly drawn before the code ran
+/// gameboy.run_frame();
+/// gameboy.run_frame();
+/// let (pixels, width, height) = gameboy.get_frame(1);
+/// assert_eq!((width, height), (160, 144));
+/// assert!(pixels.iter().all(|&pixel| pixel == COLOR_BLACK));
+/// ```
 pub struct Gameboy<Model: models::GbModel> {
     pub cpu: CpuRunner,
     pub ppu: Model::PPU,
@@ -17,22 +50,70 @@ pub struct Gameboy<Model: models::GbModel> {
     pub cart: cart::Cart,
     timer: timer::Timer,
     pub joypad: joypad::Joypad,
+    pub serial: serial::Serial,
+    oam_dma: dma::OamDma,
 
     cpu_input: CpuInputPins,
     interrupt_enable: u8,
     interrupt_request: u8,
+
+    /// Skip clocking the CPU while it's halted with no interrupt pending, or locked up, and skip iterations of
+    /// polling loops
+    fast_idle: bool,
+    polling: polling::PollingLoops,
+    cpu_halted: bool,
+    cpu_locked_up: bool,
+    illegal_opcode_policy: IllegalOpcodePolicy,
+    illegal_opcode: Option<IllegalOpcode>,
+    determinism: determinism::DeterminismConfig,
+
+    perf: debug::perf::PerfCounters,
+    /// The address of the instruction currently being executed
+    #[cfg(any(feature = "debugger", feature = "trace"))]
+    instruction_pc: u16,
+    #[cfg(feature = "debugger")]
+    watches: debug::watch::Watches,
+    #[cfg(feature = "debugger")]
+    latency: debug::latency::LatencyTracker,
+    #[cfg(feature = "debugger")]
+    stack_checks: debug::stack::StackChecks,
+    #[cfg(feature = "debugger")]
+    regions: debug::regions::Regions,
+    #[cfg(feature = "debugger")]
+    sram_views: debug::sram_view::SramViews,
+    #[cfg(feature = "debugger")]
+    breakpoints: debug::breakpoints::Breakpoints,
+    /// What reads of LY return instead of the PPU's line, for comparing against traces made that way
+    #[cfg(feature = "debugger")]
+    ly_override: Option<u8>,
+    #[cfg(feature = "trace")]
+    bus_trace: Option<debug::trace::BusTrace>,
+    #[cfg(feature = "trace")]
+    detect_conflicts: bool,
+    #[cfg(feature = "trace")]
+    bus_conflicts: Vec<debug::trace::BusConflict>,
+    #[cfg(feature = "trace")]
+    detect_stray_rom_writes: bool,
+    #[cfg(feature = "trace")]
+    stray_rom_writes: Vec<debug::trace::StrayRomWrite>,
 }
 
 pub mod models {
     use super::*;
     pub trait GbModel {
         type PPU: PPU;
+
+        /// How the PPU layers overlapping objects
+        const OBJECT_PRIORITY: ppu::object::ObjectPriority;
     }
 
     /// The original Gameboy
     pub enum DMG {}
     impl GbModel for DMG {
         type PPU = ppu::monochrome::MonochromePpu;
+
+        const OBJECT_PRIORITY: ppu::object::ObjectPriority =
+            ppu::object::ObjectPriority::XCoordinate;
     }
     // /// The Gameboy Color
     // pub enum GBC {}
@@ -43,28 +124,100 @@ pub mod models {
 }
 
 impl Gameboy<DMG> {
-    pub fn new(rom: Vec<u8>) -> Result<Self, &'static str> {
-        Ok(Gameboy {
+    /// Load a ROM leniently. Check `cart.diagnostics()` for any problems with it.
+    pub fn new(rom: impl Into<Arc<[u8]>>) -> Result<Box<Self>, &'static str> {
+        Self::with_load_mode(rom, cart::LoadMode::Lenient)
+    }
+
+    pub fn with_load_mode(
+        rom: impl Into<Arc<[u8]>>,
+        mode: cart::LoadMode,
+    ) -> Result<Box<Self>, &'static str> {
+        Ok(Self::with_cart(Cart::with_mode(rom, mode)?))
+    }
+
+    /// The `Gameboy` is boxed straight away, since with the `static-alloc` feature it holds the frame buffers and the
+    /// cartridge RAM inline and is too big to move around on the stack
+    pub fn with_cart(cart: Cart) -> Box<Self> {
+        Box::new(Gameboy {
             cpu: crate::cpu::Cpu::default().runner(),
-            ppu: ppu::monochrome::MonochromePpu::new(),
+            ppu: ppu::monochrome::MonochromePpu::with_object_priority(DMG::OBJECT_PRIORITY),
             cpu_input: CpuInputPins::default(),
             memory: Memory::new(),
-            cart: Cart::new(rom)?,
+            cart,
             timer: timer::Timer::default(),
             joypad: joypad::Joypad::default(),
+            serial: serial::Serial::default(),
+            oam_dma: dma::OamDma::default(),
 
             interrupt_enable: 0,
             interrupt_request: 0,
+
+            fast_idle: false,
+            polling: Default::default(),
+            cpu_halted: false,
+            cpu_locked_up: false,
+            illegal_opcode_policy: IllegalOpcodePolicy::Report,
+            illegal_opcode: None,
+            determinism: Default::default(),
+
+            perf: Default::default(),
+            #[cfg(any(feature = "debugger", feature = "trace"))]
+            instruction_pc: 0,
+            #[cfg(feature = "debugger")]
+            watches: Default::default(),
+            #[cfg(feature = "debugger")]
+            latency: Default::default(),
+            #[cfg(feature = "debugger")]
+            stack_checks: Default::default(),
+            #[cfg(feature = "debugger")]
+            regions: Default::default(),
+            #[cfg(feature = "debugger")]
+            sram_views: Default::default(),
+            #[cfg(feature = "debugger")]
+            breakpoints: Default::default(),
+            #[cfg(feature = "debugger")]
+            ly_override: None,
+            #[cfg(feature = "trace")]
+            bus_trace: None,
+            #[cfg(feature = "trace")]
+            detect_conflicts: false,
+            #[cfg(feature = "trace")]
+            bus_conflicts: Vec::new(),
+            #[cfg(feature = "trace")]
+            detect_stray_rom_writes: false,
+            #[cfg(feature = "trace")]
+            stray_rom_writes: Vec::new(),
         })
     }
 
-    /// temporary
+    /// Set the CPU and IO registers to what the DMG boot ROM leaves them as, ready to run the cartridge from $0100.
+    /// See [`boot`] for other revisions.
     pub fn reset(&mut self) {
-        self.cpu.cpu.registers.pc = 0x100;
-        self.cpu.cpu.registers.sp = 0xFFFE;
+        self.reset_as(boot::Revision::Dmg);
     }
 }
 
+/// What to do when the CPU runs an illegal opcode, one of the 11 the SM83 doesn't define. The CPU always locks up
+/// like the real one, which only a reset undoes; the policy decides how loudly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IllegalOpcodePolicy {
+    /// Carry on silently with the CPU locked up, while the rest of the Gameboy keeps running
+    LockUp,
+    /// Keep a report for `Gameboy::take_illegal_opcode`, e.g. for a frontend to show an error
+    Report,
+    /// Keep a report and return from `Gameboy::run_frame` straight away, like a breakpoint, so a debugger stops on it
+    Break,
+}
+
+/// The illegal opcode that locked up the CPU
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IllegalOpcode {
+    pub opcode: u8,
+    /// Where the opcode was fetched from. The CPU is stuck, so the ROM bank mapped there can't have changed since.
+    pub pc: u16,
+}
+
 /// Contains information about a clock cycle for use by debugging methods
 pub struct ClockDebug {
     is_fetch_cycle: bool,
@@ -73,58 +226,382 @@ pub struct ClockDebug {
 impl<Model: models::GbModel> Gameboy<Model> {
     /// Clock the entire gameboy by M-cycle
     pub fn clock(&mut self) -> ClockDebug {
-        let CpuRunnerYield {
-            pins: cpu_pins_out,
-            is_fetch_cycle,
-        } = self.cpu.clock(self.cpu_input);
+        let (cpu_pins_out, is_fetch_cycle, interrupt_ack) = if self.fast_idle
+            && (self.cpu_locked_up || self.cpu_halted && !self.interrupt_pending())
+        {
+            // A halted CPU would only yield idle cycles until an interrupt pin is set, and a locked up one forever
+            (CpuOutputPins::Idle, false, None)
+        } else {
+            let cpu_start = self.perf.start();
+            let cpu_output = self.cpu.clock(self.cpu_input);
+            if let Some(start) = cpu_start {
+                self.perf.cpu_time += start.elapsed();
+            }
+            let CpuRunnerYield {
+                pins,
+                is_fetch_cycle,
+                interrupt_ack,
+                halted,
+                locked_up,
+                opcode,
+                ..
+            } = cpu_output;
+            if opcode.is_none() && self.perf.coverage.is_some() {
+                self.finish_coverage(cpu_output.operands());
+            }
+            self.cpu_halted = halted;
+            if let (true, false, Some(opcode)) = (locked_up, self.cpu_locked_up, opcode) {
+                self.lock_up(opcode);
+            }
+            (pins, is_fetch_cycle, interrupt_ack)
+        };
+        self.perf.stats.cycles += 1;
+
+        #[cfg(any(feature = "debugger", feature = "trace"))]
+        if let (true, Some(addr)) = (is_fetch_cycle, cpu_pins_out.addr()) {
+            self.instruction_pc = addr;
+        }
+
+        #[cfg(feature = "debugger")]
+        {
+            if let CpuOutputPins::Write { addr, data } = cpu_pins_out {
+                if self.watches.is_watched(addr) {
+                    let old = self.debug_read(addr);
+                    self.watches.record(addr, old, data, self.instruction_pc);
+                }
+            }
+            if let CpuOutputPins::Read { addr: 0xFF00 } = cpu_pins_out {
+                self.latency.joypad_read(self.ppu.frame_count());
+            }
+            self.stack_checks
+                .check(cpu_pins_out, self.cpu.cpu.registers.sp, self.instruction_pc);
+            self.regions
+                .check(cpu_pins_out, is_fetch_cycle, self.instruction_pc);
+            if is_fetch_cycle && !self.breakpoints.is_empty() {
+                let bank = self.cart.rom_bank(self.instruction_pc);
+                self.breakpoints
+                    .check(bank.map(|bank| bank as u16), self.instruction_pc);
+            }
+        }
 
+        // OAM DMA takes the bus from the CPU, leaving it only HRAM and the I/O registers
+        let mut dma_data = None;
+        if let Some((source, index)) = self.oam_dma.next_copy() {
+            let data = self.debug_read(source);
+            self.ppu.write_oam(index, data);
+            dma_data = Some(data);
+        }
+        let blocked_by_dma = match (dma_data, cpu_pins_out.addr()) {
+            (Some(_), Some(addr)) => addr < 0xFF00,
+            _ => false,
+        };
+        let bus_pins = if blocked_by_dma {
+            CpuOutputPins::Idle
+        } else {
+            cpu_pins_out
+        };
+
+        let frame_count = self.ppu.frame_count();
         let chips: &mut [&mut dyn Chip] = &mut [
-            &mut self.ppu,
             &mut self.memory,
             &mut self.cart,
             &mut self.timer,
             &mut self.joypad,
+            &mut self.serial,
         ];
 
         let bus_output = {
             let mut data = 0xFF;
             let mut ir = self.interrupt_request;
 
+            // The PPU is clocked separately from the other chips so it can be timed on its own
+            let ppu_start = self.perf.start();
+            self.ppu.clock(bus_pins, &mut data, &mut ir);
+            if let Some(start) = ppu_start {
+                self.perf.ppu_time += start.elapsed();
+            }
+
             for chip in chips {
-                chip.clock(cpu_pins_out, &mut data, &mut ir);
+                chip.clock(bus_pins, &mut data, &mut ir);
             }
 
             self.interrupt_request = ir;
             data
         };
 
-        // Handle changes to IE & IF (handled independently from chips)
+        if self.ppu.frame_count() != frame_count {
+            self.joypad.start_frame(self.ppu.frame_count());
+        }
+
+        // Handle changes to IE & IF, and starting OAM DMA (handled independently from chips)
         match cpu_pins_out {
             CpuOutputPins::Write { addr: 0xFF0F, data } => self.interrupt_request = data & 0x1F,
             CpuOutputPins::Write { addr: 0xFFFF, data } => self.interrupt_enable = data & 0x1F,
+            CpuOutputPins::Write { addr: 0xFF46, data } => self.oam_dma.start(data),
             _ => (),
         };
+        if let Some(mask) = interrupt_ack {
+            self.interrupt_request &= !mask;
+        }
 
-        let interrupt_requests = self.interrupt_enable & self.interrupt_request;
+        self.update_interrupt_pins();
         self.cpu_input = CpuInputPins {
-            interrupt_40h: interrupt_requests & (1 << 0) != 0,
-            interrupt_48h: interrupt_requests & (1 << 1) != 0,
-            interrupt_50h: interrupt_requests & (1 << 2) != 0,
-            interrupt_58h: interrupt_requests & (1 << 3) != 0,
-            interrupt_60h: interrupt_requests & (1 << 4) != 0,
-
             // IE & IF are not part of any chip, so they must be handled separately
             data: match cpu_pins_out {
                 CpuOutputPins::Read { addr: 0xFF0F } => self.interrupt_request,
                 CpuOutputPins::Read { addr: 0xFFFF } => self.interrupt_enable,
-                _ => bus_output,
+                CpuOutputPins::Read { addr: 0xFF46 } => self.oam_dma.register(),
+                // Reads blocked by OAM DMA see the byte being copied
+                _ => match dma_data {
+                    Some(data) if blocked_by_dma => data,
+                    _ => bus_output,
+                },
             },
+            ..self.cpu_input
         };
 
+        #[cfg(feature = "debugger")]
+        if let (Some(ly), CpuOutputPins::Read { addr: 0xFF44 }) = (self.ly_override, cpu_pins_out) {
+            self.cpu_input.data = ly;
+        }
+
+        if is_fetch_cycle && self.perf.coverage.is_some() {
+            if let Some(addr) = cpu_pins_out.addr() {
+                self.record_coverage(addr, self.cpu_input.data);
+            }
+        }
+
+        #[cfg(feature = "trace")]
+        self.trace_bus(cpu_pins_out, self.cpu_input.data);
+
+        if self.fast_idle {
+            let running = !self.cpu_halted && !self.cpu_locked_up;
+            self.polling.observe(
+                cpu_pins_out,
+                self.cpu_input.data,
+                is_fetch_cycle,
+                running && dma_data.is_none() && interrupt_ack.is_none(),
+                &self.cpu.cpu,
+                self.interrupt_enable & self.interrupt_request,
+            );
+        }
+
         ClockDebug { is_fetch_cycle }
     }
 
-    /// Clock the gameboy by the time it takes to complete one instruction
+    /// Set the CPU's interrupt pins from IE and IF
+    fn update_interrupt_pins(&mut self) {
+        let interrupt_requests = self.interrupt_enable & self.interrupt_request;
+        self.cpu_input.interrupt_40h = interrupt_requests & (1 << 0) != 0;
+        self.cpu_input.interrupt_48h = interrupt_requests & (1 << 1) != 0;
+        self.cpu_input.interrupt_50h = interrupt_requests & (1 << 2) != 0;
+        self.cpu_input.interrupt_58h = interrupt_requests & (1 << 3) != 0;
+        self.cpu_input.interrupt_60h = interrupt_requests & (1 << 4) != 0;
+    }
+
+    /// With fast idle, run up to `max_cycles` M-cycles at once while the CPU is halted, locked up or going round a
+    /// polling loop, stopping short of anything that would wake it or change what it reads. Returns the number of
+    /// M-cycles run.
+    ///
+    /// Only the PPU and the timer do anything then, as long as OAM DMA and serial transfers aren't running, so none
+    /// of the bus or debugger machinery is clocked. The timer is advanced in one go, and the PPU only draws.
+    fn skip_idle_cycles(&mut self, max_cycles: u32) -> u32 {
+        let quiet = !self.oam_dma.active() && !self.serial.transferring();
+        #[cfg(feature = "trace")]
+        let quiet = quiet && self.bus_trace.is_none() && !self.detect_conflicts;
+        if !self.fast_idle || !quiet {
+            return 0;
+        }
+        if self.cpu_locked_up || self.cpu_halted && !self.interrupt_pending() {
+            self.skip_halted_cycles(max_cycles)
+        } else if let Some(polling) = self.polling.take_found() {
+            self.skip_polling_loop(polling, max_cycles)
+        } else {
+            0
+        }
+    }
+
+    fn skip_halted_cycles(&mut self, max_cycles: u32) -> u32 {
+        // The last two cycles before the interrupt go through `clock`: VBlank finishes the frame on the first, which
+        // the joypad needs to know about, and the interrupt is requested on the second
+        let limit = match self.cycles_until_interrupt() {
+            Some(cycles) => cycles.saturating_sub(2).min(max_cycles),
+            None => max_cycles,
+        };
+        // A STAT interrupt wakes the CPU
+        let wake = if self.cpu_locked_up {
+            0
+        } else {
+            self.interrupt_enable
+        };
+        let cycles = self.ppu.advance(limit, &mut self.interrupt_request, wake);
+        self.timer.advance(cycles);
+        self.update_interrupt_pins();
+        self.perf.stats.cycles += cycles as u64;
+        cycles
+    }
+
+    /// Skip as many whole iterations of the polling loop the CPU just went round as fit before an interrupt, or before
+    /// anything the loop reads changes. The CPU is left about to run the loop's first instruction, as it would be.
+    fn skip_polling_loop(&mut self, polling: polling::PollingLoop, max_cycles: u32) -> u32 {
+        // Skipped instructions would be missing from these
+        #[cfg(feature = "debugger")]
+        if !self.breakpoints.is_empty() || !self.regions.is_empty() {
+            return 0;
+        }
+        if self.perf.coverage.is_some() {
+            return 0;
+        }
+        let reads = polling.tracked_reads();
+        // One of the registers might have changed since the last iteration read it, so the next one would differ
+        if reads
+            .iter()
+            .any(|&(addr, data)| self.debug_read(addr) != data)
+        {
+            return 0;
+        }
+
+        let mut limit = max_cycles;
+        if let Some(cycles) = self.cycles_until_interrupt() {
+            limit = limit.min(cycles.saturating_sub(2));
+        }
+        // STAT interrupts come with the changes to LY and STAT
+        if !reads.is_empty() || self.interrupt_enable & 0x02 != 0 {
+            if let Some(cycles) = self.ppu.stable_cycles() {
+                limit = limit.min(cycles);
+            }
+        }
+        let cycles = limit / polling.period * polling.period;
+        if cycles == 0 {
+            return 0;
+        }
+
+        self.ppu.advance(cycles, &mut self.interrupt_request, 0);
+        self.timer.advance(cycles);
+        self.update_interrupt_pins();
+        self.perf.stats.cycles += cycles as u64;
+        cycles
+    }
+
+    /// Stop running the CPU while it's halted and no interrupt is pending, or locked up, which makes HALT-based idle
+    /// loops much cheaper to emulate. `run_frame` then skips ahead to the next VBlank or timer interrupt, only
+    /// running the PPU and the timer, and the PPU only does the work of drawing.
+    ///
+    /// Tight loops that wait for something by reading it, e.g. LY reaching a line or a flag an interrupt handler sets,
+    /// are skipped too. Once an iteration of a loop of up to 16 M-cycles reads without writing and leaves the CPU as it
+    /// was, the following iterations are skipped until whatever it reads could change. Nothing observable changes,
+    /// but the skipping is turned off while breakpoints, memory regions or coverage would see the instructions.
+    pub fn set_fast_idle(&mut self, enabled: bool) {
+        self.fast_idle = enabled;
+        self.polling.reset();
+    }
+
+    /// Defaults to `IllegalOpcodePolicy::Report`
+    pub fn set_illegal_opcode_policy(&mut self, policy: IllegalOpcodePolicy) {
+        self.illegal_opcode_policy = policy;
+    }
+
+    /// Whether the CPU has locked up after running an illegal opcode
+    pub fn is_locked_up(&self) -> bool {
+        self.cpu_locked_up
+    }
+
+    /// Take the report of the illegal opcode that locked up the CPU, which is only kept with
+    /// `IllegalOpcodePolicy::Report` or `IllegalOpcodePolicy::Break`. Taking it lets `run_frame` run whole frames
+    /// again.
+    pub fn take_illegal_opcode(&mut self) -> Option<IllegalOpcode> {
+        self.illegal_opcode.take()
+    }
+
+    fn lock_up(&mut self, opcode: u8) {
+        self.cpu_locked_up = true;
+        if self.illegal_opcode_policy != IllegalOpcodePolicy::LockUp {
+            self.illegal_opcode = Some(IllegalOpcode {
+                opcode,
+                // PC has already moved past the opcode
+                pc: self.cpu.cpu.registers.pc.wrapping_sub(1),
+            });
+        }
+    }
+
+    fn interrupt_pending(&self) -> bool {
+        let CpuInputPins {
+            interrupt_40h,
+            interrupt_48h,
+            interrupt_50h,
+            interrupt_58h,
+            interrupt_60h,
+            ..
+        } = self.cpu_input;
+        interrupt_40h || interrupt_48h || interrupt_50h || interrupt_58h || interrupt_60h
+    }
+
+    /// Set every button at once, taking effect at the end of the current frame. See `Joypad::set_input`.
+    pub fn set_input(&mut self, input: joypad::ButtonState) {
+        self.joypad.set_input(input);
+    }
+
+    /// The timer is only reachable through the bus, so this exposes it read-only (e.g. for the APU's DIV-APU events).
+    pub fn timer(&self) -> &timer::Timer {
+        &self.timer
+    }
+
+    /// The state of OAM DMA
+    pub fn oam_dma(&self) -> &dma::OamDma {
+        &self.oam_dma
+    }
+
+    /// Read a byte from the bus without clocking any of the chips or causing any side effects.
+    pub fn debug_read(&self, addr: u16) -> u8 {
+        let chips: [&dyn Chip; 6] = [
+            &self.ppu,
+            &self.memory,
+            &self.cart,
+            &self.timer,
+            &self.joypad,
+            &self.serial,
+        ];
+
+        match addr {
+            0xFF0F => self.interrupt_request,
+            0xFFFF => self.interrupt_enable,
+            0xFF46 => self.oam_dma.register(),
+            _ => {
+                let mut data = 0xFF;
+                for chip in chips {
+                    chip.debug_read(addr, &mut data);
+                }
+                data
+            }
+        }
+    }
+
+    /// Clock the gameboy by the time it takes the PPU to draw one frame. Returns early if a memory region's rules
+    /// are broken, a breakpoint is hit or an illegal opcode is run under `IllegalOpcodePolicy::Break`, see
+    /// `Gameboy::take_break`, `Gameboy::take_breakpoint_hit` and `Gameboy::take_illegal_opcode`.
+    pub fn run_frame(&mut self) {
+        const FRAME_CYCLES: u32 = ppu::monochrome::FRAME_T_CYCLES as u32 / 4;
+        let start = debug::perf::Instant::now();
+        let mut cycles = 0;
+        while cycles < FRAME_CYCLES {
+            self.clock();
+            cycles += 1;
+            if self.illegal_opcode_policy == IllegalOpcodePolicy::Break
+                && self.illegal_opcode.is_some()
+            {
+                break;
+            }
+            #[cfg(feature = "debugger")]
+            if self.regions.break_pending() || self.breakpoints.hit_pending() {
+                break;
+            }
+            cycles += self.skip_idle_cycles(FRAME_CYCLES - cycles);
+        }
+        self.perf.finish_frame(start.elapsed());
+    }
+
+    /// Clock the gameboy by the time it takes to complete one instruction. Once the CPU has locked up it never
+    /// fetches another, so this only clocks one M-cycle.
     pub fn step_instruction(&mut self) {
         loop {
             if let ClockDebug {
@@ -134,39 +611,18 @@ impl<Model: models::GbModel> Gameboy<Model> {
             {
                 break;
             }
+            if self.cpu_locked_up {
+                break;
+            }
         }
     }
 }
 impl Gameboy<DMG> {
-    /// Fetches a frame from the PPU, scales it, and returns it with its wdth and height
+    /// Fetches a frame from the PPU, scales it, and returns it with its wdth and height.
+    ///
+    /// The frame is blank while the LCD is switched off.
     pub fn get_frame(&self, scale: impl Into<Option<usize>>) -> (Vec<u32>, usize, usize) {
-        let scale = scale.into().unwrap_or(1);
-        let frame = self.ppu.get_frame();
-        let width = frame.width * scale;
-        let height = frame.height * scale;
-        let frame = {
-            let mut new_frame = vec![0; width * height];
-
-            frame
-                .pixels
-                .chunks_exact(frame.width)
-                .enumerate()
-                .for_each(|(y, row)| {
-                    let row_offset = y * scale;
-                    for yoff in row_offset..row_offset + scale {
-                        for (x, pixel) in row.iter().enumerate() {
-                            let pix_offset = x * scale;
-                            for xoff in pix_offset..pix_offset + scale {
-                                new_frame[yoff * width + xoff] = *pixel;
-                            }
-                        }
-                    }
-                });
-
-            new_frame
-        };
-
-        (frame, width, height)
+        self.ppu.get_frame().scaled(scale)
     }
 }
 
@@ -174,4 +630,7 @@ impl Gameboy<DMG> {
 trait Chip {
     /// Clock by one M-cycle
     fn clock(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8);
+
+    /// Read from the chip without any side effects. Chips that don't respond to `addr` leave `data` untouched.
+    fn debug_read(&self, addr: u16, data: &mut u8);
 }
@@ -0,0 +1,167 @@
+//! Spotting tight loops that wait for something by reading it over and over, like
+//!
+//! ```text
+//! wait: LDH A, (LY)
+//!       CP 144
+//!       JR NZ, wait
+//! ```
+//!
+//! An iteration that writes nothing and leaves the CPU as it found it does exactly the same again as long as its
+//! reads return the same, so fast idle can skip whole iterations until something it reads could change.
+
+use crate::cpu::{Cpu, CpuOutputPins, Registers};
+
+/// The longest loop looked for, in M-cycles. It's shorter than mode 2, the shortest time LY and STAT stay the same,
+/// so a register that was read in the last iteration can't have changed and changed back since.
+const MAX_PERIOD: u32 = 16;
+
+/// The most reads of registers that change by themselves a loop can make
+const MAX_TRACKED_READS: usize = 4;
+
+/// How reading an address can be affected by time passing
+enum ReadKind {
+    /// Only writes change it
+    Stable,
+    /// The PPU changes it, or interrupts being requested, which `PPU::stable_cycles` covers
+    Tracked,
+    /// It changes with the timer or the joypad, or reading it is watched by the debugger
+    Unstable,
+}
+
+fn read_kind(addr: u16) -> ReadKind {
+    match addr {
+        // ROM, VRAM, cartridge RAM, WRAM and OAM, which nothing else writes to without OAM DMA running
+        0x0000..=0xFEFF => ReadKind::Stable,
+        0xFF0F | 0xFF41 | 0xFF44 => ReadKind::Tracked,
+        // The rest of the PPU's registers, and OAM DMA's
+        0xFF40 | 0xFF42 | 0xFF43 | 0xFF45..=0xFF4B => ReadKind::Stable,
+        // HRAM and IE
+        0xFF80..=0xFFFF => ReadKind::Stable,
+        _ => ReadKind::Unstable,
+    }
+}
+
+/// The state the loop has to come back to for the next iteration to be the same
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct CpuState {
+    registers: Registers,
+    ime: bool,
+    /// The interrupts that are both enabled and requested
+    pending: u8,
+}
+
+struct LoopHead {
+    pc: u16,
+    state: CpuState,
+    /// M-cycles since the head was last fetched
+    cycles: u32,
+    /// Whether the loop has only read, since the head was last fetched
+    clean: bool,
+    /// The values read from `ReadKind::Tracked` addresses
+    reads: [(u16, u8); MAX_TRACKED_READS],
+    read_count: usize,
+}
+
+/// A loop that was just seen to go round without changing anything. The CPU is about to run its first instruction
+/// again.
+pub(crate) struct PollingLoop {
+    /// The length of an iteration, in M-cycles
+    pub period: u32,
+    reads: [(u16, u8); MAX_TRACKED_READS],
+    read_count: usize,
+}
+
+impl PollingLoop {
+    /// The registers that were read in the last iteration, and what they returned
+    pub fn tracked_reads(&self) -> &[(u16, u8)] {
+        &self.reads[..self.read_count]
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct PollingLoops {
+    /// The most recent target of a backward jump, which is where a loop would start
+    head: Option<LoopHead>,
+    last_fetch: u16,
+    found: Option<PollingLoop>,
+}
+
+impl PollingLoops {
+    /// Follow what the CPU does on one M-cycle. `data` is what it read, and `stable` is whether anything besides the
+    /// CPU could have disturbed the loop, like OAM DMA or an interrupt being serviced.
+    pub(crate) fn observe(
+        &mut self,
+        pins: CpuOutputPins,
+        data: u8,
+        is_fetch_cycle: bool,
+        stable: bool,
+        cpu: &Cpu,
+        pending: u8,
+    ) {
+        self.found = None;
+        if let Some(head) = &mut self.head {
+            head.cycles += 1;
+            head.clean &= stable;
+            match pins {
+                CpuOutputPins::Write { .. } => head.clean = false,
+                CpuOutputPins::Read { addr } => match read_kind(addr) {
+                    ReadKind::Stable => (),
+                    ReadKind::Tracked if head.read_count < MAX_TRACKED_READS => {
+                        head.reads[head.read_count] = (addr, data);
+                        head.read_count += 1;
+                    }
+                    ReadKind::Tracked | ReadKind::Unstable => head.clean = false,
+                },
+                CpuOutputPins::Idle => (),
+            }
+        }
+
+        let pc = match (is_fetch_cycle, pins) {
+            (true, CpuOutputPins::Read { addr }) => addr,
+            _ => return,
+        };
+        let state = CpuState {
+            registers: cpu.registers,
+            ime: cpu.ime,
+            pending,
+        };
+        match &mut self.head {
+            Some(head) if head.pc == pc => {
+                if head.clean && head.cycles <= MAX_PERIOD && head.state == state {
+                    self.found = Some(PollingLoop {
+                        period: head.cycles,
+                        reads: head.reads,
+                        read_count: head.read_count,
+                    });
+                }
+                *head = LoopHead::new(pc, state);
+            }
+            _ if pc < self.last_fetch => self.head = Some(LoopHead::new(pc, state)),
+            _ => (),
+        }
+        self.last_fetch = pc;
+    }
+
+    /// The loop the CPU went round on the last M-cycle observed, if it did
+    pub(crate) fn take_found(&mut self) -> Option<PollingLoop> {
+        self.found.take()
+    }
+
+    /// Forget the loop being followed, e.g. after M-cycles were run without being observed
+    pub(crate) fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl LoopHead {
+    fn new(pc: u16, state: CpuState) -> Self {
+        LoopHead {
+            pc,
+            state,
+            cycles: 0,
+            clean: true,
+            reads: [(0, 0); MAX_TRACKED_READS],
+            read_count: 0,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Simulates the slow response of the DMG's LCD
+//!
+//! Pixels on the original screen take a while to change color, so anything that flickers every other frame shows up
+//! as a faint, steady image. Some games rely on this, for instance to draw more objects on a line than the PPU
+//! allows, or to make shadows and transparency effects.
+
+use alloc::boxed::Box;
+
+use super::monochrome::Frame;
+
+/// Blends each frame with the one before it
+pub struct Ghosting {
+    /// How much of the previous frame shows through, from 0 (none) to 1 (only the previous frame)
+    persistence: f32,
+    previous: Option<Box<Frame>>,
+    output: Box<Frame>,
+}
+
+impl Ghosting {
+    pub fn new(persistence: f32) -> Self {
+        Ghosting {
+            persistence: persistence.clamp(0.0, 1.0),
+            previous: None,
+            output: Box::new(Frame::new()),
+        }
+    }
+
+    pub fn persistence(&self) -> f32 {
+        self.persistence
+    }
+
+    pub fn set_persistence(&mut self, persistence: f32) {
+        self.persistence = persistence.clamp(0.0, 1.0);
+    }
+
+    /// Blend `frame` with the frame that came before it.
+    ///
+    /// Passing the same frame twice returns the same result, so this can be called every time the screen is
+    /// redrawn.
+    pub fn apply(&mut self, frame: &Frame) -> &Frame {
+        if let Some(previous) = &self.previous {
+            if previous.index == frame.index {
+                return &self.output;
+            }
+        }
+
+        *self.output = *frame;
+        if let Some(previous) = &self.previous {
+            // The blend only comes out the same as last time once the picture has held still for two frames
+            self.output.repeated = frame.repeated && previous.repeated;
+            if !previous.lcd_off {
+                for (pixel, &old) in self.output.pixels.iter_mut().zip(previous.pixels.iter()) {
+                    *pixel = blend(*pixel, old, self.persistence);
+                }
+            }
+        }
+        self.previous = Some(Box::new(*frame));
+
+        &self.output
+    }
+}
+
+/// Mix two 0xAARRGGBB colors, taking `amount` of `old`
+fn blend(new: u32, old: u32, amount: f32) -> u32 {
+    let mut mixed = new.to_le_bytes();
+    for (channel, old) in mixed.iter_mut().zip(old.to_le_bytes()) {
+        // Adding 0.5 before truncating rounds, since `f32::round` needs std
+        *channel = (*channel as f32 * (1.0 - amount) + old as f32 * amount + 0.5) as u8;
+    }
+    u32::from_le_bytes(mixed)
+}
@@ -1,14 +1,50 @@
 use crate::cpu::CpuOutputPins;
 
+pub mod ghosting;
 pub mod monochrome;
+pub mod object;
 pub mod registers;
+mod render;
+pub mod threaded;
+pub mod vram;
 
 pub trait PPU {
     type Frame;
 
     fn clock_t_state(&mut self);
     fn perform_io(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8);
+    fn debug_read(&self, addr: u16, data: &mut u8);
     fn get_frame(&self) -> Self::Frame;
+    /// The number of frames finished so far
+    fn frame_count(&self) -> u64;
+    /// Write a byte of OAM for OAM DMA, which can write it whatever the PPU is doing
+    fn write_oam(&mut self, index: u8, data: u8);
+    /// The number of M-cycles until the VBlank interrupt is next requested, if nothing is written in the meantime.
+    /// `None` while the LCD is off.
+    fn cycles_until_vblank(&self) -> Option<u32>;
+    /// The number of M-cycles, starting with the next one, whose reads of LY and STAT are sure to return what they
+    /// would now, and which can't request an interrupt, if nothing is written in the meantime. `None` while the LCD
+    /// is off.
+    fn stable_cycles(&self) -> Option<u32>;
+
+    /// Run up to `max_cycles` M-cycles with nothing on the bus, stopping after one that requests an interrupt in
+    /// `wake`. Returns the number of M-cycles run.
+    ///
+    /// PPUs can do this faster than clocking one M-cycle at a time, by skipping dots where nothing happens.
+    fn advance(&mut self, max_cycles: u32, interrupt_request: &mut u8, wake: u8) -> u32 {
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            self.perform_io(CpuOutputPins::Idle, &mut 0xFF, interrupt_request);
+            for _ in 0..4 {
+                self.clock_t_state()
+            }
+            cycles += 1;
+            if *interrupt_request & wake != 0 {
+                break;
+            }
+        }
+        cycles
+    }
 }
 
 impl<T: PPU> super::Chip for T {
@@ -20,4 +56,9 @@ impl<T: PPU> super::Chip for T {
             self.clock_t_state()
         }
     }
+
+    #[inline]
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        PPU::debug_read(self, addr, data)
+    }
 }
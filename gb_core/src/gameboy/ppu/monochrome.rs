@@ -1,19 +1,155 @@
 //! An implementation of the Gameboy monochrome PPU
 
-use crate::cpu::CpuOutputPins;
+use crate::{cpu::CpuOutputPins, gameboy::determinism::RamFill};
 
-use super::{registers::*, PPU};
-use std::{cell::RefCell, fmt::Debug, ops::GeneratorState, rc::Rc};
+use super::{
+    object::{self, LineObjects, Object, ObjectPriority},
+    registers::*,
+    render::{render_line, tile_row_color, LineView},
+    threaded::{LineSnapshot, ThreadedRenderer, VramSnapshot},
+    vram::{BgMap, Tile, VramRegions, TILE_COUNT},
+    PPU,
+};
+use alloc::{rc::Rc, sync::Arc, vec, vec::Vec};
+use core::{borrow::Borrow, convert::TryInto, fmt::Debug};
 
 pub const FRAME_T_CYCLES: usize = 70224;
 
+/// Frames are boxed to keep the PPU small enough to move around, unless the `static-alloc` feature asks for everything
+/// to be stored inline
+#[cfg(not(feature = "static-alloc"))]
+type FrameBuffer = alloc::boxed::Box<Frame>;
+#[cfg(feature = "static-alloc")]
+type FrameBuffer = Frame;
+
+#[cfg(not(feature = "static-alloc"))]
+fn frame_buffer() -> FrameBuffer {
+    alloc::boxed::Box::new(Frame::new())
+}
+#[cfg(feature = "static-alloc")]
+fn frame_buffer() -> FrameBuffer {
+    Frame::new()
+}
+
+bitflags::bitflags! {
+    /// The layers that make up the picture, which can be hidden for debugging with
+    /// [`MonochromePpu::set_hidden_layers`]
+    pub struct Layers: u8 {
+        const BACKGROUND = 0x01;
+        const WINDOW = 0x02;
+        const OBJECTS = 0x04;
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Frame {
     pub pixels: [u32; 144 * 160],
     pub width: usize,
     pub height: usize,
+    /// The number of frames the PPU finished before this one
+    pub index: u64,
+    /// Whether the LCD was switched off before the frame finished. Frontends should show a blank screen
+    pub lcd_off: bool,
+    /// Which scanlines were drawn. Lines that weren't drawn are left as 0
+    pub rendered_lines: [bool; 144],
+    /// Whether the frame looks exactly like the one before it, so recorders can show the previous frame for longer
+    /// instead of storing this one again
+    pub repeated: bool,
 }
 
+impl Frame {
+    pub(crate) fn new() -> Self {
+        Frame {
+            pixels: [0; 144 * 160],
+            width: 160,
+            height: 144,
+            index: 0,
+            lcd_off: false,
+            rendered_lines: [false; 144],
+            repeated: false,
+        }
+    }
+
+    /// Scales the frame up, and returns it with its width and height.
+    ///
+    /// Frames from while the LCD was switched off are blank.
+    pub fn scaled(&self, scale: impl Into<Option<usize>>) -> (Vec<u32>, usize, usize) {
+        let scale = scale.into().unwrap_or(1);
+        let width = self.width * scale;
+        let height = self.height * scale;
+        if self.lcd_off {
+            return (vec![color::COLOR_WHITE; width * height], width, height);
+        }
+        let frame = {
+            let mut new_frame = vec![0; width * height];
+
+            self.pixels
+                .chunks_exact(self.width)
+                .enumerate()
+                .for_each(|(y, row)| {
+                    let row_offset = y * scale;
+                    for yoff in row_offset..row_offset + scale {
+                        for (x, pixel) in row.iter().enumerate() {
+                            let pix_offset = x * scale;
+                            for xoff in pix_offset..pix_offset + scale {
+                                new_frame[yoff * width + xoff] = *pixel;
+                            }
+                        }
+                    }
+                });
+
+            new_frame
+        };
+
+        (frame, width, height)
+    }
+
+    /// Whether every scanline of the frame was drawn
+    pub fn is_complete(&self) -> bool {
+        self.rendered_lines.iter().all(|&rendered| rendered)
+    }
+
+    /// A hash of the frame's pixels (64 bit FNV-1a), which is stable between runs and platforms
+    pub fn hash(&self) -> u64 {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_le_bytes())
+            .fold(0xCBF29CE484222325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001B3)
+            })
+    }
+}
+
+/// What the PPU does on the current dot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    /// Mode 2
+    OamScan,
+    /// Mode 3
+    Drawing(Fetcher),
+    /// Mode 0
+    HBlank,
+    /// Mode 1
+    VBlank,
+}
+
+/// Progress through drawing a line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fetcher {
+    /// The next pixel on the screen to draw
+    dot: u8,
+    /// The next pixel of `tile_row` to draw
+    x: u8,
+    /// The tile on the screen that `tile_row` belongs to
+    screen_tile_x: u8,
+    /// The low and high bytes of the background or window tile row being drawn
+    tile_row: (u8, u8),
+    /// Whether the fetcher has switched to the window
+    window: bool,
+}
+
+/// The internals of [`MonochromePpu`]. Only public for [`MonochromePpu::state_mut`].
+#[doc(hidden)]
 #[derive(Clone)]
 pub struct MonochromePpuState {
     pub tile_data: [u8; 0x9800 - 0x8000],
@@ -35,14 +171,51 @@ pub struct MonochromePpuState {
     pub obp0: u8,
     pub obp1: u8,
 
+    /// How overlapping objects are layered
+    pub object_priority: ObjectPriority,
+    /// The colors the four shades of each palette are drawn as, indexed by `Palette`. The game can't see these.
+    shades: [[u32; 4]; 3],
+    /// Layers left out of the picture. Like `shades`, this doesn't affect anything the game can see.
+    hidden_layers: Layers,
+
+    /// The regions written since they were last passed to `take_dirty`
+    dirty: VramRegions,
+
     vblank_irq: bool,
     stat_irq: bool,
+    /// The interrupt lines as of the last M-cycle. Interrupts are requested on their rising edges
+    last_vblank_irq: bool,
+    last_stat_irq: bool,
+
+    step: Step,
+    /// The line being drawn. Kept separately from LY, since the CPU can write to it
+    line: u8,
+    /// Dots since the start of the line
+    line_cycle: u16,
+    /// The objects found by the OAM scan of the current line
+    line_objects: LineObjects,
+    /// The objects found on each line of the frame being drawn, and of the last finished frame
+    next_scanline_objects: [LineObjects; 144],
+    scanline_objects: [LineObjects; 144],
+    /// Whether LY has matched WY this frame, which the window needs before it's shown
+    window_triggered: bool,
+    /// The next line of the window to draw. Only counts lines the window was actually drawn on
+    window_line: u8,
 
-    frame: Rc<Frame>,
+    /// Draws lines on another thread, if enabled
+    renderer: Option<Rc<ThreadedRenderer>>,
+    /// The copy of VRAM sent to the renderer, until VRAM is written
+    vram_snapshot: Option<Arc<VramSnapshot>>,
+
+    /// The last finished frame
+    frame: FrameBuffer,
+    /// The frame currently being drawn
+    next_frame: FrameBuffer,
+    frame_count: u64,
 }
 
 impl Debug for MonochromePpuState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("MonochromePpuState")
             .field("LCDC", &self.lcdc)
             .field("STAT", &self.stat)
@@ -60,20 +233,31 @@ impl Debug for MonochromePpuState {
 }
 
 pub struct MonochromePpu {
-    pub state: Rc<RefCell<MonochromePpuState>>,
-    gen: std::pin::Pin<
-        Box<
-            dyn std::ops::Generator<
-                Rc<RefCell<MonochromePpuState>>,
-                Yield = Rc<RefCell<MonochromePpuState>>,
-                Return = !,
-            >,
-        >,
-    >,
+    pub(crate) state: MonochromePpuState,
+}
+
+/// Getters and setters for registers. Setters change the register directly, without the side effects a CPU write
+/// would have.
+macro_rules! register_accessors {
+    ($($get:ident, $set:ident: $ty:ty;)*) => {
+        $(
+            pub fn $get(&self) -> $ty {
+                self.state.$get
+            }
+
+            pub fn $set(&mut self, value: $ty) {
+                self.state.$get = value;
+            }
+        )*
+    };
 }
 
 impl MonochromePpu {
     pub fn new() -> Self {
+        Self::with_object_priority(ObjectPriority::XCoordinate)
+    }
+
+    pub fn with_object_priority(object_priority: ObjectPriority) -> Self {
         let state = MonochromePpuState {
             tile_data: [0u8; 0x9800 - 0x8000],
 
@@ -94,64 +278,189 @@ impl MonochromePpu {
             obp0: 0u8,
             obp1: 0u8,
 
+            object_priority,
+            shades: [color::COLORS; 3],
+            hidden_layers: Layers::empty(),
+
+            dirty: VramRegions::all(),
+
             vblank_irq: false,
             stat_irq: false,
+            last_vblank_irq: false,
+            last_stat_irq: false,
+
+            step: Step::OamScan,
+            line: 0,
+            line_cycle: 0,
+            line_objects: LineObjects::new(),
+            next_scanline_objects: [LineObjects::new(); 144],
+            scanline_objects: [LineObjects::new(); 144],
+            window_triggered: false,
+            window_line: 0,
 
-            frame: Rc::new(Frame {
-                pixels: [0; 144 * 160],
-                width: 160,
-                height: 144,
-            }),
+            renderer: None,
+            vram_snapshot: None,
+
+            frame: frame_buffer(),
+            next_frame: frame_buffer(),
+            frame_count: 0,
         };
 
-        MonochromePpu {
-            state: Rc::new(RefCell::new(state)),
-            gen: Box::pin(ppu_gen()),
+        MonochromePpu { state }
+    }
+
+    /// Draw scanlines on a worker thread. See [`super::threaded`] for the tradeoffs.
+    #[cfg(feature = "std")]
+    pub fn set_threaded_rendering(&mut self, enabled: bool) {
+        self.state.renderer = enabled.then(|| Rc::new(ThreadedRenderer::new()));
+    }
+
+    register_accessors! {
+        lcdc, set_lcdc: LCDC;
+        stat, set_stat: STAT;
+        scy, set_scy: u8;
+        scx, set_scx: u8;
+        lyc, set_lyc: u8;
+        wy, set_wy: u8;
+        wx, set_wx: u8;
+        bgp, set_bgp: u8;
+        obp0, set_obp0: u8;
+        obp1, set_obp1: u8;
+    }
+
+    pub fn ly(&self) -> u8 {
+        self.state.ly
+    }
+
+    /// The mode in STAT's lowest two bits: 0 for HBlank, 1 for VBlank, 2 for the OAM scan and 3 while drawing
+    pub fn mode(&self) -> u8 {
+        self.state.stat.bits() & 0b11
+    }
+
+    pub fn object_priority(&self) -> ObjectPriority {
+        self.state.object_priority
+    }
+
+    /// The raw internal state, for tests that need to set up VRAM or OAM directly. Not part of the public API.
+    #[doc(hidden)]
+    pub fn state_mut(&mut self) -> &mut MonochromePpuState {
+        self.state.vram_snapshot = None;
+        &mut self.state
+    }
+
+    /// Overwrite VRAM with what it holds at power-on
+    pub(crate) fn fill_vram(&mut self, fill: RamFill) {
+        let state = &mut self.state;
+        for addr in 0x8000..=0x9FFF {
+            let byte = fill.byte_at(addr);
+            match addr {
+                0x8000..=0x97FF => state.tile_data[addr as usize - 0x8000] = byte,
+                0x9800..=0x9BFF => state.bg_map_1[addr as usize - 0x9800] = byte,
+                _ => state.bg_map_2[addr as usize - 0x9C00] = byte,
+            }
         }
+        state.dirty |= VramRegions::TILE_DATA | VramRegions::BG_MAPS;
+        state.vram_snapshot = None;
     }
-}
 
-impl MonochromePpuState {
-    #[inline(always)]
-    fn set_ly(&mut self, ly: u8) {
-        debug_assert!(ly <= 153);
-        self.ly = ly;
-        self.stat.set(STAT::LYC_EQUALS_LY, self.ly == self.lyc);
+    /// Which of `regions` were written since they were last taken, so debug views of them need to be drawn again.
+    /// Every region starts out dirty, and only `regions` are cleared.
+    ///
+    /// There's only one set of dirty flags, so whoever takes a region is the only one told it changed. Views that show
+    /// different regions can each take their own, but views sharing a region need to share the result.
+    pub fn take_dirty(&mut self, regions: VramRegions) -> VramRegions {
+        let dirty = self.state.dirty & regions;
+        self.state.dirty.remove(regions);
+        dirty
+    }
 
-        self.update_stat_interrupt();
+    /// Decode tile `index` (0-383), counting from $8000
+    pub fn tile(&self, index: usize) -> Tile {
+        assert!(index < TILE_COUNT, "tile index out of range: {}", index);
+        let data = self.state.tile_data[index * 16..index * 16 + 16]
+            .try_into()
+            .unwrap();
+        Tile::decode(data)
     }
 
-    #[inline(always)]
-    fn set_mode(&mut self, mode: u8) {
-        debug_assert!(mode <= 3);
-        self.stat.set_mode(STAT::from_bits_truncate(mode));
+    /// A copy of a tile map, indexed by `[y][x]`
+    pub fn bg_map(&self, which: BgMap) -> [[u8; 32]; 32] {
+        let map = match which {
+            BgMap::Low => &self.state.bg_map_1,
+            BgMap::High => &self.state.bg_map_2,
+        };
+        core::array::from_fn(|y| map[y * 32..y * 32 + 32].try_into().unwrap())
+    }
 
-        self.update_stat_interrupt();
+    /// The tile the background shows at (`map_x`, `map_y`) in its tile map, counted in tiles, decoded using the tile
+    /// map and tile data addressing currently selected by LCDC
+    pub fn bg_tile(&self, map_x: u8, map_y: u8) -> Tile {
+        let view = self.state.view();
+        let mut pixels = [[0; 8]; 8];
+        for (y, row) in pixels.iter_mut().enumerate() {
+            let (lo, hi) = view.bg_map_tile_row(map_x % 32, (map_y % 32) * 8 + y as u8);
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = tile_row_color(lo, hi, x as u8);
+            }
+        }
+        Tile { pixels }
     }
 
-    #[inline(always)]
-    fn update_stat_interrupt(&mut self) {
-        let mode = self.stat & !STAT::MODE_BITMASK;
+    /// Every object in OAM, in OAM order
+    pub fn objects(&self) -> impl Iterator<Item = Object> + '_ {
+        object::entries(&self.state.oam)
+    }
 
-        let mode_int = match mode {
-            STAT::MODE_0 if self.stat.contains(STAT::HBLANK_INTERRUPT_ENABLE) => true,
-            STAT::MODE_1 if self.stat.contains(STAT::VBLANK_INTERRUPT_ENABLE) => true,
-            STAT::MODE_2 if self.stat.contains(STAT::OAM_INTERRUPT_ENABLE) => true,
-            _ => false,
+    /// The objects (sprites) the OAM scan selected on `line` of the last finished frame, ordered from highest to
+    /// lowest priority, or `None` if `line` isn't one of the 144 visible lines. There are at most 10, so objects
+    /// missing from here were dropped by the per-line limit.
+    pub fn scanline_objects(&self, line: u8) -> Option<&[Object]> {
+        self.state
+            .scanline_objects
+            .get(line as usize)
+            .map(|objects| &**objects)
+    }
+
+    /// Leave `layers` out of the picture, e.g. to see what's under the objects. Hidden background and window pixels
+    /// are drawn as color 0, so a hidden window still covers the background. The window keeps counting its lines
+    /// while hidden, and the game still sees its own LCDC.
+    pub fn set_hidden_layers(&mut self, layers: Layers) {
+        self.state.hidden_layers = layers;
+    }
+
+    pub fn hidden_layers(&self) -> Layers {
+        self.state.hidden_layers
+    }
+
+    /// The color each of `palette`'s color IDs is drawn as, taking the palette register and any override into account
+    pub fn palette_colors(&self, palette: color::Palette) -> [u32; 4] {
+        let register = match palette {
+            color::Palette::Bg => self.state.bgp,
+            color::Palette::Obj0 => self.state.obp0,
+            color::Palette::Obj1 => self.state.obp1,
         };
+        let shades = self.state.shades[palette as usize];
+        [0, 1, 2, 3].map(|id| shades[color::calculate_monochrome_color_id(register, id)])
+    }
 
-        let lyc_int = self
-            .stat
-            .contains(STAT::LYC_INTERRUPT_ENABLE | STAT::LYC_EQUALS_LY);
+    /// Draw `palette`'s four shades, from lightest to darkest, as `colors` instead of the usual grays, or go back to
+    /// the grays with `None`. This only changes the pixels that are drawn: the game still sees its own palette
+    /// registers.
+    pub fn set_palette_override(&mut self, palette: color::Palette, colors: Option<[u32; 4]>) {
+        self.state.shades[palette as usize] = colors.unwrap_or(color::COLORS);
+    }
 
-        self.stat_irq = mode_int | lyc_int;
+    /// Override all three palettes with one of the [`color::SCHEMES`]
+    pub fn set_color_scheme(&mut self, scheme: &color::Scheme) {
+        self.set_palette_override(color::Palette::Bg, Some(scheme.bg));
+        self.set_palette_override(color::Palette::Obj0, Some(scheme.obj0));
+        self.set_palette_override(color::Palette::Obj1, Some(scheme.obj1));
     }
 
     /// Create an image displaying the entire current tile data, width, and height.
     ///
     /// The image is scaled a positive integer amount by `scale`, which defaults to 1.
     pub fn display_tile_data(&self, scale: impl Into<Option<usize>>) -> (Vec<u32>, usize, usize) {
-        const TILE_COUNT: usize = (0x9800 - 0x8000) / 16;
         const ROW_LENGTH: usize = 16;
         const TILE_WIDTH: usize = 8;
         const IMAGE_WIDTH: usize = ROW_LENGTH * TILE_WIDTH;
@@ -160,26 +469,18 @@ impl MonochromePpuState {
 
         let scale = scale.into().unwrap_or(1);
         let mut image = vec![0; IMAGE_WIDTH * scale * IMAGE_HEIGHT * scale];
-        let bgp = self.bgp;
+        let colors = self.palette_colors(color::Palette::Bg);
 
         for row in 0..ROWS {
             let basey = TILE_WIDTH * row;
             for col in 0..ROW_LENGTH {
                 let basex = TILE_WIDTH * col;
 
-                let tile_id = row * ROW_LENGTH + col;
+                let tile = self.tile(row * ROW_LENGTH + col);
                 for offy in 0..TILE_WIDTH {
-                    let row_lo = self.tile_data[tile_id * 16 + 2 * offy + 0];
-                    let row_hi = self.tile_data[tile_id * 16 + 2 * offy + 1];
                     for ypix in 0..scale {
                         for offx in 0..TILE_WIDTH {
-                            let colorbit_lo = (row_lo << offx) >> 7;
-                            let colorbit_hi = (row_hi << offx) >> 7;
-                            let color_id = color::calculate_monochrome_color_id(
-                                bgp,
-                                (colorbit_hi << 1) | colorbit_lo,
-                            );
-                            let color = color::COLORS[color_id];
+                            let color = colors[tile.pixels[offy][offx] as usize];
 
                             let imgy = (basey + offy) * scale + ypix;
                             for xpix in 0..scale {
@@ -195,105 +496,329 @@ impl MonochromePpuState {
 
         (image, IMAGE_WIDTH * scale, IMAGE_HEIGHT * scale)
     }
+
+    /// Create an image displaying the whole 256x256 background, width, and height, drawn from the tile map, tile
+    /// data and palette the background currently uses. The part SCX and SCY put on screen is outlined in red,
+    /// wrapping around the edges like the screen does.
+    ///
+    /// The image is scaled a positive integer amount by `scale`, which defaults to 1.
+    pub fn display_background(&self, scale: impl Into<Option<usize>>) -> (Vec<u32>, usize, usize) {
+        const SIZE: usize = 256;
+
+        let scale = scale.into().unwrap_or(1);
+        let view = self.state.view();
+        let colors = self.palette_colors(color::Palette::Bg);
+        let mut background = vec![0; SIZE * SIZE];
+        for (y, row) in background.chunks_exact_mut(SIZE).enumerate() {
+            for (tile_x, pixels) in row.chunks_exact_mut(8).enumerate() {
+                let (lo, hi) = view.bg_map_tile_row(tile_x as u8, y as u8);
+                for (x, pixel) in pixels.iter_mut().enumerate() {
+                    *pixel = colors[tile_row_color(lo, hi, x as u8) as usize];
+                }
+            }
+        }
+
+        let (left, top) = (self.state.scx as usize, self.state.scy as usize);
+        let (right, bottom) = (left + 159, top + 143);
+        for x in left..=right {
+            background[top * SIZE + x % SIZE] = color::COLOR_RED;
+            background[bottom % SIZE * SIZE + x % SIZE] = color::COLOR_RED;
+        }
+        for y in top..=bottom {
+            background[y % SIZE * SIZE + left] = color::COLOR_RED;
+            background[y % SIZE * SIZE + right % SIZE] = color::COLOR_RED;
+        }
+
+        let size = SIZE * scale;
+        let mut image = vec![0; size * size];
+        for (y, row) in image.chunks_exact_mut(size).enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = background[y / scale * SIZE + x / scale];
+            }
+        }
+        (image, size, size)
+    }
 }
 
-fn ppu_gen() -> impl std::ops::Generator<
-    Rc<RefCell<MonochromePpuState>>,
-    Yield = Rc<RefCell<MonochromePpuState>>,
-    Return = !,
-> {
-    |mut ppu: Rc<RefCell<MonochromePpuState>>| loop {
-        let mut frame = Frame {
-            pixels: [0; 144 * 160],
-            width: 160,
-            height: 144,
+impl MonochromePpuState {
+    #[inline(always)]
+    fn set_ly(&mut self, ly: u8) {
+        debug_assert!(ly <= 153);
+        self.ly = ly;
+        self.stat.set(STAT::LYC_EQUALS_LY, self.ly == self.lyc);
+
+        self.update_stat_interrupt();
+    }
+
+    #[inline(always)]
+    fn set_mode(&mut self, mode: u8) {
+        debug_assert!(mode <= 3);
+        self.stat.set_mode(STAT::from_bits_truncate(mode));
+
+        self.update_stat_interrupt();
+    }
+
+    #[inline(always)]
+    fn update_stat_interrupt(&mut self) {
+        let mode = self.stat & !STAT::MODE_BITMASK;
+
+        let mode_int = match mode {
+            STAT::MODE_0 if self.stat.contains(STAT::HBLANK_INTERRUPT_ENABLE) => true,
+            STAT::MODE_1 if self.stat.contains(STAT::VBLANK_INTERRUPT_ENABLE) => true,
+            STAT::MODE_2 if self.stat.contains(STAT::OAM_INTERRUPT_ENABLE) => true,
+            _ => false,
         };
 
-        // Drawing lines
-        for line in 0..144 {
-            ppu.borrow_mut().set_ly(line);
+        let lyc_int = self
+            .stat
+            .contains(STAT::LYC_INTERRUPT_ENABLE | STAT::LYC_EQUALS_LY);
 
-            let mut cycle = 0;
-            // OAM Search (mode 2)
-            ppu.borrow_mut().set_mode(2);
-            for _ in 0..80 {
-                cycle += 1;
-                ppu = yield ppu;
+        self.stat_irq = mode_int | lyc_int;
+    }
+
+    pub(crate) fn view(&self) -> LineView<'_> {
+        LineView {
+            tile_data: &self.tile_data,
+            bg_map_1: &self.bg_map_1,
+            bg_map_2: &self.bg_map_2,
+            lcdc: self.lcdc,
+            scy: self.scy,
+            scx: self.scx,
+            wx: self.wx,
+            // A hidden window still runs, so its timing and line counter are unchanged. `pixel` masks it out.
+            window_line: (self.window_triggered && self.lcdc.contains(LCDC::WINDOW_ENABLE))
+                .then_some(self.window_line),
+            bgp: self.bgp,
+            obp0: self.obp0,
+            obp1: self.obp1,
+            shades: &self.shades,
+            hidden_layers: self.hidden_layers,
+        }
+    }
+
+    /// The copy of VRAM for the renderer, which is only made again after VRAM has been written
+    fn shared_vram(&mut self) -> Arc<VramSnapshot> {
+        if self.vram_snapshot.is_none() {
+            self.vram_snapshot = Some(VramSnapshot::new(self));
+        }
+        self.vram_snapshot.clone().unwrap()
+    }
+
+    /// Advance by one dot
+    fn tick(&mut self) {
+        match self.step {
+            Step::OamScan => {
+                if self.line_cycle == 0 {
+                    if self.line == 0 {
+                        self.vblank_irq = false;
+                        self.window_triggered = false;
+                        self.window_line = 0;
+                    }
+                    if self.line == self.wy {
+                        self.window_triggered = true;
+                    }
+                    self.set_ly(self.line);
+                    self.set_mode(2);
+                    self.line_objects = object::scan_line(
+                        &self.oam,
+                        self.line,
+                        self.view().object_height(),
+                        self.object_priority,
+                    );
+                    if let Some(objects) = self.next_scanline_objects.get_mut(self.line as usize) {
+                        *objects = self.line_objects;
+                    }
+                }
+                if self.line_cycle == 79 {
+                    self.step = Step::Drawing(Fetcher {
+                        dot: 0,
+                        x: 0,
+                        screen_tile_x: 0,
+                        tile_row: (0, 0),
+                        window: false,
+                    });
+                }
             }
 
-            // Drawing (mode 3)
-            // TODO: this only draws the background for now
-            ppu.borrow_mut().set_mode(3);
-            let mut dot = 0;
-            let mut screen_tile_x = 0;
-            let mut x = ppu.borrow().scx;
-            while dot < 160 {
-                let (bg_fifo_lo, bg_fifo_hi) = {
-                    let ppu = ppu.borrow();
-                    let tilemap = if ppu.lcdc.contains(LCDC::BG_TILEMAP_AREA) {
-                        &ppu.bg_map_2
+            Step::Drawing(mut fetcher) => {
+                if fetcher.dot == 0 {
+                    self.set_mode(3);
+                    if let Some(renderer) = self.renderer.clone() {
+                        let objects = core::mem::take(&mut self.line_objects);
+                        let vram = self.shared_vram();
+                        renderer.render(LineSnapshot::new(self, vram, self.line, objects));
+                    }
+                    fetcher.x = self.scx % 8;
+                    fetcher.tile_row = self.view().bg_tile_row(self.line, 0);
+                } else if fetcher.x == 8 {
+                    fetcher.x = 0;
+                    fetcher.screen_tile_x += 1;
+                    fetcher.tile_row = if fetcher.window {
+                        self.view().window_tile_row(fetcher.screen_tile_x)
                     } else {
-                        &ppu.bg_map_1
+                        self.view().bg_tile_row(self.line, fetcher.screen_tile_x)
                     };
-                    let tile_data = &ppu.tile_data;
+                }
+                if let Some((start, start_x)) = self.view().window_start() {
+                    if !fetcher.window && fetcher.dot == start {
+                        fetcher.window = true;
+                        fetcher.x = start_x;
+                        fetcher.screen_tile_x = 0;
+                        fetcher.tile_row = self.view().window_tile_row(0);
+                    }
+                }
 
-                    let fetcher_x = ((ppu.scx / 8) + screen_tile_x) & 0x1F;
-                    let fetcher_y = ppu.scy.wrapping_add(line) / 8;
-                    let tile_idx = tilemap[fetcher_y as usize * 32 + fetcher_x as usize];
+                if self.renderer.is_none() {
+                    let (lo, hi) = fetcher.tile_row;
+                    let bg_color = tile_row_color(lo, hi, fetcher.x);
+                    let color = self.view().pixel(
+                        &self.line_objects,
+                        self.line,
+                        fetcher.dot,
+                        bg_color,
+                        fetcher.window,
+                    );
+                    self.next_frame.pixels[160 * self.line as usize + fetcher.dot as usize] = color;
+                }
+                fetcher.x += 1;
+                fetcher.dot += 1;
 
-                    let tile_y = ppu.scy.wrapping_add(line) % 8;
-                    if ppu.lcdc.contains(LCDC::BG_TILE_DATA_AREA) {
-                        // $8000 method
-                        let offset = tile_idx as usize * 16 + tile_y as usize * 2;
-                        (tile_data[offset + 0], tile_data[offset + 1])
-                    } else {
-                        // $8800 method
-                        let offset =
-                            (0x1000 + (tile_idx as i8 as i16) * 16 + (tile_y as i16) * 2) as usize;
-                        (tile_data[offset + 0], tile_data[offset + 1])
+                self.step = if fetcher.dot == 160 {
+                    if fetcher.window {
+                        self.window_line += 1;
                     }
+                    Step::HBlank
+                } else {
+                    Step::Drawing(fetcher)
                 };
+            }
 
-                while x < 8 {
-                    let bit = 7 - x;
-                    x += 1;
-                    let bg_color_hi = (bg_fifo_hi >> bit) & 1;
-                    let bg_color_lo = (bg_fifo_lo >> bit) & 1;
-                    let bg_color = (bg_color_hi << 1) | bg_color_lo;
-
-                    let bg_color_rgb =
-                        color::calculate_monochrome_color_id(ppu.borrow().bgp, bg_color);
-                    frame.pixels[160 * line as usize + dot as usize] =
-                        color::COLORS[bg_color_rgb as usize];
-                    dot += 1;
-
-                    cycle += 1;
-                    ppu = yield ppu;
+            Step::HBlank => {
+                if self.line_cycle == 80 + 160 {
+                    self.next_frame.rendered_lines[self.line as usize] = true;
+                    self.set_mode(0);
+                }
+                if self.line_cycle == 455 {
+                    self.line += 1;
+                    self.step = if self.line == 144 {
+                        Step::VBlank
+                    } else {
+                        Step::OamScan
+                    };
                 }
-                x = 0;
-                screen_tile_x += 1;
             }
 
-            // HBlank (mode 0)
-            ppu.borrow_mut().set_mode(0);
-            while cycle < 456 {
-                cycle += 1;
-                ppu = yield ppu;
+            Step::VBlank => {
+                if self.line_cycle == 0 {
+                    if self.line == 144 {
+                        self.finish_frame(false);
+                        self.set_mode(1);
+                        self.vblank_irq = true;
+                    }
+                    self.set_ly(self.line);
+                }
+                if self.line_cycle == 455 {
+                    self.line += 1;
+                    if self.line == 154 {
+                        self.line = 0;
+                        self.step = Step::OamScan;
+                    }
+                }
             }
         }
 
-        ppu.borrow_mut().frame = Rc::new(frame);
+        self.line_cycle = (self.line_cycle + 1) % 456;
+    }
 
-        // VBlank (mode 1)
-        ppu.borrow_mut().set_mode(1);
-        ppu.borrow_mut().vblank_irq = true;
-        for line in 144..154 {
-            ppu.borrow_mut().set_ly(line);
-            for _ in 0usize..456 {
-                ppu = yield ppu;
-            }
+    /// The number of dots from the current one that `tick` would only count, without changing anything else
+    fn quiet_dots(&self) -> u16 {
+        let next_change: u16 = match self.step {
+            Step::OamScan if self.line_cycle == 0 => 0,
+            Step::OamScan => 79,
+            Step::Drawing(_) => return 0,
+            Step::HBlank if self.line_cycle <= 80 + 160 => 80 + 160,
+            Step::HBlank => 455,
+            Step::VBlank if self.line_cycle == 0 => 0,
+            Step::VBlank => 455,
+        };
+        next_change.saturating_sub(self.line_cycle)
+    }
+
+    /// Draw all of the current line at once, in place of the 160 ticks of mode 3. The result is the same as long as
+    /// nothing is written to the registers or VRAM while the line is drawn.
+    fn draw_line(&mut self) {
+        debug_assert!(matches!(self.step, Step::Drawing(Fetcher { dot: 0, .. })));
+        self.set_mode(3);
+        if let Some(renderer) = self.renderer.clone() {
+            let objects = core::mem::take(&mut self.line_objects);
+            let vram = self.shared_vram();
+            renderer.render(LineSnapshot::new(self, vram, self.line, objects));
+        } else {
+            let mut pixels = [0; 160];
+            render_line(&self.view(), &self.line_objects, self.line, &mut pixels);
+            let start = 160 * self.line as usize;
+            self.next_frame.pixels[start..start + 160].copy_from_slice(&pixels);
+        }
+        if self.view().window_start().is_some() {
+            self.window_line += 1;
+        }
+        self.step = Step::HBlank;
+        self.line_cycle += 160;
+    }
+
+    /// Go back to the start of a frame, the way the PPU does when the LCD is switched off
+    fn restart(&mut self) {
+        self.step = Step::OamScan;
+        self.line = 0;
+        self.line_cycle = 0;
+        self.line_objects.clear();
+    }
+
+    /// Publish the frame being drawn, and start a new one
+    fn finish_frame(&mut self, lcd_off: bool) {
+        core::mem::swap(&mut self.frame, &mut self.next_frame);
+        if let Some(renderer) = &self.renderer {
+            self.frame.pixels = *renderer.finish();
+        }
+        self.frame.index = self.frame_count;
+        self.frame.lcd_off = lcd_off;
+        // The previous frame is still in `next_frame` until its buffer is reused below. Frames from while the LCD was
+        // off are all shown blank, whatever was drawn into them.
+        self.frame.repeated = self.next_frame.lcd_off == lcd_off
+            && (lcd_off || self.next_frame.pixels == self.frame.pixels);
+        self.frame_count += 1;
+
+        // Reuse the old frame's buffer rather than allocating a new one
+        self.next_frame.pixels = [0; 144 * 160];
+        self.next_frame.rendered_lines = [false; 144];
+
+        self.scanline_objects = self.next_scanline_objects;
+        self.next_scanline_objects = [LineObjects::new(); 144];
+    }
+
+    /// Read a register or VRAM/OAM without any side effects
+    pub(crate) fn debug_read(&self, addr: u16, data: &mut u8) {
+        match addr {
+            0x8000..=0x97FF => *data = self.tile_data[addr as usize - 0x8000],
+            0x9800..=0x9BFF => *data = self.bg_map_1[addr as usize - 0x9800],
+            0x9C00..=0x9FFF => *data = self.bg_map_2[addr as usize - 0x9C00],
+
+            0xFE00..=0xFE9F => *data = self.oam[addr as usize - 0xFE00],
+
+            0xFF40 => *data = self.lcdc.bits(),
+            0xFF41 => *data = self.stat.bits(),
+            0xFF42 => *data = self.scy,
+            0xFF43 => *data = self.scx,
+            0xFF44 => *data = self.ly,
+            0xFF45 => *data = self.lyc,
+            0xFF47 => *data = self.bgp,
+            0xFF48 => *data = self.obp0,
+            0xFF49 => *data = self.obp1,
+            0xFF4A => *data = self.wy,
+            0xFF4B => *data = self.wx,
+
+            _ => (),
         }
-        ppu.borrow_mut().vblank_irq = false;
     }
 }
 
@@ -302,7 +827,14 @@ impl PPU for MonochromePpu {
 
     #[inline]
     fn perform_io(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8) {
-        let mut state = self.state.borrow_mut();
+        let state = &mut self.state;
+        let mut lcd_switched_off = false;
+        if let CpuOutputPins::Write { addr, .. } = input {
+            state.dirty |= VramRegions::containing(addr);
+            if let 0x8000..=0x9FFF = addr {
+                state.vram_snapshot = None;
+            }
+        }
         match input {
             CpuOutputPins::Write { addr, data: v } => match addr {
                 0x8000..=0x97FF => state.tile_data[addr as usize - 0x8000] = v,
@@ -311,16 +843,22 @@ impl PPU for MonochromePpu {
 
                 0xFE00..=0xFE9F => state.oam[addr as usize - 0xFE00] = v,
 
-                0xFF40 => state.lcdc = LCDC::from_bits_truncate(v),
+                0xFF40 => {
+                    let was_enabled = state.lcdc.contains(LCDC::LCD_ENABLE);
+                    state.lcdc = LCDC::from_bits_truncate(v);
+                    lcd_switched_off = was_enabled && !state.lcdc.contains(LCDC::LCD_ENABLE);
+                }
                 0xFF41 => {
-                    state.stat = STAT::from_bits_truncate(v);
+                    // The mode and the LYC flag are read only
+                    let read_only = STAT::LYC_EQUALS_LY | STAT::MODE_3;
+                    state.stat =
+                        (state.stat & read_only) | (STAT::from_bits_truncate(v) - read_only);
                     state.update_stat_interrupt();
                 }
                 0xFF42 => state.scy = v,
                 0xFF43 => state.scx = v,
                 0xFF44 => state.ly = v,
                 0xFF45 => state.lyc = v,
-                0xFF46 => (),
                 0xFF47 => state.bgp = v,
                 0xFF48 => state.obp0 = v,
                 0xFF49 => state.obp1 = v,
@@ -328,58 +866,128 @@ impl PPU for MonochromePpu {
                 0xFF4B => state.wx = v,
                 _ => (),
             },
-            CpuOutputPins::Read { addr } => match addr {
-                0x8000..=0x97FF => *data = state.tile_data[addr as usize - 0x8000],
-                0x9800..=0x9BFF => *data = state.bg_map_1[addr as usize - 0x9800],
-                0x9C00..=0x9FFF => *data = state.bg_map_2[addr as usize - 0x9C00],
-
-                0xFE00..=0xFE9F => *data = state.oam[addr as usize - 0xFE00],
-
-                0xFF40 => *data = state.lcdc.bits(),
-                0xFF41 => *data = state.stat.bits(),
-                0xFF42 => *data = state.scy,
-                0xFF43 => *data = state.scx,
-                0xFF44 => *data = state.ly,
-                0xFF45 => *data = state.lyc,
-                0xFF46 => *data = 0,
-                0xFF47 => *data = state.bgp,
-                0xFF48 => *data = state.obp0,
-                0xFF49 => *data = state.obp1,
-                0xFF4A => *data = state.wy,
-                0xFF4B => *data = state.wx,
-
-                _ => (),
-            },
+            CpuOutputPins::Read { addr } => state.debug_read(addr, data),
+            CpuOutputPins::Idle => (),
         };
 
-        let mut irq = *interrupt_request;
-        if state.vblank_irq {
-            irq |= 1 << 0;
-        } else {
-            irq &= !(1 << 0);
+        if lcd_switched_off {
+            // The PPU stops in LY 0, mode 0 until the LCD is switched back on, and then starts a new frame
+            state.finish_frame(true);
+            state.vblank_irq = false;
+            state.set_ly(0);
+            state.set_mode(0);
+            state.restart();
         }
 
-        if state.stat_irq {
-            irq |= 1 << 1;
-        } else {
-            irq &= !(1 << 1);
+        // Only request interrupts on a rising edge, so the CPU can acknowledge them while the lines are still high
+        if state.vblank_irq && !state.last_vblank_irq {
+            *interrupt_request |= 1 << 0;
         }
-
-        *interrupt_request = irq;
+        if state.stat_irq && !state.last_stat_irq {
+            *interrupt_request |= 1 << 1;
+        }
+        state.last_vblank_irq = state.vblank_irq;
+        state.last_stat_irq = state.stat_irq;
     }
 
     fn clock_t_state(&mut self) {
-        // im not sure if theres a good way to borrow an object only for the duration of a generator run,
-        // so instead i just clone the state in and out of the generator context. unfortunately this means
-        // i have to use Rc<RefCell> to avoid doing huge copies hundreds of times a second
-        self.state = match self.gen.as_mut().resume(self.state.clone()) {
-            GeneratorState::Yielded(state) => state,
-            GeneratorState::Complete(_) => unreachable!(),
-        };
+        if self.state.lcdc.contains(LCDC::LCD_ENABLE) {
+            self.state.tick();
+        }
+    }
+
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        self.state.debug_read(addr, data)
     }
 
     fn get_frame(&self) -> Frame {
-        *self.state.borrow().frame
+        let frame: &Frame = self.state.frame.borrow();
+        *frame
+    }
+
+    fn frame_count(&self) -> u64 {
+        self.state.frame_count
+    }
+
+    fn write_oam(&mut self, index: u8, data: u8) {
+        self.state.oam[index as usize] = data;
+        self.state.dirty |= VramRegions::OAM;
+    }
+
+    /// The interrupt is requested on the M-cycle after the PPU reaches line 144
+    fn cycles_until_vblank(&self) -> Option<u32> {
+        const VBLANK_DOT: u32 = 144 * 456;
+        let state = &self.state;
+        if state.vblank_irq && !state.last_vblank_irq {
+            return Some(1);
+        }
+        if !state.lcdc.contains(LCDC::LCD_ENABLE) {
+            return None;
+        }
+        // The dot the next T-cycle runs, counted from the start of the frame
+        let dot = state.line as u32 * 456 + state.line_cycle as u32;
+        let dots = (VBLANK_DOT + FRAME_T_CYCLES as u32 - dot) % FRAME_T_CYCLES as u32;
+        Some(dots / 4 + 2)
+    }
+
+    fn stable_cycles(&self) -> Option<u32> {
+        let state = &self.state;
+        if state.vblank_irq != state.last_vblank_irq || state.stat_irq != state.last_stat_irq {
+            return Some(0);
+        }
+        if !state.lcdc.contains(LCDC::LCD_ENABLE) {
+            return None;
+        }
+        // LY and STAT change on the first dot of each line, and of modes 3 and 0. A change on a dot is seen by the
+        // reads of the M-cycles after the one that runs it.
+        let next_change = match state.line_cycle {
+            0 => 0,
+            1..=80 if state.line < 144 => 80,
+            81..=240 if state.line < 144 => 80 + 160,
+            _ => 456,
+        };
+        Some((next_change - state.line_cycle as u32) / 4 + 1)
+    }
+
+    /// Dots where the PPU only counts are skipped four at a time, and mode 3 is drawn a line at a time if there's
+    /// time to finish it. Since nothing is written, the lines can't be changed partway through.
+    fn advance(&mut self, max_cycles: u32, interrupt_request: &mut u8, wake: u8) -> u32 {
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            self.perform_io(CpuOutputPins::Idle, &mut 0xFF, interrupt_request);
+            let woken = *interrupt_request & wake != 0;
+            let state = &mut self.state;
+            let quiet = (state.quiet_dots() / 4) as u32;
+            if !state.lcdc.contains(LCDC::LCD_ENABLE) {
+                // Nothing changes until the LCD is switched back on, which needs a write
+                cycles += if woken { 1 } else { max_cycles - cycles };
+            } else if quiet > 0 {
+                // The interrupt lines can't change on these cycles, so the edges checked above are all there is
+                let skipped = if woken {
+                    1
+                } else {
+                    quiet.min(max_cycles - cycles)
+                };
+                state.line_cycle += 4 * skipped as u16;
+                cycles += skipped;
+            } else if !woken
+                && max_cycles - cycles >= 160 / 4
+                && matches!(state.step, Step::Drawing(Fetcher { dot: 0, .. }))
+            {
+                // Mode 3 can only lower the STAT interrupt line, which requests nothing
+                state.draw_line();
+                cycles += 160 / 4;
+            } else {
+                for _ in 0..4 {
+                    state.tick();
+                }
+                cycles += 1;
+            }
+            if woken {
+                break;
+            }
+        }
+        cycles
     }
 }
 
@@ -388,9 +996,74 @@ pub mod color {
     pub const COLOR_DARKGRAY: u32 = 0xFF777777;
     pub const COLOR_LIGHTGRAY: u32 = 0xFFAAAAAA;
     pub const COLOR_WHITE: u32 = 0xFFFFFFFF;
+    /// Not a Gameboy color, for drawing on top of debug views
+    pub const COLOR_RED: u32 = 0xFFFF0000;
 
     pub const COLORS: [u32; 4] = [COLOR_WHITE, COLOR_LIGHTGRAY, COLOR_DARKGRAY, COLOR_BLACK];
 
+    /// Shades for each of the three palettes, from lightest to darkest
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Scheme {
+        pub name: &'static str,
+        pub bg: [u32; 4],
+        pub obj0: [u32; 4],
+        pub obj1: [u32; 4],
+    }
+
+    const HIGH_CONTRAST: [u32; 4] = [0xFFFFFFFF, 0xFFB4B4B4, 0xFF5A5A5A, 0xFF000000];
+    const BLUES: [u32; 4] = [0xFFE8F1FA, 0xFF8DB8E0, 0xFF2F6DA8, 0xFF0B1F3A];
+    const ORANGES: [u32; 4] = [0xFFFFF1E0, 0xFFF5B461, 0xFFB8650C, 0xFF3D1F00];
+    const TEALS: [u32; 4] = [0xFFE6F7F5, 0xFF7CC9BE, 0xFF1F7A70, 0xFF062A26];
+    const PINKS: [u32; 4] = [0xFFFBE8EE, 0xFFE68AA8, 0xFF9E2A52, 0xFF33081A];
+
+    /// The built-in color schemes, starting with the usual grays. Each palette's shades get evenly darker, so they
+    /// can be told apart by lightness alone, and the schemes with hues only use ones that color-blind players can
+    /// still tell apart.
+    pub const SCHEMES: &[Scheme] = &[
+        Scheme {
+            name: "gray",
+            bg: COLORS,
+            obj0: COLORS,
+            obj1: COLORS,
+        },
+        Scheme {
+            name: "high-contrast",
+            bg: HIGH_CONTRAST,
+            obj0: HIGH_CONTRAST,
+            obj1: HIGH_CONTRAST,
+        },
+        // Blue and orange stay distinct with red-green color blindness (deuteranopia and protanopia)
+        Scheme {
+            name: "blue-orange",
+            bg: BLUES,
+            obj0: ORANGES,
+            obj1: HIGH_CONTRAST,
+        },
+        // Pink and teal stay distinct with blue-yellow color blindness (tritanopia)
+        Scheme {
+            name: "pink-teal",
+            bg: TEALS,
+            obj0: PINKS,
+            obj1: HIGH_CONTRAST,
+        },
+    ];
+
+    /// One of the [`SCHEMES`], by name
+    pub fn scheme(name: &str) -> Option<&'static Scheme> {
+        SCHEMES.iter().find(|scheme| scheme.name == name)
+    }
+
+    /// One of the DMG's three palettes
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Palette {
+        /// BGP, used by the background and window
+        Bg,
+        /// OBP0
+        Obj0,
+        /// OBP1
+        Obj1,
+    }
+
     pub fn calculate_monochrome_color_id(palette: u8, pix: u8) -> usize {
         assert!(pix < 4);
         ((palette >> (pix * 2)) & 0x03) as usize
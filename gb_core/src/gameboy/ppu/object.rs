@@ -0,0 +1,124 @@
+//! Objects (sprites) and the rules for which one is drawn when they overlap
+
+use core::ops::{Deref, DerefMut};
+
+/// How overlapping objects are layered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjectPriority {
+    /// The object with the smaller X coordinate is drawn on top, and the one earlier in OAM wins ties (DMG)
+    XCoordinate,
+    /// The object earlier in OAM is drawn on top (CGB)
+    OamIndex,
+}
+
+/// The maximum number of objects drawn on one line
+pub const OBJECTS_PER_LINE: usize = 10;
+
+bitflags::bitflags! {
+    pub struct ObjectAttributes: u8 {
+        const BG_PRIORITY = 0x80;
+        const Y_FLIP = 0x40;
+        const X_FLIP = 0x20;
+        const DMG_PALETTE = 0x10;
+    }
+}
+
+/// An entry in OAM
+#[derive(Clone, Copy, Debug)]
+pub struct Object {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub attributes: ObjectAttributes,
+    pub oam_index: u8,
+}
+
+impl Object {
+    /// Whether the object covers the pixel at screen X coordinate `x`
+    pub fn covers_x(&self, x: u8) -> bool {
+        let x = x as u16 + 8;
+        (self.x as u16..self.x as u16 + 8).contains(&x)
+    }
+}
+
+/// Decode every entry in OAM, in order
+pub fn entries(oam: &[u8]) -> impl Iterator<Item = Object> + '_ {
+    oam.chunks_exact(4).enumerate().map(|(i, entry)| Object {
+        y: entry[0],
+        x: entry[1],
+        tile: entry[2],
+        attributes: ObjectAttributes::from_bits_truncate(entry[3]),
+        oam_index: i as u8,
+    })
+}
+
+/// The objects found on one line. There are never more than [`OBJECTS_PER_LINE`], so they're kept in an array and
+/// scanning a line doesn't allocate.
+#[derive(Clone, Copy, Debug)]
+pub struct LineObjects {
+    objects: [Object; OBJECTS_PER_LINE],
+    len: usize,
+}
+
+impl LineObjects {
+    pub const fn new() -> Self {
+        const EMPTY: Object = Object {
+            y: 0,
+            x: 0,
+            tile: 0,
+            attributes: ObjectAttributes::empty(),
+            oam_index: 0,
+        };
+        LineObjects {
+            objects: [EMPTY; OBJECTS_PER_LINE],
+            len: 0,
+        }
+    }
+
+    /// Add an object after the others. Objects past the limit are ignored, like on the real hardware.
+    pub fn push(&mut self, object: Object) {
+        if self.len < OBJECTS_PER_LINE {
+            self.objects[self.len] = object;
+            self.len += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Default for LineObjects {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for LineObjects {
+    type Target = [Object];
+    fn deref(&self) -> &[Object] {
+        &self.objects[..self.len]
+    }
+}
+
+impl DerefMut for LineObjects {
+    fn deref_mut(&mut self) -> &mut [Object] {
+        &mut self.objects[..self.len]
+    }
+}
+
+/// Select the objects on line `ly` the way the PPU's OAM scan does, ordered from highest to lowest priority
+pub fn scan_line(oam: &[u8], ly: u8, height: u8, priority: ObjectPriority) -> LineObjects {
+    let line = ly as u16 + 16;
+    let mut objects = LineObjects::new();
+    entries(oam)
+        .filter(|object| (object.y as u16..object.y as u16 + height as u16).contains(&line))
+        .take(OBJECTS_PER_LINE)
+        .for_each(|object| objects.push(object));
+
+    if priority == ObjectPriority::XCoordinate {
+        // The sort is stable, so ties stay in OAM order
+        objects.sort_by_key(|object| object.x);
+    }
+    objects
+}
@@ -0,0 +1,202 @@
+//! Turning VRAM and the PPU registers into pixels, shared by the PPU and the threaded renderer
+
+use super::{
+    monochrome::{
+        color::{self, Palette},
+        Layers,
+    },
+    object::{Object, ObjectAttributes},
+    registers::LCDC,
+};
+
+/// Everything needed to work out the color of a pixel
+pub(crate) struct LineView<'a> {
+    pub tile_data: &'a [u8; 0x9800 - 0x8000],
+    pub bg_map_1: &'a [u8; 0x9C00 - 0x9800],
+    pub bg_map_2: &'a [u8; 0xA000 - 0x9C00],
+    pub lcdc: LCDC,
+    pub scy: u8,
+    pub scx: u8,
+    pub wx: u8,
+    /// The line of the window to draw, or `None` if the window isn't shown on this line
+    pub window_line: Option<u8>,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    /// The colors each palette's shades are drawn as, indexed by `color::Palette`
+    pub shades: &'a [[u32; 4]; 3],
+    pub hidden_layers: Layers,
+}
+
+impl LineView<'_> {
+    pub fn object_height(&self) -> u8 {
+        if self.lcdc.contains(LCDC::OBJ_SIZE) {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Fetch the low and high bytes of the background tile row under the `screen_tile_x`th tile of `line`
+    pub fn bg_tile_row(&self, line: u8, screen_tile_x: u8) -> (u8, u8) {
+        let map_x = (self.scx / 8).wrapping_add(screen_tile_x);
+        let map_y = self.scy.wrapping_add(line);
+        self.tile_row(self.lcdc.contains(LCDC::BG_TILEMAP_AREA), map_x, map_y)
+    }
+
+    /// Fetch the low and high bytes of a tile row anywhere in the background's tile map, ignoring scrolling.
+    /// `map_tile_x` is in tiles, and `map_y` in pixels.
+    pub fn bg_map_tile_row(&self, map_tile_x: u8, map_y: u8) -> (u8, u8) {
+        self.tile_row(self.lcdc.contains(LCDC::BG_TILEMAP_AREA), map_tile_x, map_y)
+    }
+
+    /// Fetch the low and high bytes of the `window_tile_x`th tile of the window row being drawn
+    pub fn window_tile_row(&self, window_tile_x: u8) -> (u8, u8) {
+        let map_y = self.window_line.unwrap_or(0);
+        self.tile_row(
+            self.lcdc.contains(LCDC::WINDOW_TILEMAP_AREA),
+            window_tile_x,
+            map_y,
+        )
+    }
+
+    /// The first dot of the line covered by the window, and the pixel of the window's first tile drawn there.
+    /// The window starts at WX - 7, and is cut off on the left if WX is below 7.
+    pub fn window_start(&self) -> Option<(u8, u8)> {
+        self.window_line?;
+        if self.wx >= 167 {
+            return None;
+        }
+        Some((self.wx.saturating_sub(7), 7u8.saturating_sub(self.wx)))
+    }
+
+    /// Fetch a tile row from one of the tile maps. `map_x` is in tiles, and `map_y` in pixels.
+    fn tile_row(&self, high_map: bool, map_x: u8, map_y: u8) -> (u8, u8) {
+        let tilemap = if high_map {
+            self.bg_map_2
+        } else {
+            self.bg_map_1
+        };
+
+        let tile_idx = tilemap[(map_y / 8) as usize * 32 + (map_x & 0x1F) as usize];
+
+        let tile_y = map_y % 8;
+        let offset = if self.lcdc.contains(LCDC::BG_TILE_DATA_AREA) {
+            // $8000 method
+            tile_idx as usize * 16 + tile_y as usize * 2
+        } else {
+            // $8800 method
+            (0x1000 + (tile_idx as i8 as i16) * 16 + (tile_y as i16) * 2) as usize
+        };
+        (self.tile_data[offset], self.tile_data[offset + 1])
+    }
+
+    /// Find the color ID and attributes of the highest priority non-transparent object pixel at (`x`, `line`)
+    pub fn object_pixel(
+        &self,
+        objects: &[Object],
+        line: u8,
+        x: u8,
+    ) -> Option<(u8, ObjectAttributes)> {
+        let height = self.object_height();
+        objects
+            .iter()
+            .filter(|object| object.covers_x(x))
+            .find_map(|object| {
+                let mut row = (line as u16 + 16 - object.y as u16) as usize;
+                if object.attributes.contains(ObjectAttributes::Y_FLIP) {
+                    row = height as usize - 1 - row;
+                }
+                let tile = if height == 16 {
+                    object.tile & 0xFE
+                } else {
+                    object.tile
+                };
+                // Objects always use the $8000 method
+                let offset = tile as usize * 16 + row * 2;
+                let (lo, hi) = (self.tile_data[offset], self.tile_data[offset + 1]);
+
+                let column = x + 8 - object.x;
+                let bit = if object.attributes.contains(ObjectAttributes::X_FLIP) {
+                    column
+                } else {
+                    7 - column
+                };
+                let color = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
+                if color != 0 {
+                    Some((color, object.attributes))
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Mix the background color ID `bg_color`, which is from the window if `window` is set, with any objects at
+    /// (`x`, `line`), and return the final color
+    pub fn pixel(&self, objects: &[Object], line: u8, x: u8, bg_color: u8, window: bool) -> u32 {
+        let layer = if window {
+            Layers::WINDOW
+        } else {
+            Layers::BACKGROUND
+        };
+        let bg_color = if self.hidden_layers.contains(layer) {
+            0
+        } else {
+            bg_color
+        };
+
+        let mut palette = Palette::Bg;
+        let mut shade = color::calculate_monochrome_color_id(self.bgp, bg_color);
+        if self.lcdc.contains(LCDC::OBJ_ENABLE) && !self.hidden_layers.contains(Layers::OBJECTS) {
+            if let Some((obj_color, attributes)) = self.object_pixel(objects, line, x) {
+                if !attributes.contains(ObjectAttributes::BG_PRIORITY) || bg_color == 0 {
+                    let register = if attributes.contains(ObjectAttributes::DMG_PALETTE) {
+                        palette = Palette::Obj1;
+                        self.obp1
+                    } else {
+                        palette = Palette::Obj0;
+                        self.obp0
+                    };
+                    shade = color::calculate_monochrome_color_id(register, obj_color);
+                }
+            }
+        }
+        self.shades[palette as usize][shade]
+    }
+}
+
+/// The color ID of pixel `x` (0 is the leftmost) of a tile row
+pub(crate) fn tile_row_color(lo: u8, hi: u8, x: u8) -> u8 {
+    let bit = 7 - x;
+    (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1)
+}
+
+/// Draw all of `line` at once into `pixels`
+pub(crate) fn render_line(view: &LineView, objects: &[Object], line: u8, pixels: &mut [u32; 160]) {
+    let window_start = view.window_start();
+    let mut window = false;
+    let mut tile_x = 0;
+    let mut x = view.scx % 8;
+    let (mut lo, mut hi) = view.bg_tile_row(line, 0);
+    for dot in 0..160 {
+        if x == 8 {
+            x = 0;
+            tile_x += 1;
+            (lo, hi) = if window {
+                view.window_tile_row(tile_x)
+            } else {
+                view.bg_tile_row(line, tile_x)
+            };
+        }
+        if let Some((start, start_x)) = window_start {
+            if !window && dot == start {
+                window = true;
+                x = start_x;
+                tile_x = 0;
+                (lo, hi) = view.window_tile_row(0);
+            }
+        }
+        pixels[dot as usize] = view.pixel(objects, line, dot, tile_row_color(lo, hi, x), window);
+        x += 1;
+    }
+}
@@ -0,0 +1,196 @@
+//! Drawing scanlines on a worker thread
+//!
+//! At the start of mode 3 the PPU copies the registers that affect drawing, and hands them to a worker thread which
+//! draws the whole line. The PPU's timing doesn't depend on the pixels it draws, so the emulation thread only has to
+//! wait for the worker when a frame is finished.
+//!
+//! VRAM is copied on write: lines share the same [`VramSnapshot`] until VRAM is written, so a game that only changes
+//! VRAM during VBlank costs one copy a frame rather than one a line.
+//!
+//! The catch is that writes to VRAM or the registers during mode 3 don't affect the line being drawn, which a few
+//! games rely on for raster effects.
+//!
+//! Without the `std` feature there are no threads, so lines are always drawn by the PPU itself.
+
+#[cfg(feature = "std")]
+use super::render::render_line;
+use alloc::{boxed::Box, sync::Arc};
+#[cfg(feature = "std")]
+use core::convert::TryInto;
+#[cfg(feature = "std")]
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::JoinHandle,
+};
+
+use super::{
+    monochrome::{Layers, MonochromePpuState},
+    object::LineObjects,
+    registers::LCDC,
+    render::LineView,
+};
+
+type Pixels = [u32; 144 * 160];
+
+/// A copy of VRAM, shared by every line drawn before VRAM is next written
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) struct VramSnapshot {
+    tile_data: [u8; 0x9800 - 0x8000],
+    bg_map_1: [u8; 0x9C00 - 0x9800],
+    bg_map_2: [u8; 0xA000 - 0x9C00],
+}
+
+impl VramSnapshot {
+    pub fn new(state: &MonochromePpuState) -> Arc<Self> {
+        Arc::new(VramSnapshot {
+            tile_data: state.tile_data,
+            bg_map_1: state.bg_map_1,
+            bg_map_2: state.bg_map_2,
+        })
+    }
+}
+
+/// A copy of everything needed to draw one line
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) struct LineSnapshot {
+    line: u8,
+    objects: LineObjects,
+
+    vram: Arc<VramSnapshot>,
+    lcdc: LCDC,
+    scy: u8,
+    scx: u8,
+    wx: u8,
+    window_line: Option<u8>,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    shades: [[u32; 4]; 3],
+    hidden_layers: Layers,
+}
+
+impl LineSnapshot {
+    pub fn new(
+        state: &MonochromePpuState,
+        vram: Arc<VramSnapshot>,
+        line: u8,
+        objects: LineObjects,
+    ) -> Box<Self> {
+        Box::new(LineSnapshot {
+            line,
+            objects,
+
+            vram,
+            lcdc: state.lcdc,
+            scy: state.scy,
+            scx: state.scx,
+            wx: state.wx,
+            window_line: state.view().window_line,
+            bgp: state.bgp,
+            obp0: state.obp0,
+            obp1: state.obp1,
+            shades: *state.view().shades,
+            hidden_layers: state.view().hidden_layers,
+        })
+    }
+
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    fn view(&self) -> LineView<'_> {
+        LineView {
+            tile_data: &self.vram.tile_data,
+            bg_map_1: &self.vram.bg_map_1,
+            bg_map_2: &self.vram.bg_map_2,
+            lcdc: self.lcdc,
+            scy: self.scy,
+            scx: self.scx,
+            wx: self.wx,
+            window_line: self.window_line,
+            bgp: self.bgp,
+            obp0: self.obp0,
+            obp1: self.obp1,
+            shades: &self.shades,
+            hidden_layers: self.hidden_layers,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+enum Job {
+    Line(Box<LineSnapshot>),
+    /// Send back the pixels drawn so far, and start a new frame
+    Finish,
+}
+
+#[cfg(feature = "std")]
+pub(crate) struct ThreadedRenderer {
+    jobs: Sender<Job>,
+    frames: Receiver<Box<Pixels>>,
+    _worker: JoinHandle<()>,
+}
+
+#[cfg(feature = "std")]
+impl ThreadedRenderer {
+    pub fn new() -> Self {
+        let (jobs, job_receiver) = channel();
+        let (frame_sender, frames) = channel();
+
+        let worker = std::thread::Builder::new()
+            .name("ppu renderer".to_owned())
+            .spawn(move || {
+                let mut pixels: Box<Pixels> = Box::new([0; 144 * 160]);
+                // Stops when the renderer is dropped
+                while let Ok(job) = job_receiver.recv() {
+                    match job {
+                        Job::Line(snapshot) => {
+                            let start = snapshot.line as usize * 160;
+                            let line = (&mut pixels[start..start + 160]).try_into().unwrap();
+                            render_line(&snapshot.view(), &snapshot.objects, snapshot.line, line);
+                        }
+                        Job::Finish => {
+                            let frame = core::mem::replace(&mut pixels, Box::new([0; 144 * 160]));
+                            if frame_sender.send(frame).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            })
+            .expect("Couldn't start the renderer thread");
+
+        ThreadedRenderer {
+            jobs,
+            frames,
+            _worker: worker,
+        }
+    }
+
+    /// Queue a line to be drawn
+    pub fn render(&self, snapshot: Box<LineSnapshot>) {
+        self.jobs
+            .send(Job::Line(snapshot))
+            .expect("The renderer thread stopped");
+    }
+
+    /// Wait for every queued line to be drawn, and return the frame
+    pub fn finish(&self) -> Box<Pixels> {
+        self.jobs
+            .send(Job::Finish)
+            .expect("The renderer thread stopped");
+        self.frames.recv().expect("The renderer thread stopped")
+    }
+}
+
+/// A renderer that can never be created, so the PPU always draws lines itself
+#[cfg(not(feature = "std"))]
+pub(crate) enum ThreadedRenderer {}
+
+#[cfg(not(feature = "std"))]
+impl ThreadedRenderer {
+    pub fn render(&self, _snapshot: Box<LineSnapshot>) {
+        match *self {}
+    }
+
+    pub fn finish(&self) -> Box<Pixels> {
+        match *self {}
+    }
+}
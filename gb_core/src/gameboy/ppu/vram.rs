@@ -0,0 +1,77 @@
+//! Typed views of VRAM, for debug tools and tests
+
+use super::render::tile_row_color;
+
+/// The number of tiles in VRAM ($8000-$97FF)
+pub const TILE_COUNT: usize = 384;
+
+/// A decoded 8x8 tile
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    /// The color ID (0-3, before the palette is applied) of each pixel, indexed by `[y][x]`
+    pub pixels: [[u8; 8]; 8],
+}
+
+impl Tile {
+    /// Decode a tile from its 16 bytes in VRAM: a low byte and a high byte for each row
+    pub fn decode(data: &[u8; 16]) -> Self {
+        let mut pixels = [[0; 8]; 8];
+        for (row, bytes) in pixels.iter_mut().zip(data.chunks_exact(2)) {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = tile_row_color(bytes[0], bytes[1], x as u8);
+            }
+        }
+        Tile { pixels }
+    }
+}
+
+/// One of the two 32x32 tile maps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BgMap {
+    /// $9800-$9BFF
+    Low,
+    /// $9C00-$9FFF
+    High,
+}
+
+bitflags::bitflags! {
+    /// Parts of VRAM and OAM, for tracking which ones were written. See `MonochromePpu::take_dirty`.
+    pub struct VramRegions: u8 {
+        /// Tiles 0-127, at $8000-$87FF
+        const TILE_BLOCK_0 = 0x01;
+        /// Tiles 128-255, at $8800-$8FFF
+        const TILE_BLOCK_1 = 0x02;
+        /// Tiles 256-383, at $9000-$97FF
+        const TILE_BLOCK_2 = 0x04;
+        const BG_MAP_LOW = 0x08;
+        const BG_MAP_HIGH = 0x10;
+        const OAM = 0x20;
+
+        const TILE_DATA = Self::TILE_BLOCK_0.bits | Self::TILE_BLOCK_1.bits | Self::TILE_BLOCK_2.bits;
+        const BG_MAPS = Self::BG_MAP_LOW.bits | Self::BG_MAP_HIGH.bits;
+    }
+}
+
+impl VramRegions {
+    /// The region `addr` is in, if any
+    pub fn containing(addr: u16) -> Self {
+        match addr {
+            0x8000..=0x87FF => VramRegions::TILE_BLOCK_0,
+            0x8800..=0x8FFF => VramRegions::TILE_BLOCK_1,
+            0x9000..=0x97FF => VramRegions::TILE_BLOCK_2,
+            0x9800..=0x9BFF => VramRegions::BG_MAP_LOW,
+            0x9C00..=0x9FFF => VramRegions::BG_MAP_HIGH,
+            0xFE00..=0xFE9F => VramRegions::OAM,
+            _ => VramRegions::empty(),
+        }
+    }
+}
+
+impl From<BgMap> for VramRegions {
+    fn from(map: BgMap) -> Self {
+        match map {
+            BgMap::Low => VramRegions::BG_MAP_LOW,
+            BgMap::High => VramRegions::BG_MAP_HIGH,
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! The serial port, without a link cable partner
+//!
+//! The bytes the game transmits are recorded until a frontend takes them. Test ROMs use this to report their results, which makes the serial
+//! port the easiest way to check them without looking at the screen.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::cpu::CpuOutputPins;
+
+use super::Chip;
+
+/// M-cycles taken to shift out one byte using the internal clock (8192 Hz)
+const TRANSFER_CYCLES: u16 = 8 * 128;
+
+/// The most transmitted bytes kept until they're taken with `Serial::take_output`
+pub const MAX_OUTPUT: usize = 0x10000;
+
+#[derive(Default, Debug)]
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    /// M-cycles until the current transfer finishes
+    transfer_cycles: u16,
+    output: Vec<u8>,
+}
+
+impl Serial {
+    /// The bytes transmitted since the last `take_output`. Once there are `MAX_OUTPUT` of them the older half is dropped,
+    /// so a game that keeps transmitting doesn't fill up memory when nothing takes them.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Whether a transfer using the internal clock is in progress
+    pub(crate) fn transferring(&self) -> bool {
+        self.transfer_cycles > 0
+    }
+
+    /// Returns every byte transmitted since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.output)
+    }
+}
+
+impl Chip for Serial {
+    fn clock(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8) {
+        match input {
+            CpuOutputPins::Read { addr } => self.debug_read(addr, data),
+            CpuOutputPins::Write {
+                addr: 0xFF01,
+                data: v,
+            } => self.sb = v,
+            CpuOutputPins::Write {
+                addr: 0xFF02,
+                data: v,
+            } => {
+                self.sc = v & 0x81;
+                // Transfers using an external clock never finish, since there is nothing on the other end
+                if self.sc == 0x81 {
+                    self.transfer_cycles = TRANSFER_CYCLES;
+                }
+            }
+            _ => (),
+        }
+
+        if self.transfer_cycles > 0 {
+            self.transfer_cycles -= 1;
+            if self.transfer_cycles == 0 {
+                if self.output.len() == MAX_OUTPUT {
+                    self.output.drain(..MAX_OUTPUT / 2);
+                }
+                self.output.push(self.sb);
+                // With no partner, 1s are shifted in
+                self.sb = 0xFF;
+                self.sc &= 0x7F;
+                *interrupt_request |= 1 << 3;
+            }
+        }
+    }
+
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        match addr {
+            0xFF01 => *data = self.sb,
+            0xFF02 => *data = self.sc | 0x7E,
+            _ => (),
+        }
+    }
+}
+
+/// The result reported by a test ROM over the serial port
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestVerdict {
+    Passed,
+    Failed,
+}
+
+/// Look for the result of a test ROM in its serial output.
+///
+/// Understands Blargg's test ROMs, which print "Passed" or "Failed", and Mooneye's, which send the Fibonacci
+/// sequence 3, 5, 8, 13, 21, 34 on success and six $42 bytes on failure.
+pub fn test_verdict(output: &[u8]) -> Option<TestVerdict> {
+    const MOONEYE_PASS: &[u8] = &[3, 5, 8, 13, 21, 34];
+    const MOONEYE_FAIL: &[u8] = &[0x42; 6];
+
+    if output.ends_with(MOONEYE_PASS) {
+        return Some(TestVerdict::Passed);
+    }
+    if output.ends_with(MOONEYE_FAIL) {
+        return Some(TestVerdict::Failed);
+    }
+
+    let text = String::from_utf8_lossy(output);
+    if text.contains("Passed") {
+        Some(TestVerdict::Passed)
+    } else if text.contains("Failed") {
+        Some(TestVerdict::Failed)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,203 @@
+//! An editable, frame-indexed input timeline for TAS tools
+//!
+//! Each frame of the timeline holds the buttons held during that frame. Alongside the inputs the timeline keeps a
+//! greenzone: states recorded while playing frames whose inputs haven't changed since. Editing a frame drops every
+//! state from that frame on, so a frontend can find the latest state still valid and re-simulate from there.
+//!
+//! Bookmarks are named points on the timeline, each with the state at its frame and a copy of the inputs it was set
+//! with. Jumping to a bookmark only needs its state; branching from one also brings back its inputs, so a different
+//! route can be tried from there while the bookmark keeps the old one.
+//!
+//! The greenzone is generic over the state type. Until the emulator has save states, frame hashes are a useful
+//! stand-in that at least lets a tool notice when a replay diverges.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use super::joypad::ButtonState;
+
+#[derive(Clone, Debug)]
+pub struct InputTimeline<S> {
+    inputs: Vec<ButtonState>,
+    /// States recorded at the start of a frame, keyed by that frame
+    greenzone: BTreeMap<usize, S>,
+    bookmarks: BTreeMap<String, Bookmark<S>>,
+}
+
+/// A named point on a timeline that can be returned to
+#[derive(Clone, Debug)]
+pub struct Bookmark<S> {
+    pub frame: usize,
+    /// The state at the start of `frame`
+    pub state: S,
+    /// The inputs of every frame before `frame` when the bookmark was set
+    inputs: Vec<ButtonState>,
+}
+
+impl<S> Bookmark<S> {
+    pub fn inputs(&self) -> &[ButtonState] {
+        &self.inputs
+    }
+}
+
+impl<S> Default for InputTimeline<S> {
+    fn default() -> Self {
+        InputTimeline {
+            inputs: Vec::new(),
+            greenzone: BTreeMap::new(),
+            bookmarks: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S> InputTimeline<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_inputs(inputs: Vec<ButtonState>) -> Self {
+        InputTimeline {
+            inputs,
+            greenzone: BTreeMap::new(),
+            bookmarks: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn inputs(&self) -> &[ButtonState] {
+        &self.inputs
+    }
+
+    /// The input of `frame`. Frames past the end of the timeline have no buttons held.
+    pub fn get(&self, frame: usize) -> ButtonState {
+        self.inputs.get(frame).copied().unwrap_or_default()
+    }
+
+    /// Change the input of `frame`, extending the timeline with empty frames if needed
+    pub fn set(&mut self, frame: usize, input: ButtonState) {
+        if frame < self.inputs.len() && self.inputs[frame] == input {
+            return;
+        }
+        if frame >= self.inputs.len() {
+            self.inputs.resize(frame + 1, ButtonState::empty());
+        }
+        self.inputs[frame] = input;
+        self.invalidate_from(frame + 1);
+    }
+
+    /// Insert a frame before `frame`, shifting the following frames later
+    pub fn insert(&mut self, frame: usize, input: ButtonState) {
+        if frame > self.inputs.len() {
+            self.inputs.resize(frame, ButtonState::empty());
+        }
+        self.inputs.insert(frame, input);
+        self.invalidate_from(frame + 1);
+    }
+
+    /// Remove `frame`, shifting the following frames earlier
+    pub fn delete(&mut self, frame: usize) {
+        if frame < self.inputs.len() {
+            self.inputs.remove(frame);
+            self.invalidate_from(frame + 1);
+        }
+    }
+
+    pub fn push(&mut self, input: ButtonState) {
+        self.inputs.push(input);
+    }
+
+    /// Record the state at the start of `frame`, reached by playing every frame before it from the timeline
+    pub fn record_state(&mut self, frame: usize, state: S) {
+        self.greenzone.insert(frame, state);
+    }
+
+    /// The state recorded at the start of `frame`, if it's still valid
+    pub fn state(&self, frame: usize) -> Option<&S> {
+        self.greenzone.get(&frame)
+    }
+
+    /// The latest valid state at or before `frame`, which is where re-simulating up to `frame` should start
+    pub fn latest_state(&self, frame: usize) -> Option<(usize, &S)> {
+        self.greenzone
+            .range(..=frame)
+            .next_back()
+            .map(|(frame, state)| (*frame, state))
+    }
+
+    /// One past the last frame with a recorded state
+    pub fn greenzone_end(&self) -> usize {
+        self.greenzone
+            .keys()
+            .next_back()
+            .map_or(0, |frame| frame + 1)
+    }
+
+    /// Bookmark the start of `frame`, replacing any bookmark with the same name. `state` is the state at the start of
+    /// `frame`, reached by playing the timeline's inputs up to it.
+    pub fn add_bookmark(&mut self, name: &str, frame: usize, state: S) -> Result<(), &'static str> {
+        if frame > self.inputs.len() {
+            return Err("The bookmark is past the end of the timeline");
+        }
+        let bookmark = Bookmark {
+            frame,
+            state,
+            inputs: self.inputs[..frame].to_vec(),
+        };
+        self.bookmarks.insert(name.into(), bookmark);
+        Ok(())
+    }
+
+    pub fn bookmark(&self, name: &str) -> Option<&Bookmark<S>> {
+        self.bookmarks.get(name)
+    }
+
+    /// Every bookmark and its name, in order of name
+    pub fn bookmarks(&self) -> impl Iterator<Item = (&str, &Bookmark<S>)> {
+        self.bookmarks
+            .iter()
+            .map(|(name, bookmark)| (name.as_str(), bookmark))
+    }
+
+    pub fn remove_bookmark(&mut self, name: &str) -> Option<Bookmark<S>> {
+        self.bookmarks.remove(name)
+    }
+
+    /// Start a new branch from a bookmark: the timeline's inputs become the bookmark's, ending at its frame, so the
+    /// frames after it can be recorded again. Returns the bookmark's frame and state, which is where emulation
+    /// should continue from.
+    pub fn branch_from(&mut self, name: &str) -> Result<(usize, &S), &'static str>
+    where
+        S: Clone,
+    {
+        let bookmark = self.bookmarks.get(name).ok_or("There's no such bookmark")?;
+        // States are only still valid up to the first frame whose input differs on the new branch
+        let first_change = self
+            .inputs
+            .iter()
+            .zip(&bookmark.inputs)
+            .position(|(old, new)| old != new)
+            .unwrap_or_else(|| self.inputs.len().min(bookmark.inputs.len()));
+
+        let (frame, inputs, state) = (
+            bookmark.frame,
+            bookmark.inputs.clone(),
+            bookmark.state.clone(),
+        );
+
+        self.inputs = inputs;
+        self.invalidate_from(first_change + 1);
+        self.greenzone.insert(frame, state);
+        Ok((frame, &self.greenzone[&frame]))
+    }
+
+    /// Drop the states of every frame from `frame` on, since they depended on inputs that changed
+    fn invalidate_from(&mut self, frame: usize) {
+        self.greenzone.split_off(&frame);
+    }
+}
@@ -1,13 +1,141 @@
+//! The timer is driven by the falling edge of one bit of the internal divider, ANDed with the enable bit of TAC. This
+//! matters for the obscure cases games and test ROMs notice: resetting DIV or changing TAC can cause a falling edge,
+//! and so an extra increment of TIMA.
+//!
+//! When TIMA overflows it reads 0 for one M-cycle, and is then reloaded from TMA as the interrupt is requested. Writing
+//! TIMA during that first cycle cancels the reload, and writes to TIMA during the reload cycle are ignored.
+
 use crate::cpu::CpuOutputPins;
 
 use super::Chip;
 
+/// The bit of the internal divider whose falling edge clocks the APU's frame sequencer (DIV bit 4). In CGB double
+/// speed mode this would be bit 13 instead.
+const DIV_APU_BIT: u16 = 1 << 12;
+
 #[derive(Default, Debug)]
 pub struct Timer {
     div: u16,
     tima: u8,
     tma: u8,
     tac: u8,
+    /// TIMA overflowed, and will be reloaded at the end of the next M-cycle
+    reload_pending: bool,
+    /// TIMA was reloaded on the last M-cycle, so CPU writes to it are ignored during this one
+    reloaded: bool,
+    div_apu_ticks: u64,
+}
+
+impl Timer {
+    pub fn div(&self) -> u8 {
+        (self.div >> 8) as u8
+    }
+
+    pub fn tima(&self) -> u8 {
+        self.tima
+    }
+
+    pub fn tma(&self) -> u8 {
+        self.tma
+    }
+
+    pub fn tac(&self) -> u8 {
+        self.tac
+    }
+
+    /// The number of DIV-APU events so far. The APU's frame sequencer steps once for each event, at 512 Hz unless the
+    /// game writes to DIV.
+    ///
+    /// Consumers should remember the last count they saw, and step once for every event since then.
+    pub fn div_apu_ticks(&self) -> u64 {
+        self.div_apu_ticks
+    }
+
+    /// Set DIV, TIMA, TMA or TAC directly, without the side effects of a CPU write. Setting DIV clears the bits of the
+    /// internal divider below it.
+    pub(crate) fn set_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xFF04 => self.div = (value as u16) << 8,
+            0xFF05 => self.tima = value,
+            0xFF06 => self.tma = value,
+            0xFF07 => self.tac = value,
+            _ => (),
+        }
+    }
+
+    /// Whether TIMA is currently being incremented (TAC bit 2)
+    pub fn enabled(&self) -> bool {
+        self.tac & 0b100 != 0
+    }
+
+    /// The frequency TIMA is incremented at when the timer is enabled
+    pub fn frequency_hz(&self) -> u32 {
+        4194304 / self.clock_divider() as u32
+    }
+
+    /// The number of M-cycles until TIMA next overflows and requests its interrupt, if nothing is written in the
+    /// meantime. The interrupt is requested the M-cycle after the overflow, along with the reload. `None` while the
+    /// timer is disabled.
+    pub fn cycles_until_overflow(&self) -> Option<u32> {
+        if self.reload_pending {
+            return Some(1);
+        }
+        if !self.enabled() {
+            return None;
+        }
+        // TIMA is incremented on the M-cycle that takes the divider to a multiple of the clock divider
+        let divider = self.clock_divider() as u32;
+        let first_increment = match self.div as u32 % divider {
+            0 => divider / 4,
+            phase => (divider - phase) / 4,
+        };
+        let increments = 256 - self.tima as u32;
+        Some(first_increment + (increments - 1) * divider / 4 + 1)
+    }
+
+    /// Run `cycles` M-cycles at once, with no bus accesses. TIMA mustn't overflow in that time, so `cycles` has to be
+    /// less than `cycles_until_overflow() - 1`.
+    pub(crate) fn advance(&mut self, cycles: u32) {
+        if cycles == 0 {
+            return;
+        }
+        debug_assert!(!matches!(self.cycles_until_overflow(), Some(n) if cycles + 1 >= n));
+        self.reloaded = false;
+
+        // Falling edges of a divider bit happen each time the divider passes a multiple of the bit's period
+        let start = self.div as u64;
+        let end = start + 4 * cycles as u64;
+        let edges = |period: u64| end / period - start / period;
+        self.div_apu_ticks += edges(DIV_APU_BIT as u64 * 2);
+        if self.enabled() {
+            self.tima += edges(self.clock_divider() as u64) as u8;
+        }
+        self.div = end as u16;
+    }
+
+    /// The number of T-cycles between each TIMA increment, as selected by TAC
+    fn clock_divider(&self) -> u16 {
+        match self.tac & 0b11 {
+            0b00 => 1024,
+            0b01 => 16,
+            0b10 => 64,
+            0b11 => 256,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The signal whose falling edge increments TIMA: the divider bit selected by TAC, while the timer is enabled
+    fn timer_input(&self) -> bool {
+        self.enabled() && self.div & (self.clock_divider() / 2) != 0
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, carry) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if carry {
+            self.reload_pending = true;
+        }
+    }
 }
 
 impl Chip for Timer {
@@ -17,62 +145,79 @@ impl Chip for Timer {
         data: &mut u8,
         interrupt_request: &mut u8,
     ) {
-        let mut tima_write = false;
+        let reloaded = core::mem::take(&mut self.reloaded);
+        let mut reload = core::mem::take(&mut self.reload_pending);
+        let old_div = self.div;
+        let old_input = self.timer_input();
 
         match input {
+            CpuOutputPins::Read { addr } => self.debug_read(addr, data),
+
             // DIV
             CpuOutputPins::Write { addr: 0xFF04, .. } => self.div = 0,
-            CpuOutputPins::Read { addr: 0xFF04 } => *data = (self.div >> 8) as u8,
 
             // TIMA
             CpuOutputPins::Write {
                 addr: 0xFF05,
                 data: v,
             } => {
-                self.tima = v;
-                tima_write = true;
+                if !reloaded {
+                    self.tima = v;
+                    reload = false;
+                }
             }
-            CpuOutputPins::Read { addr: 0xFF05 } => *data = self.tima,
 
             // TMA
             CpuOutputPins::Write {
                 addr: 0xFF06,
                 data: v,
-            } => self.tma = v,
-            CpuOutputPins::Read { addr: 0xFF06 } => *data = self.tma,
+            } => {
+                self.tma = v;
+                // The reload is still going on, so TIMA picks up the new value too
+                if reloaded {
+                    self.tima = v;
+                }
+            }
 
             // TAC
             CpuOutputPins::Write {
                 addr: 0xFF07,
                 data: v,
             } => self.tac = v,
-            CpuOutputPins::Read { addr: 0xFF07 } => *data = self.tac,
             _ => (),
         };
 
-        self.div = self.div.wrapping_add(4);
+        // Writing to DIV or TAC can make the timer input fall, as if the divider had ticked
+        if old_input && !self.timer_input() {
+            self.increment_tima();
+        }
 
-        let div_compare = match self.tac & 0b11 {
-            0b00 => 1024,
-            0b01 => 16,
-            0b10 => 64,
-            0b11 => 256,
-            _ => unreachable!(),
-        };
+        let input_before_tick = self.timer_input();
+        self.div = self.div.wrapping_add(4);
+        if input_before_tick && !self.timer_input() {
+            self.increment_tima();
+        }
 
-        let timer_enable = self.tac & 0b100 != 0;
+        // Resetting DIV while bit 4 is set is a falling edge too, so writing to DIV can step the frame sequencer early
+        if old_div & DIV_APU_BIT != 0 && self.div & DIV_APU_BIT == 0 {
+            self.div_apu_ticks += 1;
+        }
 
-        let timer_inc = timer_enable && self.div % div_compare == 0;
+        if reload {
+            // Set interrupt 50h
+            *interrupt_request |= 0b100;
+            self.tima = self.tma;
+            self.reloaded = true;
+        }
+    }
 
-        if !tima_write && timer_inc {
-            let (tima, carry) = self.tima.overflowing_add(1);
-            self.tima = tima;
-            if carry {
-                // Set interrupt 50h
-                *interrupt_request = *interrupt_request | 0b100;
-                // Reset TIMA to TMA
-                self.tima = self.tma;
-            }
+    fn debug_read(&self, addr: u16, data: &mut u8) {
+        match addr {
+            0xFF04 => *data = self.div(),
+            0xFF05 => *data = self.tima,
+            0xFF06 => *data = self.tma,
+            0xFF07 => *data = self.tac,
+            _ => (),
         }
     }
 }
@@ -1,9 +1,20 @@
-#![feature(generators)]
-#![feature(generator_trait)]
-#![feature(destructuring_assignment)]
-#![feature(never_type)]
+//! The emulator core, with no frontend. [`gameboy::Gameboy`] ties everything together; the CPU lives in its own
+//! crate, re-exported as [`cpu`].
+//!
+//! Everything but the threaded renderer, spectating and file output builds with only `alloc` when the default `std`
+//! feature is turned off. [`capabilities`] tells frontends which optional features a build has.
+
 #![feature(assert_matches)]
 #![feature(array_chunks)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
-pub mod cpu;
+pub use gb_cpu as cpu;
+mod capabilities;
 pub mod gameboy;
+#[cfg(feature = "spectate")]
+pub mod spectate;
+pub mod triple_buffer;
+
+pub use capabilities::{capabilities, Capabilities};
@@ -0,0 +1,209 @@
+//! Streams a running game to read-only spectators over TCP.
+//!
+//! The protocol is deliberately simple. When a spectator connects, the server sends `MAGIC` followed by the input
+//! history so far: a little-endian `u64` count of at most `MAX_HISTORY_FRAMES`, then one `ButtonState` byte per frame.
+//! After that, every finished frame is sent as
+//!
+//! | Field   | Size                            |
+//! |---------|---------------------------------|
+//! | index   | `u64`, little-endian            |
+//! | input   | 1 byte, a `ButtonState`         |
+//! | lcd_off | 1 byte, 0 or 1                  |
+//! | pixels  | 160 * 144 `u32`s, little-endian |
+//!
+//! Each spectator is written to from its own thread, so a slow one never holds up emulation. Spectators that fall
+//! more than `QUEUE_FRAMES` frames behind are disconnected, since skipping frames would lose their inputs.
+
+use std::{
+    convert::TryInto,
+    io::{self, BufReader, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::gameboy::{joypad::ButtonState, ppu::monochrome::Frame};
+
+/// Sent first on every connection, so spectators can tell they've connected to the right thing
+pub const MAGIC: &[u8; 8] = b"GBSPECT1";
+
+/// How many frames may wait to be sent to a spectator before it's disconnected, about half a second
+const QUEUE_FRAMES: usize = 30;
+
+/// How long a spectator's writer thread may block on one write before it gives up
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The longest input history a spectator accepts, a day at 60 frames per second. Anything longer is taken to be a
+/// broken or malicious server rather than allocated.
+pub const MAX_HISTORY_FRAMES: u64 = 60 * 60 * 60 * 24;
+
+const PIXELS: usize = 160 * 144;
+const FRAME_MESSAGE_LEN: usize = 8 + 1 + 1 + PIXELS * 4;
+
+/// The server side: accepts spectators and sends them every frame
+pub struct Broadcaster {
+    listener: TcpListener,
+    spectators: Vec<Connection>,
+    history: Vec<ButtonState>,
+}
+
+/// A spectator, and the queue of messages its writer thread sends it
+struct Connection {
+    /// Kept to shut the connection down, which also stops a writer thread stuck on a write
+    stream: TcpStream,
+    queue: SyncSender<Arc<[u8]>>,
+}
+
+impl Connection {
+    fn open(stream: TcpStream, handshake: Vec<u8>) -> io::Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+
+        let (queue, messages) = mpsc::sync_channel::<Arc<[u8]>>(QUEUE_FRAMES);
+        let mut writer = stream.try_clone()?;
+        thread::spawn(move || {
+            writer.write_all(&handshake)?;
+            for message in messages {
+                writer.write_all(&message)?;
+            }
+            io::Result::Ok(())
+        });
+        Ok(Connection { stream, queue })
+    }
+
+    /// Queue `message`, or return false if the spectator has fallen too far behind or disconnected
+    fn send(&self, message: &Arc<[u8]>) -> bool {
+        let sent = self.queue.try_send(message.clone()).is_ok();
+        if !sent {
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+        sent
+    }
+}
+
+impl Broadcaster {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Broadcaster {
+            listener,
+            spectators: Vec::new(),
+            history: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn spectator_count(&self) -> usize {
+        self.spectators.len()
+    }
+
+    /// The input of every frame sent so far
+    pub fn input_history(&self) -> &[ButtonState] {
+        &self.history
+    }
+
+    /// Accept any waiting spectators, then queue `frame` for them, which was played with `input` held. This never waits
+    /// for a spectator.
+    pub fn send_frame(&mut self, frame: &Frame, input: ButtonState) {
+        self.accept_spectators();
+        self.history.push(input);
+
+        let mut message = Vec::with_capacity(FRAME_MESSAGE_LEN);
+        message.extend_from_slice(&frame.index.to_le_bytes());
+        message.push(input.bits());
+        message.push(frame.lcd_off as u8);
+        message.extend(frame.pixels.iter().flat_map(|pixel| pixel.to_le_bytes()));
+
+        let message: Arc<[u8]> = message.into();
+        self.spectators.retain(|spectator| spectator.send(&message));
+    }
+
+    fn accept_spectators(&mut self) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            let mut handshake = Vec::with_capacity(MAGIC.len() + 8 + self.history.len());
+            handshake.extend_from_slice(MAGIC);
+            handshake.extend_from_slice(&(self.history.len() as u64).to_le_bytes());
+            handshake.extend(self.history.iter().map(|input| input.bits()));
+
+            if let Ok(connection) = Connection::open(stream, handshake) {
+                self.spectators.push(connection);
+            }
+        }
+    }
+}
+
+/// The client side: receives the frames of a `Broadcaster`
+pub struct Spectator {
+    stream: BufReader<TcpStream>,
+    history: Vec<ButtonState>,
+}
+
+impl Spectator {
+    /// Connect to a broadcaster. This blocks until the broadcaster sends its next frame, since that's when it
+    /// accepts new spectators.
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let mut stream = BufReader::new(TcpStream::connect(addr)?);
+
+        let mut magic = [0; MAGIC.len()];
+        stream.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a spectator stream",
+            ));
+        }
+
+        let mut count = [0; 8];
+        stream.read_exact(&mut count)?;
+        let count = u64::from_le_bytes(count);
+        if count > MAX_HISTORY_FRAMES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the input history is too long",
+            ));
+        }
+        let mut history = vec![0; count as usize];
+        stream.read_exact(&mut history)?;
+        let history = history
+            .into_iter()
+            .map(ButtonState::from_bits_truncate)
+            .collect();
+
+        Ok(Spectator { stream, history })
+    }
+
+    /// The input of every frame played so far, including those from before this spectator connected
+    pub fn input_history(&self) -> &[ButtonState] {
+        &self.history
+    }
+
+    /// Wait for the next frame, returning it with the input it was played with
+    pub fn recv_frame(&mut self) -> io::Result<(Frame, ButtonState)> {
+        let mut message = vec![0; FRAME_MESSAGE_LEN];
+        self.stream.read_exact(&mut message)?;
+
+        let mut frame = Frame::new();
+        frame.index = u64::from_le_bytes(message[0..8].try_into().unwrap());
+        let input = ButtonState::from_bits_truncate(message[8]);
+        frame.lcd_off = message[9] != 0;
+        frame.rendered_lines = [true; 144];
+        for (pixel, bytes) in frame
+            .pixels
+            .iter_mut()
+            .zip(message[10..].array_chunks::<4>())
+        {
+            *pixel = u32::from_le_bytes(*bytes);
+        }
+
+        self.history.push(input);
+        Ok((frame, input))
+    }
+}
@@ -0,0 +1,89 @@
+//! Handing frames from one thread to another without locking or allocating
+//!
+//! There are three buffers: the writer fills one, the reader shows another, and the third holds the newest finished
+//! value. Publishing swaps the writer's buffer with the middle one, and reading swaps the reader's buffer with it if
+//! it's newer. Neither side ever waits for the other, and a slow reader simply skips the values it didn't get to.
+
+use alloc::sync::Arc;
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Set in `Shared::middle` when the middle buffer hasn't been read yet
+const NEW: u8 = 0b100;
+const INDEX: u8 = 0b011;
+
+struct Shared<T> {
+    buffers: [UnsafeCell<T>; 3],
+    /// The index of the middle buffer, and the `NEW` flag
+    middle: AtomicU8,
+}
+
+// Each buffer is only ever reachable through one of the writer, the reader, or `middle`
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// The writing end of a triple buffer
+pub struct Writer<T> {
+    shared: Arc<Shared<T>>,
+    index: u8,
+}
+
+/// The reading end of a triple buffer
+pub struct Reader<T> {
+    shared: Arc<Shared<T>>,
+    index: u8,
+}
+
+/// Make a triple buffer, with every buffer starting out as `initial`
+pub fn triple_buffer<T: Clone + Send>(initial: T) -> (Writer<T>, Reader<T>) {
+    let shared = Arc::new(Shared {
+        buffers: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        middle: AtomicU8::new(1),
+    });
+    let writer = Writer {
+        shared: shared.clone(),
+        index: 0,
+    };
+    let reader = Reader { shared, index: 2 };
+    (writer, reader)
+}
+
+impl<T> Writer<T> {
+    /// The buffer being written. It holds whatever was published a couple of swaps ago, so fill in all of it.
+    pub fn buffer_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.shared.buffers[self.index as usize].get() }
+    }
+
+    /// Make the buffer being written the newest value, replacing any the reader hasn't picked up yet
+    pub fn publish(&mut self) {
+        let old = self.shared.middle.swap(self.index | NEW, Ordering::AcqRel);
+        self.index = old & INDEX;
+    }
+
+    /// Overwrite the buffer being written with `value`, and publish it
+    pub fn write(&mut self, value: T) {
+        *self.buffer_mut() = value;
+        self.publish();
+    }
+}
+
+impl<T> Reader<T> {
+    /// Whether a value was published since the last `read`
+    pub fn has_new(&self) -> bool {
+        self.shared.middle.load(Ordering::Acquire) & NEW != 0
+    }
+
+    /// The newest published value, or the same value as last time if nothing was published since
+    pub fn read(&mut self) -> &T {
+        if self.has_new() {
+            let old = self.shared.middle.swap(self.index, Ordering::AcqRel);
+            self.index = old & INDEX;
+        }
+        unsafe { &*self.shared.buffers[self.index as usize].get() }
+    }
+}
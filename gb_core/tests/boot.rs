@@ -0,0 +1,92 @@
+mod common;
+
+use gb_core::gameboy::{boot::Revision, Gameboy};
+
+#[test]
+#[rustfmt::skip]
+fn post_boot_state() {
+    // From Pan Docs' "Power Up Sequence": A, F, B, C, D, E, H, L and DIV, which varies on a CGB
+    let expected = [
+        (Revision::Dmg0, [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03], Some(0x18)),
+        (Revision::Dmg,  [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D], Some(0xAB)),
+        (Revision::Mgb,  [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D], Some(0xAB)),
+        (Revision::Cgb,  [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D], None),
+    ];
+    for &(revision, registers, div) in &expected {
+        let mut rom = common::rom_with_code(&[0x18, 0xFE]); // JR -2
+        // Any header checksum but 0, which makes the DMG and MGB boot ROMs clear H and C
+        rom[0x14D] = 0x01;
+        let mut gb = Gameboy::new(rom).unwrap();
+        gb.reset_as(revision);
+
+        let r = &gb.cpu.cpu.registers;
+        assert_eq!(
+            [r.a, u8::from(r.f), r.b, r.c, r.d, r.e, r.h, r.l],
+            registers,
+            "{:?}",
+            revision
+        );
+        assert_eq!((r.sp, r.pc), (0xFFFE, 0x0100));
+        assert_eq!(div.is_some(), revision.post_boot_state().io.iter().any(|&(addr, _)| addr == 0xFF04));
+
+        let io = [
+            (0xFF05, 0x00), (0xFF06, 0x00), (0xFF07, 0xF8), (0xFF0F, 0xE1), (0xFF40, 0x91), (0xFF42, 0x00),
+            (0xFF43, 0x00), (0xFF45, 0x00), (0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00), (0xFFFF, 0x00),
+        ];
+        for &(addr, value) in io.iter().chain(div.map(|div| (0xFF04, div)).iter()) {
+            // IF's unused bits aren't emulated
+            let unused = if addr == 0xFF0F { 0xE0 } else { 0x00 };
+            assert_eq!(
+                gb.debug_read(addr) | unused,
+                value,
+                "{:?} ${:04X}",
+                revision,
+                addr
+            );
+        }
+    }
+}
+
+#[test]
+fn header_checksum_flags() {
+    // `rom_with_code` leaves the header checksum at 0
+    let gb = common::gameboy_with_code(&[0x18, 0xFE]);
+    assert_eq!(u8::from(gb.cpu.cpu.registers.f), 0x80);
+}
+
+#[test]
+#[rustfmt::skip]
+fn model_detection() {
+    // The usual check for a CGB, which only runs once: C is set to 1 if A is $11
+    let code = [
+        0x0E, 0x00, // LD C, $00
+        0xFE, 0x11, // CP $11
+        0x20, 0x02, // JR NZ, +2
+        0x0E, 0x01, // LD C, $01
+        0x18, 0xFE, // JR -2
+    ];
+    for &(revision, cgb) in &[(Revision::Dmg, 0), (Revision::Mgb, 0), (Revision::Cgb, 1)] {
+        let mut gb = Gameboy::new(common::rom_with_code(&code)).unwrap();
+        gb.reset_as(revision);
+        for _ in 0..100 {
+            gb.clock();
+        }
+        assert_eq!(gb.cpu.cpu.registers.c, cgb, "{:?}", revision);
+    }
+}
+
+#[test]
+fn fits_on_the_default_stack() {
+    // With the `static-alloc` feature the `Gameboy` is hundreds of KiB, so building one mustn't pass it around by value.
+    // Threads get 2 MiB by default, which `RUST_MIN_STACK` would otherwise change.
+    std::thread::Builder::new()
+        .stack_size(2 * 1024 * 1024)
+        .spawn(|| {
+            let mut gb = common::gameboy_with_code(&[0x18, 0xFE]); // JR -2
+            gb.run_frame();
+            gb.run_frame();
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -0,0 +1,10 @@
+#[test]
+fn capabilities() {
+    let capabilities = gb_core::capabilities();
+    assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(capabilities.debugger, cfg!(feature = "debugger"));
+    assert_eq!(capabilities.trace, cfg!(feature = "trace"));
+    assert_eq!(capabilities.spectate, cfg!(feature = "spectate"));
+    assert_eq!(capabilities.threaded_rendering, cfg!(feature = "std"));
+    assert_eq!(capabilities.static_alloc, cfg!(feature = "static-alloc"));
+}
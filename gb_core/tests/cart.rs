@@ -0,0 +1,401 @@
+mod common;
+
+use std::sync::Arc;
+
+use gb_core::gameboy::{
+    cart::{
+        header::{fix_checksums, CartHeader, Checksums, NINTENDO_LOGO},
+        Cart, Diagnostic, LoadMode, RAM_BANK_SIZE,
+    },
+    Gameboy,
+};
+
+#[test]
+fn header_title() {
+    let mut rom = common::rom_with_code(&[]);
+    rom[0x134..0x13F].copy_from_slice(b"POKEMON RED");
+    rom[0x14E] = 0x91;
+    rom[0x14F] = 0xE6;
+    let header = CartHeader::parse(&rom).unwrap();
+    assert_eq!(header.title, "POKEMON RED");
+    assert_eq!(header.global_checksum, 0x91E6);
+
+    // On CGB cartridges the last title byte is the CGB flag
+    rom[0x134..0x143].copy_from_slice(b"ABCDEFGHIJKLMNO");
+    rom[0x143] = 0xC0;
+    let header = CartHeader::parse(&rom).unwrap();
+    assert_eq!(header.title, "ABCDEFGHIJKLMNO");
+    assert_eq!(header.cgb_flag, 0xC0);
+
+    assert_eq!(CartHeader::parse(&rom[..0x14F]), None);
+}
+
+#[test]
+fn checksums() {
+    let mut rom = common::rom_with_code(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP $0150
+    rom[0x134..0x13F].copy_from_slice(b"POKEMON RED");
+    let checksums = Checksums::compute(&rom).unwrap();
+    assert_ne!(CartHeader::parse(&rom).unwrap().checksums(), checksums);
+
+    assert_eq!(fix_checksums(&mut rom), Some(checksums));
+    let header = CartHeader::parse(&rom).unwrap();
+    assert_eq!(header.checksums(), checksums);
+    assert_eq!(Checksums::compute(&rom), Some(checksums));
+
+    // x = x - byte - 1 over $0134-$014C
+    let expected = rom[0x134..=0x14C]
+        .iter()
+        .fold(0u8, |x, &b| x.wrapping_sub(b).wrapping_sub(1));
+    assert_eq!(header.header_checksum, expected);
+    let sum: u32 = rom.iter().map(|&b| b as u32).sum();
+    let stored = rom[0x14E] as u32 + rom[0x14F] as u32;
+    assert_eq!(header.global_checksum, (sum - stored) as u16);
+
+    assert_eq!(Checksums::compute(&rom[..0x14C]), None);
+}
+
+#[test]
+fn header_sizes() {
+    let mut rom = common::rom_with_code(&[]);
+    rom[0x147] = 0x03;
+    rom[0x148] = 0x05;
+    rom[0x149] = 0x03;
+    let header = CartHeader::parse(&rom).unwrap();
+    assert_eq!(header.cart_type_name(), Some("MBC1+RAM+BATTERY"));
+    assert_eq!(header.rom_size_bytes(), Some(1024 * 1024));
+    assert_eq!(header.ram_size_bytes(), Some(32 * 1024));
+}
+
+#[test]
+#[rustfmt::skip]
+fn mbc1_multicart() {
+    let code = [
+        0x3E, 0x01,       // LD A, $01
+        0xEA, 0x00, 0x40, // LD ($4000), A
+        0x3E, 0x13,       // LD A, $13
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0x3E, 0x01,       // LD A, $01
+        0xEA, 0x00, 0x60, // LD ($6000), A
+        0x18, 0xFE,       // JR -2
+    ];
+
+    // Four 256 KiB games, each with a header and the same code, and each bank marked with its number
+    let mut rom = vec![0; 0x100000];
+    for (bank, data) in rom.chunks_exact_mut(0x4000).enumerate() {
+        data[0] = bank as u8;
+        if bank % 0x10 == 0 {
+            data[0x100..0x103].copy_from_slice(&[0xC3, 0x50, 0x01]); // JP $0150
+            data[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+            data[0x150..0x150 + code.len()].copy_from_slice(&code);
+        }
+    }
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x05;
+    let mut gb = Gameboy::new(rom).unwrap();
+    gb.reset();
+    for _ in 0..32 {
+        gb.clock();
+    }
+
+    // The upper bits go to bit 4 rather than bit 5, and only 4 bits of the lower register are used
+    assert_eq!(gb.debug_read(0x4000), 0x13);
+    // Mode 1 maps the second game's first bank at $0000, which is how the menu boots it
+    assert_eq!(gb.debug_read(0x0000), 0x10);
+}
+
+#[test]
+#[rustfmt::skip]
+fn wisdom_tree() {
+    let code = [
+        0xEA, 0x02, 0x00, // LD ($0002), A
+        0x18, 0xFE,       // JR -2
+    ];
+
+    // A 128 KiB "ROM only" game, with each 32 KiB bank marked with its number
+    let mut rom = vec![0; 0x20000];
+    for (bank, data) in rom.chunks_exact_mut(0x8000).enumerate() {
+        data[0x100..0x100 + code.len()].copy_from_slice(&code);
+        data[0x7FFF] = bank as u8;
+    }
+    let mut gb = Gameboy::new(rom).unwrap();
+    gb.reset();
+    assert_eq!(gb.debug_read(0x7FFF), 0);
+
+    // The bank comes from the address written to, not the data
+    for _ in 0..8 {
+        gb.clock();
+    }
+    assert_eq!(gb.debug_read(0x7FFF), 2);
+}
+
+#[test]
+fn load_modes() {
+    let mut rom = common::rom_with_code(&[]);
+    let diagnostics = Cart::new(rom.clone()).unwrap().diagnostics().to_vec();
+    assert_eq!(diagnostics.len(), 3);
+    assert_eq!(diagnostics[0], Diagnostic::BadLogo);
+    assert!(matches!(
+        diagnostics[1],
+        Diagnostic::HeaderChecksum { stored: 0, .. }
+    ));
+    assert!(matches!(
+        diagnostics[2],
+        Diagnostic::GlobalChecksum { stored: 0, .. }
+    ));
+    assert!(Cart::with_mode(rom.clone(), LoadMode::Strict).is_err());
+
+    rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    fix_checksums(&mut rom);
+    let cart = Cart::with_mode(rom.clone(), LoadMode::Strict).unwrap();
+    assert!(cart.diagnostics().is_empty());
+
+    // Unknown mappers and bad sizes load leniently instead of panicking
+    rom[0x147] = 0x19;
+    rom.truncate(0x6000);
+    fix_checksums(&mut rom);
+    let cart = Cart::new(rom.clone()).unwrap();
+    assert_eq!(
+        cart.diagnostics(),
+        [
+            Diagnostic::RomSize {
+                declared: Some(0x8000),
+                actual: 0x6000
+            },
+            Diagnostic::UnknownMapper(0x19)
+        ]
+    );
+    assert_eq!(
+        Cart::with_mode(rom, LoadMode::Strict).err(),
+        Some("The ROM size doesn't match the header")
+    );
+}
+
+#[test]
+#[rustfmt::skip]
+fn shared_rom_and_mirroring() {
+    let code = [
+        0x3E, 0x05,       // LD A, $05
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0x18, 0xFE,       // JR -2
+    ];
+
+    // A 64 KiB MBC1 ROM, so banks 4 and up mirror banks 0-3
+    let mut rom = vec![0; 0x10000];
+    for (bank, data) in rom.chunks_exact_mut(0x4000).enumerate() {
+        data[0x3FFF] = bank as u8;
+    }
+    rom[0x100..0x100 + code.len()].copy_from_slice(&code);
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+    let rom: Arc<[u8]> = rom.into();
+
+    let mut gb = Gameboy::new(rom.clone()).unwrap();
+    let other = Gameboy::new(rom.clone()).unwrap();
+    assert_eq!(Arc::strong_count(&rom), 3);
+
+    gb.reset();
+    for _ in 0..16 {
+        gb.clock();
+    }
+    assert_eq!(gb.debug_read(0x7FFF), 1);
+    assert_eq!(other.debug_read(0x7FFF), 1);
+    assert_eq!(gb.debug_read(0x3FFF), 0);
+}
+
+#[test]
+#[rustfmt::skip]
+fn injected_rom_and_ram() {
+    let code = [
+        0x3E, 0x0A,       // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A
+        0x18, 0xF9,       // JR -7
+    ];
+    let mut rom = common::rom_with_code(&code);
+    rom[0x147] = 0x02; // MBC1+RAM
+    rom[0x149] = 0x02; // 8 KiB
+    let rom: Arc<[u8]> = rom.into();
+
+    let mut cart = Cart::from_parts(rom.clone(), &[0x12, 0x34]).unwrap();
+    assert!(Cart::from_parts(rom.clone(), &[0; 0x2001]).is_err());
+
+    // Patching the ROM copies it rather than changing the shared image
+    cart.write_rom(0x101, &[0x00]).unwrap();
+    assert_eq!(rom[0x101], 0x0A);
+    assert!(cart.write_rom(0x7FFF, &[0, 0]).is_err());
+    assert!(cart.write_rom(usize::MAX, &[0]).is_err());
+    assert!(cart.write_ram(usize::MAX, &[0]).is_err());
+
+    let mut gb = Gameboy::with_cart(cart);
+    gb.reset();
+    for _ in 0..16 {
+        gb.clock();
+    }
+    // RAM hasn't been enabled yet
+    assert_eq!(gb.debug_read(0xA000), 0xFF);
+
+    // The ROM can be patched while the game is running
+    gb.cart.write_rom(0x101, &[0x1A]).unwrap();
+    for _ in 0..16 {
+        gb.clock();
+    }
+    assert_eq!(gb.debug_read(0xA000), 0x12);
+    assert_eq!(gb.debug_read(0xA001), 0x34);
+
+    gb.cart.write_ram(1, &[0x56]).unwrap();
+    assert_eq!(gb.debug_read(0xA001), 0x56);
+}
+
+#[test]
+#[rustfmt::skip]
+fn rom_patches() {
+    let code = [
+        0x3E, 0x05,       // LD A, $05
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0x18, 0xF9,       // JR -7
+    ];
+
+    // A 64 KiB MBC1 ROM, so bank 5 mirrors bank 1
+    let mut rom = vec![0; 0x10000];
+    for (bank, data) in rom.chunks_exact_mut(0x4000).enumerate() {
+        data[0x3FFF] = bank as u8;
+    }
+    rom[0x100..0x100 + code.len()].copy_from_slice(&code);
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+
+    let mut gb = Gameboy::new(rom).unwrap();
+    // Patches apply to the code the CPU runs, not just to debug reads
+    let bank_select = gb.cart.patch_rom(0, 0x101, 0x05, 0x02).unwrap();
+    gb.reset();
+    for _ in 0..16 {
+        gb.clock();
+    }
+    assert_eq!(gb.debug_read(0x101), 0x02);
+    assert_eq!(gb.debug_read(0x7FFF), 2);
+
+    // Patches only apply while their bank is mapped
+    gb.cart.patch_rom(2, 0x7FFF, 2, 0x42).unwrap();
+    gb.cart.patch_rom(1, 0x7FFF, 1, 0x99).unwrap();
+    assert_eq!(gb.debug_read(0x7FFF), 0x42);
+
+    assert!(gb.cart.revert_patch(bank_select));
+    assert!(!gb.cart.revert_patch(bank_select));
+    for _ in 0..16 {
+        gb.clock();
+    }
+    assert_eq!(gb.debug_read(0x101), 0x05);
+    assert_eq!(gb.debug_read(0x7FFF), 0x99, "bank 5 mirrors bank 1");
+
+    assert!(gb.cart.patch_rom(1, 0x7FFF, 0x12, 0x34).is_err(), "wrong original byte");
+    assert!(gb.cart.patch_rom(4, 0x4000, 0x00, 0x34).is_err(), "missing bank");
+    assert!(gb.cart.patch_rom(0, 0x8000, 0x00, 0x34).is_err(), "not ROM");
+
+    gb.cart.revert_all_patches();
+    assert_eq!(gb.debug_read(0x7FFF), 1);
+}
+
+#[test]
+#[rustfmt::skip]
+fn mbc1_ram_banking() {
+    let code = [
+        0x3E, 0x0A,       // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A    Enable RAM
+        0x3E, 0x01,       // LD A, $01
+        0xEA, 0x00, 0x60, // LD ($6000), A    Mode 1
+        0x3E, 0x02,       // LD A, $02
+        0xEA, 0x00, 0x40, // LD ($4000), A    RAM bank 2
+        0x3E, 0x99,       // LD A, $99
+        0xEA, 0x01, 0xA0, // LD ($A001), A
+        0xAF,             // XOR A
+        0xEA, 0x00, 0x60, // LD ($6000), A    Mode 0
+        0x18, 0xFE,       // JR -2
+    ];
+    let run = |ram_size: u8| {
+        let mut rom = common::rom_with_code(&code);
+        rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+        rom[0x149] = ram_size;
+        let mut cart = Cart::new(rom).unwrap();
+        for bank in 0..4 {
+            // Only as many banks as the cart has can be written
+            let _ = cart.write_ram(bank * 0x2000, &[bank as u8 + 1]);
+        }
+        let mut gb = Gameboy::with_cart(cart);
+        gb.reset();
+        // The first step only reaches the fetch of the first instruction
+        let mut reads = Vec::new();
+        for instructions in [7, 2, 2] {
+            for _ in 0..instructions {
+                gb.step_instruction();
+            }
+            reads.push((gb.debug_read(0xA000), gb.debug_read(0xA001)));
+        }
+        reads
+    };
+
+    // With 32 KiB, mode 1 switches $A000-$BFFF to bank 2, and mode 0 goes back to bank 0
+    assert_eq!(run(0x03), [(0x03, 0x00), (0x03, 0x99), (0x01, 0x00)]);
+    // With only 8 KiB the bank lines aren't connected, so bank 2 mirrors bank 0
+    assert_eq!(run(0x02), [(0x01, 0x00), (0x01, 0x99), (0x01, 0x99)]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn cart_ram_sizes() {
+    let code = [
+        0x3E, 0x0A,       // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A    Enable RAM
+        0x3E, 0x77,       // LD A, $77
+        0xEA, 0x00, 0xA0, // LD ($A000), A
+        0xEA, 0x00, 0xA8, // LD ($A800), A
+        0x18, 0xFE,       // JR -2
+    ];
+    let run = |ram_size: u8| {
+        let mut rom = common::rom_with_code(&code);
+        rom[0x147] = 0x02; // MBC1+RAM
+        rom[0x149] = ram_size;
+        let mut gb = Gameboy::new(rom).unwrap();
+        gb.reset();
+        for _ in 0..6 {
+            gb.step_instruction();
+        }
+        (gb.debug_read(0xA000), gb.debug_read(0xA800))
+    };
+
+    // Past the end of the RAM nothing answers, so reads see open bus and writes are lost
+    assert_eq!(run(0x00), (0xFF, 0xFF));
+    assert_eq!(run(0x01), (0x77, 0xFF));
+    assert_eq!(run(0x02), (0x77, 0x77));
+
+    let mut rom = common::rom_with_code(&code);
+    rom[0x147] = 0x02;
+    rom[0x149] = 0x01; // 2 KiB
+    assert!(Cart::from_parts(rom.clone(), &[0; 0x800]).is_ok());
+    assert!(Cart::from_parts(rom.clone(), &[0; 0x801]).is_err());
+    rom[0x149] = 0x00;
+    assert!(Cart::from_parts(rom, &[0]).is_err());
+}
+
+#[test]
+fn ram_banks() {
+    let cart = |cart_type: u8, ram_size: u8| {
+        let mut rom = common::rom_with_code(&[]);
+        rom[0x147] = cart_type;
+        rom[0x149] = ram_size;
+        Cart::new(rom).unwrap()
+    };
+
+    let mut mbc1 = cart(0x03, 0x03);
+    mbc1.write_ram(2 * RAM_BANK_SIZE + 5, &[0xAB]).unwrap();
+    assert_eq!(mbc1.ram().len(), 0x8000);
+    assert_eq!(mbc1.ram_banks().count(), 4);
+    // Bank 0 is the one mapped, but every bank can be read
+    assert_eq!(mbc1.ram_bank(2).unwrap()[5], 0xAB);
+    assert_eq!(mbc1.ram_bank(4), None);
+
+    let small = cart(0x02, 0x01);
+    let banks: Vec<usize> = small.ram_banks().map(<[u8]>::len).collect();
+    assert_eq!(banks, [0x800]);
+
+    assert_eq!(cart(0x01, 0x00).ram_banks().count(), 0);
+    assert!(cart(0x00, 0x00).ram().is_empty());
+}
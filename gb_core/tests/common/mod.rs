@@ -0,0 +1,43 @@
+//! Helpers shared between the integration tests that run a whole `Gameboy`
+#![allow(dead_code)]
+
+use gb_core::gameboy::{models::DMG, Gameboy};
+
+/// Builds a 32 KiB ROM-only cartridge with `code` placed at the entry point ($0100)
+pub fn rom_with_code(code: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x100 + code.len()].copy_from_slice(code);
+    rom
+}
+
+/// Creates a `Gameboy` that starts executing `code` at $0100
+pub fn gameboy_with_code(code: &[u8]) -> Box<Gameboy<DMG>> {
+    let mut gb = Gameboy::new(rom_with_code(code)).unwrap();
+    gb.reset();
+    gb
+}
+
+/// Read a third party test ROM from the directory in `$GB_TEST_ROMS`, or `test_roms` at the root of the workspace.
+///
+/// Test ROMs aren't checked in, so this returns `None` (and says so) when the ROM is missing. Tests that need one are
+/// ignored by default, and fail when it's missing rather than passing without running anything.
+pub fn test_rom(path: &str) -> Option<Vec<u8>> {
+    let dir = std::env::var_os("GB_TEST_ROMS")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_roms"));
+    let path = dir.join(path);
+    match std::fs::read(&path) {
+        Ok(rom) => Some(rom),
+        Err(e) => {
+            eprintln!("skipping, couldn't read {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Loads and resets a test ROM, see [`test_rom`]
+pub fn gameboy_with_test_rom(path: &str) -> Option<Box<Gameboy<DMG>>> {
+    let mut gb = Gameboy::new(test_rom(path)?).unwrap();
+    gb.reset();
+    Some(gb)
+}
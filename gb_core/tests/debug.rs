@@ -0,0 +1,811 @@
+mod common;
+
+use gb_core::gameboy::debug::{DmaSnapshot, Interrupts};
+
+#[test]
+#[rustfmt::skip]
+fn io_snapshot() {
+    let code = [
+        0x3E, 0x05, // LD A, $05
+        0xE0, 0x07, // LDH (TAC), A
+        0x3E, 0x12, // LD A, $12
+        0xE0, 0x43, // LDH (SCX), A
+        0x3E, 0x05, // LD A, $05
+        0xE0, 0xFF, // LDH (IE), A
+        0x3E, 0xC0, // LD A, $C0
+        0xE0, 0x46, // LDH (DMA), A
+        0x18, 0xFE, // JR -2
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    for _ in 0..9 {
+        gb.step_instruction();
+    }
+
+    let io = gb.io_snapshot();
+    assert_eq!(io.interrupt_enable, Interrupts::VBLANK | Interrupts::TIMER);
+    assert!(!io.ime);
+    assert_eq!(io.timer.tac, 0x05);
+    assert!(io.timer.enabled);
+    assert_eq!(io.timer.frequency_hz, 262144);
+    assert_eq!(io.lcd.scx, 0x12);
+    assert_eq!(io.lcd.mode, 3);
+    assert_eq!(io.dma, DmaSnapshot { source: 0xC0, active: true });
+
+    let registers = io.registers();
+    let scx = registers.iter().find(|r| r.name == "SCX").unwrap();
+    assert_eq!((scx.addr, scx.value), (0xFF43, 0x12));
+    let sc = registers.iter().find(|r| r.name == "SC").unwrap();
+    assert_eq!((sc.addr, sc.value), (0xFF02, 0x7E));
+    let dma = registers.iter().find(|r| r.name == "DMA").unwrap();
+    assert_eq!((dma.addr, dma.value), (0xFF46, 0xC0));
+    let ie = registers.iter().find(|r| r.name == "IE").unwrap();
+    assert_eq!((ie.addr, ie.value), (0xFFFF, 0x05));
+}
+
+#[test]
+#[rustfmt::skip]
+fn watch_hits() {
+    let code = [
+        0x3E, 0x01,       // $0100: LD A, 1
+        0xEA, 0x00, 0xC0, // $0102: LD ($C000), A
+        0xEA, 0x00, 0xC0, // $0105: LD ($C000), A
+        0x3C,             // $0108: INC A
+        0xEA, 0x01, 0xC0, // $0109: LD ($C001), A
+        0xEA, 0x00, 0xD0, // $010C: LD ($D000), A
+        0x18, 0xFE,       // $010F: JR -2
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    let watch = gb.add_watch(0xC000..=0xC001);
+    for _ in 0..8 {
+        gb.step_instruction();
+    }
+
+    let hits = gb
+        .take_watch_hits()
+        .into_iter()
+        .map(|hit| (hit.watch, hit.addr, hit.old, hit.new, hit.pc))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        hits,
+        vec![
+            (watch, 0xC000, 0x00, 0x01, 0x0102),
+            (watch, 0xC001, 0x00, 0x02, 0x0109),
+        ]
+    );
+    assert!(gb.take_watch_hits().is_empty());
+    assert_eq!(gb.debug_read(0xD000), 0x02);
+
+    gb.remove_watch(watch);
+    gb.reset();
+    for _ in 0..8 {
+        gb.step_instruction();
+    }
+    assert!(gb.take_watch_hits().is_empty());
+}
+
+#[test]
+fn ram_search() {
+    use gb_core::gameboy::{
+        debug::{RamSearch, SearchFilter},
+        memory::Memory,
+    };
+
+    let mut memory = Memory::new();
+    memory[0xC123] = 100;
+    memory[0xD456] = 100;
+    memory[0xDFFF] = 7;
+
+    let mut search = RamSearch::new(&memory);
+    assert_eq!(search.candidates().len(), 0x2000);
+
+    assert_eq!(search.filter(&memory, SearchFilter::EqualTo(100)), 2);
+
+    memory[0xC123] = 90;
+    memory[0xD456] = 110;
+    assert_eq!(search.filter(&memory, SearchFilter::Less), 1);
+    assert_eq!(search.candidates(), &[0xC123]);
+
+    memory[0xC123] = 80;
+    assert_eq!(search.filter(&memory, SearchFilter::ChangedBy(-10)), 1);
+    assert_eq!(search.filter(&memory, SearchFilter::Unchanged), 1);
+    assert_eq!(search.snapshot_value(0xC123), Some(80));
+    assert_eq!(search.snapshot_value(0xFF80), None);
+
+    memory[0xC123] = 81;
+    assert_eq!(search.filter(&memory, SearchFilter::Greater), 1);
+    assert_eq!(search.filter(&memory, SearchFilter::Changed), 0);
+}
+
+#[test]
+fn map_capture() {
+    use gb_core::gameboy::{
+        debug::MapCapture,
+        ppu::{
+            monochrome::{color::COLORS, MonochromePpu},
+            registers::LCDC,
+        },
+    };
+
+    let mut ppu = MonochromePpu::new();
+    ppu.set_lcdc(LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA);
+    ppu.set_bgp(0b11100100);
+    // Tiles 0-3 are filled with colors 0-3, and map column x uses tile x % 4
+    for tile in 0..4 {
+        for row in 0..8 {
+            ppu.state_mut().tile_data[tile * 16 + row * 2] = 0xFF * (tile as u8 & 1);
+            ppu.state_mut().tile_data[tile * 16 + row * 2 + 1] = 0xFF * (tile as u8 >> 1);
+        }
+    }
+    for (i, tile) in ppu.state_mut().bg_map_1.iter_mut().enumerate() {
+        *tile = (i % 32 % 4) as u8;
+    }
+
+    let mut capture = MapCapture::new();
+    assert_eq!(capture.image(), (vec![], 0, 0));
+    capture.capture(&ppu);
+    assert_eq!(capture.tile_count(), 20 * 18);
+
+    // Half a tile isn't enough to reveal the next column
+    ppu.set_scx(4);
+    capture.capture(&ppu);
+    assert_eq!(capture.tile_count(), 20 * 18);
+    ppu.set_scx(8);
+    capture.capture(&ppu);
+    assert_eq!(capture.tile_count(), 21 * 18);
+
+    // Scrolling left past 0 wraps around the tile map, but carries on to the left of the level
+    ppu.state_mut().bg_map_1[31] = 2;
+    ppu.set_scx(248);
+    capture.capture(&ppu);
+    assert_eq!(capture.tile_count(), 22 * 18);
+
+    // Tiles under the window aren't captured
+    ppu.set_lcdc(ppu.lcdc() | LCDC::WINDOW_ENABLE);
+    ppu.set_wx(7);
+    ppu.set_wy(136);
+    ppu.set_scy(8);
+    capture.capture(&ppu);
+    assert_eq!(capture.tile_count(), 22 * 18);
+
+    let (image, width, height) = capture.image();
+    assert_eq!((width, height), (22 * 8, 18 * 8));
+    assert_eq!(
+        image[0], COLORS[2],
+        "the tile map's last column is left of the first"
+    );
+    assert_eq!(image[8 + 2 * 8], COLORS[2]);
+    assert_eq!(image[8 + 20 * 8 + 7], COLORS[0]);
+
+    // Scrolling right forever stops capturing once the area reaches the cap
+    ppu.set_lcdc(ppu.lcdc() - LCDC::WINDOW_ENABLE);
+    let mut scroll_right = |capture: &mut MapCapture| {
+        for _ in 0..2_000 {
+            ppu.set_scx(ppu.scx().wrapping_add(100));
+            capture.capture(&ppu);
+        }
+    };
+    scroll_right(&mut capture);
+    let (image, width, height) = capture.image();
+    assert!((width / 8) * (height / 8) <= MapCapture::MAX_TILES);
+    assert_eq!(image.len(), width * height);
+    let tile_count = capture.tile_count();
+    scroll_right(&mut capture);
+    assert_eq!(capture.tile_count(), tile_count);
+}
+
+#[test]
+fn perf_stats() {
+    use gb_core::gameboy::ppu::monochrome::FRAME_T_CYCLES;
+
+    let mut gb = common::gameboy_with_code(&[0x18, 0xFE]); // JR -2
+    gb.run_frame();
+    let stats = *gb.perf_stats();
+    assert_eq!(stats.frames, 1);
+    assert_eq!(stats.cycles, FRAME_T_CYCLES as u64 / 4);
+    assert_eq!(stats.cpu_time, None);
+    assert_eq!(stats.ppu_time, None);
+
+    gb.set_perf_instrumentation(true);
+    gb.run_frame();
+    let stats = *gb.perf_stats();
+    assert_eq!(stats.frames, 2);
+    assert_eq!(stats.cycles, FRAME_T_CYCLES as u64 / 2);
+    assert!(stats.cpu_time.is_some());
+    assert!(stats.ppu_time.is_some());
+}
+
+#[test]
+fn coverage() {
+    #[rustfmt::skip]
+    let code = [
+        0x00,       // NOP
+        0x3E, 0x05, // LD A, $05
+        0xCB, 0x37, // SWAP A
+        0x18, 0xFE, // JR -2
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    assert!(gb.coverage().is_none());
+    gb.set_coverage(true);
+    // Instructions are counted as they're fetched and their operands once they've finished. The JR is fetched twice.
+    for _ in 0..5 {
+        gb.step_instruction();
+    }
+
+    let coverage = gb.coverage().unwrap();
+    assert_eq!(coverage.instructions(), 5);
+    assert_eq!(coverage.opcode_count(0x18), 2);
+    assert_eq!(coverage.opcode_count(0xCB), 1);
+    assert_eq!(coverage.cb_opcode_count(0x37), 1);
+    assert!(coverage.is_executed(0x102), "operands count as executed");
+    assert!(!coverage.is_executed(0x107));
+    assert_eq!(coverage.executed_bytes(), 7);
+    assert_eq!(coverage.executed_ranges(), vec![0x100..0x107]);
+    assert_eq!(
+        coverage.to_json(),
+        "{\"instructions\":5,\"opcodes\":{\"00\":1,\"18\":2,\"3E\":1,\"CB\":1},\"cb_opcodes\":{\"37\":1},\
+         \"rom_size\":32768,\"executed_bytes\":7,\"percent\":0.02,\"executed_ranges\":[[256,263]]}"
+    );
+
+    gb.set_coverage(false);
+    assert!(gb.coverage().is_none());
+}
+
+#[test]
+fn input_latency() {
+    #[rustfmt::skip]
+    let code = [
+        0xF0, 0x00, // LDH A, (P1)
+        0x18, 0xFC, // JR -4
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    gb.input_event();
+    gb.set_latency_tracking(true);
+    gb.run_frame();
+    assert_eq!(
+        gb.frame_presented().count(),
+        0,
+        "Inputs from before tracking started aren't measured"
+    );
+
+    gb.input_event();
+    gb.step_instruction();
+    gb.step_instruction();
+    assert_eq!(
+        gb.frame_presented().count(),
+        0,
+        "The input was latched in a frame that isn't finished"
+    );
+
+    gb.run_frame();
+    let samples: Vec<_> = gb.frame_presented().collect();
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].latched_frame, 1);
+    assert!(samples[0].to_present >= samples[0].to_latch);
+    assert_eq!(gb.frame_presented().count(), 0);
+
+    let stats = gb.latency_stats();
+    assert_eq!(stats.samples, 1);
+    assert_eq!(stats.average_to_present, samples[0].to_present);
+
+    // Games that don't read the joypad never latch the input
+    let mut gb = common::gameboy_with_code(&[0x18, 0xFE]); // JR -2
+    gb.set_latency_tracking(true);
+    gb.input_event();
+    gb.run_frame();
+    gb.run_frame();
+    assert_eq!(gb.frame_presented().count(), 0);
+    assert_eq!(gb.latency_stats().samples, 0);
+}
+
+#[test]
+#[rustfmt::skip]
+fn bus_trace() {
+    use gb_core::gameboy::debug::{trace::write_vcd, BusAccess, Responders};
+
+    let code = [
+        0x3E, 0x42,       // LD A, $42
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0xF0, 0x0F,       // LDH A, (IF)
+        0x03,             // INC BC
+        0x18, 0xFE,       // JR -2
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    gb.enable_bus_trace(100);
+    for _ in 0..5 {
+        gb.step_instruction();
+    }
+
+    let trace = gb.take_bus_trace();
+    assert_eq!(trace.len(), 12);
+    assert!(trace.windows(2).all(|pair| pair[1].cycle == pair[0].cycle + 1));
+    assert_eq!(trace[0].access, BusAccess::Read);
+    assert_eq!((trace[0].addr, trace[0].data), (0x100, 0x3E));
+    assert_eq!(trace[0].responders, Responders::CART);
+
+    let write = trace.iter().find(|event| event.access == BusAccess::Write).unwrap();
+    assert_eq!((write.addr, write.data), (0xC000, 0x42));
+    assert_eq!(write.responders, Responders::MEMORY);
+    let read_if = trace.iter().find(|event| event.addr == 0xFF0F).unwrap();
+    assert_eq!(read_if.responders, Responders::INTERRUPTS);
+    assert_eq!(trace.iter().filter(|event| event.access == BusAccess::Idle).count(), 1);
+
+    let mut vcd = Vec::new();
+    write_vcd(&trace, &mut vcd).unwrap();
+    let vcd = String::from_utf8(vcd).unwrap();
+    assert!(vcd.contains("$enddefinitions $end"));
+    assert!(vcd.contains("b1100000000000000 a\nb01000010 d\n0r\n1w\n"));
+
+    // Only the most recent cycles are kept
+    gb.enable_bus_trace(3);
+    for _ in 0..3 {
+        gb.step_instruction();
+    }
+    let trace = gb.take_bus_trace();
+    assert_eq!(trace.len(), 3);
+    assert!(gb.take_bus_trace().is_empty());
+
+    gb.disable_bus_trace();
+    gb.step_instruction();
+    assert!(gb.take_bus_trace().is_empty());
+
+    // Nothing is kept with no room
+    gb.enable_bus_trace(0);
+    gb.step_instruction();
+    assert!(gb.take_bus_trace().is_empty());
+}
+
+#[test]
+fn bus_conflicts() {
+    use gb_core::gameboy::debug::Responders;
+
+    assert!(!Responders::empty().is_conflict());
+    assert!(!Responders::CART.is_conflict());
+    assert!((Responders::CART | Responders::MEMORY).is_conflict());
+
+    // Touch every address, including ones nothing responds to
+    #[rustfmt::skip]
+    let code = [
+        0x21, 0x00, 0x00, // LD HL, $0000
+        0x7E,             // LD A, (HL)
+        0x23,             // INC HL
+        0x7C,             // LD A, H
+        0xB5,             // OR L
+        0x20, 0xFA,       // JR NZ, -6
+        0x18, 0xFE,       // JR -2
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    gb.set_conflict_detection(true);
+    for _ in 0..0x10000 * 5 {
+        gb.step_instruction();
+    }
+    assert_eq!(gb.cpu.cpu.registers.get_hl(), 0);
+    assert_eq!(gb.take_bus_conflicts(), vec![]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn stray_rom_writes() {
+    use gb_core::gameboy::{debug::StrayRomWrite, Gameboy};
+
+    let code = [
+        0x3E, 0x42,       // LD A, $42
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x18, 0xF6,       // JR -10
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    gb.step_instruction();
+    gb.step_instruction();
+    assert!(gb.take_stray_rom_writes().is_empty(), "detection is off by default");
+
+    gb.set_stray_rom_write_detection(true);
+    for _ in 0..4 {
+        gb.step_instruction();
+    }
+    let writes = gb.take_stray_rom_writes();
+    assert_eq!(writes.len(), 1, "a ROM only cart has no registers, but RAM is fine");
+    let StrayRomWrite { addr, data, pc, .. } = writes[0];
+    assert_eq!((addr, data, pc), (0x2000, 0x42, 0x102));
+    assert!(gb.take_stray_rom_writes().is_empty());
+
+    // $2000 is MBC1's bank register
+    let mut rom = common::rom_with_code(&code);
+    rom[0x147] = 0x01;
+    let mut gb = Gameboy::new(rom).unwrap();
+    gb.reset();
+    gb.set_stray_rom_write_detection(true);
+    for _ in 0..8 {
+        gb.step_instruction();
+    }
+    assert!(gb.take_stray_rom_writes().is_empty());
+}
+
+#[test]
+#[rustfmt::skip]
+fn stack_checks() {
+    use gb_core::gameboy::debug::StackWarning;
+
+    let code = [
+        0x31, 0x02, 0xC1, // LD SP, $C102
+        0xC5,             // PUSH BC
+        0xC5,             // PUSH BC
+        0xEA, 0x00, 0xC1, // LD ($C100), A
+        0x31, 0x00, 0xE0, // LD SP, $E000
+        0xC5,             // PUSH BC
+        0x31, 0x10, 0xFF, // LD SP, $FF10
+        0x31, 0x20, 0xFF, // LD SP, $FF20
+        0x31, 0xFE, 0xFF, // LD SP, $FFFE
+        0x31, 0x00, 0x00, // LD SP, $0000
+        0x18, 0xFE,       // JR -2
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    gb.set_stack_checks(true);
+    let protection = gb.protect_from_stack(0xC100..=0xC100);
+    for _ in 0..11 {
+        gb.step_instruction();
+    }
+
+    assert_eq!(
+        gb.take_stack_warnings(),
+        [
+            // Only pushes count, not other writes
+            StackWarning::ProtectedWrite { protection, addr: 0xC100, data: gb.cpu.cpu.registers.c, pc: 0x103 },
+            // $E000 is the top of work RAM, not echo RAM, since pushes write below SP. Moving around inside the I/O
+            // registers is one warning, and $0000 is one too, since a push there writes to IE.
+            StackWarning::UnusualSp { sp: 0xFF10, pc: 0x10C },
+            StackWarning::UnusualSp { sp: 0x0000, pc: 0x115 },
+        ]
+    );
+    assert!(gb.take_stack_warnings().is_empty());
+
+    gb.unprotect_from_stack(protection);
+    gb.set_stack_checks(false);
+    gb.step_instruction();
+    assert!(gb.take_stack_warnings().is_empty());
+}
+
+#[test]
+#[rustfmt::skip]
+fn memory_regions() {
+    use gb_core::gameboy::debug::{
+        regions::ConfigError, Protection, RegionSpec, RegionViolation, Violation,
+    };
+
+    let code = [
+        0x3E, 0x42,       // LD A, $42
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0xC3, 0x00, 0xC1, // JP $C100
+    ];
+    let config = "
+        # Comments and blank lines are ignored
+
+        C000-C00F state read-only
+        $C100-$C1FF data no-exec read-only  # so is this
+        0000-7FFF rom
+    ";
+    let specs = RegionSpec::parse_config(config).unwrap();
+    assert_eq!(specs[1].range, 0xC100..=0xC1FF);
+    assert_eq!(specs[1].protection, Protection::READ_ONLY | Protection::NO_EXEC);
+    assert_eq!(specs[2].protection, Protection::empty());
+
+    let mut gb = common::gameboy_with_code(&code);
+    // JR -2
+    gb.memory[0xC100] = 0x18;
+    gb.memory[0xC101] = 0xFE;
+    let ids: Vec<_> = specs.into_iter().map(|spec| gb.add_region(spec)).collect();
+    let names: Vec<_> = gb.regions_at(0x0105).map(|spec| spec.name.as_str()).collect();
+    assert_eq!(names, ["rom"]);
+
+    // The write still happens, but the frame stops straight after it
+    gb.run_frame();
+    assert_eq!(gb.perf_stats().frames, 1);
+    assert_eq!(
+        gb.take_break(),
+        Some(RegionViolation { region: ids[0], violation: Violation::Write { addr: 0xC000, data: 0x42 }, pc: 0x102 })
+    );
+    assert_eq!(gb.debug_read(0xC000), 0x42);
+    assert_eq!(gb.take_break(), None);
+
+    gb.run_frame();
+    assert_eq!(
+        gb.take_break(),
+        Some(RegionViolation { region: ids[1], violation: Violation::Execute { addr: 0xC100 }, pc: 0xC100 })
+    );
+
+    gb.remove_region(ids[1]);
+    assert!(gb.region(ids[1]).is_none());
+    gb.run_frame();
+    assert_eq!(gb.take_break(), None);
+
+    assert_eq!(
+        RegionSpec::parse_config("C000-C0FF ok\nC000 state"),
+        Err(ConfigError { line: 2, message: "Expected an address range like C000-C0FF" })
+    );
+    assert!(RegionSpec::parse_config("C0FF-C000 backwards").is_err());
+    assert!(RegionSpec::parse_config("C000-C0FF").is_err());
+    assert!(RegionSpec::parse_config("C000-C0FF state writable").is_err());
+}
+
+#[test]
+#[rustfmt::skip]
+fn doctor_traces() {
+    use gb_core::gameboy::{
+        debug::{TraceError, TraceLine},
+        models::DMG,
+        Gameboy,
+    };
+
+    let code = [
+        0x3E, 0x05, // LD A, $05
+        0x06, 0x07, // LD B, $07
+        0x80,       // ADD A, B
+        0xF0, 0x44, // LDH A, (LY)
+        0x18, 0xFE, // JR -2
+    ];
+    let trace = "
+        A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:3E,05,06,07
+        A:05 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0102 PCMEM:06,07,80,F0
+        A:05 F:B0 B:07 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0104 PCMEM:80,F0,44,18
+        A:0C F:00 B:07 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0105 PCMEM:F0,44,18,FE
+        A:90 F:00 B:07 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0107 PCMEM:18,FE,00,00
+        A:90 F:00 B:07 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0107 PCMEM:18,FE,00,00
+    ";
+    let mut gb = Gameboy::<DMG>::new(common::rom_with_code(&code)).unwrap();
+    assert_eq!(gb.compare_trace(trace, 2), Ok(6));
+
+    // Lines count from the start of the text, blank ones included, and are written back out the same way
+    let first = trace.lines().nth(1).unwrap().trim();
+    assert_eq!(TraceLine::parse(first).unwrap().to_string(), first);
+
+    // Pretend the reference emulator got the half carry of ADD A, B wrong
+    let wrong = trace.replace("A:0C F:00", "A:0C F:20");
+    let mut gb = Gameboy::<DMG>::new(common::rom_with_code(&code)).unwrap();
+    let divergence = match gb.compare_trace(&wrong, 2) {
+        Err(TraceError::Diverged(divergence)) => divergence,
+        result => panic!("Expected a divergence, got {:?}", result),
+    };
+    assert_eq!(divergence.line, 5);
+    assert_eq!(divergence.fields, ["F"]);
+    assert_eq!(u8::from(divergence.actual.registers.f), 0x00);
+    assert_eq!(u8::from(divergence.expected.registers.f), 0x20);
+    let history: Vec<u16> = divergence.history.iter().map(|line| line.registers.pc).collect();
+    assert_eq!(history, [0x0102, 0x0104]);
+
+    let broken = trace.replace("PCMEM:80,F0,44,18", "PCMEM:80,F0,44");
+    let mut gb = Gameboy::<DMG>::new(common::rom_with_code(&code)).unwrap();
+    assert_eq!(
+        gb.compare_trace(&broken, 0),
+        Err(TraceError::Parse { line: 4, message: "PCMEM needs four bytes" })
+    );
+    assert!(TraceLine::parse("A:01 F:B0 B:00").is_err());
+    assert!(TraceLine::parse("A:XY F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,00,00,00").is_err());
+}
+
+#[test]
+#[rustfmt::skip]
+fn state_diff() {
+    use gb_core::gameboy::debug::{RegisterDiff, StateDiff};
+
+    let code = [
+        0x3E, 0x05, // LD A, $05
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x18, 0xFE, // JR -2
+    ];
+    let mut a = common::gameboy_with_code(&code);
+    let mut b = common::gameboy_with_code(&code);
+    for _ in 0..4 {
+        a.step_instruction();
+        b.step_instruction();
+    }
+    let diff = StateDiff::compare(&a.snapshot(), &b.snapshot());
+    assert!(diff.is_empty(), "{}", diff);
+
+    b.cpu.cpu.registers.a = 0x06;
+    for (i, addr) in (0xC100..0xC110).enumerate() {
+        b.memory[addr] = i as u8 + 1;
+    }
+    b.memory[0xC000] = 0x07;
+    let diff = StateDiff::compare(&a.snapshot(), &b.snapshot());
+    assert_eq!(diff.registers, [RegisterDiff { name: "A", a: 0x05, b: 0x06 }]);
+    let ranges: Vec<(u16, u16)> = diff.ranges.iter().map(|range| (range.start, range.end)).collect();
+    assert_eq!(ranges, [(0xC000, 0xC000), (0xC100, 0xC10F)]);
+    assert_eq!(
+        diff.to_string(),
+        "A: $05 -> $06\n\
+         $C000 (work RAM): 05 -> 07\n\
+         $C100-$C10F (work RAM): 16 bytes differ\n"
+    );
+}
+
+#[test]
+#[rustfmt::skip]
+fn sram_views() {
+    use gb_core::gameboy::{
+        debug::{SramChange, SramView},
+        models::DMG,
+        Gameboy,
+    };
+
+    let code = [
+        0x3E, 0x0A,       // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A    Enable RAM
+        0x3E, 0x01,       // LD A, $01
+        0xEA, 0x00, 0x60, // LD ($6000), A    Mode 1
+        0xEA, 0x00, 0x40, // LD ($4000), A    RAM bank 1
+        0x3E, 0x42,       // LD A, $42
+        0xEA, 0x02, 0xA0, // LD ($A002), A
+        0x18, 0xFE,       // JR -2
+    ];
+    let mut rom = common::rom_with_code(&code);
+    rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x149] = 0x03; // 32 KiB
+    let mut gb = Gameboy::<DMG>::new(rom).unwrap();
+    gb.reset();
+
+    let party = SramView { name: "party".to_owned(), range: 0x2000..0x2004 };
+    let id = gb.add_sram_view(party.clone()).unwrap();
+    assert!(gb.add_sram_view(SramView { name: "empty".to_owned(), range: 0x10..0x10 }).is_err());
+    assert!(gb.add_sram_view(SramView { name: "past the end".to_owned(), range: 0x7FFF..0x8001 }).is_err());
+    assert_eq!(gb.sram_views().collect::<Vec<_>>(), [(id, &party)]);
+
+    for _ in 0..10 {
+        gb.step_instruction();
+    }
+    assert_eq!(gb.read_sram_view(id), Some(&[0x00, 0x00, 0x42, 0x00][..]));
+    assert_eq!(
+        gb.take_sram_changes(),
+        [SramChange { view: id, offset: 0x2002, old: 0x00, new: 0x42 }]
+    );
+    assert!(gb.take_sram_changes().is_empty());
+
+    // Changes made from outside the game are noticed too
+    gb.cart.write_ram(0x2000, &[0x07]).unwrap();
+    assert_eq!(
+        gb.take_sram_changes(),
+        [SramChange { view: id, offset: 0x2000, old: 0x00, new: 0x07 }]
+    );
+
+    gb.remove_sram_view(id);
+    assert_eq!(gb.read_sram_view(id), None);
+}
+
+#[test]
+fn rgbds_map_files() {
+    use gb_core::gameboy::debug::{ConfigError, MapFile, SectionType};
+
+    let map = MapFile::parse(
+        "SUMMARY:
+	ROM0: 336 bytes used / 16048 free
+	ROMX: 2048 bytes used / 14336 free in 1 bank
+
+ROM0 bank #0:
+	SECTION: $0100-$014f ($0050 bytes) [\"Header\"]
+	         $0100 = EntryPoint
+	         $0150 = Main
+	EMPTY: $0150-$3fff ($3eb0 bytes)
+	TOTAL EMPTY: $3eb0 bytes
+
+ROMX bank #2:
+	SECTION: $4000-$47ff ($0800 bytes) [\"Level data\"]
+	         $4000 = Level1
+	SECTION: $4800 ($0000 bytes) [\"Nothing\"]
+
+WRAM0 bank #0:
+	SECTION: $c000-$c0ff ($0100 bytes) [\"Variables\"]
+	         $c000 = wPlayerX
+",
+    )
+    .unwrap();
+
+    let names: Vec<_> = map.sections.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["Header", "Level data", "Nothing", "Variables"]);
+    assert_eq!(map.sections[1].kind, SectionType::RomX);
+    assert_eq!(map.sections[1].bank, 2);
+    assert_eq!(map.sections[2].size, 0);
+
+    // Banked addresses only match sections in the same bank
+    assert_eq!(map.section_at(2, 0x4100).unwrap().name, "Level data");
+    assert!(!map.is_mapped(1, 0x4100));
+    assert!(!map.is_mapped(2, 0x4800));
+    // Fixed memory doesn't care about the bank
+    assert_eq!(map.section_at(5, 0xC010).unwrap().name, "Variables");
+    assert_eq!(map.section_at(0, 0x0100).unwrap().name, "Header");
+
+    assert_eq!(map.symbol("Level1"), Some((2, 0x4000)));
+    assert_eq!(map.symbol("wPlayerX"), Some((0, 0xC000)));
+    assert_eq!(map.symbol("Missing"), None);
+
+    // The format of older versions
+    let old = MapFile::parse(
+        "ROM Bank #0 (HOME):
+  SECTION: $0000-$0007 ($0008 bytes) [\"RST_00\"]
+           $0000 = Reset
+    SLACK: $3ff8 bytes
+
+ROM Bank #1:
+  SECTION: $4000-$3fff ($0000 bytes) [\"Empty\"]
+  SECTION: $4000-$40ff ($0100 bytes) [\"Code\"]
+",
+    )
+    .unwrap();
+    let kinds: Vec<_> = old
+        .sections
+        .iter()
+        .map(|s| (s.kind, s.bank, s.size))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (SectionType::Rom0, 0, 8),
+            (SectionType::RomX, 1, 0),
+            (SectionType::RomX, 1, 0x100)
+        ]
+    );
+
+    assert_eq!(
+        MapFile::parse("\tSECTION: $0000-$0007 ($0008 bytes) [\"RST_00\"]"),
+        Err(ConfigError {
+            line: 1,
+            message: "A section comes before any bank"
+        })
+    );
+    assert!(MapFile::parse("ROMX bank #1:\n\tSECTION: $40zz ($0000 bytes) [\"A\"]").is_err());
+}
+
+#[test]
+#[rustfmt::skip]
+fn banked_breakpoints() {
+    use gb_core::gameboy::{
+        debug::{BankedAddr, BreakpointHit},
+        models::DMG,
+        Gameboy,
+    };
+
+    let code = [
+        0x3E, 0x02,       // LD A, $02
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xCD, 0x00, 0x40, // CALL $4000
+        0x3E, 0x03,       // LD A, $03
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xCD, 0x00, 0x40, // CALL $4000
+        0x18, 0xFE,       // JR -2
+    ];
+    // A 64 KiB MBC1 ROM, with a subroutine at $4000 in banks 2 and 3
+    let mut rom = vec![0; 0x10000];
+    rom[0x100..0x100 + code.len()].copy_from_slice(&code);
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+    for bank in [2, 3] {
+        rom[bank * 0x4000 + 1] = 0xC9; // RET
+    }
+
+    let parse = |s: &str| s.parse::<BankedAddr>().unwrap();
+    assert_eq!(parse("03:4000"), BankedAddr { bank: Some(3), addr: 0x4000 });
+    assert_eq!(parse("$4000"), BankedAddr { bank: None, addr: 0x4000 });
+    assert!("03:xyz".parse::<BankedAddr>().is_err());
+    assert_eq!(parse("03:4000").to_string(), "03:4000");
+
+    let mut gb = Gameboy::<DMG>::new(rom.clone()).unwrap();
+    gb.reset();
+    assert_eq!(gb.cart.rom_bank(0x0150), Some(0));
+    assert_eq!(gb.cart.rom_bank(0x4000), Some(1));
+    assert_eq!(gb.cart.rom_bank(0xC000), None);
+
+    // Only the call into bank 3 stops
+    let id = gb.add_breakpoint(parse("03:4000"));
+    gb.run_frame();
+    let hit = gb.take_breakpoint_hit().unwrap();
+    assert_eq!(hit, BreakpointHit { breakpoint: id, location: parse("03:4000") });
+    assert_eq!(gb.instruction_location(), parse("03:4000"));
+    assert_eq!(gb.cpu.cpu.registers.a, 0x03);
+    gb.remove_breakpoint(id);
+
+    // Without a bank it stops in any bank
+    let mut gb = Gameboy::<DMG>::new(rom).unwrap();
+    gb.reset();
+    gb.add_breakpoint(parse("4000"));
+    gb.run_frame();
+    assert_eq!(gb.take_breakpoint_hit().unwrap().location, parse("02:4000"));
+    gb.run_frame();
+    assert_eq!(gb.take_breakpoint_hit().unwrap().location, parse("03:4000"));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use gb_core::gameboy::determinism::{DeterminismConfig, RamFill};
+
+const REGIONS: [(u16, u16); 3] = [(0xC000, 0xDFFF), (0x8000, 0x9FFF), (0xFF80, 0xFFFE)];
+
+#[test]
+fn zeroed_by_default() {
+    let gb = common::gameboy_with_code(&[0x18, 0xFE]);
+    assert_eq!(gb.determinism_config(), DeterminismConfig::default());
+    for &(start, end) in &REGIONS {
+        assert!((start..=end).all(|addr| gb.debug_read(addr) == 0x00));
+    }
+}
+
+#[test]
+fn fills() {
+    for &fill in &[
+        RamFill::Ones,
+        RamFill::Nibbles,
+        RamFill::Random { seed: 0 },
+        RamFill::Random { seed: 0x1234 },
+    ] {
+        let mut gb = common::gameboy_with_code(&[0x18, 0xFE]);
+        gb.set_determinism_config(DeterminismConfig::uniform(fill));
+        for &(start, end) in &REGIONS {
+            for addr in start..=end {
+                assert_eq!(
+                    gb.debug_read(addr),
+                    fill.byte_at(addr),
+                    "{:?} ${:04X}",
+                    fill,
+                    addr
+                );
+            }
+        }
+    }
+
+    assert_eq!(RamFill::Nibbles.byte_at(0xC000), 0x0F);
+    assert_eq!(RamFill::Nibbles.byte_at(0xC008), 0xF0);
+}
+
+#[test]
+fn regions_filled_separately() {
+    let mut gb = common::gameboy_with_code(&[0x18, 0xFE]);
+    gb.set_determinism_config(DeterminismConfig {
+        wram: RamFill::Ones,
+        vram: RamFill::Zero,
+        hram: RamFill::Nibbles,
+    });
+    assert_eq!(gb.debug_read(0xD123), 0xFF);
+    assert_eq!(gb.debug_read(0x9123), 0x00);
+    assert_eq!(gb.debug_read(0xFF80), 0x0F);
+}
+
+#[test]
+fn random_fill_is_seeded() {
+    let bytes = |seed| {
+        (0xC000..=0xDFFF)
+            .map(|addr| RamFill::Random { seed }.byte_at(addr))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(bytes(1), bytes(1));
+    assert_ne!(bytes(1), bytes(2));
+
+    // Random enough that a game seeding its RNG from RAM doesn't see the same few values
+    let mut seen = [false; 256];
+    for byte in bytes(1) {
+        seen[byte as usize] = true;
+    }
+    assert!(seen.iter().all(|&seen| seen));
+}
+
+#[test]
+#[rustfmt::skip]
+fn cpu_reads_fill() {
+    let mut gb = common::gameboy_with_code(&[
+        0xFA, 0x00, 0xC0, // LD A, ($C000)
+        0x47,             // LD B, A
+        0xF0, 0x80,       // LDH A, ($FF80)
+        0x18, 0xFE,       // JR -2
+    ]);
+    gb.set_determinism_config(DeterminismConfig::uniform(RamFill::Random { seed: 7 }));
+    for _ in 0..20 {
+        gb.clock();
+    }
+    let r = &gb.cpu.cpu.registers;
+    assert_eq!(r.b, RamFill::Random { seed: 7 }.byte_at(0xC000));
+    assert_eq!(r.a, RamFill::Random { seed: 7 }.byte_at(0xFF80));
+}
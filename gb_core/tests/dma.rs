@@ -0,0 +1,94 @@
+mod common;
+
+/// The usual OAM DMA routine, meant to be copied to HRAM: start a transfer from page `source`, and wait 160 M-cycles
+#[rustfmt::skip]
+fn dma_routine(source: u8) -> [u8; 9] {
+    [
+        0x3E, source, // LD A, source
+        0xE0, 0x46,   // LDH (DMA), A
+        0x3E, 0x28,   // LD A, 40
+        0x3D,         // .wait: DEC A
+        0x20, 0xFD,   // JR NZ, .wait
+    ]
+}
+
+/// Code that copies `routine` to HRAM, followed by a `RET`, and calls it
+fn call_from_hram(routine: &[u8]) -> Vec<u8> {
+    let mut code = Vec::new();
+    for (i, &byte) in routine.iter().chain(&[0xC9]).enumerate() {
+        code.extend_from_slice(&[0x3E, byte, 0xE0, 0x80 + i as u8]); // LD A, byte; LDH ($80 + i), A
+    }
+    code.extend_from_slice(&[0xCD, 0x80, 0xFF]); // CALL $FF80
+    code
+}
+
+#[test]
+fn dma_from_hram() {
+    let mut code = call_from_hram(&dma_routine(0x02));
+    code.extend_from_slice(&[
+        0x06, 0x42, // LD B, $42
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut rom = common::rom_with_code(&code);
+    for i in 0..0xA0 {
+        rom[0x200 + i] = i as u8 ^ 0x5A;
+    }
+    let mut gb = gb_core::gameboy::Gameboy::new(rom).unwrap();
+    gb.reset();
+
+    for _ in 0..1000 {
+        gb.clock();
+    }
+    // The routine returned normally
+    assert_eq!(gb.cpu.cpu.registers.b, 0x42);
+    assert!(!gb.oam_dma().active());
+    assert_eq!(gb.debug_read(0xFF46), 0x02);
+    for i in 0..0xA0 {
+        assert_eq!(gb.debug_read(0xFE00 + i), i as u8 ^ 0x5A);
+    }
+}
+
+#[test]
+fn dma_blocks_the_cpu_outside_hram() {
+    // Starting a transfer from ROM means the CPU fetches the bytes being copied instead of its own code. The source is
+    // all NOPs, so the INC Bs that would have run during the transfer are skipped.
+    let mut code = vec![
+        0x3E, 0x02, // LD A, $02
+        0xE0, 0x46, // LDH (DMA), A
+    ];
+    code.extend_from_slice(&[0x04; 200]); // INC B
+    code.extend_from_slice(&[0x18, 0xFE]); // JR -2
+    let mut gb = common::gameboy_with_code(&code);
+
+    for _ in 0..1000 {
+        gb.clock();
+    }
+    assert_eq!(gb.cpu.cpu.registers.b, 200 - 160);
+}
+
+#[test]
+#[rustfmt::skip]
+fn dma_drops_writes_outside_hram() {
+    let routine = [
+        0x3E, 0x02, // LD A, $02
+        0xE0, 0x46, // LDH (DMA), A
+        0x3E, 0x99, // LD A, $99
+        0xEA, 0x00, 0xC0, // LD ($C000), A ; dropped
+        0xE0, 0xFE, // LDH ($FE), A ; HRAM is still reachable
+        0x3E, 0x26, // LD A, 38
+        0x3D,       // .wait: DEC A
+        0x20, 0xFD, // JR NZ, .wait
+        0x3E, 0x77, // LD A, $77
+        0xEA, 0x01, 0xC0, // LD ($C001), A ; the transfer is over
+    ];
+    let mut code = call_from_hram(&routine);
+    code.extend_from_slice(&[0x18, 0xFE]); // JR -2
+    let mut gb = common::gameboy_with_code(&code);
+
+    for _ in 0..1000 {
+        gb.clock();
+    }
+    assert_eq!(gb.debug_read(0xC000), 0x00);
+    assert_eq!(gb.debug_read(0xFFFE), 0x99);
+    assert_eq!(gb.debug_read(0xC001), 0x77);
+}
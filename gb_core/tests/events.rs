@@ -0,0 +1,123 @@
+mod common;
+
+use gb_core::gameboy::{
+    models::DMG,
+    ppu::{registers::LCDC, PPU},
+    Gameboy,
+};
+
+const IF: u16 = 0xFF0F;
+
+/// Run `setup`, followed by a `JR -2` loop, until the loop is reached so every write in `setup` has happened
+fn gameboy_after(setup: &[u8]) -> Box<Gameboy<DMG>> {
+    let mut code = setup.to_vec();
+    code.extend_from_slice(&[0x18, 0xFE]); // JR -2
+    let mut gb = common::gameboy_with_code(&code);
+    let jr = 0x100 + setup.len() as u16;
+    while gb.cpu.cpu.registers.pc != jr + 1 {
+        gb.clock();
+    }
+    gb
+}
+
+#[test]
+#[rustfmt::skip]
+fn vblank() {
+    let setup = [
+        0xAF,       // XOR A
+        0xE0, 0x0F, // LDH (IF), A ; clear the VBlank request left by the boot ROM
+    ];
+    // Start from points all over the frame, including the middle of VBlank
+    for offset in (0..20000).step_by(997) {
+        let mut gb = gameboy_after(&setup);
+        for _ in 0..offset {
+            gb.clock();
+        }
+        let cycles = gb.next_events().vblank.unwrap();
+        let frame_count = gb.ppu.frame_count();
+        let irq = gb.debug_read(IF) & 0x01;
+
+        for remaining in (1..=cycles).rev() {
+            assert_eq!(gb.next_events().vblank, Some(remaining));
+            if remaining == 1 {
+                // The frame is finished as the PPU reaches line 144, and the interrupt follows on the next M-cycle
+                assert_ne!(gb.ppu.frame_count(), frame_count, "offset {}", offset);
+            } else {
+                assert_eq!(gb.ppu.frame_count(), frame_count, "offset {}", offset);
+            }
+            assert_eq!(gb.debug_read(IF) & 0x01, irq, "offset {}", offset);
+            gb.clock();
+        }
+        assert_eq!(gb.debug_read(IF) & 0x01, 0x01, "offset {}", offset);
+        assert_eq!(gb.next_events().vblank, Some(70224 / 4));
+    }
+}
+
+#[test]
+#[rustfmt::skip]
+fn timer_overflow() {
+    for &tac in &[0x04, 0x05, 0x06, 0x07] {
+        // Far enough from overflowing that it can't happen before the loop is reached
+        for &tima in &[0xF0, 0xC0, 0x00] {
+            let setup = [
+                0x3E, tima, // LD A, tima
+                0xE0, 0x05, // LDH (TIMA), A
+                0xAF,       // XOR A
+                0xE0, 0x0F, // LDH (IF), A
+                0x3E, tac,  // LD A, tac
+                0xE0, 0x07, // LDH (TAC), A
+            ];
+            // Start from different phases of the divider
+            for offset in 0..8 {
+                let mut gb = gameboy_after(&setup);
+                for _ in 0..offset {
+                    gb.clock();
+                }
+                let cycles = gb.next_events().timer_overflow.unwrap();
+                for remaining in (1..=cycles).rev() {
+                    assert_eq!(gb.next_events().timer_overflow, Some(remaining));
+                    assert_eq!(gb.debug_read(IF) & 0x04, 0, "TAC {:02X} TIMA {:02X}", tac, tima);
+                    gb.clock();
+                }
+                assert_eq!(gb.debug_read(IF) & 0x04, 0x04, "TAC {:02X} TIMA {:02X}", tac, tima);
+            }
+        }
+    }
+}
+
+#[test]
+#[rustfmt::skip]
+fn disabled() {
+    // The timer is disabled after boot
+    let gb = common::gameboy_with_code(&[0x18, 0xFE]);
+    assert_eq!(gb.next_events().timer_overflow, None);
+    assert!(gb.next_events().vblank.is_some());
+
+    let gb = gameboy_after(&[
+        0xAF,       // XOR A
+        0xE0, 0x40, // LDH (LCDC), A
+    ]);
+    let events = gb.next_events();
+    assert_eq!((events.vblank, events.ppu_mode, events.ly), (None, 0, 0));
+}
+
+#[test]
+fn ppu_phase() {
+    let mut gb = common::gameboy_with_code(&[0x18, 0xFE]);
+    for _ in 0..70224 / 4 {
+        let events = gb.next_events();
+        assert_eq!(events.ppu_mode, gb.debug_read(0xFF41) & 0b11);
+        assert_eq!(events.ly, gb.debug_read(0xFF44));
+        gb.clock();
+    }
+}
+
+#[test]
+fn next_interrupt() {
+    let mut gb = common::gameboy_with_code(&[0x18, 0xFE]);
+    let events = gb.next_events();
+    assert_eq!(events.next_interrupt(), events.vblank);
+
+    gb.ppu.set_lcdc(LCDC::empty());
+    assert_eq!(gb.next_events().next_interrupt(), None);
+}
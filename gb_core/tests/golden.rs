@@ -0,0 +1,74 @@
+//! Golden-frame tests: run a test ROM until its screen stops changing, and compare a hash of the frame with a
+//! reference kept in `tests/golden/<name>.hash`.
+//!
+//! The ROMs themselves aren't checked in, see [`common::test_rom`], so the tests are ignored by default: run them with
+//! `cargo test --test golden -- --ignored` once the ROMs are in place. Like the other test ROM tests, a missing ROM
+//! fails them rather than skipping. To add or update a reference, check the frame by eye (e.g. with
+//! `gb_iced test <rom> --hash`) and run the tests with `GB_BLESS=1`, which writes the current hashes.
+//!
+//! cgb-acid2 belongs here too once there's a CGB model.
+
+mod common;
+
+use std::path::PathBuf;
+
+use gb_core::gameboy::{models::DMG, ppu::PPU, Gameboy};
+
+/// How many identical frames in a row count as the screen having settled
+const STABLE_FRAMES: u32 = 10;
+
+/// Run until `STABLE_FRAMES` frames in a row have the same hash, and return it
+fn stable_frame_hash(gb: &mut Gameboy<DMG>, max_frames: u32) -> u64 {
+    let mut last_hash = None;
+    let mut unchanged = 0;
+    for _ in 0..max_frames {
+        gb.run_frame();
+        let hash = gb.ppu.get_frame().hash();
+        if last_hash == Some(hash) {
+            unchanged += 1;
+            if unchanged == STABLE_FRAMES {
+                return hash;
+            }
+        } else {
+            unchanged = 0;
+        }
+        last_hash = Some(hash);
+    }
+    panic!("the screen didn't settle within {} frames", max_frames);
+}
+
+fn check_golden_frame(rom: &str, name: &str, max_frames: u32) {
+    let mut gb = common::gameboy_with_test_rom(rom)
+        .unwrap_or_else(|| panic!("{} is missing, see `common::test_rom`", rom));
+    let hash = stable_frame_hash(&mut gb, max_frames);
+
+    let reference = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.hash", name));
+    if std::env::var_os("GB_BLESS").is_some() {
+        std::fs::create_dir_all(reference.parent().unwrap()).unwrap();
+        std::fs::write(&reference, format!("{:016x}\n", hash)).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&reference).unwrap_or_else(|e| {
+        panic!(
+            "no reference hash in {} ({}). The frame hashed to {:016x}; if it looks right, rerun with GB_BLESS=1",
+            reference.display(),
+            e,
+            hash
+        )
+    });
+    let expected = u64::from_str_radix(expected.trim(), 16).expect("malformed reference hash");
+    assert_eq!(
+        hash, expected,
+        "the frame hashed to {:016x} rather than {:016x}",
+        hash, expected
+    );
+}
+
+#[test]
+#[ignore = "needs dmg-acid2.gb in the test ROM directory"]
+fn dmg_acid2() {
+    check_golden_frame("dmg-acid2.gb", "dmg-acid2", 600);
+}
@@ -0,0 +1,361 @@
+mod common;
+
+use gb_core::gameboy::{models::DMG, ppu::PPU, Gameboy, IllegalOpcode, IllegalOpcodePolicy};
+
+/// Requests the interrupts in `request` with the ones in `enable` enabled, and runs until they've been serviced.
+///
+/// Every handler records its vector and the value of IF on entry. Returns the records in the order the handlers ran.
+#[rustfmt::skip]
+fn service_interrupts(request: u8, enable: u8) -> (Vec<(u8, u8)>, Box<Gameboy<DMG>>) {
+    let code = [
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        0x3E, enable,     // LD A, enable
+        0xE0, 0xFF,       // LDH (IE), A
+        0x3E, request,    // LD A, request
+        0xE0, 0x0F,       // LDH (IF), A
+        0xFB,             // EI
+        0x00,             // NOP
+        0x18, 0xFE,       // JR -2
+    ];
+    let mut rom = common::rom_with_code(&code);
+    for vector in (0x40..=0x60).step_by(8) {
+        rom[vector..vector + 7].copy_from_slice(&[
+            0x36, vector as u8, // LD (HL), vector
+            0x23,               // INC HL
+            0xF0, 0x0F,         // LDH A, (IF)
+            0x22,               // LD (HL+), A
+            0xD9,               // RETI
+        ]);
+    }
+    let mut gb = Gameboy::new(rom).unwrap();
+    gb.reset();
+
+    for _ in 0..500 {
+        gb.clock();
+    }
+    let records = (0xC000..0xC00A)
+        .step_by(2)
+        .map(|addr| (gb.debug_read(addr), gb.debug_read(addr + 1)))
+        .take_while(|&(vector, _)| vector != 0)
+        .collect();
+    (records, gb)
+}
+
+#[test]
+fn simultaneous_interrupts_in_priority_order() {
+    let (records, gb) = service_interrupts(0b11100, 0b11111);
+    // Each dispatch clears only the bit of the interrupt it services
+    assert_eq!(records, [(0x50, 0b11000), (0x58, 0b10000), (0x60, 0b00000)]);
+    assert_eq!(gb.debug_read(0xFF0F), 0);
+}
+
+#[test]
+fn all_interrupts_in_priority_order() {
+    let (records, gb) = service_interrupts(0b11111, 0b11111);
+    assert_eq!(
+        records,
+        [
+            (0x40, 0b11110),
+            (0x48, 0b11100),
+            (0x50, 0b11000),
+            (0x58, 0b10000),
+            (0x60, 0b00000),
+        ]
+    );
+    assert_eq!(gb.debug_read(0xFF0F), 0);
+}
+
+#[test]
+fn only_enabled_interrupts_are_serviced() {
+    let (records, gb) = service_interrupts(0b11100, 0b01000);
+    assert_eq!(records, [(0x58, 0b10100)]);
+    // The others stay requested
+    assert_eq!(gb.debug_read(0xFF0F), 0b10100);
+}
+
+#[test]
+#[rustfmt::skip]
+fn no_interrupts_without_ime() {
+    let mut rom = common::rom_with_code(&[
+        0x3E, 0x1C, // LD A, $1C
+        0xE0, 0xFF, // LDH (IE), A
+        0xE0, 0x0F, // LDH (IF), A
+        0x18, 0xFE, // JR -2
+    ]);
+    rom[0x50] = 0x76; // HALT, so servicing the interrupt would be noticed
+    let mut gb = Gameboy::new(rom).unwrap();
+    gb.reset();
+
+    for _ in 0..500 {
+        gb.clock();
+    }
+    let pc = gb.cpu.cpu.registers.pc;
+    assert!((0x106..=0x108).contains(&pc), "PC was {:04X}", pc);
+    assert_eq!(gb.debug_read(0xFF0F), 0x1C);
+}
+
+/// Turns the LCD on with `stat` written to STAT and the interrupts in `enable` enabled, then counts the VBlank and
+/// STAT interrupts serviced over `frames` frames in B and C
+#[rustfmt::skip]
+fn count_ppu_interrupts(enable: u8, stat: u8, frames: u32) -> (u8, u8) {
+    let mut rom = common::rom_with_code(&[
+        0x01, 0x00, 0x00, // LD BC, $0000
+        0x3E, 0x80,   // LD A, $80
+        0xE0, 0x40,   // LDH (LCDC), A
+        0x3E, stat,   // LD A, stat
+        0xE0, 0x41,   // LDH (STAT), A
+        0x3E, enable, // LD A, enable
+        0xE0, 0xFF,   // LDH (IE), A
+        0xAF,         // XOR A
+        0xE0, 0x0F,   // LDH (IF), A, dropping the VBlank interrupt left over from boot
+        0xFB,         // EI
+        0x18, 0xFE,   // JR -2
+    ]);
+    rom[0x40..0x42].copy_from_slice(&[0x04, 0xD9]); // INC B; RETI
+    rom[0x48..0x4A].copy_from_slice(&[0x0C, 0xD9]); // INC C; RETI
+    let mut gb = Gameboy::new(rom).unwrap();
+    gb.reset();
+
+    for _ in 0..frames {
+        gb.run_frame();
+    }
+    (gb.cpu.cpu.registers.b, gb.cpu.cpu.registers.c)
+}
+
+#[test]
+fn vblank_interrupt_once_per_frame() {
+    // VBlank lasts 10 lines, but acknowledging the interrupt means it only runs once
+    assert_eq!(count_ppu_interrupts(0b01, 0, 3), (3, 0));
+}
+
+#[test]
+fn stat_interrupt_once_per_line() {
+    // The HBlank STAT interrupt
+    assert_eq!(count_ppu_interrupts(0b10, 0x08, 1), (0, 144));
+}
+
+/// Sleeps in HALT between interrupts, counting VBlank interrupts in B, timer interrupts in C and STAT interrupts in D.
+/// `ie` picks the interrupts, and `stat` the STAT interrupt sources.
+#[rustfmt::skip]
+fn halt_loop(fast_idle: bool, ie: u8, stat: u8) -> Box<Gameboy<DMG>> {
+    let mut rom = common::rom_with_code(&[
+        0x01, 0x00, 0x00, // LD BC, $0000
+        0x16, 0x00, // LD D, $00
+        0x3E, 0x80, // LD A, $80
+        0xE0, 0x40, // LDH (LCDC), A
+        0x3E, stat, // LD A, stat
+        0xE0, 0x41, // LDH (STAT), A
+        0x3E, 0x05, // LD A, $05
+        0xE0, 0x07, // LDH (TAC), A
+        0x3E, ie,   // LD A, ie
+        0xE0, 0xFF, // LDH (IE), A
+        0xAF,       // XOR A
+        0xE0, 0x0F, // LDH (IF), A, dropping the VBlank interrupt left over from boot
+        0xFB,       // EI
+        0x76,       // HALT
+        0x18, 0xFD, // JR -3
+    ]);
+    rom[0x40..0x42].copy_from_slice(&[0x04, 0xD9]); // INC B; RETI
+    rom[0x48..0x4A].copy_from_slice(&[0x14, 0xD9]); // INC D; RETI
+    rom[0x50..0x52].copy_from_slice(&[0x0C, 0xD9]); // INC C; RETI
+    let mut gb = Gameboy::new(rom).unwrap();
+    gb.reset();
+    gb.set_fast_idle(fast_idle);
+
+    for _ in 0..3 {
+        gb.run_frame();
+    }
+    gb
+}
+
+/// Everything fast idle could get wrong
+fn idle_state(gb: &Gameboy<DMG>) -> impl PartialEq + std::fmt::Debug {
+    let registers = &gb.cpu.cpu.registers;
+    (
+        [registers.b, registers.c, registers.d],
+        (registers.pc, registers.sp),
+        [0xFF04, 0xFF05, 0xFF0F, 0xFF41, 0xFF44].map(|addr| gb.debug_read(addr)),
+        gb.timer().div_apu_ticks(),
+        gb.perf_stats().cycles,
+        (gb.ppu.frame_count(), gb.ppu.get_frame().hash()),
+    )
+}
+
+#[test]
+fn fast_idle_matches_normal_clocking() {
+    // VBlank and timer interrupts, and then the HBlank STAT interrupt too, which wakes the CPU in the middle of a skip
+    for &(ie, stat) in &[(0x05, 0x00), (0x07, 0x08)] {
+        let normal = halt_loop(false, ie, stat);
+        let fast = halt_loop(true, ie, stat);
+        assert_eq!(idle_state(&fast), idle_state(&normal), "IE {:02X}", ie);
+
+        // The interrupts actually woke the CPU
+        let registers = &normal.cpu.cpu.registers;
+        assert_eq!(registers.b, 3);
+        assert!(registers.c > 0);
+        assert_eq!(registers.d > 0, ie & 0x02 != 0);
+    }
+}
+
+/// Waits in polling loops, counting each time one is left: for LY to reach 144 in B, for HBlank in C, for a flag in
+/// HRAM the VBlank handler sets in D, and for the timer's bit in IF, which isn't enabled in IE, in E. `ie` and `stat`
+/// can add a STAT interrupt.
+#[rustfmt::skip]
+fn polling_loop(fast_idle: bool, ie: u8, stat: u8) -> Box<Gameboy<DMG>> {
+    let mut rom = common::rom_with_code(&[
+        0x3E, 0x07, // LD A, $07
+        0xE0, 0x07, // LDH (TAC), A
+        0x3E, stat, // LD A, stat
+        0xE0, 0x41, // LDH (STAT), A
+        0x3E, ie,   // LD A, ie
+        0xE0, 0xFF, // LDH (IE), A
+        0xAF,       // XOR A
+        0xE0, 0x0F, // LDH (IF), A
+        0x01, 0x00, 0x00, // LD BC, $0000
+        0x11, 0x00, 0x00, // LD DE, $0000
+        0xFB,       // EI
+        // main:
+        0xF0, 0x44, // LDH A, (LY)
+        0xFE, 0x90, // CP 144
+        0x20, 0xFA, // JR NZ, -6
+        0x04,       // INC B
+        0xAF,       // XOR A
+        0xE0, 0x80, // LDH ($FF80), A
+        0xF0, 0x41, // LDH A, (STAT)
+        0xE6, 0x03, // AND $03
+        0x20, 0xFA, // JR NZ, -6
+        0x0C,       // INC C
+        0xF0, 0x80, // LDH A, ($FF80)
+        0xA7,       // AND A
+        0x28, 0xFB, // JR Z, -5
+        0x14,       // INC D
+        0xF0, 0x0F, // LDH A, (IF)
+        0xE6, 0xFB, // AND $FB
+        0xE0, 0x0F, // LDH (IF), A
+        0xF0, 0x0F, // LDH A, (IF)
+        0xCB, 0x57, // BIT 2, A
+        0x28, 0xFA, // JR Z, -6
+        0x1C,       // INC E
+        0x18, 0xDA, // JR main
+    ]);
+    rom[0x40..0x45].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x80, 0xD9]); // LD A, $01; LDH ($FF80), A; RETI
+    rom[0x48] = 0xD9; // RETI
+    let mut gb = Gameboy::new(rom).unwrap();
+    gb.reset();
+    gb.set_fast_idle(fast_idle);
+
+    for _ in 0..10 {
+        gb.run_frame();
+    }
+    gb
+}
+
+#[test]
+fn fast_idle_skips_polling_loops_exactly() {
+    for &(ie, stat) in &[(0x01, 0x00), (0x03, 0x08)] {
+        let normal = polling_loop(false, ie, stat);
+        let fast = polling_loop(true, ie, stat);
+        assert_eq!(idle_state(&fast), idle_state(&normal), "IE {:02X}", ie);
+        assert_eq!(fast.cpu.cpu.registers.e, normal.cpu.cpu.registers.e);
+
+        // Every loop was left more than once
+        let registers = &normal.cpu.cpu.registers;
+        assert!(
+            [registers.b, registers.c, registers.d, registers.e]
+                .iter()
+                .all(|&count| count >= 2),
+            "{:?}",
+            registers
+        );
+    }
+}
+
+/// Runs an illegal opcode with the timer interrupt enabled
+#[rustfmt::skip]
+fn lock_up(policy: IllegalOpcodePolicy) -> Box<Gameboy<DMG>> {
+    let mut rom = common::rom_with_code(&[
+        0x01, 0x00, 0x00, // LD BC, $0000
+        0x3E, 0x05, // LD A, $05
+        0xE0, 0x07, // LDH (TAC), A
+        0x3E, 0x04, // LD A, $04
+        0xE0, 0xFF, // LDH (IE), A
+        0xFB,       // EI
+        0x04,       // INC B
+        0xE4,       // illegal
+        0x04,       // INC B
+    ]);
+    rom[0x50..0x52].copy_from_slice(&[0x0C, 0xD9]); // INC C; RETI
+    let mut gb = Gameboy::new(rom).unwrap();
+    gb.reset();
+    gb.set_illegal_opcode_policy(policy);
+    gb
+}
+
+#[test]
+fn illegal_opcodes_lock_up() {
+    let mut gb = lock_up(IllegalOpcodePolicy::Break);
+    gb.run_frame();
+    assert!(gb.is_locked_up());
+    assert!(
+        gb.perf_stats().cycles < 100,
+        "run_frame stopped at the illegal opcode"
+    );
+    assert_eq!(
+        gb.take_illegal_opcode(),
+        Some(IllegalOpcode {
+            opcode: 0xE4,
+            pc: 0x10D
+        })
+    );
+
+    gb.step_instruction();
+    for _ in 0..2 {
+        gb.run_frame();
+    }
+    assert_eq!(gb.take_illegal_opcode(), None);
+    let registers = &gb.cpu.cpu.registers;
+    assert_eq!((registers.b, registers.c, registers.pc), (1, 0, 0x10E));
+    assert_ne!(
+        gb.debug_read(0xFF0F) & 0x04,
+        0,
+        "the timer interrupt is never serviced"
+    );
+
+    let mut gb = lock_up(IllegalOpcodePolicy::LockUp);
+    gb.set_fast_idle(true);
+    gb.run_frame();
+    assert!(gb.is_locked_up());
+    assert_eq!(gb.take_illegal_opcode(), None);
+}
+
+#[test]
+fn reset_after_lock_up() {
+    let mut gb = lock_up(IllegalOpcodePolicy::Report);
+    gb.run_frame();
+    assert!(gb.is_locked_up());
+
+    gb.reset();
+    assert!(!gb.is_locked_up());
+    assert_eq!(gb.take_illegal_opcode(), None);
+    // The code runs again from the start, and locks up at the same place
+    gb.run_frame();
+    assert!(gb.is_locked_up());
+    assert_eq!(gb.cpu.cpu.registers.b, 1);
+    assert_eq!(
+        gb.take_illegal_opcode().map(|illegal| illegal.pc),
+        Some(0x10D)
+    );
+}
+
+#[test]
+fn fast_idle_while_locked_up() {
+    let run = |fast_idle| {
+        let mut gb = lock_up(IllegalOpcodePolicy::LockUp);
+        gb.set_fast_idle(fast_idle);
+        for _ in 0..3 {
+            gb.run_frame();
+        }
+        gb
+    };
+    assert_eq!(idle_state(&run(true)), idle_state(&run(false)));
+}
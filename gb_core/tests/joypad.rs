@@ -0,0 +1,196 @@
+mod common;
+
+use gb_core::gameboy::{
+    joypad::{Button, ButtonState},
+    models::DMG,
+    ppu::PPU,
+    Gameboy,
+};
+
+/// Writes `select` to P1, then counts joypad interrupts in C (clearing IF after each one)
+#[rustfmt::skip]
+fn joypad_test(select: u8) -> Box<Gameboy<DMG>> {
+    let code = [
+        0x3E, select, // LD A, select
+        0xE0, 0x00,   // LDH (P1), A
+        0x0E, 0x00,   // LD C, 0
+        0xF0, 0x0F,   // loop: LDH A, (IF)
+        0xE6, 0x10,   // AND $10
+        0x28, 0xFA,   // JR Z, loop
+        0x0C,         // INC C
+        0xAF,         // XOR A
+        0xE0, 0x0F,   // LDH (IF), A
+        0x18, 0xF4,   // JR loop
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    run(&mut gb);
+    gb
+}
+
+fn run(gb: &mut Gameboy<DMG>) {
+    for _ in 0..32 {
+        gb.clock();
+    }
+}
+
+fn p1(gb: &Gameboy<DMG>) -> u8 {
+    gb.debug_read(0xFF00) & 0x0F
+}
+
+fn interrupts(gb: &Gameboy<DMG>) -> u8 {
+    gb.cpu.cpu.registers.c
+}
+
+#[test]
+fn select_lines() {
+    let press_a_and_left = |gb: &mut Gameboy<DMG>| {
+        gb.joypad.press(Button::A);
+        gb.joypad.press(Button::Left);
+        run(gb);
+    };
+
+    // Action buttons
+    let mut gb = joypad_test(0x10);
+    press_a_and_left(&mut gb);
+    assert_eq!(p1(&gb), 0b1110);
+
+    // Directions
+    let mut gb = joypad_test(0x20);
+    press_a_and_left(&mut gb);
+    assert_eq!(p1(&gb), 0b1101);
+
+    // Both groups are ANDed together
+    let mut gb = joypad_test(0x00);
+    press_a_and_left(&mut gb);
+    assert_eq!(p1(&gb), 0b1100);
+
+    // Neither
+    let mut gb = joypad_test(0x30);
+    press_a_and_left(&mut gb);
+    assert_eq!(p1(&gb), 0b1111);
+    assert_eq!(interrupts(&gb), 0);
+}
+
+#[test]
+fn opposing_directions() {
+    let mut gb = joypad_test(0x20);
+    gb.joypad.press(Button::Left);
+    gb.joypad.press(Button::Right);
+    gb.joypad.press(Button::Up);
+    run(&mut gb);
+    assert_eq!(p1(&gb), 0b1000);
+
+    gb.joypad.forbid_opposing_directions = true;
+    run(&mut gb);
+    assert_eq!(p1(&gb), 0b1011);
+
+    gb.joypad.press(Button::Down);
+    run(&mut gb);
+    assert_eq!(p1(&gb), 0b1111);
+
+    gb.joypad.release(Button::Up);
+    run(&mut gb);
+    assert_eq!(p1(&gb), 0b0111);
+}
+
+#[test]
+fn interrupt_on_falling_edge() {
+    let mut gb = joypad_test(0x10);
+    assert_eq!(interrupts(&gb), 0);
+
+    gb.joypad.press(Button::A);
+    run(&mut gb);
+    assert_eq!(interrupts(&gb), 1);
+
+    // Holding a button doesn't request any more interrupts, and neither does releasing it
+    run(&mut gb);
+    assert_eq!(interrupts(&gb), 1);
+    gb.joypad.release(Button::A);
+    run(&mut gb);
+    assert_eq!(interrupts(&gb), 1);
+
+    // A second line going low does, even while the first is still low
+    gb.joypad.press(Button::A);
+    run(&mut gb);
+    gb.joypad.press(Button::Start);
+    run(&mut gb);
+    assert_eq!(interrupts(&gb), 3);
+
+    // Buttons on the unselected line never reach P1
+    gb.joypad.press(Button::Down);
+    run(&mut gb);
+    assert_eq!(interrupts(&gb), 3);
+}
+
+#[test]
+fn set_input_latches_at_frame_end() {
+    let mut gb = joypad_test(0x10);
+    gb.set_input(ButtonState::A | ButtonState::LEFT);
+    run(&mut gb);
+    assert_eq!(p1(&gb), 0b1111);
+    assert_eq!(gb.joypad.input(), ButtonState::empty());
+
+    let frame_count = gb.ppu.frame_count();
+    while gb.ppu.frame_count() == frame_count {
+        gb.clock();
+    }
+    run(&mut gb);
+    assert_eq!(p1(&gb), 0b1110);
+    assert_eq!(gb.joypad.input(), ButtonState::A | ButtonState::LEFT);
+    assert_eq!(interrupts(&gb), 1);
+
+    // The snapshot replaces buttons held with press
+    gb.joypad.press(Button::Start);
+    gb.set_input(ButtonState::B);
+    let frame_count = gb.ppu.frame_count();
+    while gb.ppu.frame_count() == frame_count {
+        gb.clock();
+    }
+    assert_eq!(gb.joypad.input(), ButtonState::B);
+}
+
+#[test]
+fn scheduled_presses() {
+    let mut gb = joypad_test(0x10);
+    let start = gb.ppu.frame_count();
+    gb.joypad.schedule_press(Button::A, start + 2, 3);
+    gb.joypad.schedule_press(Button::B, start + 3, 1);
+
+    let mut held = Vec::new();
+    for _ in 0..6 {
+        let frame_count = gb.ppu.frame_count();
+        while gb.ppu.frame_count() == frame_count {
+            gb.clock();
+        }
+        held.push(gb.joypad.input());
+    }
+    assert_eq!(
+        held,
+        [
+            ButtonState::empty(),
+            ButtonState::A,
+            ButtonState::A | ButtonState::B,
+            ButtonState::A,
+            ButtonState::empty(),
+            ButtonState::empty(),
+        ]
+    );
+    assert!(gb.joypad.scheduled_presses().is_empty());
+    assert_eq!(interrupts(&gb), 2);
+}
+
+#[test]
+fn scheduled_press_held_forever() {
+    let mut gb = joypad_test(0x10);
+    let start = gb.ppu.frame_count();
+    gb.joypad.schedule_press(Button::Start, start + 1, u64::MAX);
+
+    for _ in 0..3 {
+        let frame_count = gb.ppu.frame_count();
+        while gb.ppu.frame_count() == frame_count {
+            gb.clock();
+        }
+        assert_eq!(gb.joypad.input(), ButtonState::START);
+    }
+    assert_eq!(gb.joypad.scheduled_presses().len(), 1);
+}
@@ -0,0 +1,47 @@
+//! Mooneye's acceptance tests. The ROMs aren't checked in, see [`common::test_rom`]; they're expected under
+//! `mooneye/`, laid out the way mooneye-test-suite builds them (e.g. `mooneye/acceptance/timer/div_write.gb`).
+//!
+//! The tests are ignored by default, and fail if a ROM is missing: run them with
+//! `cargo test --test mooneye -- --ignored` once the ROMs are in place.
+
+mod common;
+
+use gb_core::gameboy::serial::{test_verdict, TestVerdict};
+
+/// Mooneye's tests finish well within a second
+const MAX_FRAMES: u32 = 600;
+
+fn run_mooneye(path: &str) {
+    let rom = format!("mooneye/{}", path);
+    let mut gb = common::gameboy_with_test_rom(&rom)
+        .unwrap_or_else(|| panic!("{} is missing, see `common::test_rom`", rom));
+    for _ in 0..MAX_FRAMES {
+        gb.run_frame();
+        if let Some(verdict) = test_verdict(gb.serial.output()) {
+            assert_eq!(verdict, TestVerdict::Passed, "{} failed", path);
+            return;
+        }
+    }
+    panic!(
+        "{} didn't report a result within {} frames",
+        path, MAX_FRAMES
+    );
+}
+
+#[test]
+#[ignore = "needs mooneye-test-suite ROMs"]
+fn timer_div_write() {
+    run_mooneye("acceptance/timer/div_write.gb");
+}
+
+#[test]
+#[ignore = "needs mooneye-test-suite ROMs"]
+fn timer_tima_reload() {
+    run_mooneye("acceptance/timer/tima_reload.gb");
+}
+
+#[test]
+#[ignore = "needs mooneye-test-suite ROMs"]
+fn timer_rapid_toggle() {
+    run_mooneye("acceptance/timer/rapid_toggle.gb");
+}
@@ -1,4 +1,11 @@
-use gb_core::gameboy::ppu::{monochrome, registers::*, PPU};
+use gb_core::gameboy::ppu::{
+    ghosting::Ghosting,
+    monochrome,
+    object::{ObjectAttributes, ObjectPriority},
+    registers::*,
+    vram::{BgMap, VramRegions},
+    PPU,
+};
 
 fn set_tile_singlecolor(ppu: &mut monochrome::MonochromePpu, tile_idx: usize, color: u8) {
     assert!(color <= 3);
@@ -8,9 +15,9 @@ fn set_tile_singlecolor(ppu: &mut monochrome::MonochromePpu, tile_idx: usize, co
     let color_low = color & 1;
     for i in 0..16 {
         if i % 2 == 0 {
-            ppu.state.borrow_mut().tile_data[offset + i] = 0xff * color_low;
+            ppu.state_mut().tile_data[offset + i] = 0xff * color_low;
         } else {
-            ppu.state.borrow_mut().tile_data[offset + i] = 0xff * color_high;
+            ppu.state_mut().tile_data[offset + i] = 0xff * color_high;
         }
     }
 }
@@ -21,13 +28,62 @@ fn advance_frame(ppu: &mut monochrome::MonochromePpu) {
     }
 }
 
+/// Run a whole frame from its first line, calling `setup_line` before each line (VBlank included) so registers can
+/// be changed between scanlines
+fn draw_frame_by_line(
+    ppu: &mut monochrome::MonochromePpu,
+    mut setup_line: impl FnMut(&mut monochrome::MonochromePpu, u8),
+) -> monochrome::Frame {
+    for line in 0..154 {
+        setup_line(ppu, line);
+        for _ in 0..456 {
+            ppu.clock_t_state();
+        }
+    }
+    ppu.get_frame()
+}
+
+/// A PPU with the LCD and background on, tile data at $8000, identity palettes, and tiles 0-3 filled with colors 0-3
+fn test_ppu(threaded: bool) -> monochrome::MonochromePpu {
+    let mut ppu = monochrome::MonochromePpu::new();
+    ppu.set_threaded_rendering(threaded);
+    ppu.set_lcdc(LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA | LCDC::OBJ_ENABLE);
+    ppu.set_bgp(0b11100100);
+    ppu.set_obp0(0b11100100);
+    ppu.set_obp1(0b11100100);
+    for color in 0..4 {
+        set_tile_singlecolor(&mut ppu, color, color as u8);
+    }
+    ppu
+}
+
+/// Check every pixel of `line` against `expected`, which gives the color ID at each x
+fn assert_line(frame: &monochrome::Frame, line: usize, expected: impl Fn(usize) -> usize) {
+    for x in 0..160 {
+        assert_eq!(
+            frame.pixels[line * 160 + x],
+            monochrome::color::COLORS[expected(x)],
+            "pixel ({}, {})",
+            x,
+            line
+        );
+    }
+}
+
+/// Check every pixel of `frame` against `expected`, which gives the color ID at each (x, line)
+fn assert_frame(frame: &monochrome::Frame, expected: impl Fn(usize, usize) -> usize) {
+    for line in 0..144 {
+        assert_line(frame, line, |x| expected(x, line));
+    }
+}
+
 #[test]
 fn ppu_singlecolor() {
     let mut ppu = monochrome::MonochromePpu::new();
 
-    ppu.state.borrow_mut().bg_map_1.fill(0);
-    ppu.state.borrow_mut().lcdc = LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA;
-    ppu.state.borrow_mut().bgp = 0b11100100;
+    ppu.state_mut().bg_map_1.fill(0);
+    ppu.set_lcdc(LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA);
+    ppu.set_bgp(0b11100100);
 
     for color in [0b00, 0b01, 0b10, 0b11] {
         println!("color: {:b}", color);
@@ -37,10 +93,8 @@ fn ppu_singlecolor() {
         frame.pixels.iter().for_each(|&pix| {
             assert_eq!(
                 pix,
-                monochrome::color::COLORS[monochrome::color::calculate_monochrome_color_id(
-                    ppu.state.borrow_mut().bgp,
-                    color
-                ) as usize]
+                monochrome::color::COLORS
+                    [monochrome::color::calculate_monochrome_color_id(ppu.bgp(), color) as usize]
             )
         });
     }
@@ -50,17 +104,17 @@ fn ppu_singlecolor() {
 fn ppu_bgp() {
     let mut ppu = monochrome::MonochromePpu::new();
 
-    ppu.state.borrow_mut().lcdc = LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA;
+    ppu.set_lcdc(LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA);
     set_tile_singlecolor(&mut ppu, 0, 0b00);
     set_tile_singlecolor(&mut ppu, 1, 0b01);
     set_tile_singlecolor(&mut ppu, 2, 0b10);
     set_tile_singlecolor(&mut ppu, 3, 0b11);
     for i in 0..0x400 {
-        ppu.state.borrow_mut().bg_map_1[i] = (i % 4) as u8;
+        ppu.state_mut().bg_map_1[i] = (i % 4) as u8;
     }
 
     for bgp in 0..=0xFF {
-        ppu.state.borrow_mut().bgp = bgp;
+        ppu.set_bgp(bgp);
         advance_frame(&mut ppu);
 
         let frame = ppu.get_frame();
@@ -100,3 +154,747 @@ fn calculate_monochrome_color() {
         }
     }
 }
+
+#[test]
+fn frame_metadata() {
+    let mut ppu = monochrome::MonochromePpu::new();
+
+    advance_frame(&mut ppu);
+    let frame = ppu.get_frame();
+    assert_eq!(frame.index, 0);
+    assert!(!frame.lcd_off);
+    assert!(frame.is_complete());
+
+    // Switch the LCD off halfway through drawing line 10
+    for _ in 0..456 * 10 + 200 {
+        ppu.clock_t_state();
+    }
+    let (mut data, mut irq) = (0, 0);
+    let lcdc = ppu.lcdc() - LCDC::LCD_ENABLE;
+    ppu.perform_io(
+        gb_core::cpu::CpuOutputPins::Write {
+            addr: 0xFF40,
+            data: lcdc.bits(),
+        },
+        &mut data,
+        &mut irq,
+    );
+    let frame = ppu.get_frame();
+    assert_eq!(frame.index, 1);
+    assert!(frame.lcd_off);
+    assert!(frame.rendered_lines[..10].iter().all(|&rendered| rendered));
+    assert!(!frame.rendered_lines[10..].iter().any(|&rendered| rendered));
+    assert_eq!(ppu.ly(), 0);
+
+    // Nothing happens while the LCD is off
+    advance_frame(&mut ppu);
+    assert_eq!(ppu.get_frame().index, 1);
+    assert_eq!(ppu.ly(), 0);
+
+    ppu.set_lcdc(lcdc | LCDC::LCD_ENABLE);
+    advance_frame(&mut ppu);
+    let frame = ppu.get_frame();
+    assert_eq!(frame.index, 2);
+    assert!(!frame.lcd_off);
+    assert!(frame.is_complete());
+}
+
+/// Place an object in OAM, with `x` and `y` as screen coordinates
+fn set_object(
+    ppu: &mut monochrome::MonochromePpu,
+    index: usize,
+    x: u8,
+    y: u8,
+    tile: u8,
+    attributes: u8,
+) {
+    ppu.state_mut().oam[index * 4..index * 4 + 4].copy_from_slice(&[
+        y + 16,
+        x + 8,
+        tile,
+        attributes,
+    ]);
+}
+
+/// Two overlapping objects: object 0 in color 3 at x 20, and object 1 in color 1 at x 16
+fn overlapping_objects(priority: ObjectPriority) -> monochrome::Frame {
+    let mut ppu = monochrome::MonochromePpu::with_object_priority(priority);
+
+    ppu.set_lcdc(LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA | LCDC::OBJ_ENABLE);
+    ppu.set_bgp(0b11100100);
+    ppu.set_obp0(0b11100100);
+    set_tile_singlecolor(&mut ppu, 1, 0b11);
+    set_tile_singlecolor(&mut ppu, 2, 0b01);
+    set_object(&mut ppu, 0, 20, 0, 1, 0);
+    set_object(&mut ppu, 1, 16, 0, 2, 0);
+
+    advance_frame(&mut ppu);
+    ppu.get_frame()
+}
+
+#[test]
+fn object_priority_x_coordinate() {
+    let frame = overlapping_objects(ObjectPriority::XCoordinate);
+    let colors = monochrome::color::COLORS;
+
+    assert_eq!(frame.pixels[16], colors[0b01]);
+    // The object further left is drawn on top, even though it's later in OAM
+    assert_eq!(frame.pixels[20], colors[0b01]);
+    assert_eq!(frame.pixels[23], colors[0b01]);
+    assert_eq!(frame.pixels[24], colors[0b11]);
+    assert_eq!(frame.pixels[28], colors[0b00]);
+}
+
+#[test]
+fn object_priority_oam_index() {
+    let frame = overlapping_objects(ObjectPriority::OamIndex);
+    let colors = monochrome::color::COLORS;
+
+    assert_eq!(frame.pixels[16], colors[0b01]);
+    assert_eq!(frame.pixels[20], colors[0b11]);
+    assert_eq!(frame.pixels[27], colors[0b11]);
+    assert_eq!(frame.pixels[28], colors[0b00]);
+}
+
+#[test]
+fn scanline_objects() {
+    let mut ppu = test_ppu(false);
+    // Twelve objects on lines 50-57, each further left than the one before
+    for i in 0..12 {
+        set_object(&mut ppu, i, 100 - i as u8 * 5, 50, 1, 0);
+    }
+    assert!(
+        ppu.scanline_objects(50).unwrap().is_empty(),
+        "No frame has finished yet"
+    );
+    assert!(ppu.scanline_objects(143).is_some());
+    assert!(ppu.scanline_objects(144).is_none());
+
+    advance_frame(&mut ppu);
+    let indices = |ppu: &monochrome::MonochromePpu, line| -> Vec<u8> {
+        ppu.scanline_objects(line)
+            .unwrap()
+            .iter()
+            .map(|object| object.oam_index)
+            .collect()
+    };
+    // Only the first 10 in OAM are selected, and then ordered by X coordinate
+    assert_eq!(indices(&ppu, 50), [9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+    assert_eq!(indices(&ppu, 57), indices(&ppu, 50));
+    assert!(indices(&ppu, 49).is_empty());
+    assert!(indices(&ppu, 58).is_empty());
+
+    // Results are kept until the next frame finishes
+    set_object(&mut ppu, 0, 0, 0, 0, 0);
+    for _ in 0..456 * 60 {
+        ppu.clock_t_state();
+    }
+    assert_eq!(indices(&ppu, 50).len(), 10);
+    advance_frame(&mut ppu);
+    assert_eq!(indices(&ppu, 50), [10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+}
+
+#[test]
+fn object_transparency_and_bg_priority() {
+    let mut ppu = monochrome::MonochromePpu::new();
+    let colors = monochrome::color::COLORS;
+
+    ppu.set_lcdc(LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA | LCDC::OBJ_ENABLE);
+    ppu.set_bgp(0b11100100);
+    ppu.set_obp0(0b11100100);
+    ppu.set_obp1(0b11111111);
+    set_tile_singlecolor(&mut ppu, 1, 0b10);
+    set_tile_singlecolor(&mut ppu, 3, 0b11);
+    // Tile 2 is transparent on its right half
+    for row in 0..8 {
+        ppu.state_mut().tile_data[2 * 16 + row * 2] = 0xF0;
+    }
+    // The background is color 3 from x 80 onwards on the first line
+    ppu.state_mut().bg_map_1[10..20].fill(3);
+
+    // Object 0 is transparent where it overlaps object 1, so object 1 shows through
+    set_object(&mut ppu, 0, 8, 0, 2, 0);
+    set_object(&mut ppu, 1, 12, 0, 1, 0x10);
+    // Behind a non-zero background color
+    set_object(&mut ppu, 2, 76, 0, 1, 0x80);
+
+    advance_frame(&mut ppu);
+    let frame = ppu.get_frame();
+
+    assert_eq!(frame.pixels[8], colors[0b01]);
+    assert_eq!(frame.pixels[11], colors[0b01]);
+    assert_eq!(frame.pixels[12], colors[0b11]);
+    assert_eq!(frame.pixels[19], colors[0b11]);
+    assert_eq!(frame.pixels[76], colors[0b10]);
+    assert_eq!(frame.pixels[80], colors[0b11]);
+    assert_eq!(frame.pixels[160 * 8 + 76], colors[0b00]);
+}
+
+#[test]
+fn ghosting() {
+    let mut ppu = monochrome::MonochromePpu::new();
+    let mut ghosting = Ghosting::new(0.5);
+
+    ppu.set_bgp(0b11100100);
+    set_tile_singlecolor(&mut ppu, 0, 0b00);
+    advance_frame(&mut ppu);
+    let white = ghosting.apply(&ppu.get_frame()).pixels[0];
+    assert_eq!(white, monochrome::color::COLOR_WHITE);
+
+    set_tile_singlecolor(&mut ppu, 0, 0b11);
+    advance_frame(&mut ppu);
+    let frame = ppu.get_frame();
+    assert_eq!(ghosting.apply(&frame).pixels[0], 0xFF808080);
+    // Applying the same frame again doesn't blend it with itself
+    assert_eq!(ghosting.apply(&frame).pixels[0], 0xFF808080);
+
+    advance_frame(&mut ppu);
+    let frame = ppu.get_frame();
+    let ghosted = ghosting.apply(&frame);
+    assert_eq!(ghosted.pixels[0], monochrome::color::COLOR_BLACK);
+    // The picture held still, but the ghost of the white frame faded out of it
+    assert!(frame.repeated);
+    assert!(!ghosted.repeated);
+
+    advance_frame(&mut ppu);
+    assert!(ghosting.apply(&ppu.get_frame()).repeated);
+}
+
+#[test]
+fn repeated_frames() {
+    for threaded in [false, true] {
+        let mut ppu = test_ppu(threaded);
+        advance_frame(&mut ppu);
+        assert!(!ppu.get_frame().repeated);
+        advance_frame(&mut ppu);
+        assert!(ppu.get_frame().repeated);
+
+        set_tile_singlecolor(&mut ppu, 0, 3);
+        advance_frame(&mut ppu);
+        assert!(!ppu.get_frame().repeated);
+        advance_frame(&mut ppu);
+        assert!(ppu.get_frame().repeated);
+    }
+}
+
+#[test]
+fn threaded_rendering() {
+    let frames: Vec<_> = [false, true]
+        .iter()
+        .map(|&threaded| {
+            let mut ppu = monochrome::MonochromePpu::new();
+            ppu.set_threaded_rendering(threaded);
+
+            ppu.set_lcdc(
+                LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA | LCDC::OBJ_ENABLE,
+            );
+            ppu.set_bgp(0b11100100);
+            ppu.set_obp0(0b00011011);
+            ppu.set_scx(3);
+            ppu.set_scy(5);
+            for color in 0..4 {
+                set_tile_singlecolor(&mut ppu, color, color as u8);
+            }
+            for i in 0..0x400 {
+                ppu.state_mut().bg_map_1[i] = (i * 7 % 4) as u8;
+            }
+            set_object(&mut ppu, 0, 30, 40, 3, 0);
+
+            advance_frame(&mut ppu);
+            advance_frame(&mut ppu);
+            ppu.get_frame()
+        })
+        .collect();
+
+    assert_eq!(frames[0].index, frames[1].index);
+    assert!(frames[1].is_complete());
+    assert_eq!(frames[0].hash(), frames[1].hash());
+}
+
+#[test]
+fn vram_views() {
+    let mut ppu = monochrome::MonochromePpu::new();
+
+    // Tile 383 is the last one, at $97F0. Its first row has one pixel of each color
+    ppu.state_mut().tile_data[383 * 16] = 0b0101_0000;
+    ppu.state_mut().tile_data[383 * 16 + 1] = 0b0011_0000;
+    let tile = ppu.tile(383);
+    assert_eq!(tile.pixels[0][..4], [0, 1, 2, 3]);
+    assert_eq!(tile.pixels[1], [0; 8]);
+
+    ppu.state_mut().bg_map_2[32 * 3 + 5] = 0x42;
+    assert_eq!(ppu.bg_map(BgMap::High)[3][5], 0x42);
+    assert_eq!(ppu.bg_map(BgMap::Low)[3][5], 0);
+
+    set_object(&mut ppu, 39, 10, 20, 7, 0x20);
+    let object = ppu.objects().last().unwrap();
+    assert_eq!((object.x, object.y, object.tile), (18, 36, 7));
+    assert_eq!(object.attributes, ObjectAttributes::X_FLIP);
+    assert_eq!(object.oam_index, 39);
+    assert_eq!(ppu.objects().count(), 40);
+}
+
+#[test]
+fn vram_dirty_regions() {
+    use gb_core::cpu::CpuOutputPins;
+
+    let mut ppu = monochrome::MonochromePpu::new();
+    let write = |ppu: &mut monochrome::MonochromePpu, addr| {
+        ppu.perform_io(CpuOutputPins::Write { addr, data: 1 }, &mut 0xFF, &mut 0)
+    };
+    assert_eq!(
+        ppu.take_dirty(VramRegions::TILE_DATA),
+        VramRegions::TILE_DATA,
+        "Nothing has been drawn from VRAM yet"
+    );
+    assert_eq!(
+        ppu.take_dirty(VramRegions::all()),
+        VramRegions::BG_MAPS | VramRegions::OAM
+    );
+    assert!(ppu.take_dirty(VramRegions::all()).is_empty());
+
+    write(&mut ppu, 0xFF47);
+    write(&mut ppu, 0xC000);
+    write(&mut ppu, 0xFEA0);
+    assert!(
+        ppu.take_dirty(VramRegions::all()).is_empty(),
+        "Only VRAM and OAM writes count"
+    );
+
+    let regions = [
+        (0x8000, VramRegions::TILE_BLOCK_0),
+        (0x87FF, VramRegions::TILE_BLOCK_0),
+        (0x8800, VramRegions::TILE_BLOCK_1),
+        (0x9000, VramRegions::TILE_BLOCK_2),
+        (0x97FF, VramRegions::TILE_BLOCK_2),
+        (0x9800, VramRegions::BG_MAP_LOW),
+        (0x9FFF, VramRegions::BG_MAP_HIGH),
+        (0xFE00, VramRegions::OAM),
+    ];
+    for (addr, region) in regions {
+        write(&mut ppu, addr);
+        assert_eq!(
+            ppu.take_dirty(VramRegions::all()),
+            region,
+            "Write to {:04X}",
+            addr
+        );
+    }
+
+    // Taking some regions leaves the others dirty
+    write(&mut ppu, 0x8000);
+    write(&mut ppu, 0x9C00);
+    assert_eq!(
+        ppu.take_dirty(VramRegions::BG_MAPS),
+        VramRegions::BG_MAP_HIGH
+    );
+    assert_eq!(
+        ppu.take_dirty(VramRegions::all()),
+        VramRegions::TILE_BLOCK_0
+    );
+
+    ppu.write_oam(0, 1);
+    assert_eq!(
+        ppu.take_dirty(VramRegions::all()),
+        VramRegions::OAM,
+        "OAM DMA writes count too"
+    );
+}
+
+/// Fill a tile map with tiles 0-3 in a diagonal pattern, so the color at tile (x, y) is `(x + y) % 4`
+fn diagonal_map(map: &mut [u8; 0x400]) {
+    for (i, tile) in map.iter_mut().enumerate() {
+        *tile = ((i % 32 + i / 32) % 4) as u8;
+    }
+}
+
+#[test]
+fn background_scrolling() {
+    for &(scx, scy) in &[(3, 5), (250, 252), (0, 0)] {
+        let mut ppu = test_ppu(false);
+        diagonal_map(&mut ppu.state_mut().bg_map_1);
+        ppu.set_scx(scx);
+        ppu.set_scy(scy);
+
+        let frame = draw_frame_by_line(&mut ppu, |_, _| {});
+        assert_frame(&frame, |x, line| {
+            let map_x = (x + scx as usize) % 256;
+            let map_y = (line + scy as usize) % 256;
+            (map_x / 8 + map_y / 8) % 4
+        });
+    }
+}
+
+#[test]
+fn scroll_changed_between_lines() {
+    let mut ppu = test_ppu(false);
+    diagonal_map(&mut ppu.state_mut().bg_map_1);
+
+    // A raster effect: each line is shifted one more pixel than the last
+    let frame = draw_frame_by_line(&mut ppu, |ppu, line| ppu.set_scx(line));
+    assert_frame(&frame, |x, line| ((x + line) % 256 / 8 + line / 8) % 4);
+}
+
+#[test]
+fn vram_written_between_lines() {
+    use gb_core::cpu::CpuOutputPins;
+
+    for &threaded in &[false, true] {
+        let mut ppu = test_ppu(threaded);
+
+        // Each row of tiles is only written to the map just before its first line is drawn
+        let frame = draw_frame_by_line(&mut ppu, |ppu, line| {
+            if line < 144 && line % 8 == 0 {
+                let row = line as u16 / 8;
+                for addr in 0x9800 + row * 32..0x9800 + row * 32 + 32 {
+                    let write = CpuOutputPins::Write {
+                        addr,
+                        data: row as u8 % 4,
+                    };
+                    ppu.perform_io(write, &mut 0xFF, &mut 0);
+                }
+            }
+        });
+        assert_frame(&frame, |_, line| line / 8 % 4);
+    }
+}
+
+#[test]
+fn window_position() {
+    for &threaded in &[false, true] {
+        let mut ppu = test_ppu(threaded);
+        ppu.set_lcdc(ppu.lcdc() | LCDC::WINDOW_ENABLE | LCDC::WINDOW_TILEMAP_AREA);
+        diagonal_map(&mut ppu.state_mut().bg_map_2);
+        // The window isn't affected by scrolling
+        ppu.set_scx(5);
+        ppu.set_scy(7);
+        ppu.set_wy(16);
+        ppu.set_wx(47);
+
+        let frame = draw_frame_by_line(&mut ppu, |_, _| {});
+        assert_frame(&frame, |x, line| {
+            if line >= 16 && x >= 40 {
+                ((x - 40) / 8 + (line - 16) / 8) % 4
+            } else {
+                0
+            }
+        });
+    }
+}
+
+#[test]
+fn window_cut_off_on_the_left() {
+    let mut ppu = test_ppu(false);
+    ppu.set_lcdc(ppu.lcdc() | LCDC::WINDOW_ENABLE | LCDC::WINDOW_TILEMAP_AREA);
+    diagonal_map(&mut ppu.state_mut().bg_map_2);
+    ppu.set_wy(0);
+    ppu.set_wx(3);
+
+    let frame = draw_frame_by_line(&mut ppu, |_, _| {});
+    // With WX 3, the first 4 pixels of the window are off screen
+    assert_frame(&frame, |x, line| ((x + 4) / 8 + line / 8) % 4);
+}
+
+#[test]
+fn window_trigger_and_line_counter() {
+    for &threaded in &[false, true] {
+        let mut ppu = test_ppu(threaded);
+        ppu.set_lcdc(ppu.lcdc() | LCDC::WINDOW_ENABLE);
+        diagonal_map(&mut ppu.state_mut().bg_map_1);
+        ppu.set_wx(7);
+        ppu.set_wy(200);
+
+        let frame = draw_frame_by_line(&mut ppu, |ppu, line| match line {
+            // WY has already passed, so this doesn't show the window
+            40 => ppu.set_wy(30),
+            // Shows the window from this line on
+            60 => ppu.set_wy(60),
+            // Hiding the window pauses its line counter
+            80 => ppu.set_lcdc(ppu.lcdc() - LCDC::WINDOW_ENABLE),
+            88 => ppu.set_lcdc(ppu.lcdc() | LCDC::WINDOW_ENABLE),
+            _ => {}
+        });
+        assert_frame(&frame, |x, line| match line {
+            60..=79 => (x / 8 + (line - 60) / 8) % 4,
+            88.. => (x / 8 + (line - 68) / 8) % 4,
+            _ => (x / 8 + line / 8) % 4,
+        });
+
+        // WY is still 60, and the window starts over from its first line
+        let frame = draw_frame_by_line(&mut ppu, |_, _| {});
+        assert_frame(&frame, |x, line| match line {
+            60.. => (x / 8 + (line - 60) / 8) % 4,
+            _ => (x / 8 + line / 8) % 4,
+        });
+    }
+}
+
+#[test]
+fn object_flipping() {
+    let mut ppu = test_ppu(false);
+    // Tile 4 has a single pixel of color 3 in its top left corner
+    ppu.state_mut().tile_data[4 * 16] = 0x80;
+    ppu.state_mut().tile_data[4 * 16 + 1] = 0x80;
+    set_object(&mut ppu, 0, 16, 8, 4, 0);
+    set_object(&mut ppu, 1, 32, 8, 4, ObjectAttributes::X_FLIP.bits());
+    set_object(&mut ppu, 2, 48, 8, 4, ObjectAttributes::Y_FLIP.bits());
+    set_object(
+        &mut ppu,
+        3,
+        64,
+        8,
+        4,
+        (ObjectAttributes::X_FLIP | ObjectAttributes::Y_FLIP).bits(),
+    );
+
+    let frame = draw_frame_by_line(&mut ppu, |_, _| {});
+    assert_frame(&frame, |x, line| match (x, line) {
+        (16, 8) | (39, 8) | (48, 15) | (71, 15) => 3,
+        _ => 0,
+    });
+}
+
+#[test]
+fn tall_object_flipping() {
+    let mut ppu = test_ppu(false);
+    ppu.set_lcdc(ppu.lcdc() | LCDC::OBJ_SIZE);
+    // Tiles 6 and 7 make up one 8x16 object, with a single pixel in the top left corner of tile 6
+    ppu.state_mut().tile_data[6 * 16] = 0x80;
+    ppu.state_mut().tile_data[6 * 16 + 1] = 0x80;
+    // The low bit of the tile number is ignored
+    set_object(&mut ppu, 0, 16, 24, 7, 0);
+    set_object(&mut ppu, 1, 32, 24, 6, ObjectAttributes::Y_FLIP.bits());
+    set_object(
+        &mut ppu,
+        2,
+        48,
+        24,
+        6,
+        (ObjectAttributes::X_FLIP | ObjectAttributes::Y_FLIP).bits(),
+    );
+
+    let frame = draw_frame_by_line(&mut ppu, |_, _| {});
+    assert_frame(&frame, |x, line| match (x, line) {
+        (16, 24) | (32, 39) | (55, 39) => 3,
+        _ => 0,
+    });
+}
+
+#[test]
+fn palette_mapping() {
+    let mut ppu = test_ppu(false);
+    ppu.set_bgp(0b00_01_10_11);
+    ppu.set_obp0(0b10_01_11_00);
+    ppu.set_obp1(0b01_11_10_00);
+    for (i, tile) in ppu.state_mut().bg_map_1.iter_mut().enumerate() {
+        *tile = (i % 4) as u8;
+    }
+    // Tile 4's rows are colors 0, 1, 2, 3, 0, 1, 2, 3
+    for row in 0..8 {
+        ppu.state_mut().tile_data[4 * 16 + row * 2] = 0b0101_0101;
+        ppu.state_mut().tile_data[4 * 16 + row * 2 + 1] = 0b0011_0011;
+    }
+    set_object(&mut ppu, 0, 80, 0, 4, 0);
+    set_object(&mut ppu, 1, 120, 0, 4, ObjectAttributes::DMG_PALETTE.bits());
+
+    let frame = draw_frame_by_line(&mut ppu, |_, _| {});
+    assert_frame(&frame, |x, line| {
+        let bg = 3 - (x / 8) % 4;
+        match (x, line) {
+            // Color 0 is transparent whatever the palette says
+            (80..=87, 0..=7) => [bg, 3, 1, 2][(x - 80) % 4],
+            (120..=127, 0..=7) => [bg, 2, 3, 1][(x - 120) % 4],
+            _ => bg,
+        }
+    });
+}
+
+#[test]
+fn color_schemes() {
+    use monochrome::color::{scheme, Palette, SCHEMES};
+
+    // Relative luminance, close enough to tell whether the shades get darker
+    let luminance = |color: u32| {
+        let [b, g, r, _] = color.to_le_bytes();
+        2126 * r as u32 + 7152 * g as u32 + 722 * b as u32
+    };
+    for scheme in SCHEMES {
+        for shades in [scheme.bg, scheme.obj0, scheme.obj1] {
+            assert!(
+                shades
+                    .windows(2)
+                    .all(|pair| luminance(pair[0]) > luminance(pair[1])),
+                "{}'s shades don't get darker: {:08X?}",
+                scheme.name,
+                shades
+            );
+        }
+    }
+
+    let mut ppu = test_ppu(false);
+    let blue_orange = scheme("blue-orange").unwrap();
+    ppu.set_color_scheme(blue_orange);
+    assert_eq!(ppu.palette_colors(Palette::Bg), blue_orange.bg);
+    assert_eq!(ppu.palette_colors(Palette::Obj0), blue_orange.obj0);
+    assert_eq!(ppu.palette_colors(Palette::Obj1), blue_orange.obj1);
+    assert_eq!(scheme("no-such-scheme"), None);
+}
+
+#[test]
+fn palette_overrides() {
+    use monochrome::color::{Palette, COLORS};
+
+    let greens = [0xFFE0F8D0, 0xFF88C070, 0xFF346856, 0xFF081820];
+    let reds = [0xFFFFE0E0, 0xFFFF8080, 0xFFC00000, 0xFF400000];
+    for threaded in [false, true] {
+        let mut ppu = test_ppu(threaded);
+        ppu.set_bgp(0b00_01_10_11);
+        for (i, tile) in ppu.state_mut().bg_map_1.iter_mut().enumerate() {
+            *tile = (i % 4) as u8;
+        }
+        set_object(&mut ppu, 0, 80, 0, 1, 0);
+
+        assert_eq!(
+            ppu.palette_colors(Palette::Bg),
+            [COLORS[3], COLORS[2], COLORS[1], COLORS[0]]
+        );
+        ppu.set_palette_override(Palette::Bg, Some(greens));
+        ppu.set_palette_override(Palette::Obj0, Some(reds));
+        assert_eq!(
+            ppu.palette_colors(Palette::Bg),
+            [greens[3], greens[2], greens[1], greens[0]]
+        );
+        assert_eq!(ppu.palette_colors(Palette::Obj0), reds);
+        assert_eq!(ppu.palette_colors(Palette::Obj1), COLORS);
+        assert_eq!(
+            ppu.bgp(),
+            0b00_01_10_11,
+            "The game's registers aren't touched"
+        );
+
+        let frame = draw_frame_by_line(&mut ppu, |_, _| {});
+        for x in 0..160 {
+            let expected = match x {
+                80..=87 => reds[1],
+                _ => greens[3 - (x / 8) % 4],
+            };
+            assert_eq!(frame.pixels[x], expected, "x {}, threaded {}", x, threaded);
+        }
+
+        ppu.set_palette_override(Palette::Bg, None);
+        assert_eq!(
+            ppu.palette_colors(Palette::Bg),
+            [COLORS[3], COLORS[2], COLORS[1], COLORS[0]]
+        );
+    }
+}
+
+#[test]
+fn hidden_layers() {
+    use monochrome::Layers;
+
+    for threaded in [false, true] {
+        let mut ppu = test_ppu(threaded);
+        let lcdc = ppu.lcdc() | LCDC::WINDOW_ENABLE | LCDC::WINDOW_TILEMAP_AREA;
+        ppu.set_lcdc(lcdc);
+        diagonal_map(&mut ppu.state_mut().bg_map_1);
+        ppu.state_mut().bg_map_2.fill(3);
+        ppu.set_wy(72);
+        ppu.set_wx(87);
+        set_object(&mut ppu, 0, 0, 0, 2, 0);
+
+        let object = |x: usize, line: usize| x < 8 && line < 8;
+        let window = |x: usize, line: usize| x >= 80 && line >= 72;
+        let background = |x: usize, line: usize| (x / 8 + line / 8) % 4;
+        for hidden in [
+            Layers::empty(),
+            Layers::BACKGROUND,
+            Layers::WINDOW,
+            Layers::OBJECTS,
+            Layers::all(),
+        ] {
+            ppu.set_hidden_layers(hidden);
+            assert_eq!(ppu.hidden_layers(), hidden);
+            let frame = draw_frame_by_line(&mut ppu, |_, _| {});
+            assert_frame(&frame, |x, line| {
+                if object(x, line) && !hidden.contains(Layers::OBJECTS) {
+                    2
+                } else if window(x, line) {
+                    // A hidden window still covers the background
+                    if hidden.contains(Layers::WINDOW) {
+                        0
+                    } else {
+                        3
+                    }
+                } else if hidden.contains(Layers::BACKGROUND) {
+                    0
+                } else {
+                    background(x, line)
+                }
+            });
+            assert_eq!(ppu.lcdc(), lcdc, "The game's LCDC isn't touched");
+        }
+    }
+}
+
+#[test]
+fn hidden_window_keeps_counting_lines() {
+    use monochrome::Layers;
+
+    for threaded in [false, true] {
+        let mut ppu = test_ppu(threaded);
+        ppu.set_lcdc(ppu.lcdc() | LCDC::WINDOW_ENABLE | LCDC::WINDOW_TILEMAP_AREA);
+        diagonal_map(&mut ppu.state_mut().bg_map_2);
+        ppu.set_wy(0);
+        ppu.set_wx(7);
+
+        // Hiding the window for the first 40 lines doesn't change which window line is drawn after them
+        let frame = draw_frame_by_line(&mut ppu, |ppu, line| {
+            ppu.set_hidden_layers(if line < 40 {
+                Layers::WINDOW
+            } else {
+                Layers::empty()
+            });
+        });
+        assert_frame(
+            &frame,
+            |x, line| {
+                if line < 40 {
+                    0
+                } else {
+                    (x / 8 + line / 8) % 4
+                }
+            },
+        );
+    }
+}
+
+#[test]
+fn display_background() {
+    use monochrome::color::{COLORS, COLOR_RED};
+
+    let mut ppu = test_ppu(false);
+    diagonal_map(&mut ppu.state_mut().bg_map_1);
+    ppu.set_scx(200);
+    ppu.set_scy(150);
+
+    let (image, width, height) = ppu.display_background(None);
+    assert_eq!((width, height), (256, 256));
+    for y in 0..256 {
+        for x in 0..256 {
+            // The viewport covers x 200-255 and 0-103, and y 150-255 and 0-37
+            let outline = ([200, 103].contains(&x) && (y >= 150 || y <= 37))
+                || ([150, 37].contains(&y) && (x >= 200 || x <= 103));
+            let expected = if outline {
+                COLOR_RED
+            } else {
+                COLORS[(x / 8 + y / 8) % 4]
+            };
+            assert_eq!(image[y * 256 + x], expected, "pixel ({}, {})", x, y);
+        }
+    }
+
+    let (scaled, width, height) = ppu.display_background(2);
+    assert_eq!((width, height), (512, 512));
+    assert_eq!(scaled[3 * 512 + 5], image[256 + 2]);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use gb_core::gameboy::serial::{test_verdict, TestVerdict};
+
+#[test]
+#[rustfmt::skip]
+fn serial_transfer() {
+    let code = [
+        0x3E, b'P', // LD A, 'P'
+        0xE0, 0x01, // LDH (SB), A
+        0x3E, 0x81, // LD A, $81
+        0xE0, 0x02, // LDH (SC), A
+        0x18, 0xFE, // JR -2
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    // The first step only fetches LD A, 'P'
+    for _ in 0..5 {
+        gb.step_instruction();
+    }
+    assert_eq!(gb.debug_read(0xFF02), 0xFF);
+    assert!(gb.serial.output().is_empty());
+
+    for _ in 0..8 * 128 {
+        gb.clock();
+    }
+    assert_eq!(gb.serial.output(), b"P");
+    assert_eq!(gb.debug_read(0xFF01), 0xFF);
+    assert_eq!(gb.debug_read(0xFF02), 0x7F);
+    assert_eq!(gb.debug_read(0xFF0F) & 0x08, 0x08);
+
+    assert_eq!(gb.serial.take_output(), b"P");
+    assert!(gb.serial.output().is_empty());
+}
+
+#[test]
+fn verdicts() {
+    assert_eq!(test_verdict(b"cpu_instrs\n\n01:ok"), None);
+    assert_eq!(
+        test_verdict(b"cpu_instrs\n\nPassed all tests\n"),
+        Some(TestVerdict::Passed)
+    );
+    assert_eq!(
+        test_verdict(b"02:01\n\nFailed #3\n"),
+        Some(TestVerdict::Failed)
+    );
+    assert_eq!(
+        test_verdict(&[3, 5, 8, 13, 21, 34]),
+        Some(TestVerdict::Passed)
+    );
+    assert_eq!(test_verdict(&[0x42; 6]), Some(TestVerdict::Failed));
+}
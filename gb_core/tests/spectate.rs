@@ -0,0 +1,101 @@
+mod common;
+
+use std::{
+    io::{self, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use gb_core::{
+    gameboy::{joypad::ButtonState, ppu::PPU},
+    spectate::{Broadcaster, Spectator, MAGIC},
+};
+
+#[test]
+fn spectate() {
+    let mut gb = common::gameboy_with_code(&[0x18, 0xFE]); // JR -2
+    let mut broadcaster = Broadcaster::bind("127.0.0.1:0").unwrap();
+    let addr = broadcaster.local_addr().unwrap();
+
+    gb.run_frame();
+    broadcaster.send_frame(&gb.ppu.get_frame(), ButtonState::A);
+
+    // The spectator is accepted when the next frame is sent
+    let spectator = thread::spawn(move || {
+        let mut spectator = Spectator::connect(addr).unwrap();
+        assert_eq!(spectator.input_history(), [ButtonState::A]);
+        let frames: Vec<_> = (0..2).map(|_| spectator.recv_frame().unwrap()).collect();
+        (spectator, frames)
+    });
+
+    let mut sent = Vec::new();
+    while broadcaster.spectator_count() == 0 {
+        gb.run_frame();
+        let frame = gb.ppu.get_frame();
+        broadcaster.send_frame(&frame, ButtonState::B);
+        sent.push(frame);
+    }
+    gb.run_frame();
+    broadcaster.send_frame(&gb.ppu.get_frame(), ButtonState::START | ButtonState::UP);
+
+    let (spectator, frames) = spectator.join().unwrap();
+    let (frame, input) = &frames[0];
+    let last_sent = sent.last().unwrap();
+    assert_eq!(frame.index, last_sent.index);
+    assert_eq!(frame.hash(), last_sent.hash());
+    assert_eq!(*input, ButtonState::B);
+    assert_eq!(frames[1].1, ButtonState::START | ButtonState::UP);
+    assert_eq!(frames[1].0.index, gb.ppu.get_frame().index);
+
+    assert_eq!(
+        spectator.input_history(),
+        [
+            ButtonState::A,
+            ButtonState::B,
+            ButtonState::START | ButtonState::UP
+        ]
+    );
+    assert_eq!(broadcaster.input_history().len(), sent.len() + 2);
+}
+
+#[test]
+fn slow_spectator() {
+    let mut gb = common::gameboy_with_code(&[0x18, 0xFE]); // JR -2
+    gb.run_frame();
+    let frame = gb.ppu.get_frame();
+    let mut broadcaster = Broadcaster::bind("127.0.0.1:0").unwrap();
+
+    // Never reads anything, so its socket buffers fill up
+    let _stalled = TcpStream::connect(broadcaster.local_addr().unwrap()).unwrap();
+    broadcaster.send_frame(&frame, ButtonState::empty());
+    assert_eq!(broadcaster.spectator_count(), 1);
+
+    let mut frames = 0;
+    let mut slowest = Duration::ZERO;
+    while broadcaster.spectator_count() > 0 {
+        let start = Instant::now();
+        broadcaster.send_frame(&frame, ButtonState::empty());
+        slowest = slowest.max(start.elapsed());
+        frames += 1;
+        assert!(frames < 10_000, "the spectator was never disconnected");
+    }
+    // Sending only queues the frame, so the stalled spectator never held it up
+    assert!(slowest < Duration::from_millis(50), "{:?}", slowest);
+}
+
+#[test]
+fn oversized_history() {
+    // A server claiming an absurdly long history is refused instead of being trusted with an allocation
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(MAGIC).unwrap();
+        stream.write_all(&u64::MAX.to_le_bytes()).unwrap();
+    });
+
+    let error = Spectator::connect(addr).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    server.join().unwrap();
+}
@@ -0,0 +1,89 @@
+use gb_core::gameboy::{joypad::ButtonState, timeline::InputTimeline};
+
+#[test]
+fn editing() {
+    let mut timeline = InputTimeline::<()>::new();
+    timeline.push(ButtonState::A);
+    timeline.set(3, ButtonState::B);
+    assert_eq!(
+        timeline.inputs(),
+        [
+            ButtonState::A,
+            ButtonState::empty(),
+            ButtonState::empty(),
+            ButtonState::B
+        ]
+    );
+    assert_eq!(timeline.get(10), ButtonState::empty());
+
+    timeline.insert(1, ButtonState::START);
+    timeline.delete(0);
+    assert_eq!(
+        timeline.inputs(),
+        [
+            ButtonState::START,
+            ButtonState::empty(),
+            ButtonState::empty(),
+            ButtonState::B
+        ]
+    );
+}
+
+#[test]
+fn greenzone() {
+    let mut timeline = InputTimeline::from_inputs(vec![ButtonState::empty(); 10]);
+    for frame in 0..=10 {
+        timeline.record_state(frame, frame * 100);
+    }
+    assert_eq!(timeline.greenzone_end(), 11);
+
+    // Setting a frame to what it already was changes nothing
+    timeline.set(4, ButtonState::empty());
+    assert_eq!(timeline.greenzone_end(), 11);
+
+    // Frame 6's input only affects the states after it
+    timeline.set(6, ButtonState::A);
+    assert_eq!(timeline.state(6), Some(&600));
+    assert_eq!(timeline.state(7), None);
+    assert_eq!(timeline.latest_state(9), Some((6, &600)));
+
+    timeline.delete(2);
+    assert_eq!(timeline.greenzone_end(), 3);
+    timeline.insert(0, ButtonState::B);
+    assert_eq!(timeline.latest_state(9), Some((0, &0)));
+}
+
+#[test]
+fn bookmarks() {
+    let mut timeline = InputTimeline::from_inputs(vec![ButtonState::A; 6]);
+    for frame in 0..=6 {
+        timeline.record_state(frame, frame * 100);
+    }
+    timeline.add_bookmark("boss", 4, 400).unwrap();
+    assert!(timeline.add_bookmark("too far", 7, 700).is_err());
+
+    // Try another route from frame 2, then change our mind
+    timeline.set(2, ButtonState::B);
+    timeline.push(ButtonState::START);
+    assert_eq!(timeline.greenzone_end(), 3);
+
+    let (frame, state) = timeline.branch_from("boss").unwrap();
+    assert_eq!((frame, *state), (4, 400));
+    assert_eq!(timeline.inputs(), [ButtonState::A; 4]);
+    // The states before the first changed input survive, and the bookmark's own state is back
+    assert_eq!(timeline.state(2), Some(&200));
+    assert_eq!(timeline.state(3), None);
+    assert_eq!(timeline.latest_state(10), Some((4, &400)));
+
+    let bookmark = timeline.bookmark("boss").unwrap();
+    assert_eq!(bookmark.inputs(), [ButtonState::A; 4]);
+    assert_eq!(
+        timeline
+            .bookmarks()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>(),
+        ["boss"]
+    );
+    assert!(timeline.remove_bookmark("boss").is_some());
+    assert!(timeline.branch_from("boss").is_err());
+}
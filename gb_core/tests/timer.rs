@@ -0,0 +1,122 @@
+mod common;
+
+#[test]
+fn div_apu_ticks() {
+    let mut gb = common::gameboy_with_code(&[0x18, 0xFE]); // JR -2
+
+    // DIV is $AB after boot, so bit 4 first falls after 1344 M-cycles, and then every 2048
+    for _ in 0..1343 {
+        gb.clock();
+    }
+    assert_eq!(gb.timer().div_apu_ticks(), 0);
+    gb.clock();
+    assert_eq!(gb.timer().div_apu_ticks(), 1);
+
+    for _ in 0..2048 * 3 {
+        gb.clock();
+    }
+    assert_eq!(gb.timer().div_apu_ticks(), 4);
+}
+
+#[test]
+#[rustfmt::skip]
+fn div_write_ticks_div_apu() {
+    let code = [
+        0xF0, 0x04, // LDH A, (DIV)
+        0xE6, 0x10, // AND $10
+        0x28, 0xFA, // JR Z, -6
+        0xE0, 0x04, // LDH (DIV), A
+        0x18, 0xFE, // JR -2
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+
+    // DIV is $AB after boot, so bit 4 is set after 320 M-cycles, and then the write resets DIV
+    for _ in 0..400 {
+        gb.clock();
+    }
+    assert_eq!(gb.timer().div_apu_ticks(), 1);
+    assert!(gb.debug_read(0xFF04) < 0x10);
+
+    for _ in 0..1800 {
+        gb.clock();
+    }
+    assert_eq!(gb.timer().div_apu_ticks(), 1);
+}
+
+/// Step one instruction at a time, returning TIMA after each
+fn tima_after_each_instruction(code: &[u8], instructions: usize) -> Vec<u8> {
+    let mut gb = common::gameboy_with_code(code);
+    // The first step only fetches the first instruction
+    gb.step_instruction();
+    (0..instructions)
+        .map(|_| {
+            gb.step_instruction();
+            gb.debug_read(0xFF05)
+        })
+        .collect()
+}
+
+#[test]
+#[rustfmt::skip]
+fn div_write_falling_edge() {
+    let code = [
+        0x3E, 0x05, // LD A, $05
+        0xE0, 0x07, // LDH (TAC), A ; TIMA is clocked by bit 3 of the divider
+        0xE0, 0x04, // LDH (DIV), A
+        0xE0, 0x04, // LDH (DIV), A
+        0xE0, 0x04, // LDH (DIV), A
+        0xE0, 0x04, // LDH (DIV), A
+        0x18, 0xFE, // JR -2
+    ];
+    let tima = tima_after_each_instruction(&code, 6);
+
+    // Between writes the divider never gets as far as clearing bit 3 itself, but each write clears it while it's set
+    assert_eq!(tima[3] - tima[2], 1);
+    assert_eq!(tima[4] - tima[3], 1);
+    assert_eq!(tima[5] - tima[4], 1);
+}
+
+#[test]
+#[rustfmt::skip]
+fn tac_disable_falling_edge() {
+    let code = [
+        0x3E, 0x05, // LD A, $05
+        0xE0, 0x07, // LDH (TAC), A
+        0x0E, 0x07, // LD C, $07
+        0x3E, 0x01, // LD A, $01
+        0xE0, 0x04, // LDH (DIV), A
+        0xE2,       // LD (C), A ; disables the timer while bit 3 is set
+        0x18, 0xFE, // JR -2
+    ];
+    let tima = tima_after_each_instruction(&code, 7);
+
+    assert_eq!(tima[5] - tima[4], 1);
+    assert_eq!(tima[6], tima[5]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn tima_reload_delay() {
+    let code = [
+        0x3E, 0xAB, // LD A, $AB
+        0xE0, 0x06, // LDH (TMA), A
+        0x3E, 0xFF, // LD A, $FF
+        0xE0, 0x05, // LDH (TIMA), A
+        0x3E, 0x05, // LD A, $05
+        0xE0, 0x07, // LDH (TAC), A
+        0x18, 0xFE, // JR -2
+    ];
+    let mut gb = common::gameboy_with_code(&code);
+    let mut cycles = Vec::new();
+    for _ in 0..100 {
+        gb.clock();
+        cycles.push((gb.debug_read(0xFF05), gb.debug_read(0xFF0F) & 0b100 != 0));
+    }
+
+    let start = cycles.iter().position(|&(tima, _)| tima == 0xFF).unwrap();
+    let overflow = start + cycles[start..].iter().position(|&(tima, _)| tima == 0).unwrap();
+    // TIMA reads 0 for one M-cycle before it's reloaded, and the interrupt is requested with the reload
+    assert_eq!(cycles[overflow - 1], (0xFF, false));
+    assert_eq!(cycles[overflow], (0x00, false));
+    assert_eq!(cycles[overflow + 1], (0xAB, true));
+}
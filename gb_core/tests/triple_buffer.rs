@@ -0,0 +1,57 @@
+use std::thread;
+
+use gb_core::triple_buffer::triple_buffer;
+
+#[test]
+fn reads_newest_value() {
+    let (mut writer, mut reader) = triple_buffer(0);
+    assert!(!reader.has_new());
+    assert_eq!(*reader.read(), 0);
+
+    writer.write(1);
+    writer.write(2);
+    assert!(reader.has_new());
+    assert_eq!(
+        *reader.read(),
+        2,
+        "Values the reader didn't get to are skipped"
+    );
+    assert!(!reader.has_new());
+    assert_eq!(*reader.read(), 2);
+
+    *writer.buffer_mut() = 3;
+    assert_eq!(*reader.read(), 2, "Nothing is seen until it's published");
+    writer.publish();
+    assert_eq!(*reader.read(), 3);
+}
+
+#[test]
+fn buffers_are_reused() {
+    let (mut writer, mut reader) = triple_buffer(Vec::<u8>::new());
+    for i in 0..10 {
+        let buffer = writer.buffer_mut();
+        buffer.clear();
+        buffer.extend_from_slice(&[i; 4]);
+        writer.publish();
+        assert_eq!(*reader.read(), vec![i; 4]);
+    }
+}
+
+#[test]
+fn across_threads() {
+    let (mut writer, mut reader) = triple_buffer([0u32; 64]);
+    let writing = thread::spawn(move || {
+        for i in 1..=10_000 {
+            writer.write([i; 64]);
+        }
+    });
+
+    let mut last = 0;
+    while last != 10_000 {
+        let value = reader.read();
+        assert!(value.iter().all(|&x| x == value[0]), "Torn read");
+        assert!(value[0] >= last, "Went back from {} to {}", last, value[0]);
+        last = value[0];
+    }
+    writing.join().unwrap();
+}
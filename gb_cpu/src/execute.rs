@@ -1,5 +1,7 @@
 //! Contains logic for CPU operation
 
+use alloc::boxed::Box;
+
 use super::decode;
 use super::{CpuInputPins, CpuOutputPins, FRegister};
 
@@ -22,7 +24,7 @@ impl super::Cpu {
     }
 
     fn nop(&self) -> CpuOutputPins {
-        CpuOutputPins::Read { addr: 0 }
+        CpuOutputPins::Idle
     }
 
     fn store_16_bits(&mut self, v: u16, dest: LoadDest16Bit) {
@@ -365,14 +367,70 @@ pub struct CpuRunnerYield {
     pub pins: CpuOutputPins,
     /// Indicates that the CPU is fetching the next opcode. Used for debug purposes.
     pub is_fetch_cycle: bool,
+    /// The IF bit of the interrupt the CPU started servicing on this cycle. Whatever holds IF must clear it.
+    pub interrupt_ack: Option<u8>,
+    /// The opcode of the instruction this cycle belongs to. `None` on fetch cycles, as the opcode hasn't been read
+    /// yet, and while halted or dispatching an interrupt. CB-prefixed instructions report $CB, with the second
+    /// byte as their first operand.
+    pub opcode: Option<u8>,
+    /// The operand bytes read so far, of which only the first `operand_count` are valid. See `operands`. On cycles
+    /// without an `opcode` they're all of the last instruction's operands, including any read on its final cycle.
+    pub operand_bytes: [u8; 2],
+    pub operand_count: u8,
+    /// Whether this cycle is part of dispatching an interrupt, from the wait states to the jump to the vector
+    pub interrupt_dispatch: bool,
+    /// The CPU is halted. It doesn't use the bus or change any state until one of the interrupt pins is set, so
+    /// callers may skip clocking it until then.
+    pub halted: bool,
+    /// The CPU ran one of the opcodes the SM83 doesn't define ($D3, $DB, $DD, $E3, $E4, $EB, $EC, $ED, $F4, $FC and
+    /// $FD) and has locked up, like the real one does. It never uses the bus again and interrupts don't wake it, so
+    /// only a reset gets it going. `opcode` is the illegal opcode, and PC points just past it.
+    pub locked_up: bool,
+}
+
+impl CpuRunnerYield {
+    /// The operand bytes of the current instruction read before this cycle, or all of the last instruction's if
+    /// there's no current one
+    pub fn operands(&self) -> &[u8] {
+        &self.operand_bytes[..self.operand_count as usize]
+    }
 }
 
 /// Provides a wrapper to use around the generator underneath the CPU execution logic.
+///
+/// Each call to [`CpuRunner::clock`] runs one M-cycle: it takes what the bus returned for the last cycle, and returns
+/// what the CPU does with the bus on this one.
+///
+/// ```
+/// use gb_cpu::{Cpu, CpuInputPins, CpuOutputPins};
+///
+/// let mut memory = [0; 0x10000];
+/// memory[..4].copy_from_slice(&[
+///     0x3E, 0x41, // LD A, $41
+///     0x3C,       // INC A
+///     0x00,       // NOP
+/// ]);
+///
+/// // Starts at $0000
+/// let mut runner = Cpu::default().runner();
+/// let mut input = CpuInputPins::default();
+/// for _ in 0..4 {
+///     input.data = match runner.clock(input).pins {
+///         CpuOutputPins::Read { addr } => memory[addr as usize],
+///         CpuOutputPins::Write { addr, data } => {
+///             memory[addr as usize] = data;
+///             0xFF
+///         }
+///         CpuOutputPins::Idle => 0xFF,
+///     };
+/// }
+/// assert_eq!(runner.cpu.registers.a, 0x42);
+/// ```
 pub struct CpuRunner {
     pub cpu: super::Cpu,
-    gen: std::pin::Pin<
+    gen: core::pin::Pin<
         Box<
-            dyn std::ops::Generator<
+            dyn core::ops::Generator<
                 (super::Cpu, CpuInputPins),
                 Yield = (super::Cpu, CpuRunnerYield),
                 Return = !,
@@ -384,7 +442,7 @@ pub struct CpuRunner {
 impl CpuRunner {
     /// Clock the CPU by exactly one M-cycle
     pub fn clock(&mut self, pins: CpuInputPins) -> CpuRunnerYield {
-        use std::ops::GeneratorState;
+        use core::ops::GeneratorState;
         match self.gen.as_mut().resume((self.cpu, pins)) {
             GeneratorState::Yielded((cpu, pins_out)) => {
                 self.cpu = cpu;
@@ -395,8 +453,8 @@ impl CpuRunner {
     }
 }
 
-impl std::fmt::Debug for CpuRunner {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for CpuRunner {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("CpuRunner")
             .field("Cpu", &self.cpu)
             .finish_non_exhaustive()
@@ -404,26 +462,52 @@ impl std::fmt::Debug for CpuRunner {
 }
 
 /// Yields a generator containing state that will run the cpu
-fn cpu_runner_gen(
-) -> impl std::ops::Generator<(super::Cpu, CpuInputPins), Yield = (super::Cpu, CpuRunnerYield), Return = !>
-{
+fn cpu_runner_gen() -> impl core::ops::Generator<
+    (super::Cpu, CpuInputPins),
+    Yield = (super::Cpu, CpuRunnerYield),
+    Return = !,
+> {
     // Every `yield` here will cause the CPU to wait for one memory cycle.
     #[allow(unused_assignments)]
     move |t: (super::Cpu, CpuInputPins)| {
         let (mut cpu, mut pins) = t;
         let mut halted = false;
+        // Set by EI, which enables interrupts one instruction late
+        let mut enable_ime = false;
+        let mut locked_up = false;
         let mut fetch = false;
+        let mut interrupt_ack = None;
+        let mut interrupt_dispatch = false;
+        let mut current_opcode = None;
+        let mut operands = [0; 2];
+        let mut operand_count = 0;
         loop {
             macro_rules! cpu_yield {
                 ($pins:expr) => {
                     let _yielded = CpuRunnerYield {
                         pins: $pins,
                         is_fetch_cycle: fetch,
+                        interrupt_ack: interrupt_ack.take(),
+                        opcode: current_opcode,
+                        operand_bytes: operands,
+                        operand_count,
+                        interrupt_dispatch,
+                        halted,
+                        locked_up,
                     };
                     (cpu, pins) = yield (cpu, _yielded);
                 };
             }
 
+            /// Read the next byte of the instruction after the opcode. Yields a cpu cycle.
+            macro_rules! fetch_operand {
+                () => {
+                    cpu_yield!(cpu.fetch_byte());
+                    operands[operand_count as usize] = pins.data;
+                    operand_count += 1;
+                };
+            }
+
             /// Store an 8 bit value into a register specified by the `r` table. Yields a cpu cycle on indirect HL write, unyielding otherwise.
             ///
             /// See https://gb-archive.github.io/salvage/decoding_gbz80_opcodes/Decoding%20Gamboy%20Z80%20Opcodes.html
@@ -465,7 +549,17 @@ fn cpu_runner_gen(
                 };
             }
 
-            // Handle interrupts
+            if locked_up {
+                cpu_yield!(cpu.nop());
+                continue;
+            }
+
+            // Handle interrupts. EI only takes effect after this check, so the instruction after it runs first
+            let ime = cpu.ime;
+            if enable_ime {
+                cpu.ime = true;
+                enable_ime = false;
+            }
             let interrupt = if pins.interrupt_40h {
                 Some(0x40)
             } else if pins.interrupt_48h {
@@ -482,16 +576,17 @@ fn cpu_runner_gen(
 
             if let Some(vector) = interrupt {
                 halted = false;
-                if cpu.ime {
+                if ime {
                     // Interrupt Service Routine (5 clock cycles)
                     // https://gbdev.io/pandocs/Interrupts.html#interrupt-handling
 
-                    // Normally, these two cycles would both be NOPs, but in this architecture we
-                    // have to take advantage of these cycles to reset the IF flag.
-                    cpu_yield!(cpu.read_byte(0xFF0F));
-                    let interrupt_flag = pins.data;
-                    let if_mask = !(1 << ((vector - 0x40) / 8));
-                    cpu_yield!(cpu.write_byte(0xFF0F, interrupt_flag & if_mask));
+                    interrupt_dispatch = true;
+                    current_opcode = None;
+
+                    // Two wait states. The interrupt is acknowledged on the first, which clears its IF bit
+                    interrupt_ack = Some(1 << ((vector - 0x40) / 8));
+                    cpu_yield!(cpu.nop());
+                    cpu_yield!(cpu.nop());
 
                     let pc = cpu.registers.get_pc();
                     let pc_lo = (pc & 0xFF) as u8;
@@ -508,19 +603,24 @@ fn cpu_runner_gen(
                     cpu.ime = false;
 
                     cpu_yield!(cpu.nop());
+                    interrupt_dispatch = false;
                 }
             }
 
             // If the CPU is halted, stop processing instructions, and wait for an interrupt to wake up the CPU.
             if halted {
+                current_opcode = None;
                 cpu_yield!(cpu.nop());
                 continue;
             }
 
             // Fetch
             fetch = true;
+            current_opcode = None;
             cpu_yield!(cpu.fetch_byte());
             fetch = false;
+            current_opcode = Some(pins.data);
+            operand_count = 0;
             let opcode = super::decode::Opcode(pins.data);
 
             // Decode & execute
@@ -536,9 +636,9 @@ fn cpu_runner_gen(
                         0 => continue, // NOP
                         1 => {
                             // LD (nn), SP
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let low = pins.data;
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let high = pins.data;
 
                             let addr = ((high as u16) << 8) | (low as u16);
@@ -558,7 +658,7 @@ fn cpu_runner_gen(
                         }
                         3 => {
                             // JR d
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let offset = pins.data as i8 as i16;
                             let pc = cpu.registers.get_pc() as i16;
                             let new_pc = (pc + offset) as u16;
@@ -571,7 +671,7 @@ fn cpu_runner_gen(
                         y @ 4..=7 => {
                             // JR d
                             let cond = decode::cc(y - 4);
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let offset = pins.data as i8 as i16;
 
                             if cpu.test_condition(cond) {
@@ -592,9 +692,9 @@ fn cpu_runner_gen(
                         // 16-bit LD
                         let dst = decode::rp(opcode.p());
 
-                        cpu_yield!(cpu.fetch_byte());
+                        fetch_operand!();
                         let low = pins.data;
-                        cpu_yield!(cpu.fetch_byte());
+                        fetch_operand!();
                         let high = pins.data;
 
                         let v = ((high as u16) << 8) | (low as u16);
@@ -726,7 +826,7 @@ fn cpu_runner_gen(
                         // LD from immediate
                         let dst = decode::r(opcode.y());
 
-                        cpu_yield!(cpu.fetch_byte());
+                        fetch_operand!();
                         store_8_bits!(cpu, pins.data, dst);
                         continue;
                     }
@@ -857,7 +957,7 @@ fn cpu_runner_gen(
                         }
                         4 => {
                             // LDH (n), A
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let n = pins.data;
                             let addr = 0xFF00 + (n as u16);
                             let v = cpu.registers.get_a();
@@ -866,7 +966,7 @@ fn cpu_runner_gen(
                         }
                         5 => {
                             // ADD SP, n
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let n = pins.data as i8 as i16 as u16;
                             let sp = cpu.registers.get_sp();
                             let v = sp.wrapping_add(n);
@@ -887,7 +987,7 @@ fn cpu_runner_gen(
                         }
                         6 => {
                             // LDH A, (n)
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let n = pins.data;
                             let addr = 0xFF00 + (n as u16);
                             cpu_yield!(cpu.read_byte(addr));
@@ -896,7 +996,7 @@ fn cpu_runner_gen(
                         }
                         7 => {
                             // LD HL, SP+d
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let n = pins.data as i8 as i16 as u16;
                             let sp = cpu.registers.get_sp();
                             let v = sp.wrapping_add(n);
@@ -982,9 +1082,9 @@ fn cpu_runner_gen(
                             // JP c nn
                             let condition = decode::cc(y);
 
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let low = pins.data;
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let high = pins.data;
 
                             let addr = ((high as u16) << 8) | (low as u16);
@@ -1014,9 +1114,9 @@ fn cpu_runner_gen(
                         }
                         5 => {
                             // LD (nn), A
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let low = pins.data;
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let high = pins.data;
 
                             let addr = ((high as u16) << 8) | (low as u16);
@@ -1026,9 +1126,9 @@ fn cpu_runner_gen(
                         }
                         7 => {
                             // LD A, (nn)
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let low = pins.data;
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let high = pins.data;
 
                             let addr = ((high as u16) << 8) | (low as u16);
@@ -1042,19 +1142,21 @@ fn cpu_runner_gen(
                     3 => match opcode.y() {
                         0 => {
                             // JP nn
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let low = pins.data;
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let high = pins.data;
 
                             let addr = ((high as u16) << 8) | (low as u16);
                             cpu.registers.set_pc(addr);
+                            // Pause for a cycle
+                            cpu_yield!(cpu.nop());
                             continue;
                         }
                         1 => {
                             // CB Prefix
 
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let opcode = decode::Opcode(pins.data);
 
                             let dest = decode::r(opcode.z());
@@ -1072,7 +1174,8 @@ fn cpu_runner_gen(
                                         f.set(FRegister::HALFCARRY);
                                         f
                                     });
-                                    v
+                                    // Only tests the value, so (HL) isn't written back
+                                    continue;
                                 }
                                 2 => {
                                     // RES
@@ -1096,17 +1199,21 @@ fn cpu_runner_gen(
                         }
                         7 => {
                             // EI
-                            cpu.ime = true;
+                            enable_ime = true;
+                            continue;
+                        }
+                        _ => {
+                            // $D3, $DB, $E3 and $EB
+                            locked_up = true;
                             continue;
                         }
-                        _ => panic!("Unidentified opcode"),
                     },
                     4 => match opcode.y() {
                         y @ 0..=3 => {
                             // CALL cc, nn
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let low = pins.data;
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let high = pins.data;
 
                             let addr = ((high as u16) << 8) | (low as u16);
@@ -1130,13 +1237,19 @@ fn cpu_runner_gen(
                                 continue;
                             }
                         }
-                        4..=7 => panic!(),
+                        4..=7 => {
+                            // $E4, $EC, $F4 and $FC
+                            locked_up = true;
+                            continue;
+                        }
                         _ => unreachable!(),
                     },
                     5 if opcode.q() == 0 => {
                         // PUSH
                         let from = decode::rp2(opcode.p());
                         let v = cpu.read_16_bits(from);
+                        // Pause for a cycle
+                        cpu_yield!(cpu.nop());
 
                         cpu.registers.modify_sp(|sp| sp.wrapping_sub(1));
                         let high = (v >> 8) as u8;
@@ -1149,9 +1262,9 @@ fn cpu_runner_gen(
                     5 if opcode.q() == 1 => match opcode.p() {
                         0 => {
                             // CALL nn
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let low = pins.data;
-                            cpu_yield!(cpu.fetch_byte());
+                            fetch_operand!();
                             let high = pins.data;
 
                             let addr = ((high as u16) << 8) | (low as u16);
@@ -1171,13 +1284,17 @@ fn cpu_runner_gen(
 
                             continue;
                         }
-                        1..=3 => panic!(),
+                        1..=3 => {
+                            // $DD, $ED and $FD
+                            locked_up = true;
+                            continue;
+                        }
                         _ => unreachable!(),
                     },
                     6 => {
                         let operation = decode::alu(opcode.y());
 
-                        cpu_yield!(cpu.fetch_byte());
+                        fetch_operand!();
                         let n = pins.data;
 
                         cpu.do_math(n, operation);
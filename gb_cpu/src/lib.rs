@@ -1,9 +1,26 @@
+//! An emulator for the Sharp SM83, the CPU in the Gameboy
+//!
+//! [`Sm83`] runs the CPU against anything implementing [`Bus`], one instruction at a time. For cycle by cycle
+//! control, [`CpuRunner`] exposes the CPU's pins directly: each call to [`CpuRunner::clock`] is one M-cycle.
+//!
+//! The crate only needs `alloc`. Without the default `std` feature it builds as `no_std`.
+
+#![feature(generators)]
+#![feature(generator_trait)]
+#![feature(destructuring_assignment)]
+#![feature(never_type)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 pub mod assembler;
 mod decode;
 mod execute;
+mod sm83;
 
 pub use execute::{CpuRunner, CpuRunnerYield};
 pub use registers::{FRegister, Registers};
+pub use sm83::{Bus, Sm83};
 
 /// Contains the state of a LR35902 CPU.
 #[derive(Clone, Copy, Debug, Default)]
@@ -12,18 +29,21 @@ pub struct Cpu {
     pub ime: bool,
 }
 
+/// What the CPU does with the bus during an M-cycle. On `Idle` cycles it is busy internally.
 #[derive(Debug, Clone, Copy)]
 pub enum CpuOutputPins {
     Read { addr: u16 },
     Write { addr: u16, data: u8 },
+    Idle,
 }
 
 impl CpuOutputPins {
     #[inline]
-    pub fn addr(&self) -> u16 {
+    pub fn addr(&self) -> Option<u16> {
         match self {
-            Self::Read { addr } => *addr,
-            Self::Write { addr, .. } => *addr,
+            Self::Read { addr } => Some(*addr),
+            Self::Write { addr, .. } => Some(*addr),
+            Self::Idle => None,
         }
     }
 }
@@ -39,11 +59,11 @@ pub struct CpuInputPins {
 }
 
 mod registers {
-    use paste::paste;
-    use std::{
+    use core::{
         fmt::Debug,
         ops::{BitAnd, BitOr, BitOrAssign, Not},
     };
+    use paste::paste;
 
     #[derive(Clone, Copy, PartialEq, Eq, Default)]
     pub struct Registers {
@@ -60,7 +80,7 @@ mod registers {
     }
 
     impl Debug for Registers {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
             f.debug_struct("Registers")
                 .field("A", &format_args!("{:02X}", self.a))
                 .field("B", &format_args!("{:02X}", self.b))
@@ -239,7 +259,7 @@ mod registers {
     }
 
     impl Debug for FRegister {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
             write!(
                 f,
                 "{}",
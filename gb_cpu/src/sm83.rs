@@ -0,0 +1,118 @@
+//! Running the CPU one instruction at a time against a [`Bus`]
+
+use super::{Cpu, CpuInputPins, CpuOutputPins, CpuRunner, Registers};
+
+/// Everything the CPU is connected to. Each call to `read`, `write` or `idle` is one M-cycle.
+///
+/// `pending_interrupts` and `acknowledge_interrupt` aren't bus accesses: the real CPU sees IE and IF on dedicated
+/// lines. Implementations should answer them from their own state without counting cycles or causing side effects.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+
+    /// Called on M-cycles where the CPU doesn't access the bus
+    fn idle(&mut self) {}
+
+    /// The interrupts that are both requested and enabled, as a mask of IF/IE bits. Asked once per M-cycle.
+    fn pending_interrupts(&mut self) -> u8;
+
+    /// Called when the CPU starts servicing an interrupt, with that interrupt's IF bit, which should be cleared
+    fn acknowledge_interrupt(&mut self, mask: u8);
+}
+
+/// An SM83 CPU
+pub struct Sm83 {
+    runner: CpuRunner,
+    /// What the bus returned on the last cycle
+    input: CpuInputPins,
+    locked_up: bool,
+}
+
+impl Sm83 {
+    /// Create a CPU which starts executing at `registers.pc`
+    pub fn new(registers: Registers) -> Self {
+        Sm83 {
+            runner: Cpu {
+                registers,
+                ime: false,
+            }
+            .runner(),
+            input: CpuInputPins::default(),
+            locked_up: false,
+        }
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.runner.cpu.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.runner.cpu.registers
+    }
+
+    /// Whether interrupts are enabled
+    pub fn ime(&self) -> bool {
+        self.runner.cpu.ime
+    }
+
+    /// Whether the CPU has locked up after running an illegal opcode, see [`CpuRunnerYield::locked_up`]
+    ///
+    /// [`CpuRunnerYield::locked_up`]: crate::CpuRunnerYield::locked_up
+    pub fn is_locked_up(&self) -> bool {
+        self.locked_up
+    }
+
+    /// Run for one M-cycle. Returns true if the CPU fetched an opcode.
+    pub fn clock(&mut self, bus: &mut impl Bus) -> bool {
+        let out = self.runner.clock(self.input);
+        self.locked_up = out.locked_up;
+        let data = match out.pins {
+            CpuOutputPins::Read { addr } => bus.read(addr),
+            CpuOutputPins::Write { addr, data } => {
+                bus.write(addr, data);
+                0xFF
+            }
+            CpuOutputPins::Idle => {
+                bus.idle();
+                0xFF
+            }
+        };
+
+        if let Some(mask) = out.interrupt_ack {
+            bus.acknowledge_interrupt(mask);
+        }
+
+        let interrupts = bus.pending_interrupts();
+        self.input = CpuInputPins {
+            data,
+            interrupt_40h: interrupts & 0x01 != 0,
+            interrupt_48h: interrupts & 0x02 != 0,
+            interrupt_50h: interrupts & 0x04 != 0,
+            interrupt_58h: interrupts & 0x08 != 0,
+            interrupt_60h: interrupts & 0x10 != 0,
+        };
+        out.is_fetch_cycle
+    }
+
+    /// Run until the CPU has fetched the next opcode, and return the number of M-cycles that took.
+    ///
+    /// Since the SM83 fetches an opcode during the last cycle of the previous instruction, this finishes the
+    /// current instruction (or interrupt dispatch, or halt) and then fetches the next one. A CPU that has locked up
+    /// never fetches again, so this only runs one cycle.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        let mut cycles = 1;
+        while !self.clock(bus) && !self.locked_up {
+            cycles += 1;
+        }
+        cycles
+    }
+}
+
+impl core::fmt::Debug for Sm83 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Sm83")
+            .field("registers", self.registers())
+            .field("ime", &self.ime())
+            .finish()
+    }
+}
@@ -0,0 +1,113 @@
+//! Helpers shared between the CPU's integration tests
+#![allow(dead_code)]
+
+use std::ops::RangeInclusive;
+
+use gb_cpu::{Bus, CpuInputPins, CpuOutputPins, CpuRunner, CpuRunnerYield};
+
+type ReadStub = Box<dyn FnMut(u16) -> u8>;
+
+/// 64 KiB of RAM behind every address, IE and IF included, so any instruction can run against it. Memory-mapped
+/// registers that need to behave differently can be stubbed out with [`FlatBus::stub_io`].
+pub struct FlatBus {
+    pub memory: Vec<u8>,
+    /// M-cycles on which the CPU didn't use the bus
+    pub idle_cycles: u32,
+    stubs: Vec<(RangeInclusive<u16>, ReadStub)>,
+}
+
+/// Zeroed memory
+impl Default for FlatBus {
+    fn default() -> Self {
+        FlatBus {
+            memory: vec![0; 0x10000],
+            idle_cycles: 0,
+            stubs: Vec::new(),
+        }
+    }
+}
+
+impl FlatBus {
+    /// Zeroed memory with `code` placed at `addr`
+    pub fn with_code(addr: u16, code: &[u8]) -> Self {
+        let mut bus = FlatBus::default();
+        bus.load(addr, code);
+        bus
+    }
+
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) {
+        self.memory[addr as usize..addr as usize + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Make reads of `addrs` return what `read` does, e.g. `|_| 0x90` for LY, and drop writes to them
+    pub fn stub_io(&mut self, addrs: RangeInclusive<u16>, read: impl FnMut(u16) -> u8 + 'static) {
+        self.stubs.push((addrs, Box::new(read)));
+    }
+
+    /// Clock `runner` for one M-cycle against the bus the same way `Sm83` does, for tests that need to see what the
+    /// CPU yields. `input` holds what the bus returned on the last cycle.
+    pub fn clock(&mut self, runner: &mut CpuRunner, input: &mut CpuInputPins) -> CpuRunnerYield {
+        let out = runner.clock(*input);
+        let data = match out.pins {
+            CpuOutputPins::Read { addr } => self.read(addr),
+            CpuOutputPins::Write { addr, data } => {
+                self.write(addr, data);
+                0xFF
+            }
+            CpuOutputPins::Idle => {
+                self.idle();
+                0xFF
+            }
+        };
+        if let Some(mask) = out.interrupt_ack {
+            self.acknowledge_interrupt(mask);
+        }
+        *input = self.input_pins(data);
+        out
+    }
+
+    /// What the CPU sees on its next cycle, with `data` on the data bus and the pending interrupts on their pins
+    pub fn input_pins(&mut self, data: u8) -> CpuInputPins {
+        let interrupts = self.pending_interrupts();
+        CpuInputPins {
+            data,
+            interrupt_40h: interrupts & 0x01 != 0,
+            interrupt_48h: interrupts & 0x02 != 0,
+            interrupt_50h: interrupts & 0x04 != 0,
+            interrupt_58h: interrupts & 0x08 != 0,
+            interrupt_60h: interrupts & 0x10 != 0,
+        }
+    }
+}
+
+impl Bus for FlatBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        match self
+            .stubs
+            .iter_mut()
+            .find(|(addrs, _)| addrs.contains(&addr))
+        {
+            Some((_, read)) => read(addr),
+            None => self.memory[addr as usize],
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        if !self.stubs.iter().any(|(addrs, _)| addrs.contains(&addr)) {
+            self.memory[addr as usize] = data;
+        }
+    }
+
+    fn idle(&mut self) {
+        self.idle_cycles += 1;
+    }
+
+    /// IE and IF straight from memory, so stubs and cycle counts aren't affected
+    fn pending_interrupts(&mut self) -> u8 {
+        self.memory[0xFFFF] & self.memory[0xFF0F] & 0x1F
+    }
+
+    fn acknowledge_interrupt(&mut self, mask: u8) {
+        self.memory[0xFF0F] &= !mask;
+    }
+}
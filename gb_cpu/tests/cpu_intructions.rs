@@ -0,0 +1,1380 @@
+mod common;
+
+use std::ops::Range;
+
+use common::FlatBus;
+use gb_cpu::{Cpu, CpuInputPins, CpuOutputPins, CpuRunner, CpuRunnerYield, FRegister};
+
+pub const RESULT_ADDR: u16 = 0xAA55;
+pub const RESULT_ADDR_LO: u8 = 0x55;
+pub const RESULT_ADDR_HI: u8 = 0xAA;
+
+/// The interrupt flag register
+const IF: u16 = 0xFF0F;
+
+/// Represents either a write to $AA55, or the reason the test machine stopped.
+pub type InstructionTestResult = Result<(Cpu, u8), InstructionTestError>;
+
+/// A write to $AA55 with the number of M-cycles since the one before it, including the write itself
+pub type TimedTestResult = Result<(Cpu, u8, u64), InstructionTestError>;
+
+#[derive(Debug)]
+pub enum InstructionTestError {
+    /// The CPU fetched an opcode from outside of the code, which is how tests end
+    LeftCode(u16),
+    MaxCyclesReached,
+}
+
+/// Runs code on a [`FlatBus`], so it can use all of memory, with the stack and IO registers where they normally are.
+pub struct InstructionTest {
+    pub cpu: Cpu,
+    pub bus: FlatBus,
+    /// Where code was loaded
+    code: Vec<Range<usize>>,
+    /// Interrupts to request, as the M-cycle to request them on and their IF bits
+    interrupts: Vec<(u64, u8)>,
+}
+
+impl InstructionTest {
+    pub fn new(init_cpu: Cpu, code: Vec<u8>, code_offset: u16) -> Self {
+        InstructionTest {
+            cpu: init_cpu,
+            bus: FlatBus::default(),
+            code: Vec::new(),
+            interrupts: Vec::new(),
+        }
+        .with_code(code_offset, &code)
+    }
+
+    /// Load more code at `addr`, e.g. an interrupt handler
+    pub fn with_code(mut self, addr: u16, code: &[u8]) -> Self {
+        self.bus.load(addr, code);
+        self.code.push(addr as usize..addr as usize + code.len());
+        self
+    }
+
+    /// Set the IF bits in `mask` at the end of M-cycle `cycle`, counting the first one as 1, like a peripheral
+    /// requesting an interrupt would. The CPU sees the request from the next cycle on. IE is left alone, so the code
+    /// has to enable the interrupt itself.
+    pub fn request_interrupt(mut self, cycle: u64, mask: u8) -> Self {
+        self.interrupts.push((cycle, mask));
+        self
+    }
+
+    fn is_code(&self, addr: u16) -> bool {
+        self.code.iter().any(|code| code.contains(&(addr as usize)))
+    }
+
+    /// Clock the CPU for M-cycle number `cycle`, then request the interrupts scheduled for it
+    fn clock(
+        &mut self,
+        runner: &mut CpuRunner,
+        input: &mut CpuInputPins,
+        cycle: u64,
+    ) -> CpuRunnerYield {
+        let out = self.bus.clock(runner, input);
+        let requested = self
+            .interrupts
+            .iter()
+            .filter(|(at, _)| *at == cycle)
+            .fold(0, |mask, (_, bits)| mask | bits);
+        if requested != 0 {
+            self.bus.memory[IF as usize] |= requested;
+            *input = self.bus.input_pins(input.data);
+        }
+        out
+    }
+
+    /// Run the cpu and return every write to $AA55 (stops after n cycles)
+    pub fn run<'a>(
+        self,
+        max_cycles: Option<u64>,
+    ) -> impl Iterator<Item = InstructionTestResult> + 'a {
+        self.run_timed(max_cycles)
+            .map(|result| result.map(|(cpu, d, _)| (cpu, d)))
+    }
+
+    /// Like `run`, but also returns how many M-cycles passed between each write to $AA55 and the one before it. The
+    /// first is counted from the start.
+    pub fn run_timed<'a>(
+        self,
+        max_cycles: Option<u64>,
+    ) -> impl Iterator<Item = TimedTestResult> + 'a {
+        struct Running {
+            error: bool,
+            cycles_elapsed: u64,
+            /// When $AA55 was last written to
+            last_result: u64,
+            max_cycles: Option<u64>,
+            cpu: CpuRunner,
+            input: CpuInputPins,
+            test: InstructionTest,
+        }
+
+        impl Iterator for Running {
+            type Item = TimedTestResult;
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.error {
+                    return None;
+                }
+
+                loop {
+                    self.cycles_elapsed += 1;
+                    let out = self
+                        .test
+                        .clock(&mut self.cpu, &mut self.input, self.cycles_elapsed);
+
+                    println!("CPU: {:?}", self.cpu.cpu);
+                    if self.cycles_elapsed >= self.max_cycles.unwrap_or(u64::MAX) {
+                        self.error = true;
+                        return Some(Err(InstructionTestError::MaxCyclesReached));
+                    }
+
+                    match out.pins {
+                        CpuOutputPins::Read { addr }
+                            if out.is_fetch_cycle && !self.test.is_code(addr) =>
+                        {
+                            self.error = true;
+                            return Some(Err(InstructionTestError::LeftCode(addr)));
+                        }
+                        CpuOutputPins::Write {
+                            addr: RESULT_ADDR,
+                            data,
+                        } => {
+                            let cycles = self.cycles_elapsed - self.last_result;
+                            self.last_result = self.cycles_elapsed;
+                            return Some(Ok((self.cpu.cpu, data, cycles)));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Running {
+            error: false,
+            cycles_elapsed: 0,
+            last_result: 0,
+            max_cycles,
+            cpu: self.cpu.runner(),
+            input: CpuInputPins::default(),
+            test: self,
+        }
+    }
+
+    /// Assert how many M-cycles pass before each write to $AA55, counting from the one before it, or the start. The
+    /// code should end by jumping out of itself.
+    pub fn assert_cycles(self, expected: &[u64]) {
+        let cycles: Vec<u64> = self
+            .run_timed(Some(100_000))
+            .map_while(Result::ok)
+            .map(|(_, _, cycles)| cycles)
+            .collect();
+        assert_eq!(cycles, expected);
+    }
+
+    /// Run `count` instructions, and return the address of each one with the number of M-cycles it took, from its
+    /// fetch to the next instruction's. An interrupt dispatch counts towards the instruction before it.
+    pub fn timings(mut self, count: usize) -> Vec<(u16, u32)> {
+        let mut runner = self.cpu.runner();
+        let mut input = CpuInputPins::default();
+        let mut timings: Vec<(u16, u32)> = Vec::new();
+        let mut cycle = 0;
+        while timings.len() <= count {
+            cycle += 1;
+            let out = self.clock(&mut runner, &mut input, cycle);
+            match (out.is_fetch_cycle, out.pins.addr()) {
+                (true, Some(addr)) => timings.push((addr, 1)),
+                _ => timings.last_mut().expect("the first cycle is a fetch").1 += 1,
+            }
+        }
+        // The last fetch only ends the instruction before it
+        timings.pop();
+        timings
+    }
+}
+
+#[test]
+fn nop() {
+    let cpu = Cpu::default();
+
+    let mut cpu = cpu.runner();
+
+    assert!(matches!(
+        cpu.clock(CpuInputPins {
+            data: 0,
+            ..Default::default()
+        })
+        .pins,
+        // Should fetch first instruction
+        CpuOutputPins::Read { addr: 0 }
+    ),);
+
+    assert!(matches!(
+        cpu.clock(CpuInputPins {
+            data: 0x00, // NOP
+            ..Default::default()
+        })
+        .pins,
+        // Recieved NOP, should immediately fetch next instruction due to fetch/execute overlap
+        CpuOutputPins::Read { addr: 1 }
+    ));
+}
+
+#[test]
+fn load() {
+    let cpu = Cpu::default();
+
+    let code = vec![
+        0x3E, 0xA5, // LD A, $A5
+        0x21, 0x55, 0xAA, // LD HL, $AA55
+        0x77, // LD (HL), A
+    ];
+
+    let tester = InstructionTest::new(cpu, code, 0);
+
+    assert_eq!(
+        tester
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|t| t.1)
+            .collect::<Vec<_>>(),
+        vec![0xA5]
+    );
+}
+
+#[test]
+fn add() {
+    let cpu = Cpu::default();
+
+    let code = vec![
+        0x21, 0x55, 0xAA, // LD HL, $AA55
+        0x3E, 12, // LD A, 12
+        0x06, 17,   // LD B, 17
+        0x80, // ADD A,B
+        0x77, // LD (HL), A
+        0x3E, 0xFF, // LD A, $FF
+        0x06, 1,    // LD B, 1
+        0x80, // ADD A,B
+        0x77, // LD (HL), A
+        0x3E, 0x0F, // LD A, $F,
+        0x06, 1,    // LD B, 1
+        0x80, // ADD A,B
+        0x77, // LD (HL), A
+    ];
+
+    let tester = InstructionTest::new(cpu, code, 0);
+
+    assert_eq!(
+        tester
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|(cpu, d)| (cpu.registers.get_f(), d))
+            .collect::<Vec<_>>(),
+        vec![
+            (FRegister::EMPTY, 29),
+            (FRegister::ZERO | FRegister::CARRY | FRegister::HALFCARRY, 0),
+            (FRegister::HALFCARRY, 16),
+        ]
+    );
+}
+
+#[test]
+fn adc() {
+    let cpu = Cpu::default();
+
+    let code = vec![
+        0x21, 0x55, 0xAA, // LD HL, $AA55
+        0x3E, 12, // LD A, 12
+        0x06, 17,   // LD B, 17
+        0x88, // ADC A,B
+        0x77, // LD (HL), A
+        0x3E, 0xFF, // LD A, $FF
+        0x06, 1,    // LD B, 1
+        0x88, // ADC A,B
+        0x77, // LD (HL), A
+        0x3E, 0x0F, // LD A, $F,
+        0x06, 1,    // LD B, 1
+        0x88, // ADC A,B
+        0x77, // LD (HL), A
+        0x3E, 0xFF, // LD A, $FF
+        0x06, 1,    // LD B, 1
+        0x88, // ADC A,B
+        0x77, // LD (HL), A
+        0x3E, 1, // LD A, 1
+        0x06, 0xFF, // LD B, $FF
+        0x88, // ADC A,B
+        0x77, // LD (HL), A
+    ];
+
+    let tester = InstructionTest::new(cpu, code, 0);
+
+    assert_eq!(
+        tester
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|(cpu, d)| (cpu.registers.get_f(), d))
+            .collect::<Vec<_>>(),
+        vec![
+            (FRegister::EMPTY, 29),
+            (FRegister::ZERO | FRegister::CARRY | FRegister::HALFCARRY, 0),
+            (FRegister::HALFCARRY, 17),
+            (FRegister::ZERO | FRegister::CARRY | FRegister::HALFCARRY, 0),
+            (FRegister::CARRY | FRegister::HALFCARRY, 1),
+        ]
+    );
+}
+
+#[test]
+fn sub() {
+    let cpu = Cpu::default();
+
+    let code = vec![
+        0x21, 0x55, 0xAA, // LD HL, $AA55
+        0x3E, 17, // LD A, 17
+        0x06, 12,   // LD B, 12
+        0x90, // SUB B
+        0x77, // LD (HL), A
+        0x3E, 0, // LD A, 0
+        0x06, 1,    // LD B, 1
+        0x90, // SUB B
+        0x77, // LD (HL), A
+        0x3E, 0x10, // LD A, $10,
+        0x06, 1,    // LD B, 1
+        0x90, // SUB B
+        0x77, // LD (HL), A
+        0x3E, 5, // LD A, 5,
+        0x06, 5,    // LD B, 5
+        0x90, // SUB B
+        0x77, // LD (HL), A
+    ];
+
+    let tester = InstructionTest::new(cpu, code, 0);
+
+    assert_eq!(
+        tester
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|(cpu, d)| (cpu.registers.get_f(), d))
+            .collect::<Vec<_>>(),
+        vec![
+            (FRegister::NEGATIVE | FRegister::HALFCARRY, 5),
+            (
+                FRegister::NEGATIVE | FRegister::HALFCARRY | FRegister::CARRY,
+                0xFF
+            ),
+            (FRegister::NEGATIVE | FRegister::HALFCARRY, 15),
+            (FRegister::NEGATIVE | FRegister::ZERO, 0),
+        ]
+    );
+}
+
+#[test]
+fn add_hl() {
+    /// Output the opcodes necessary to perform an addition of two u16s and output the result in little endian order
+    fn perform_add(a: u16, b: u16) -> Vec<u8> {
+        let a_lo = (a & 0xff) as u8;
+        let b_lo = (b & 0xff) as u8;
+        let a_hi = (a >> 8) as u8;
+        let b_hi = (b >> 8) as u8;
+
+        vec![
+            0x21, a_lo, a_hi, // LD HL, <a>
+            0x01, b_lo, b_hi, // LD BC, <b>
+            0x09, // ADD HL, BC
+            0x54, // LD D, H
+            0x5D, // LD E, L
+            0x21, 0x55, 0xAA, // LD HL, $AA55
+            0x73, // LD (HL), E
+            0x72, // LD (HL), D
+        ]
+    }
+
+    let cpu = Cpu::default();
+
+    let additions = [
+        (0x0105, 0x010B, 0x0210, FRegister::EMPTY),
+        (0x00FF, 0x0001, 0x0100, FRegister::EMPTY),
+        (
+            0xFFFF,
+            0x0001,
+            0x0000,
+            FRegister::HALFCARRY | FRegister::CARRY,
+        ),
+        (
+            0xFF00,
+            0x0100,
+            0x0000,
+            FRegister::CARRY | FRegister::HALFCARRY,
+        ),
+        (
+            0xFFFF,
+            0xFFFF,
+            0xFFFE,
+            FRegister::HALFCARRY | FRegister::CARRY,
+        ),
+    ];
+
+    let code = additions
+        .iter()
+        .flat_map(|(a, b, _, _)| perform_add(*a, *b))
+        .collect::<Vec<u8>>();
+
+    let compare_against = additions
+        .iter()
+        .flat_map(|(_, _, r, f)| {
+            let l = (r & 0xff) as u8;
+            let h = (r >> 8) as u8;
+            vec![(*f, l), (*f, h)]
+        })
+        .collect::<Vec<(FRegister, u8)>>();
+
+    let tester = InstructionTest::new(cpu, code, 0);
+
+    assert_eq!(
+        tester
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|(cpu, d)| (cpu.registers.get_f(), d))
+            .collect::<Vec<_>>(),
+        compare_against
+    )
+}
+
+#[test]
+#[rustfmt::skip]
+fn sp_plus_signed_offset() {
+    // (SP, offset, result, flags). The offset is sign extended, but C and H come from adding it to SP's low byte.
+    let cases = [
+        (0x0FF8, 0x08, 0x1000, FRegister::CARRY | FRegister::HALFCARRY),
+        (0x000F, 0x01, 0x0010, FRegister::HALFCARRY),
+        (0x1000, 0xFF, 0x0FFF, FRegister::EMPTY),
+        (0x0001, 0xFF, 0x0000, FRegister::CARRY | FRegister::HALFCARRY),
+        (0x00F0, 0xF0, 0x00E0, FRegister::CARRY),
+        (0xFFF8, 0x80, 0xFF78, FRegister::CARRY),
+        (0x0005, 0x80, 0xFF85, FRegister::EMPTY),
+    ];
+
+    let code = cases
+        .iter()
+        .flat_map(|&(sp, offset, _, _)| {
+            let (sp_lo, sp_hi) = ((sp & 0xFF) as u8, (sp >> 8) as u8);
+            vec![
+                0x31, sp_lo, sp_hi, // LD SP, <sp>
+                0xE8, offset,       // ADD SP, <offset>
+                0x02,               // LD (BC), A
+                0x31, sp_lo, sp_hi, // LD SP, <sp>
+                0xF8, offset,       // LD HL, SP+<offset>
+                0x02,               // LD (BC), A
+            ]
+        })
+        .collect();
+
+    // Z and N are always cleared
+    let mut cpu = Cpu::default();
+    cpu.registers.set_bc(RESULT_ADDR);
+    cpu.registers.set_f(FRegister::ZERO | FRegister::NEGATIVE);
+    let results: Vec<Cpu> = InstructionTest::new(cpu, code, 0)
+        .run(None)
+        .filter_map(Result::ok)
+        .map(|(cpu, _)| cpu)
+        .collect();
+
+    assert_eq!(results.len(), cases.len() * 2);
+    for (&(sp, offset, result, flags), run) in cases.iter().zip(results.chunks(2)) {
+        let (add, load) = (&run[0].registers, &run[1].registers);
+        assert_eq!((add.sp, add.f), (result, flags), "ADD SP, {:02X} with SP {:04X}", offset, sp);
+        assert_eq!(
+            (load.get_hl(), load.sp, load.f),
+            (result, sp, flags),
+            "LD HL, SP+{:02X} with SP {:04X}",
+            offset,
+            sp
+        );
+    }
+}
+
+#[test]
+#[rustfmt::skip]
+fn push_pop() {
+    let code = vec![
+        0x31, 0xFE, 0xFF, // LD SP, $FFFE
+        0x01, 0x34, 0x12, // LD BC, $1234
+        0x11, 0x78, 0x56, // LD DE, $5678
+        0x21, 0xBC, 0x9A, // LD HL, $9ABC
+        0x3E, 0xDE,       // LD A, $DE
+        0x37,             // SCF
+        0xC5,             // PUSH BC
+        0xD5,             // PUSH DE
+        0xE5,             // PUSH HL
+        0xF5,             // PUSH AF
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        // Each pair is stored high byte first, below the one pushed before it
+        0xFA, 0xFD, 0xFF, // LD A, ($FFFD)
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0xFA, 0xFC, 0xFF, // LD A, ($FFFC)
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0xFA, 0xF6, 0xFF, // LD A, ($FFF6)
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0x01, 0x00, 0x00, // LD BC, $0000
+        0x11, 0x00, 0x00, // LD DE, $0000
+        0x21, 0x00, 0x00, // LD HL, $0000
+        0xAF,             // XOR A
+        0xF1,             // POP AF
+        0xE1,             // POP HL
+        0xD1,             // POP DE
+        0xC1,             // POP BC
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0xC3, 0x00, 0x80, // JP $8000, out of the code, which ends it
+    ];
+
+    let results: Vec<_> = InstructionTest::new(Cpu::default(), code, 0)
+        .run(None)
+        .filter_map(Result::ok)
+        .collect();
+    assert_eq!(results.len(), 5);
+
+    let (pushed, a) = &results[0];
+    assert_eq!((pushed.registers.sp, *a), (0xFFF6, 0xDE));
+    let stack: Vec<u8> = results[1..4].iter().map(|(_, d)| *d).collect();
+    assert_eq!(stack, [0x12, 0x34, u8::from(FRegister::CARRY)]);
+
+    let (popped, a) = &results[4];
+    let registers = &popped.registers;
+    assert_eq!(*a, 0xDE);
+    assert_eq!(registers.f, FRegister::CARRY);
+    assert_eq!(
+        (registers.get_bc(), registers.get_de(), registers.get_hl()),
+        (0x1234, 0x5678, 0x9ABC)
+    );
+    assert_eq!(registers.sp, 0xFFFE);
+}
+
+#[test]
+#[rustfmt::skip]
+fn ldh() {
+    let code = vec![
+        0xF0, 0x44,       // LDH A, (LY)
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0x0E, 0x44,       // LD C, $44
+        0xAF,             // XOR A
+        0xF2,             // LD A, (C)
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0x3E, 0x12,       // LD A, $12
+        0xE0, 0x80,       // LDH ($80), A
+        0xE0, 0x44,       // LDH (LY), A, which doesn't change the stub
+        0x0E, 0x80,       // LD C, $80
+        0xAF,             // XOR A
+        0xF2,             // LD A, (C)
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0xF0, 0x44,       // LDH A, (LY)
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+    ];
+
+    let mut tester = InstructionTest::new(Cpu::default(), code, 0);
+    tester.bus.stub_io(0xFF44..=0xFF44, |_| 0x90);
+    let outputs: Vec<u8> = tester
+        .run(Some(1000))
+        .filter_map(|o| o.ok().map(|(_, v)| v))
+        .collect();
+    assert_eq!(outputs, [0x90, 0x90, 0x12, 0x90]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn interrupt_vector() {
+    let code = vec![
+        0x31, 0xFE, 0xFF, // LD SP, $FFFE
+        0x3E, 0x04,       // LD A, $04
+        0xE0, 0xFF,       // LDH (IE), A
+        0xFB,             // EI
+        0xE0, 0x0F,       // LDH (IF), A, requesting the timer interrupt
+        0x3E, 0x01,       // LD A, $01
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+    ];
+    let handler = [
+        0xF0, 0x0F,       // LDH A, (IF)
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0xFA, 0xFC, 0xFF, // LD A, ($FFFC)
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0xD9,             // RETI
+    ];
+
+    let results: Vec<_> = InstructionTest::new(Cpu::default(), code, 0)
+        .with_code(0x50, &handler)
+        .run(Some(1000))
+        .collect();
+    let outputs: Vec<(u8, bool, u16)> = results
+        .iter()
+        .filter_map(|o| o.as_ref().ok())
+        .map(|(cpu, d)| (*d, cpu.ime, cpu.registers.sp))
+        .collect();
+    assert_eq!(
+        outputs,
+        [
+            // The request is cleared, and the address of the instruction after the request is pushed
+            (0x00, false, 0xFFFC),
+            (0x0A, false, 0xFFFC),
+            // RETI returns there with interrupts enabled
+            (0x01, true, 0xFFFE),
+        ]
+    );
+    assert!(matches!(results.last(), Some(Err(InstructionTestError::LeftCode(0x000F)))));
+}
+
+#[test]
+fn inc() {
+    let code = vec![
+        0x21, 0x55, 0xAA, // LD HL, $AA55
+        0x3E, 0x1E, // LD A, $1E
+        0x3C, // INC A
+        0x77, // LD (HL), A
+        0x3C, // INC A
+        0x77, // LD (HL), A
+        0x3E, 0xFF, // LD A, $FF
+        0x3C, // INC A
+        0x77, // LD (HL), A
+    ];
+
+    let tester = InstructionTest::new(Cpu::default(), code, 0);
+
+    assert_eq!(
+        tester
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|(cpu, d)| (cpu.registers.get_f(), d))
+            .collect::<Vec<_>>(),
+        vec![
+            (FRegister::EMPTY, 0x1F),
+            (FRegister::HALFCARRY, 0x20),
+            (FRegister::ZERO | FRegister::HALFCARRY, 0),
+        ]
+    );
+}
+
+#[test]
+fn dec() {
+    let code = vec![
+        0x21, 0x55, 0xAA, // LD HL, $AA55
+        0x3E, 0x21, // LD A, $21
+        0x3D, // DEC A
+        0x77, // LD (HL), A
+        0x3D, // DEC A
+        0x77, // LD (HL), A
+        0x3E, 0x01, // LD A, $01
+        0x3D, // DEC A
+        0x77, // LD (HL), A
+        0x3D, // DEC A
+        0x77, // LD (HL), A
+    ];
+
+    let tester = InstructionTest::new(Cpu::default(), code, 0);
+
+    assert_eq!(
+        tester
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|(cpu, d)| (cpu.registers.get_f(), d))
+            .collect::<Vec<_>>(),
+        vec![
+            (FRegister::NEGATIVE, 0x20),
+            (FRegister::NEGATIVE | FRegister::HALFCARRY, 0x1F),
+            (FRegister::NEGATIVE | FRegister::ZERO, 0),
+            (FRegister::NEGATIVE | FRegister::HALFCARRY, 0xFF),
+        ]
+    );
+}
+
+#[test]
+fn inc_dec_16_bit() {
+    let inc_checks = vec![(0x0123, 0x0124), (0x01FF, 0x0200), (0xFFFF, 0x0000)];
+
+    let dec_checks = vec![(0x0123, 0x0122), (0x0200, 0x01FF), (0x0000, 0xFFFF)];
+
+    let mut code = vec![
+        0x21, 0x55, 0xAA, // LD HL, $AA55
+    ];
+
+    for (i, _) in inc_checks.iter() {
+        let lo = (i & 0xFF) as u8;
+        let hi = (i >> 8) as u8;
+        code.extend_from_slice(&[
+            0x01, lo, hi,   // LD BC, <i>
+            0x03, // INC BC
+            0x71, // LD (HL), C
+            0x70, // LD (HL), B
+        ]);
+    }
+
+    for (i, _) in dec_checks.iter() {
+        let lo = (i & 0xFF) as u8;
+        let hi = (i >> 8) as u8;
+        code.extend_from_slice(&[
+            0x01, lo, hi,   // LD BC, <i>
+            0x0B, // DEC BC
+            0x71, // LD (HL), C
+            0x70, // LD (HL), B
+        ]);
+    }
+
+    let compare_against = [inc_checks, dec_checks]
+        .iter()
+        .flatten()
+        .flat_map(|(_, o)| {
+            let lo = (o & 0xff) as u8;
+            let hi = (o >> 8) as u8;
+            vec![(FRegister::EMPTY, lo), (FRegister::EMPTY, hi)]
+        })
+        .collect::<Vec<(FRegister, u8)>>();
+
+    let tester = InstructionTest::new(Cpu::default(), code, 0);
+
+    assert_eq!(
+        tester
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|(cpu, d)| (cpu.registers.get_f(), d))
+            .collect::<Vec<_>>(),
+        compare_against
+    )
+}
+
+#[test]
+#[rustfmt::skip]
+fn jp() {
+    let code = vec![
+        0x21, 0x55, 0xAA, // LD HL, $AA55
+        0x3E, 0x00, // LD A, $00
+        0xC3, 0x0A, 0x00, // JP .jp1
+        0x3E, 0xFF, // LD A, $FF
+        // .jp1
+        0x77, // LD (HL), A
+    ];
+
+    let tester = InstructionTest::new(Cpu::default(), code, 0);
+
+    assert_eq!(
+        tester
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|(cpu, d)| (cpu.registers.get_f(), d))
+            .collect::<Vec<_>>(),
+        vec![(FRegister::EMPTY, 0)]
+    )
+}
+
+#[test]
+#[rustfmt::skip]
+fn jp_conditional() {
+    // jp_conditional_test.S
+    let code = vec![
+        0x21, 0x55, 0xAA, 0xAF, 0x06, 0x00, 0xCA, 0x0B, 0x00, 0x06, 0xFF, 0x70, 0xF6, 0xFF, 0x06, 
+        0xFF, 0xCA, 0x15, 0x00, 0x06, 0x00, 0x70, 0xAF, 0x06, 0xFF, 0xC2, 0x1E, 0x00, 0x06, 0x00, 
+        0x70, 0xF6, 0xFF, 0x06, 0x00, 0xC2, 0x28, 0x00, 0x06, 0xFF, 0x70, 0x37, 0x06, 0x00, 0xDA, 
+        0x31, 0x00, 0x06, 0xFF, 0x70, 0x37, 0x3F, 0x06, 0xFF, 0xDA, 0x3B, 0x00, 0x06, 0x00, 0x70, 
+        0x37, 0x06, 0xFF, 0xD2, 0x44, 0x00, 0x06, 0x00, 0x70, 0x37, 0x3F, 0x06, 0x00, 0xD2, 0x4E, 
+        0x00, 0x06, 0xFF, 0x70,
+    ];
+
+    let tester = InstructionTest::new(Cpu::default(), code, 0);
+
+    assert!(
+        tester
+            .run(Some(1000))
+            .all(|d| {
+                match d {
+                    Ok((_, d)) => d==0,
+                    Err(InstructionTestError::MaxCyclesReached) => panic!(),
+                    _ => true
+                }
+            })
+    )
+}
+
+#[test]
+fn call_ret() {
+    // call_ret_test.S
+    let code = vec![
+        0x21, 0x00, 0x01, 0xF9, 0x21, 0x55, 0xAA, 0xC3, 0x10, 0x00, 0x06, 0x00, 0xC9, 0x06, 0xFF,
+        0xC9, 0x06, 0xFF, 0xCD, 0x0A, 0x00, 0x70, 0xAF, 0x06, 0xFF, 0xCC, 0x0A, 0x00, 0x70, 0x06,
+        0x00, 0xC4, 0x0D, 0x00, 0x70, 0x3C, 0x06, 0x00, 0xCC, 0x0D, 0x00, 0x70, 0x06, 0xFF, 0xC4,
+        0x0A, 0x00, 0x70, 0x37, 0x06, 0xFF, 0xDC, 0x0A, 0x00, 0x70, 0x06, 0x00, 0xD4, 0x0D, 0x00,
+        0x70, 0x3F, 0x06, 0x00, 0xCC, 0x0D, 0x00, 0x70, 0x06, 0xFF, 0xC4, 0x0A, 0x00, 0x70, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    let tester = InstructionTest::new(Cpu::default(), code, 0);
+
+    let (outputs, end) = tester.run(None).partition::<Vec<_>, _>(|o| o.is_ok());
+
+    let outputs: Vec<u8> = outputs.iter().map(|o| o.as_ref().unwrap().1).collect();
+    let end = end[0].as_ref().unwrap_err();
+
+    assert_eq!(
+        outputs,
+        vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        "end: {:?}",
+        end
+    );
+}
+
+#[test]
+#[rustfmt::skip]
+fn jr() {
+    // jr_test.S
+    let code = vec![
+        0x21, 0x55, 0xAA, 0xAF, 0x06, 0x00, 0x28, 0x02, 0x06, 0xFF, 0x70, 0xF6, 0xFF, 0x06, 0xFF, 0x28, 
+        0x02, 0x06, 0x00, 0x70, 0xAF, 0x06, 0xFF, 0x20, 0x02, 0x06, 0x00, 0x70, 0xF6, 0xFF, 0x06, 0x00, 
+        0x20, 0x02, 0x06, 0xFF, 0x70, 0x37, 0x06, 0x00, 0x38, 0x02, 0x06, 0xFF, 0x70, 0x37, 0x3F, 0x06, 
+        0xFF, 0x38, 0x02, 0x06, 0x00, 0x70, 0x37, 0x06, 0xFF, 0x30, 0x02, 0x06, 0x00, 0x70, 0x37, 0x3F, 
+        0x06, 0x00, 0x30, 0x02, 0x06, 0xFF, 0x70, 0xC3, 0xAF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 
+        0x4E, 0x06, 0x20, 0x70, 0xC3, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 
+        0x10, 0x70, 0x18, 0xAD,
+    ];
+
+    let tester = InstructionTest::new(Cpu::default(), code, 0);
+
+    let outputs = tester
+        .run(None)
+        .filter_map(|o| o.ok().map(|(_,v)| v))
+        .collect::<Vec<u8>>();
+
+    assert_eq!(
+        outputs, 
+        vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x20]
+    );
+}
+
+/// Builds code that writes A to $AA55 after each of `instructions`, so `InstructionTest::assert_cycles` can time them.
+/// Each write takes `RESULT_WRITE_CYCLES` of its own, and there's one before the first instruction.
+fn time_each(instructions: &[&[u8]]) -> Vec<u8> {
+    const WRITE: [u8; 3] = [0xEA, RESULT_ADDR_LO, RESULT_ADDR_HI]; // LD ($AA55), A
+    let mut code = WRITE.to_vec();
+    for instruction in instructions {
+        code.extend_from_slice(instruction);
+        code.extend_from_slice(&WRITE);
+    }
+    code.extend_from_slice(&[0xC3, 0x00, 0x80]); // JP $8000, out of the code
+    code
+}
+
+/// The M-cycles taken by `LD ($AA55), A`
+const RESULT_WRITE_CYCLES: u64 = 4;
+
+#[test]
+#[rustfmt::skip]
+fn instruction_cycles() {
+    // HL points at $C000 in work RAM, and SP at the top of HRAM
+    let instructions: [(&[u8], u64); 24] = [
+        (&[0x00], 1),             // NOP
+        (&[0x41], 1),             // LD B, C
+        (&[0x06, 0x12], 2),       // LD B, $12
+        (&[0x7E], 2),             // LD A, (HL)
+        (&[0x77], 2),             // LD (HL), A
+        (&[0x36, 0x12], 3),       // LD (HL), $12
+        (&[0x34], 3),             // INC (HL)
+        (&[0x86], 2),             // ADD A, (HL)
+        (&[0xC6, 0x01], 2),       // ADD A, $01
+        (&[0x03], 2),             // INC BC
+        (&[0x09], 2),             // ADD HL, BC
+        (&[0x01, 0x00, 0xC0], 3), // LD BC, $C000
+        (&[0x0A], 2),             // LD A, (BC)
+        (&[0xFA, 0x00, 0xC0], 4), // LD A, ($C000)
+        (&[0x08, 0x00, 0xC0], 5), // LD ($C000), SP
+        (&[0xC5], 4),             // PUSH BC
+        (&[0xC1], 3),             // POP BC
+        (&[0xE8, 0x00], 4),       // ADD SP, $00
+        (&[0xF8, 0x00], 3),       // LD HL, SP+$00
+        (&[0xF9], 2),             // LD SP, HL
+        (&[0xCB, 0x00], 2),       // RLC B
+        (&[0xCB, 0x06], 4),       // RLC (HL)
+        (&[0xCB, 0x46], 3),       // BIT 0, (HL)
+        (&[0xF3], 1),             // DI
+    ];
+    let code = time_each(&instructions.iter().map(|(bytes, _)| *bytes).collect::<Vec<_>>());
+
+    let mut cpu = Cpu::default();
+    cpu.registers.set_hl(0xC000);
+    cpu.registers.set_sp(0xFFFE);
+    let expected: Vec<u64> = std::iter::once(0)
+        .chain(instructions.iter().map(|(_, cycles)| *cycles))
+        .map(|cycles| cycles + RESULT_WRITE_CYCLES)
+        .collect();
+    InstructionTest::new(cpu, code, 0).assert_cycles(&expected);
+}
+
+#[test]
+#[rustfmt::skip]
+fn branch_timing() {
+    // Every branch that's taken goes to the next instruction, so they all run in order
+    let mut code = vec![
+        0xAF,             // $00 XOR A
+        0x18, 0x00,       // $01 JR +0
+        0x28, 0x00,       // $03 JR Z, +0
+        0x20, 0x00,       // $05 JR NZ, +0
+        0xC3, 0x0A, 0x00, // $07 JP $000A
+        0xCA, 0x0D, 0x00, // $0A JP Z, $000D
+        0xC2, 0x10, 0x00, // $0D JP NZ, $0010
+        0x21, 0x14, 0x00, // $10 LD HL, $0014
+        0xE9,             // $13 JP HL
+        0x31, 0x00, 0x01, // $14 LD SP, $0100
+        0xCD, 0x40, 0x00, // $17 CALL $0040
+        0xCC, 0x40, 0x00, // $1A CALL Z, $0040
+        0xC4, 0x40, 0x00, // $1D CALL NZ, $0040
+        0xCD, 0x48, 0x00, // $20 CALL $0048
+        0xFF,             // $23 RST $38
+        0x18, 0xFE,       // $24 JR -2
+    ];
+    code.resize(0x100, 0);
+    code[0x38] = 0xD9; // RETI
+    code[0x40] = 0xC9; // RET
+    code[0x48] = 0xC0; // RET NZ
+    code[0x49] = 0xC8; // RET Z
+
+    let expected = [
+        (0x00, 1),
+        (0x01, 3),
+        (0x03, 3), // taken
+        (0x05, 2), // not taken
+        (0x07, 4),
+        (0x0A, 4), // taken
+        (0x0D, 3), // not taken
+        (0x10, 3),
+        (0x13, 1),
+        (0x14, 3),
+        (0x17, 6),
+        (0x40, 4),
+        (0x1A, 6), // taken
+        (0x40, 4),
+        (0x1D, 3), // not taken
+        (0x20, 6),
+        (0x48, 2), // not taken
+        (0x49, 5), // taken
+        (0x23, 4),
+        (0x38, 4),
+        // Offsets count from the end of the JR, so -2 jumps back to itself
+        (0x24, 3),
+        (0x24, 3),
+    ];
+    let timings = InstructionTest::new(Cpu::default(), code, 0).timings(expected.len());
+    assert_eq!(timings, expected);
+}
+
+#[test]
+#[rustfmt::skip]
+fn runner_yield_info() {
+    let mut memory = [0; 0x10000];
+    memory[..5].copy_from_slice(&[
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0xCB, 0x37,       // SWAP A
+    ]);
+
+    let mut runner = Cpu::default().runner();
+    let mut input = CpuInputPins::default();
+    let clock = |runner: &mut CpuRunner, input: &mut CpuInputPins| {
+        let out = runner.clock(*input);
+        input.data = match out.pins {
+            CpuOutputPins::Read { addr } => memory[addr as usize],
+            _ => 0xFF,
+        };
+        out
+    };
+
+    let cycles: Vec<_> = (0..8)
+        .map(|_| {
+            let out = clock(&mut runner, &mut input);
+            (out.opcode, out.operands().to_vec(), out.is_fetch_cycle)
+        })
+        .collect();
+    assert_eq!(
+        cycles,
+        [
+            (None, vec![], true),
+            (Some(0xEA), vec![], false),
+            (Some(0xEA), vec![0x00], false),
+            (Some(0xEA), vec![0x00, 0xC0], false),
+            // Fetch cycles show the whole of the instruction before
+            (None, vec![0x00, 0xC0], true),
+            // The second byte of a CB instruction is read like an operand
+            (Some(0xCB), vec![], false),
+            (None, vec![0x37], true),
+            // NOPs are only a fetch
+            (None, vec![], true),
+        ]
+    );
+
+    // The interrupt is taken after the NOP, instead of fetching the next instruction
+    runner.cpu.ime = true;
+    input.interrupt_40h = true;
+    for _ in 0..5 {
+        let out = clock(&mut runner, &mut input);
+        input.interrupt_40h = false;
+        assert!(out.interrupt_dispatch);
+        assert_eq!(out.opcode, None);
+    }
+    let out = clock(&mut runner, &mut input);
+    assert!(!out.interrupt_dispatch);
+    assert!(out.is_fetch_cycle);
+}
+
+/// Sets SP to the top of HRAM, enables the interrupts in `enable` and then interrupts as a whole
+#[rustfmt::skip]
+fn enable_interrupts(enable: u8) -> Vec<u8> {
+    vec![
+        0x31, 0xFE, 0xFF, // LD SP, $FFFE
+        0x3E, enable,     // LD A, enable
+        0xE0, 0xFF,       // LDH (IE), A
+        0xFB,             // EI
+    ]
+}
+
+#[test]
+fn interrupt_dispatch_timing() {
+    let mut code = enable_interrupts(0x04);
+    code.resize(0x20, 0x00); // NOPs
+    let timings = InstructionTest::new(Cpu::default(), code, 0)
+        .with_code(0x50, &[0x00, 0x00])
+        .request_interrupt(12, 0x04)
+        .timings(9);
+    assert_eq!(
+        timings,
+        [
+            (0x0000, 3),
+            (0x0003, 2),
+            (0x0005, 3),
+            (0x0007, 1),
+            (0x0008, 1),
+            (0x0009, 1),
+            // Fetched on the cycle the interrupt was requested, and followed by the 5 cycles of the dispatch
+            (0x000A, 6),
+            (0x0050, 1),
+            (0x0051, 1),
+        ]
+    );
+}
+
+#[test]
+#[rustfmt::skip]
+fn ei_delay() {
+    let code = vec![
+        0x31, 0xFE, 0xFF, // LD SP, $FFFE
+        0x3E, 0x04,       // LD A, $04
+        0xE0, 0xFF,       // LDH (IE), A
+        0xE0, 0x0F,       // LDH (IF), A
+        0xFB,             // EI
+        0x00,             // NOP
+        0x00,             // NOP
+    ];
+    let timings = InstructionTest::new(Cpu::default(), code.clone(), 0)
+        .with_code(0x50, &[0x00])
+        .timings(7);
+    // The interrupt is already requested, but is only taken after the instruction following EI
+    assert_eq!(
+        timings,
+        [(0x0000, 3), (0x0003, 2), (0x0005, 3), (0x0007, 3), (0x0009, 1), (0x000A, 6), (0x0050, 1)]
+    );
+
+    // So DI right after EI keeps it from being taken at all
+    let mut code = code;
+    code[0x0A] = 0xF3; // DI
+    let timings = InstructionTest::new(Cpu::default(), code, 0).timings(7);
+    assert_eq!(
+        timings,
+        [(0x0000, 3), (0x0003, 2), (0x0005, 3), (0x0007, 3), (0x0009, 1), (0x000A, 1), (0x000B, 1)]
+    );
+}
+
+#[test]
+#[rustfmt::skip]
+fn halt_wakeup() {
+    // With interrupts disabled, the CPU wakes up and carries on after HALT
+    let code = vec![
+        0x3E, 0x04,       // LD A, $04
+        0xE0, 0xFF,       // LDH (IE), A
+        0x76,             // HALT
+        0x3E, 0x01,       // LD A, $01
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+    ];
+    let results: Vec<_> = InstructionTest::new(Cpu::default(), code, 0)
+        .request_interrupt(40, 0x04)
+        .run_timed(Some(1000))
+        .collect();
+    let (cpu, d, cycles) = results[0].as_ref().unwrap();
+    assert_eq!((*d, cpu.ime, *cycles), (0x01, false, 40 + 2 + 4));
+    assert!(matches!(results[1], Err(InstructionTestError::LeftCode(0x000A))));
+
+    // With them enabled, it services the interrupt first
+    let mut code = enable_interrupts(0x04);
+    code.push(0x76); // HALT
+    let handler = [
+        0x3E, 0x50,       // LD A, $50
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+    ];
+    let results: Vec<_> = InstructionTest::new(Cpu::default(), code, 0)
+        .with_code(0x50, &handler)
+        .request_interrupt(40, 0x04)
+        .run_timed(Some(1000))
+        .collect();
+    let (cpu, d, cycles) = results[0].as_ref().unwrap();
+    assert_eq!((*d, cpu.registers.sp, *cycles), (0x50, 0xFFFC, 40 + 5 + 2 + 4));
+}
+
+#[test]
+#[rustfmt::skip]
+fn nested_interrupts() {
+    let mut code = enable_interrupts(0x05);
+    code.resize(0x30, 0x00); // NOPs
+    code.extend_from_slice(&[0xC3, 0x00, 0x80]); // JP $8000, out of the code
+    let vblank = [
+        0x3E, 0x40,       // LD A, $40
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0xD9,             // RETI
+    ];
+    let timer = [
+        0xFB,             // EI
+        0x00,             // NOP
+        0x00,             // NOP
+        0x00,             // NOP
+        0x00,             // NOP
+        0x3E, 0x50,       // LD A, $50
+        0xEA, 0x55, 0xAA, // LD ($AA55), A
+        0xD9,             // RETI
+    ];
+
+    // The timer handler re-enables interrupts, so VBlank interrupts it
+    let outputs: Vec<(u8, u16, bool)> = InstructionTest::new(Cpu::default(), code.clone(), 0)
+        .with_code(0x40, &vblank)
+        .with_code(0x50, &timer)
+        .request_interrupt(12, 0x04)
+        .request_interrupt(20, 0x01)
+        .run(Some(1000))
+        .filter_map(Result::ok)
+        .map(|(cpu, d)| (d, cpu.registers.sp, cpu.ime))
+        .collect();
+    assert_eq!(outputs, [(0x40, 0xFFFA, false), (0x50, 0xFFFC, true)]);
+
+    // Without the EI, VBlank waits until the timer handler returns
+    let mut timer = timer;
+    timer[0] = 0x00; // NOP
+    let outputs: Vec<(u8, u16, bool)> = InstructionTest::new(Cpu::default(), code, 0)
+        .with_code(0x40, &vblank)
+        .with_code(0x50, &timer)
+        .request_interrupt(12, 0x04)
+        .request_interrupt(20, 0x01)
+        .run(Some(1000))
+        .filter_map(Result::ok)
+        .map(|(cpu, d)| (d, cpu.registers.sp, cpu.ime))
+        .collect();
+    assert_eq!(outputs, [(0x50, 0xFFFC, false), (0x40, 0xFFFC, false)]);
+}
+
+/// What the CB-prefixed `opcode` does to `v` with flags `f`, as the new value and flags
+#[rustfmt::skip]
+fn cb_reference(opcode: u8, v: u8, f: u8) -> (u8, u8) {
+    let bit = 1 << ((opcode >> 3) & 7);
+    let carry = f & 0x10 != 0;
+    let (result, carry_out) = match opcode >> 3 {
+        0x00 => (v.rotate_left(1), v & 0x80 != 0),             // RLC
+        0x01 => (v.rotate_right(1), v & 0x01 != 0),            // RRC
+        0x02 => (v << 1 | carry as u8, v & 0x80 != 0),         // RL
+        0x03 => (v >> 1 | (carry as u8) << 7, v & 0x01 != 0),  // RR
+        0x04 => (v << 1, v & 0x80 != 0),                       // SLA
+        0x05 => (v >> 1 | v & 0x80, v & 0x01 != 0),            // SRA
+        0x06 => (v.rotate_left(4), false),                     // SWAP
+        0x07 => (v >> 1, v & 0x01 != 0),                       // SRL
+        // BIT sets Z if the bit is clear, sets H, and leaves C alone
+        0x08..=0x0F => return (v, (if v & bit == 0 { 0xA0 } else { 0x20 }) | f & 0x10),
+        0x10..=0x17 => return (v & !bit, f),                   // RES
+        _ => return (v | bit, f),                              // SET
+    };
+    let z = if result == 0 { 0x80 } else { 0x00 };
+    let c = if carry_out { 0x10 } else { 0x00 };
+    (result, z | c)
+}
+
+#[test]
+fn cb_opcodes() {
+    const VALUES: [u8; 9] = [0x00, 0x01, 0x0F, 0x10, 0x7F, 0x80, 0x81, 0xA5, 0xFF];
+    // Every other flag starts set, so those an instruction should clear are checked too
+    const FLAGS: [u8; 2] = [0xE0, 0xF0];
+
+    for opcode in 0..=0xFF {
+        // B, C, D, E, H, L, (HL) or A
+        let operand = opcode & 7;
+        let mut code = Vec::new();
+        for &v in &VALUES {
+            for &f in &FLAGS {
+                #[rustfmt::skip]
+                code.extend_from_slice(&[
+                    0x21, 0x00, 0xC0,           // LD HL, $C000
+                    0x36, v,                    // LD (HL), v
+                    0x11, f, 0x00,              // LD DE, f
+                    0xD5,                       // PUSH DE
+                    0xF1,                       // POP AF, so F = f
+                    0x06 | operand << 3, v,     // LD r, v
+                    0xCB, opcode,
+                ]);
+                if operand != 7 {
+                    code.push(0x7E); // LD A, (HL)
+                }
+                // LD ($AA55), A
+                code.extend_from_slice(&[0xEA, RESULT_ADDR_LO, RESULT_ADDR_HI]);
+            }
+        }
+
+        let mut cpu = Cpu::default();
+        cpu.registers.set_sp(0xFFFE);
+        let results: Vec<_> = InstructionTest::new(cpu, code, 0)
+            .run(Some(10_000))
+            .filter_map(Result::ok)
+            .collect();
+        assert_eq!(
+            results.len(),
+            VALUES.len() * FLAGS.len(),
+            "CB {:02X}",
+            opcode
+        );
+
+        let cases = VALUES
+            .iter()
+            .flat_map(|&v| FLAGS.iter().map(move |&f| (v, f)));
+        for ((v, f), (cpu, written)) in cases.zip(results) {
+            let registers = &cpu.registers;
+            let result = match operand {
+                0 => registers.get_b(),
+                1 => registers.get_c(),
+                2 => registers.get_d(),
+                3 => registers.get_e(),
+                4 => registers.get_h(),
+                5 => registers.get_l(),
+                // Written out from (HL) or A
+                _ => written,
+            };
+            let (expected, expected_f) = cb_reference(opcode, v, f);
+            assert_eq!(
+                (result, u8::from(registers.get_f())),
+                (expected, expected_f),
+                "CB {:02X} on {:02X} with flags {:02X}",
+                opcode,
+                v,
+                f
+            );
+        }
+    }
+}
+
+/// DAA as it's usually described: the correction is worked out from the flags and A before it's applied, and N decides
+/// whether it's added or subtracted
+fn daa_reference(a: u8, f: u8) -> (u8, u8) {
+    let (n, h, c) = (f & 0x40 != 0, f & 0x20 != 0, f & 0x10 != 0);
+    let mut correction = 0;
+    let mut carry = false;
+    if h || (!n && a & 0x0F > 0x09) {
+        correction |= 0x06;
+    }
+    if c || (!n && a > 0x99) {
+        correction |= 0x60;
+        carry = true;
+    }
+    let a = if n {
+        a.wrapping_sub(correction)
+    } else {
+        a.wrapping_add(correction)
+    };
+    let z = if a == 0 { 0x80 } else { 0x00 };
+    let c = if carry { 0x10 } else { 0x00 };
+    (a, z | (f & 0x40) | c)
+}
+
+#[test]
+fn daa_exhaustive() {
+    let cases: Vec<(u8, u8)> = (0..=0xFF)
+        .flat_map(|a| (0..=0xF0).step_by(0x10).map(move |f| (a, f as u8)))
+        .collect();
+    let mut code = Vec::new();
+    for &(a, f) in &cases {
+        #[rustfmt::skip]
+        code.extend_from_slice(&[
+            0x11, f, a,       // LD DE, af
+            0xD5,             // PUSH DE
+            0xF1,             // POP AF
+            0x27,             // DAA
+            0xEA, RESULT_ADDR_LO, RESULT_ADDR_HI, // LD ($AA55), A
+        ]);
+    }
+
+    let mut cpu = Cpu::default();
+    cpu.registers.set_sp(0xFFFE);
+    let results: Vec<_> = InstructionTest::new(cpu, code, 0)
+        .run(None)
+        .filter_map(Result::ok)
+        .collect();
+    assert_eq!(results.len(), cases.len());
+    for (&(a, f), (cpu, d)) in cases.iter().zip(results) {
+        assert_eq!(
+            (d, u8::from(cpu.registers.get_f())),
+            daa_reference(a, f),
+            "DAA with A={:02X} F={:02X}",
+            a,
+            f
+        );
+    }
+}
+
+#[test]
+fn daa_bcd_arithmetic() {
+    let bcd = |n: u8| ((n / 10) << 4) | (n % 10);
+    for x in 0..100 {
+        let mut code = Vec::new();
+        for y in 0..100 {
+            #[rustfmt::skip]
+            code.extend_from_slice(&[
+                0x3E, bcd(x),     // LD A, x
+                0xC6, bcd(y),     // ADD A, y
+                0x27,             // DAA
+                0xEA, RESULT_ADDR_LO, RESULT_ADDR_HI, // LD ($AA55), A
+                0x3E, bcd(x),     // LD A, x
+                0xD6, bcd(y),     // SUB A, y
+                0x27,             // DAA
+                0xEA, RESULT_ADDR_LO, RESULT_ADDR_HI, // LD ($AA55), A
+            ]);
+        }
+
+        let results: Vec<(u8, bool)> = InstructionTest::new(Cpu::default(), code, 0)
+            .run(None)
+            .filter_map(Result::ok)
+            .map(|(cpu, d)| (d, cpu.registers.get_f().contains(FRegister::CARRY)))
+            .collect();
+        let expected: Vec<(u8, bool)> = (0..100)
+            .flat_map(|y| {
+                [
+                    (bcd((x + y) % 100), x + y >= 100),
+                    (bcd((x + 100 - y) % 100), x < y),
+                ]
+            })
+            .collect();
+        assert_eq!(results, expected, "{:02} plus and minus 0 to 99", x);
+    }
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::FlatBus;
+use gb_cpu::{Registers, Sm83};
+
+fn cpu() -> Sm83 {
+    Sm83::new(Registers {
+        pc: 0x100,
+        sp: 0xFFFE,
+        ..Default::default()
+    })
+}
+
+#[test]
+#[rustfmt::skip]
+fn step() {
+    let mut bus = FlatBus::with_code(0x100, &[
+        0x3E, 0x42,       // LD A, $42
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x03,             // INC BC
+        0x00,             // NOP
+    ]);
+    let mut cpu = cpu();
+
+    // The first step only fetches LD A, $42
+    assert_eq!(cpu.step(&mut bus), 1);
+    assert_eq!(cpu.step(&mut bus), 2);
+    assert_eq!(cpu.registers().a, 0x42);
+    assert_eq!(cpu.step(&mut bus), 4);
+    assert_eq!(bus.memory[0xC000], 0x42);
+    assert_eq!(cpu.step(&mut bus), 2);
+    assert_eq!(cpu.registers().get_bc(), 1);
+    assert_eq!(bus.idle_cycles, 1);
+    assert_eq!(cpu.registers().pc, 0x107);
+}
+
+#[test]
+#[rustfmt::skip]
+fn interrupt() {
+    let mut bus = FlatBus::with_code(0x100, &[
+        0xFB,       // EI
+        0x00,       // NOP
+        0x18, 0xFE, // JR -2
+    ]);
+    bus.memory[0x50] = 0x3C; // INC A
+    bus.memory[0xFFFF] = 0x04;
+    let mut cpu = cpu();
+
+    for _ in 0..4 {
+        cpu.step(&mut bus);
+    }
+    assert!(cpu.ime());
+    assert_eq!(cpu.registers().a, 0);
+
+    // Request the timer interrupt
+    bus.memory[0xFF0F] = 0x04;
+    while cpu.registers().pc < 0x50 || cpu.registers().pc > 0x51 {
+        cpu.step(&mut bus);
+    }
+    assert!(!cpu.ime());
+    assert_eq!(bus.memory[0xFF0F], 0);
+    assert_eq!(cpu.registers().sp, 0xFFFC);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.registers().a, 1);
+}
+
+#[test]
+#[rustfmt::skip]
+fn illegal_opcode() {
+    let mut bus = FlatBus::with_code(0x100, &[
+        0xFB, // EI
+        0x3C, // INC A
+        0xD3, // illegal
+        0x3C, // INC A
+    ]);
+    bus.memory[0x50] = 0x04; // INC B
+    bus.memory[0xFFFF] = 0x04;
+    let mut cpu = cpu();
+
+    for _ in 0..3 {
+        cpu.step(&mut bus);
+    }
+    assert!(!cpu.is_locked_up());
+    cpu.step(&mut bus);
+    assert!(cpu.is_locked_up());
+
+    // Neither the next instruction nor an interrupt runs, and stepping doesn't wait for a fetch that never comes
+    bus.memory[0xFF0F] = 0x04;
+    for _ in 0..10 {
+        assert_eq!(cpu.step(&mut bus), 1);
+    }
+    assert_eq!(cpu.registers().a, 1);
+    assert_eq!(cpu.registers().b, 0);
+    assert_eq!(cpu.registers().pc, 0x103);
+    assert_eq!(bus.memory[0xFF0F], 0x04);
+}
@@ -0,0 +1,17 @@
+# English, which every other language falls back to. See gb_core/src/i18n.rs for the syntax.
+
+## Window title
+title = GameBoy
+title-game = GameBoy - { $game }
+title-speed = x{ $speed }
+title-paused = Paused
+
+## Launcher
+launcher-prompt = Enter the path of a ROM to play
+launcher-loading = Loading...
+launcher-path = ROM path
+launcher-load = Load
+
+## Errors
+error-read-rom = Couldn't read { $path }: { $error }
+error-load-rom = Couldn't load the ROM: { $error }
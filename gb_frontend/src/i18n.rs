@@ -0,0 +1,251 @@
+//! Translations of the frontends' menus, messages and on-screen text, written in a subset of
+//! [Fluent](https://projectfluent.org)'s syntax:
+//!
+//! ```text
+//! # Comments start with a hash
+//! launcher-prompt = Enter the path of a ROM to play
+//! error-read-rom = Couldn't read { $path }: { $error }
+//! long-message =
+//!     Indented lines continue
+//!     the message above them
+//! ```
+//!
+//! Only messages and `{ $variable }` placeables are supported, not terms, attributes or selectors.
+//!
+//! To add a language, copy `gb_frontend/locales/en.ftl` to a file named after the language's code (e.g. `sv.ftl`),
+//! translate its messages and add it to `BUILT_IN`. Messages a language doesn't translate fall back to English, and the
+//! `i18n` tests check that every built-in language parses and only has messages English has too.
+
+use std::{collections::BTreeMap, fmt, fmt::Write};
+
+/// The built-in languages and their messages. English comes first, since it's what the others fall back to.
+const BUILT_IN: &[(&str, &str)] = &[("en", include_str!("../locales/en.ftl"))];
+
+/// The codes of the built-in languages
+pub fn languages() -> impl Iterator<Item = &'static str> {
+    BUILT_IN.iter().map(|&(language, _)| language)
+}
+
+/// A line of a translation that couldn't be parsed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Counting from 1
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Variable(String),
+}
+
+/// The messages of one language
+#[derive(Clone, Debug)]
+pub struct Bundle {
+    language: String,
+    messages: BTreeMap<String, Vec<Piece>>,
+}
+
+impl Bundle {
+    pub fn parse(language: &str, source: &str) -> Result<Self, ParseError> {
+        let mut messages = BTreeMap::new();
+        // The message being read, which indented lines are added to, and the line it started on
+        let mut current: Option<(String, String, usize)> = None;
+        for (i, line) in source.lines().enumerate() {
+            let number = i + 1;
+            let error = |message| ParseError {
+                line: number,
+                message,
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with(char::is_whitespace) {
+                let (_, text, _) = current.as_mut().ok_or_else(|| {
+                    error("Indented lines continue a message, but there isn't one")
+                })?;
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(line.trim());
+                continue;
+            }
+
+            if let Some((id, text, line)) = current.take() {
+                add_message(&mut messages, id, &text, line)?;
+            }
+            let (id, text) = line
+                .split_once('=')
+                .ok_or_else(|| error("Expected a message like `id = text`"))?;
+            let id = id.trim();
+            if !id.starts_with(|c: char| c.is_ascii_alphabetic())
+                || !id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(error(
+                    "Message ids are letters, digits, `-` and `_`, starting with a letter",
+                ));
+            }
+            current = Some((id.to_owned(), text.trim().to_owned(), number));
+        }
+        if let Some((id, text, line)) = current {
+            add_message(&mut messages, id, &text, line)?;
+        }
+
+        Ok(Bundle {
+            language: language.to_owned(),
+            messages,
+        })
+    }
+
+    /// One of the built-in languages, if there is one for `language`
+    pub fn built_in(language: &str) -> Option<Self> {
+        let &(language, source) = BUILT_IN.iter().find(|&&(code, _)| code == language)?;
+        match Self::parse(language, source) {
+            Ok(bundle) => Some(bundle),
+            Err(e) => panic!("The built-in {} translation is broken: {}", language, e),
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The ids of every message in the bundle
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.messages.keys().map(String::as_str)
+    }
+}
+
+fn add_message(
+    messages: &mut BTreeMap<String, Vec<Piece>>,
+    id: String,
+    text: &str,
+    line: usize,
+) -> Result<(), ParseError> {
+    let pieces = parse_pattern(text).map_err(|message| ParseError { line, message })?;
+    if messages.insert(id, pieces).is_some() {
+        return Err(ParseError {
+            line,
+            message: "The message was already defined",
+        });
+    }
+    Ok(())
+}
+
+fn parse_pattern(text: &str) -> Result<Vec<Piece>, &'static str> {
+    let mut pieces = Vec::new();
+    let push_text = |pieces: &mut Vec<Piece>, text: &str| {
+        if text.contains('}') {
+            return Err("A `}` isn't opened");
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text.to_owned()));
+        }
+        Ok(())
+    };
+
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        push_text(&mut pieces, &rest[..start])?;
+        let end = start + rest[start..].find('}').ok_or("A `{` isn't closed")?;
+        let name = rest[start + 1..end]
+            .trim()
+            .strip_prefix('$')
+            .filter(|name| {
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            })
+            .ok_or("Only variables like { $name } can go in braces")?;
+        pieces.push(Piece::Variable(name.to_owned()));
+        rest = &rest[end + 1..];
+    }
+    push_text(&mut pieces, rest)?;
+
+    if pieces.is_empty() {
+        return Err("The message is empty");
+    }
+    Ok(pieces)
+}
+
+/// Looks messages up in the preferred language, falling back to English
+#[derive(Clone, Debug)]
+pub struct Localizer {
+    /// In order of preference, with English last
+    bundles: Vec<Bundle>,
+}
+
+impl Localizer {
+    /// Prefer the built-in translation for `language`, a code like `sv` or `sv-SE`, if there is one
+    pub fn new(language: &str) -> Self {
+        let mut localizer = Localizer {
+            bundles: vec![Bundle::built_in("en").unwrap()],
+        };
+        let language = language.split(['-', '_', '.']).next().unwrap();
+        if let Some(bundle) = Bundle::built_in(language).filter(|_| language != "en") {
+            localizer.add(bundle);
+        }
+        localizer
+    }
+
+    /// Prefer the language the system's locale environment variables ask for
+    pub fn from_env() -> Self {
+        let language = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        Self::new(&language)
+    }
+
+    /// Prefer `bundle` over the languages added before it, e.g. to try out a translation that isn't built in yet
+    pub fn add(&mut self, bundle: Bundle) {
+        self.bundles.insert(0, bundle);
+    }
+
+    /// The preferred language
+    pub fn language(&self) -> &str {
+        self.bundles[0].language()
+    }
+
+    /// A message without any variables
+    pub fn get(&self, id: &str) -> String {
+        self.format(id, &[])
+    }
+
+    /// A message with its variables filled in from `args`. Messages that no language has come out as their id,
+    /// which is easy to spot and search for.
+    pub fn format(&self, id: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+        let pieces = match self
+            .bundles
+            .iter()
+            .find_map(|bundle| bundle.messages.get(id))
+        {
+            Some(pieces) => pieces,
+            None => return id.to_owned(),
+        };
+
+        let mut message = String::new();
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => message.push_str(text),
+                Piece::Variable(name) => match args.iter().find(|(arg, _)| *arg == name.as_str()) {
+                    Some((_, value)) => write!(message, "{}", value).unwrap(),
+                    None => write!(message, "{{${}}}", name).unwrap(),
+                },
+            }
+        }
+        message
+    }
+}
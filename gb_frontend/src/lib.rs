@@ -0,0 +1,5 @@
+//! Code the frontends share that the emulator itself doesn't need: where their files go ([`paths`]) and the
+//! translations of their text ([`i18n`]). It's kept out of `gb_core` so the core can still build without `std`.
+
+pub mod i18n;
+pub mod paths;
@@ -0,0 +1,198 @@
+//! Where the frontends keep the files they write, so they all agree on one layout instead of each writing next to the
+//! executable.
+//!
+//! By default everything goes in the platform's usual places, found the same way as the `directories` crate:
+//!
+//! | Platform | Config                                 | Everything else                               |
+//! |----------|----------------------------------------|-----------------------------------------------|
+//! | Linux    | `$XDG_CONFIG_HOME/gb-emu`              | `$XDG_DATA_HOME/gb-emu/<kind>`                |
+//! | macOS    | `~/Library/Application Support/gb-emu` | `~/Library/Application Support/gb-emu/<kind>` |
+//! | Windows  | `%APPDATA%\gb-emu\config`              | `%APPDATA%\gb-emu\data\<kind>`                |
+//!
+//! Each kind of data can be moved somewhere else with an override, e.g. from a `--dir saves=PATH` option.
+//!
+//! In portable mode everything is kept in a [`PORTABLE_DIR`] folder next to the executable instead, so the emulator
+//! can run from a USB stick without leaving anything behind on the machine.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+/// The name of the folder the emulator's data is kept in
+pub const APP_DIR: &str = "gb-emu";
+
+/// The name of the folder next to the executable that portable mode keeps everything in
+pub const PORTABLE_DIR: &str = "gb-emu-data";
+
+/// The kinds of files the emulator keeps
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DataKind {
+    Config,
+    /// Cartridge RAM
+    Saves,
+    /// Save states
+    States,
+    Screenshots,
+    /// Input movies
+    Movies,
+}
+
+impl DataKind {
+    pub const ALL: [DataKind; 5] = [
+        DataKind::Config,
+        DataKind::Saves,
+        DataKind::States,
+        DataKind::Screenshots,
+        DataKind::Movies,
+    ];
+
+    /// The name of the kind's folder, which is also how overrides refer to it
+    pub fn name(self) -> &'static str {
+        match self {
+            DataKind::Config => "config",
+            DataKind::Saves => "saves",
+            DataKind::States => "states",
+            DataKind::Screenshots => "screenshots",
+            DataKind::Movies => "movies",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+}
+
+/// The folders each kind of data is kept in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Paths {
+    config: PathBuf,
+    data: PathBuf,
+    overrides: BTreeMap<DataKind, PathBuf>,
+}
+
+impl Paths {
+    /// The usual locations for the platform this is running on
+    pub fn platform() -> Result<Self, &'static str> {
+        Self::for_platform(env::consts::OS, |name| env::var_os(name).map(PathBuf::from))
+    }
+
+    /// The usual locations on `os` (one of the values of `std::env::consts::OS`), looking environment variables up
+    /// with `var`. Fails if the variables the locations are based on aren't set.
+    pub fn for_platform(
+        os: &str,
+        var: impl Fn(&str) -> Option<PathBuf>,
+    ) -> Result<Self, &'static str> {
+        // Relative paths in these variables are invalid, and must be ignored
+        let var = |name: &str| var(name).filter(|path| path.is_absolute());
+        let home = || var("HOME").ok_or("$HOME isn't set");
+
+        let (config, data) = match os {
+            "windows" => {
+                let app_data = var("APPDATA").ok_or("%APPDATA% isn't set")?.join(APP_DIR);
+                (app_data.join("config"), app_data.join("data"))
+            }
+            "macos" => {
+                let dir = home()?.join("Library/Application Support").join(APP_DIR);
+                (dir.clone(), dir)
+            }
+            _ => {
+                let config = match var("XDG_CONFIG_HOME") {
+                    Some(config) => config,
+                    None => home()?.join(".config"),
+                };
+                let data = match var("XDG_DATA_HOME") {
+                    Some(data) => data,
+                    None => home()?.join(".local/share"),
+                };
+                (config.join(APP_DIR), data.join(APP_DIR))
+            }
+        };
+        Ok(Paths {
+            config,
+            data,
+            overrides: BTreeMap::new(),
+        })
+    }
+
+    /// Keep everything in folders under `root`, one for each kind of data
+    pub fn in_dir(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Paths {
+            config: root.join(DataKind::Config.name()),
+            data: root,
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    /// Keep everything next to the running executable. See [`Paths::portable_for`].
+    pub fn portable() -> Result<Self, &'static str> {
+        let exe = env::current_exe().map_err(|_| "Couldn't find the executable")?;
+        Self::portable_for(&exe)
+    }
+
+    /// Keep everything in a [`PORTABLE_DIR`] folder next to `exe`, the path of the executable
+    pub fn portable_for(exe: &Path) -> Result<Self, &'static str> {
+        let dir = exe.parent().ok_or("The executable isn't in a folder")?;
+        Ok(Self::in_dir(dir.join(PORTABLE_DIR)))
+    }
+
+    /// Keep one kind of data in `dir` instead
+    pub fn set_override(&mut self, kind: DataKind, dir: impl Into<PathBuf>) {
+        self.overrides.insert(kind, dir.into());
+    }
+
+    /// Apply an override written as `KIND=DIR`, e.g. `saves=/mnt/usb/saves`
+    pub fn parse_override(&mut self, spec: &str) -> Result<(), &'static str> {
+        let (kind, dir) = spec
+            .split_once('=')
+            .ok_or("Overrides are written as KIND=DIR")?;
+        let kind = DataKind::from_name(kind.trim()).ok_or(
+            "The kind of data must be one of config, saves, states, screenshots or movies",
+        )?;
+        if dir.is_empty() {
+            return Err("The directory is missing");
+        }
+        self.set_override(kind, dir);
+        Ok(())
+    }
+
+    /// The folder `kind` is kept in. It may not exist yet; see [`Paths::create_dir`].
+    pub fn dir(&self, kind: DataKind) -> PathBuf {
+        match (self.overrides.get(&kind), kind) {
+            (Some(dir), _) => dir.clone(),
+            (None, DataKind::Config) => self.config.clone(),
+            (None, kind) => self.data.join(kind.name()),
+        }
+    }
+
+    /// Make sure the folder for `kind` exists, and return it
+    pub fn create_dir(&self, kind: DataKind) -> std::io::Result<PathBuf> {
+        let dir = self.dir(kind);
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// The file to keep `kind` of data for `game` in, e.g. `file(DataKind::Saves, "TETRIS", "sav")`. Characters
+    /// that some filesystems don't allow are replaced in the game's name.
+    pub fn file(&self, kind: DataKind, game: &str, extension: &str) -> PathBuf {
+        let mut name: String = game
+            .trim()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || " -_.()".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        // Names can't be empty or only dots, and Windows doesn't allow them to end with a dot
+        if name.trim_matches('.').is_empty() {
+            name = "untitled".to_owned();
+        }
+        let name = name.trim_end_matches('.');
+        self.dir(kind).join(format!("{}.{}", name, extension))
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeSet;
+
+use gb_frontend::i18n::{languages, Bundle, Localizer, ParseError};
+
+const SOURCE: &str = "
+# A comment
+greeting = Hello, { $name }!
+multiline =
+    First line
+    second line
+plain = No variables here
+";
+
+#[test]
+fn messages_and_variables() {
+    let mut localizer = Localizer::new("en");
+    localizer.add(Bundle::parse("test", SOURCE).unwrap());
+    assert_eq!(localizer.language(), "test");
+
+    assert_eq!(
+        localizer.format("greeting", &[("name", &"world")]),
+        "Hello, world!"
+    );
+    assert_eq!(localizer.get("multiline"), "First line\nsecond line");
+    assert_eq!(localizer.get("plain"), "No variables here");
+    // Variables that weren't given are left in, so the message still makes sense
+    assert_eq!(localizer.get("greeting"), "Hello, {$name}!");
+    assert_eq!(localizer.get("no-such-message"), "no-such-message");
+}
+
+#[test]
+fn falls_back_to_english() {
+    let mut localizer = Localizer::new("xx-YY");
+    assert_eq!(localizer.language(), "en");
+    assert_eq!(localizer.get("launcher-load"), "Load");
+
+    localizer.add(Bundle::parse("sv", "launcher-load = Ladda").unwrap());
+    assert_eq!(localizer.get("launcher-load"), "Ladda");
+    assert_eq!(localizer.get("launcher-path"), "ROM path");
+    assert_eq!(
+        localizer.format("error-load-rom", &[("error", &"Unsupported mapper")]),
+        "Couldn't load the ROM: Unsupported mapper"
+    );
+}
+
+#[test]
+fn parse_errors() {
+    let error = |source| Bundle::parse("test", source).unwrap_err();
+    assert_eq!(
+        error("ok = fine\nnot a message"),
+        ParseError {
+            line: 2,
+            message: "Expected a message like `id = text`"
+        }
+    );
+    assert_eq!(error("  indented = first").line, 1);
+    assert_eq!(error("a = { $name").line, 1);
+    assert_eq!(error("a = { name }").line, 1);
+    assert_eq!(error("a = b }").line, 1);
+    assert_eq!(error("a =").line, 1);
+    assert_eq!(error("a = b\n\na = c").line, 3);
+    assert_eq!(error("-term = b").line, 1);
+}
+
+#[test]
+fn built_in_languages() {
+    let english = Bundle::built_in("en").unwrap();
+    let english_ids: BTreeSet<_> = english.ids().collect();
+    for language in languages() {
+        let bundle = Bundle::built_in(language).unwrap();
+        let extra: Vec<_> = bundle
+            .ids()
+            .filter(|id| !english_ids.contains(id))
+            .collect();
+        assert!(
+            extra.is_empty(),
+            "{} has messages English doesn't: {:?}",
+            language,
+            extra
+        );
+    }
+}
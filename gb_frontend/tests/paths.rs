@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+
+use gb_frontend::paths::{DataKind, Paths};
+
+fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<PathBuf> + 'a {
+    move |name| {
+        vars.iter()
+            .find(|(var, _)| *var == name)
+            .map(|(_, value)| PathBuf::from(value))
+    }
+}
+
+#[test]
+fn platform_locations() {
+    let linux = Paths::for_platform("linux", env(&[("HOME", "/home/ben")])).unwrap();
+    assert_eq!(
+        linux.dir(DataKind::Config),
+        Path::new("/home/ben/.config/gb-emu")
+    );
+    assert_eq!(
+        linux.dir(DataKind::Saves),
+        Path::new("/home/ben/.local/share/gb-emu/saves")
+    );
+
+    // The XDG variables take precedence, unless they're relative
+    let xdg = Paths::for_platform(
+        "linux",
+        env(&[
+            ("HOME", "/home/ben"),
+            ("XDG_CONFIG_HOME", "/etc/ben"),
+            ("XDG_DATA_HOME", "data"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(xdg.dir(DataKind::Config), Path::new("/etc/ben/gb-emu"));
+    assert_eq!(
+        xdg.dir(DataKind::States),
+        Path::new("/home/ben/.local/share/gb-emu/states")
+    );
+
+    let macos = Paths::for_platform("macos", env(&[("HOME", "/Users/ben")])).unwrap();
+    assert_eq!(
+        macos.dir(DataKind::Config),
+        Path::new("/Users/ben/Library/Application Support/gb-emu")
+    );
+    assert_eq!(
+        macos.dir(DataKind::Movies),
+        Path::new("/Users/ben/Library/Application Support/gb-emu/movies")
+    );
+
+    assert!(Paths::for_platform("linux", env(&[])).is_err());
+    assert!(Paths::for_platform("windows", env(&[("HOME", "/home/ben")])).is_err());
+}
+
+#[test]
+fn overrides() {
+    let mut paths = Paths::in_dir("/data");
+    assert_eq!(paths.dir(DataKind::Config), Path::new("/data/config"));
+    assert_eq!(
+        paths.dir(DataKind::Screenshots),
+        Path::new("/data/screenshots")
+    );
+
+    paths.parse_override("saves=/mnt/usb/saves").unwrap();
+    paths.set_override(DataKind::Config, "/etc/gb-emu");
+    assert_eq!(paths.dir(DataKind::Saves), Path::new("/mnt/usb/saves"));
+    assert_eq!(paths.dir(DataKind::Config), Path::new("/etc/gb-emu"));
+    assert_eq!(paths.dir(DataKind::States), Path::new("/data/states"));
+
+    assert!(paths.parse_override("/mnt/usb/saves").is_err());
+    assert!(paths.parse_override("battery=/mnt/usb").is_err());
+    assert!(paths.parse_override("saves=").is_err());
+}
+
+#[test]
+fn portable() {
+    let paths = Paths::portable_for(Path::new("/mnt/usb/gb_cli")).unwrap();
+    assert_eq!(
+        paths.dir(DataKind::Config),
+        Path::new("/mnt/usb/gb-emu-data/config")
+    );
+    assert_eq!(
+        paths.dir(DataKind::Saves),
+        Path::new("/mnt/usb/gb-emu-data/saves")
+    );
+    assert!(Paths::portable_for(Path::new("/")).is_err());
+}
+
+#[test]
+fn game_files() {
+    let paths = Paths::in_dir("/data");
+    assert_eq!(
+        paths.file(DataKind::Saves, "TETRIS", "sav"),
+        Path::new("/data/saves/TETRIS.sav")
+    );
+    // Dots in the name are kept, but not ones that would hide or end it
+    assert_eq!(
+        paths.file(DataKind::States, "Game v1.1", "state"),
+        Path::new("/data/states/Game v1.1.state")
+    );
+    assert_eq!(
+        paths.file(DataKind::Screenshots, "A/B:C", "png"),
+        Path::new("/data/screenshots/A_B_C.png")
+    );
+    assert_eq!(
+        paths.file(DataKind::Movies, "..", "gbm"),
+        Path::new("/data/movies/untitled.gbm")
+    );
+}
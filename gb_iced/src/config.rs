@@ -0,0 +1,60 @@
+//! Settings that are changed in the window and kept between runs, stored as `key = value` lines in
+//! `gb_iced.conf` in the config folder from the [`Paths`] given on the command line
+
+use std::{io, path::PathBuf};
+
+use gb_frontend::paths::{DataKind, Paths};
+
+const FILE_NAME: &str = "gb_iced.conf";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    /// The name of one of the PPU's color schemes
+    pub palette: Option<String>,
+    /// Draw the interface with stronger colors and bigger text
+    pub high_contrast_ui: bool,
+}
+
+impl Config {
+    fn path(paths: &Paths) -> PathBuf {
+        paths.dir(DataKind::Config).join(FILE_NAME)
+    }
+
+    /// The settings saved in `paths`, or the defaults if there aren't any
+    pub fn load(paths: &Paths) -> Self {
+        std::fs::read_to_string(Self::path(paths))
+            .ok()
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    /// Lines that can't be understood are skipped, so an old or hand-edited file never stops the emulator starting
+    fn parse(text: &str) -> Self {
+        let mut config = Config::default();
+        let settings = text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()));
+        for (key, value) in settings {
+            match key {
+                "palette" => config.palette = Some(value.to_owned()),
+                "high-contrast-ui" => config.high_contrast_ui = value == "true",
+                _ => (),
+            }
+        }
+        config
+    }
+
+    pub fn save(&self, paths: &Paths) -> io::Result<()> {
+        let path = paths.create_dir(DataKind::Config)?.join(FILE_NAME);
+
+        let mut text =
+            String::from("# Written by gb_iced when settings are changed in the window\n");
+        if let Some(palette) = &self.palette {
+            text += &format!("palette = {}\n", palette);
+        }
+        text += &format!("high-contrast-ui = {}\n", self.high_contrast_ui);
+        std::fs::write(path, text)
+    }
+}
@@ -1,49 +1,349 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use gb_core::{
+    gameboy::{
+        models::DMG,
+        ppu::{
+            ghosting::Ghosting,
+            monochrome::color::{self, Scheme},
+            vram::VramRegions,
+            PPU,
+        },
+        Gameboy,
+    },
+    spectate::Broadcaster,
+};
+use gb_frontend::{i18n::Localizer, paths::Paths};
 use iced::{keyboard::KeyCode, window, Application, Color, Element, Length, Settings};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+mod config;
+mod spectate;
+
+use config::Config;
+
+#[derive(Debug, Clone)]
 enum Message {
     Pressed(gb_core::gameboy::joypad::Button),
     Released(gb_core::gameboy::joypad::Button),
     TickFrame,
     TogglePause,
+    ToggleTurbo,
     DebugCpu,
     StepInstruction,
+    FocusChanged(bool),
+    /// Switch to the next color scheme
+    CyclePalette,
+    ToggleHighContrast,
+    /// The path typed into the launcher changed
+    PathChanged(String),
+    /// Load the ROM at the launcher's path
+    Load,
+    /// Reading a ROM file finished
+    Loaded(Result<Vec<u8>, LoadError>),
+    /// See if the watched ROM has changed on disk
+    CheckRom,
+    /// Reading the watched ROM again finished
+    Reloaded(Result<Vec<u8>, LoadError>),
+}
+
+/// Why a ROM couldn't be loaded
+#[derive(Debug, Clone)]
+enum LoadError {
+    Read { path: PathBuf, error: String },
+    Rom(&'static str),
+}
+
+impl LoadError {
+    fn describe(&self, strings: &Localizer) -> String {
+        match self {
+            LoadError::Read { path, error } => strings.format(
+                "error-read-rom",
+                &[("path", &path.display()), ("error", error)],
+            ),
+            LoadError::Rom(error) => strings.format("error-load-rom", &[("error", error)]),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Flags {
+    /// A ROM to load at startup
+    rom_path: Option<PathBuf>,
+    /// Pause emulation while the window doesn't have focus
+    pause_on_focus_loss: bool,
+    turbo: bool,
+    /// Persistence of the LCD ghosting filter, if enabled
+    ghosting: Option<f32>,
+    threaded_rendering: bool,
+    /// Address to stream the game to spectators from
+    broadcast: Option<String>,
+    /// Print the latency of each input
+    report_latency: bool,
+    /// The language of the interface, instead of the system's
+    language: Option<String>,
+    /// The color scheme to start with, instead of the saved one
+    palette: Option<String>,
+    /// Reload the ROM when its file changes
+    watch: bool,
+    /// Where the settings are kept, if anywhere
+    paths: Option<Paths>,
 }
 
 struct App {
-    gameboy: gb_core::gameboy::Gameboy<gb_core::gameboy::models::DMG>,
+    /// The game being played. The launcher is shown until a ROM has been loaded.
+    gameboy: Option<Box<Gameboy<DMG>>>,
+    launcher: Launcher,
+    strings: Localizer,
+    config: Config,
+    /// Where `config` is saved. Without any, changed settings only last until the window is closed.
+    paths: Option<Paths>,
+    threaded_rendering: bool,
+    report_latency: bool,
+    /// Paused by the user
     paused: bool,
+    /// Paused because the window lost focus. Kept separate from `paused` so regaining focus doesn't
+    /// resume a game the user paused themselves.
+    focus_paused: bool,
+    pause_on_focus_loss: bool,
+    /// Frames emulated per tick
+    speed: u32,
+    ghosting: Option<Ghosting>,
+    broadcaster: Option<Broadcaster>,
+    /// The tile data image and the BGP it was drawn with. Only drawn again when the tile data or BGP changes.
+    tile_data: Option<(iced::image::Handle, u8)>,
+    watch: bool,
+    /// The ROM that was last loaded, if it's being watched for changes
+    watched: Option<WatchedRom>,
+}
+
+/// A ROM file that's reloaded when it changes, for homebrew development
+struct WatchedRom {
+    path: PathBuf,
+    /// When the file was last changed, as of the last check
+    modified: Option<SystemTime>,
+}
+
+/// How often to check the watched ROM for changes
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Asks for a ROM to play, and explains why the last one couldn't be loaded
+#[derive(Default)]
+struct Launcher {
+    path: String,
+    path_input: iced::text_input::State,
+    load_button: iced::button::State,
+    /// A ROM is being read
+    loading: bool,
+    error: Option<LoadError>,
+}
+
+impl Launcher {
+    fn view(&mut self, strings: &Localizer, high_contrast: bool) -> Element<'_, Message> {
+        let (text_size, error_color) = if high_contrast {
+            (28, HIGH_CONTRAST_ERROR_COLOR)
+        } else {
+            (20, ERROR_COLOR)
+        };
+        let (status, color) = match (&self.error, self.loading) {
+            (_, true) => (strings.get("launcher-loading"), Color::WHITE),
+            (Some(error), false) => (error.describe(strings), error_color),
+            (None, false) => (strings.get("launcher-prompt"), Color::WHITE),
+        };
+        let path = iced::TextInput::new(
+            &mut self.path_input,
+            &strings.get("launcher-path"),
+            &self.path,
+            Message::PathChanged,
+        )
+        .on_submit(Message::Load)
+        .size(text_size)
+        .padding(5);
+        let load = iced::Button::new(
+            &mut self.load_button,
+            iced::Text::new(strings.get("launcher-load")).size(text_size),
+        )
+        .on_press(Message::Load);
+
+        let content = iced::Column::new()
+            .spacing(10)
+            .max_width(500)
+            .push(iced::Text::new(status).color(color).size(text_size))
+            .push(iced::Row::new().spacing(10).push(path).push(load));
+        iced::Container::new(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(20)
+            .center_x()
+            .center_y()
+            .into()
+    }
+}
+
+/// Frames emulated per tick while turbo is enabled
+const TURBO_SPEED: u32 = 4;
+
+const ERROR_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.4,
+    b: 0.4,
+    a: 1.0,
+};
+
+const HIGH_CONTRAST_ERROR_COLOR: Color = Color {
+    r: 1.0,
+    g: 1.0,
+    b: 0.0,
+    a: 1.0,
+};
+
+impl App {
+    fn is_paused(&self) -> bool {
+        self.paused || self.focus_paused
+    }
+
+    /// Read the ROM at `path` in the background. `Message::Loaded` starts the game once it's been read.
+    fn load(&mut self, path: PathBuf) -> iced::Command<Message> {
+        self.launcher.loading = true;
+        self.launcher.error = None;
+        if self.watch {
+            self.watched = Some(WatchedRom {
+                modified: modified(&path),
+                path: path.clone(),
+            });
+        }
+        iced::Command::perform(async move { read_rom(&path) }, Message::Loaded)
+    }
+
+    fn start(&mut self, rom: Vec<u8>) {
+        match gameboy_from_rom(rom) {
+            Ok(gameboy) => self.set_gameboy(gameboy),
+            Err(e) => self.launcher.error = Some(e),
+        }
+    }
+
+    /// Swap in a rebuilt ROM, keeping the cartridge RAM so the game's saves survive. A ROM that can't be loaded is
+    /// most likely still being written, so the old one keeps running until the next change.
+    fn reload(&mut self, rom: Result<Vec<u8>, LoadError>) {
+        let mut gameboy = match rom.and_then(gameboy_from_rom) {
+            Ok(gameboy) => gameboy,
+            Err(e) => {
+                eprintln!("Couldn't reload the ROM: {}", e.describe(&self.strings));
+                return;
+            }
+        };
+        if let Some(old) = &self.gameboy {
+            // The RAM can't be kept if the new build has less of it
+            let _ = gameboy.cart.write_ram(0, old.cart.ram());
+        }
+        self.set_gameboy(gameboy);
+        println!("Reloaded the ROM");
+    }
+
+    fn set_gameboy(&mut self, mut gameboy: Box<Gameboy<DMG>>) {
+        gameboy.ppu.set_threaded_rendering(self.threaded_rendering);
+        gameboy.set_latency_tracking(self.report_latency);
+        gameboy.ppu.set_color_scheme(self.color_scheme());
+        self.gameboy = Some(gameboy);
+        self.tile_data = None;
+    }
+
+    /// The chosen color scheme, or the grays if there isn't one with the configured name
+    fn color_scheme(&self) -> &'static Scheme {
+        self.config
+            .palette
+            .as_deref()
+            .and_then(color::scheme)
+            .unwrap_or(&color::SCHEMES[0])
+    }
+
+    fn save_config(&self) {
+        if let Some(paths) = &self.paths {
+            if let Err(e) = self.config.save(paths) {
+                eprintln!("Couldn't save the settings: {}", e);
+            }
+        }
+    }
 }
 
 impl Application for App {
     type Executor = iced::executor::Default;
-    type Flags = PathBuf;
+    type Flags = Flags;
     type Message = Message;
 
-    fn new(rom_path: PathBuf) -> (Self, iced::Command<Message>) {
-        use std::io::Read;
-        let mut rom = std::fs::File::open(rom_path).unwrap();
-        let mut buf = vec![];
-        rom.read_to_end(&mut buf).unwrap();
+    fn new(flags: Flags) -> (Self, iced::Command<Message>) {
+        let broadcaster = flags.broadcast.map(|addr| {
+            Broadcaster::bind(&addr)
+                .unwrap_or_else(|e| panic!("Couldn't listen on {}: {}", addr, e))
+        });
+        let mut config = flags.paths.as_ref().map(Config::load).unwrap_or_default();
+        if flags.palette.is_some() {
+            config.palette = flags.palette.clone();
+        }
+        if let Some(palette) = config
+            .palette
+            .as_deref()
+            .filter(|&name| color::scheme(name).is_none())
+        {
+            eprintln!("warning: there's no color scheme called {}", palette);
+        }
 
         let mut app = App {
-            gameboy: gb_core::gameboy::Gameboy::new(buf).unwrap(),
+            gameboy: None,
+            launcher: Launcher::default(),
+            strings: match &flags.language {
+                Some(language) => Localizer::new(language),
+                None => Localizer::from_env(),
+            },
+            config,
+            paths: flags.paths,
+            threaded_rendering: flags.threaded_rendering,
+            report_latency: flags.report_latency,
             paused: true,
+            focus_paused: false,
+            pause_on_focus_loss: flags.pause_on_focus_loss,
+            speed: if flags.turbo { TURBO_SPEED } else { 1 },
+            ghosting: flags.ghosting.map(Ghosting::new),
+            broadcaster,
+            tile_data: None,
+            watch: flags.watch,
+            watched: None,
         };
-        app.gameboy.reset();
 
-        let cmd = iced::Command::none();
+        let cmd = match flags.rom_path {
+            Some(path) => {
+                app.launcher.path = path.display().to_string();
+                app.load(path)
+            }
+            None => iced::Command::none(),
+        };
         (app, cmd)
     }
 
     fn title(&self) -> String {
-        if !self.paused {
-            format!("GameBoy")
+        let gameboy = match &self.gameboy {
+            Some(gameboy) => gameboy,
+            None => return self.strings.get("title"),
+        };
+        let game = &gameboy.cart.header().title;
+        let mut title = if game.is_empty() {
+            self.strings.get("title")
         } else {
-            format!("GameBoy - Paused")
+            self.strings.format("title-game", &[("game", game)])
+        };
+        if self.speed != 1 {
+            let speed = format!("{:.1}", self.speed as f32);
+            title += " - ";
+            title += &self.strings.format("title-speed", &[("speed", &speed)]);
+        }
+        if self.is_paused() {
+            title += " - ";
+            title += &self.strings.get("title-paused");
         }
+        title
     }
 
     fn update(
@@ -53,20 +353,37 @@ impl Application for App {
     ) -> iced::Command<Message> {
         match message {
             Message::TickFrame => {
-                if !self.paused {
-                    for _ in 0..gb_core::gameboy::ppu::monochrome::FRAME_T_CYCLES / 4 {
-                        self.gameboy.clock();
+                let paused = self.is_paused();
+                if let (Some(gameboy), false) = (&mut self.gameboy, paused) {
+                    for _ in 0..self.speed {
+                        gameboy.run_frame();
+                        if let Some(illegal) = gameboy.take_illegal_opcode() {
+                            eprintln!(
+                                "The game locked up on illegal opcode {:02X} at {:04X}",
+                                illegal.opcode, illegal.pc
+                            );
+                        }
+                        if let Some(broadcaster) = &mut self.broadcaster {
+                            broadcaster
+                                .send_frame(&gameboy.ppu.get_frame(), gameboy.joypad.input());
+                        }
                     }
                 }
                 iced::Command::none()
             }
 
             Message::Pressed(button) => {
-                self.gameboy.joypad.press(button);
+                if let Some(gameboy) = &mut self.gameboy {
+                    gameboy.input_event();
+                    gameboy.joypad.press(button);
+                }
                 iced::Command::none()
             }
             Message::Released(button) => {
-                self.gameboy.joypad.release(button);
+                if let Some(gameboy) = &mut self.gameboy {
+                    gameboy.input_event();
+                    gameboy.joypad.release(button);
+                }
                 iced::Command::none()
             }
 
@@ -75,21 +392,143 @@ impl Application for App {
                 iced::Command::none()
             }
 
+            Message::ToggleTurbo => {
+                self.speed = if self.speed == 1 { TURBO_SPEED } else { 1 };
+                iced::Command::none()
+            }
+
+            Message::FocusChanged(focused) => {
+                if self.pause_on_focus_loss {
+                    self.focus_paused = !focused;
+                }
+                iced::Command::none()
+            }
+
             Message::DebugCpu => {
-                println!("{:?}", self.gameboy.cpu);
+                if let Some(gameboy) = &self.gameboy {
+                    println!("{:?}", gameboy.cpu);
+                }
                 iced::Command::none()
             }
             Message::StepInstruction => {
-                self.gameboy.step_instruction();
-                println!("{:?}", self.gameboy.cpu);
+                if let Some(gameboy) = &mut self.gameboy {
+                    gameboy.step_instruction();
+                    println!("{:?}", gameboy.cpu);
+                }
+                iced::Command::none()
+            }
+
+            Message::CyclePalette => {
+                let current = self.color_scheme();
+                let index = color::SCHEMES
+                    .iter()
+                    .position(|scheme| scheme == current)
+                    .unwrap_or(0);
+                let next = &color::SCHEMES[(index + 1) % color::SCHEMES.len()];
+                if let Some(gameboy) = &mut self.gameboy {
+                    gameboy.ppu.set_color_scheme(next);
+                }
+                // The tile viewer is only redrawn when BGP changes, so drop it to show the new colors
+                self.tile_data = None;
+                self.config.palette = Some(next.name.to_owned());
+                self.save_config();
+                iced::Command::none()
+            }
+            Message::ToggleHighContrast => {
+                self.config.high_contrast_ui = !self.config.high_contrast_ui;
+                self.save_config();
+                iced::Command::none()
+            }
+
+            Message::PathChanged(path) => {
+                self.launcher.path = path;
+                iced::Command::none()
+            }
+            Message::Load => {
+                if self.launcher.loading || self.launcher.path.trim().is_empty() {
+                    return iced::Command::none();
+                }
+                let path = PathBuf::from(self.launcher.path.trim());
+                self.load(path)
+            }
+            Message::Loaded(rom) => {
+                self.launcher.loading = false;
+                match rom {
+                    Ok(rom) => self.start(rom),
+                    Err(e) => self.launcher.error = Some(e),
+                }
+                iced::Command::none()
+            }
+
+            Message::CheckRom => {
+                let watched = match &mut self.watched {
+                    Some(watched) if !self.launcher.loading => watched,
+                    _ => return iced::Command::none(),
+                };
+                let modified = modified(&watched.path);
+                if modified.is_none() || modified == watched.modified {
+                    return iced::Command::none();
+                }
+                watched.modified = modified;
+                let path = watched.path.clone();
+                iced::Command::perform(async move { read_rom(&path) }, Message::Reloaded)
+            }
+            Message::Reloaded(rom) => {
+                self.reload(rom);
                 iced::Command::none()
             }
         }
     }
 
     fn view(&mut self) -> Element<'_, Self::Message> {
-        let (frame, framew, frameh) = self.gameboy.get_frame(2);
-        let (tile_data, tilew, tileh) = self.gameboy.ppu.state.borrow().display_tile_data(2);
+        let gameboy = match &mut self.gameboy {
+            Some(gameboy) => gameboy,
+            None => {
+                return self
+                    .launcher
+                    .view(&self.strings, self.config.high_contrast_ui)
+            }
+        };
+
+        let samples: Vec<_> = gameboy.frame_presented().collect();
+        for sample in samples {
+            let stats = gameboy.latency_stats();
+            println!(
+                "Input latency: latched after {:.1} ms in frame {}, presented after {:.1} ms (average {:.1} ms, \
+                 max {:.1} ms over {} inputs)",
+                sample.to_latch.as_secs_f64() * 1000.0,
+                sample.latched_frame,
+                sample.to_present.as_secs_f64() * 1000.0,
+                stats.average_to_present.as_secs_f64() * 1000.0,
+                stats.max_to_present.as_secs_f64() * 1000.0,
+                stats.samples,
+            );
+        }
+
+        let frame = gameboy.ppu.get_frame();
+        let (frame, framew, frameh) = match &mut self.ghosting {
+            Some(ghosting) => ghosting.apply(&frame).scaled(2),
+            None => frame.scaled(2),
+        };
+        let bgp = gameboy.ppu.bgp();
+        let dirty = gameboy.ppu.take_dirty(VramRegions::TILE_DATA);
+        let tile_data = match &self.tile_data {
+            Some((handle, old_bgp)) if dirty.is_empty() && *old_bgp == bgp => handle.clone(),
+            _ => {
+                let (tile_data, tilew, tileh) = gameboy.ppu.display_tile_data(2);
+                let handle = iced::image::Handle::from_pixels(
+                    tilew as u32,
+                    tileh as u32,
+                    u32_to_bgra(tile_data),
+                );
+                self.tile_data = Some((handle.clone(), bgp));
+                handle
+            }
+        };
+        // Scrolling changes every frame, so this is always drawn again
+        let (background, bgw, bgh) = gameboy.ppu.display_background(None);
+        let background =
+            iced::image::Handle::from_pixels(bgw as u32, bgh as u32, u32_to_bgra(background));
         iced::Row::new()
             // .push(iced::Text::new("Hello, world!"))
             .push(
@@ -102,19 +541,24 @@ impl Application for App {
                 .height(Length::FillPortion(3)),
             )
             .push(
-                iced::Image::new(iced::image::Handle::from_pixels(
-                    tilew as u32,
-                    tileh as u32,
-                    u32_to_bgra(tile_data),
-                ))
-                .width(Length::FillPortion(4))
-                .height(Length::FillPortion(4)),
+                iced::Column::new()
+                    .push(
+                        iced::Image::new(tile_data)
+                            .width(Length::FillPortion(4))
+                            .height(Length::FillPortion(4)),
+                    )
+                    .push(
+                        iced::Image::new(background)
+                            .width(Length::FillPortion(4))
+                            .height(Length::FillPortion(4)),
+                    )
+                    .width(Length::FillPortion(4)),
             )
             .into()
     }
 
     fn subscription(&self) -> iced::Subscription<Self::Message> {
-        iced_futures::subscription::Subscription::batch([
+        let mut subscriptions = vec![
             iced_futures::time::every(std::time::Duration::from_millis(16))
                 .map(|_| Message::TickFrame),
             iced_native::subscription::events_with(|event, _status| match event {
@@ -124,8 +568,11 @@ impl Application for App {
                             .map(Message::Pressed)
                             .or_else(|| match key_code {
                                 KeyCode::P => Some(Message::TogglePause),
+                                KeyCode::T => Some(Message::ToggleTurbo),
                                 KeyCode::D => Some(Message::DebugCpu),
                                 KeyCode::N => Some(Message::StepInstruction),
+                                KeyCode::C => Some(Message::CyclePalette),
+                                KeyCode::U => Some(Message::ToggleHighContrast),
                                 _ => None,
                             })
                     }
@@ -134,9 +581,19 @@ impl Application for App {
                     }
                     _ => None,
                 },
+                iced_native::Event::Window(e) => match e {
+                    iced_native::window::Event::Focused => Some(Message::FocusChanged(true)),
+                    iced_native::window::Event::Unfocused => Some(Message::FocusChanged(false)),
+                    _ => None,
+                },
                 _ => None,
             }),
-        ])
+        ];
+        if self.watched.is_some() {
+            subscriptions
+                .push(iced_futures::time::every(WATCH_INTERVAL).map(|_| Message::CheckRom));
+        }
+        iced_futures::subscription::Subscription::batch(subscriptions)
     }
 
     fn background_color(&self) -> Color {
@@ -144,9 +601,160 @@ impl Application for App {
     }
 }
 
+#[derive(Parser)]
+#[clap(about = "A Gameboy emulator")]
+struct Cli {
+    #[clap(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Play a ROM
+    Run {
+        /// A ROM to start with. Without one, the launcher asks for it.
+        rom: Option<PathBuf>,
+        /// The model of Gameboy to emulate
+        #[clap(long, value_enum, default_value = "dmg")]
+        model: Model,
+        /// Start with turbo enabled
+        #[clap(long)]
+        turbo: bool,
+        /// Keep running while the window is unfocused
+        #[clap(long)]
+        no_focus_pause: bool,
+        /// Blend each frame with the previous one like the original LCD, keeping this much (0 to 1) of the
+        /// previous frame
+        #[clap(long, value_name = "PERSISTENCE")]
+        ghosting: Option<f32>,
+        /// Draw scanlines on a separate thread
+        #[clap(long)]
+        threaded_renderer: bool,
+        /// Stream the game to spectators connecting to this address, e.g. 0.0.0.0:7000
+        #[clap(long, value_name = "ADDR")]
+        broadcast: Option<String>,
+        /// Print how long each input takes to reach the screen, for checking the frontend and vsync settings
+        #[clap(long)]
+        report_latency: bool,
+        /// The language of the interface, e.g. `en`. Defaults to the system's.
+        #[clap(long, value_name = "CODE")]
+        lang: Option<String>,
+        /// The color scheme to start with: gray, high-contrast, blue-orange (for red-green color blindness) or
+        /// pink-teal (for blue-yellow color blindness). Press C to switch while playing.
+        #[clap(long, value_name = "NAME")]
+        palette: Option<String>,
+        /// Reload the ROM whenever its file changes, keeping the cartridge RAM, for homebrew development
+        #[clap(long)]
+        watch: bool,
+        #[clap(flatten)]
+        paths: PathArgs,
+    },
+    /// Watch a game streamed by `run --broadcast`
+    Spectate { addr: String },
+    /// Run a ROM without opening a window
+    Test {
+        rom: PathBuf,
+        /// The number of frames to run for
+        #[clap(long, default_value_t = 60)]
+        frames: u32,
+        /// Print a hash of the last frame
+        #[clap(long)]
+        hash: bool,
+    },
+    /// Print the cartridge header of a ROM
+    DumpHeader { rom: PathBuf },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Model {
+    Dmg,
+}
+
+/// Where to keep the settings, instead of the platform's usual locations. The same as gb_cli's.
+#[derive(Args)]
+struct PathArgs {
+    /// Keep everything in folders under this one
+    #[clap(long, value_name = "DIR")]
+    data_dir: Option<PathBuf>,
+    /// Keep everything in a gb-emu-data folder next to the executable, e.g. to run from a USB stick
+    #[clap(long, conflicts_with = "data-dir")]
+    portable: bool,
+    /// Keep one kind of data somewhere else, e.g. `config=/mnt/usb/config`. May be given more than once.
+    #[clap(long = "dir", value_name = "KIND=DIR")]
+    dirs: Vec<String>,
+}
+
+impl PathArgs {
+    fn resolve(&self) -> Result<Paths, String> {
+        let mut paths = match (&self.data_dir, self.portable) {
+            (Some(dir), _) => Ok(Paths::in_dir(dir)),
+            (None, true) => Paths::portable(),
+            (None, false) => Paths::platform(),
+        }
+        .map_err(|e| format!("Couldn't find the data folders: {}", e))?;
+        for spec in &self.dirs {
+            paths
+                .parse_override(spec)
+                .map_err(|e| format!("Bad --dir {}: {}", spec, e))?;
+        }
+        Ok(paths)
+    }
+}
+
 fn main() {
+    match Cli::parse().command {
+        CliCommand::Run {
+            rom,
+            model: Model::Dmg,
+            turbo,
+            no_focus_pause,
+            ghosting,
+            threaded_renderer,
+            broadcast,
+            report_latency,
+            lang,
+            palette,
+            watch,
+            paths,
+        } => run(Flags {
+            rom_path: rom,
+            pause_on_focus_loss: !no_focus_pause,
+            turbo,
+            ghosting,
+            threaded_rendering: threaded_renderer,
+            broadcast,
+            report_latency,
+            language: lang,
+            palette,
+            watch,
+            paths: match paths.resolve() {
+                Ok(paths) => Some(paths),
+                Err(e) => {
+                    eprintln!("warning: {}, so settings won't be kept", e);
+                    None
+                }
+            },
+        }),
+        CliCommand::Spectate { addr } => spectate::run(addr),
+        CliCommand::Test { rom, frames, hash } => {
+            let mut gameboy = load_gameboy(&rom);
+            for _ in 0..frames {
+                gameboy.run_frame();
+            }
+            if hash {
+                println!("{:016x}", gameboy.ppu.get_frame().hash());
+            }
+        }
+        CliCommand::DumpHeader { rom } => {
+            let gameboy = load_gameboy(&rom);
+            println!("{:#04X?}", gameboy.cart.header());
+        }
+    }
+}
+
+fn run(flags: Flags) {
     let mut settings = Settings {
-        flags: std::env::args().nth(1).expect("Expected 1 argument").into(),
+        flags,
         window: window::Settings {
             size: (160 * 2, 144 * 2),
             ..Default::default()
@@ -157,7 +765,38 @@ fn main() {
     App::run(settings).unwrap();
 }
 
-fn u32_to_bgra(x: Vec<u32>) -> Vec<u8> {
+fn read_rom(path: &Path) -> Result<Vec<u8>, LoadError> {
+    std::fs::read(path).map_err(|e| LoadError::Read {
+        path: path.to_owned(),
+        error: e.to_string(),
+    })
+}
+
+/// When the file at `path` was last changed, if that can be found out
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn gameboy_from_rom(rom: Vec<u8>) -> Result<Box<Gameboy<DMG>>, LoadError> {
+    let mut gameboy = Gameboy::new(rom).map_err(LoadError::Rom)?;
+    for diagnostic in gameboy.cart.diagnostics() {
+        eprintln!("warning: {}", diagnostic);
+    }
+    gameboy.reset();
+    Ok(gameboy)
+}
+
+/// Load a ROM for the subcommands that don't open a window, exiting if it can't be
+fn load_gameboy(path: &Path) -> Box<Gameboy<DMG>> {
+    read_rom(path)
+        .and_then(gameboy_from_rom)
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e.describe(&Localizer::from_env()));
+            std::process::exit(1)
+        })
+}
+
+pub(crate) fn u32_to_bgra(x: Vec<u32>) -> Vec<u8> {
     x.iter().copied().flat_map(|p| p.to_le_bytes()).collect()
 }
 
@@ -0,0 +1,111 @@
+//! A window that shows a game streamed by another instance
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use gb_core::{
+    gameboy::ppu::monochrome::Frame,
+    spectate::Spectator,
+    triple_buffer::{triple_buffer, Reader},
+};
+use iced::{window, Application, Color, Element, Length, Settings};
+
+#[derive(Debug, Clone, Copy)]
+enum Message {
+    TickFrame,
+}
+
+struct SpectatorApp {
+    addr: String,
+    /// The latest frame received, written by the receiving thread
+    frame: Reader<Option<Frame>>,
+    /// Why the stream ended, if it has
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl Application for SpectatorApp {
+    type Executor = iced::executor::Default;
+    type Flags = String;
+    type Message = Message;
+
+    fn new(addr: String) -> (Self, iced::Command<Message>) {
+        let (mut frame_writer, frame) = triple_buffer(None);
+        let error = Arc::new(Mutex::new(None));
+
+        // Receiving blocks, so it is kept off the UI thread
+        {
+            let addr = addr.clone();
+            let error = error.clone();
+            thread::spawn(move || {
+                let result =
+                    Spectator::connect(&addr).and_then(|mut spectator| -> std::io::Result<()> {
+                        loop {
+                            let (next, _input) = spectator.recv_frame()?;
+                            frame_writer.write(Some(next));
+                        }
+                    });
+                if let Err(e) = result {
+                    *error.lock().unwrap() = Some(e.to_string());
+                }
+            });
+        }
+
+        let app = SpectatorApp { addr, frame, error };
+        (app, iced::Command::none())
+    }
+
+    fn title(&self) -> String {
+        match &*self.error.lock().unwrap() {
+            Some(error) => format!("GameBoy - {} - {}", self.addr, error),
+            None => format!("GameBoy - Spectating {}", self.addr),
+        }
+    }
+
+    fn update(
+        &mut self,
+        message: Self::Message,
+        _clip: &mut iced::Clipboard,
+    ) -> iced::Command<Message> {
+        match message {
+            Message::TickFrame => iced::Command::none(),
+        }
+    }
+
+    fn view(&mut self) -> Element<'_, Self::Message> {
+        let (frame, framew, frameh) = match self.frame.read() {
+            Some(frame) => frame.scaled(2),
+            None => (vec![0; 160 * 2 * 144 * 2], 160 * 2, 144 * 2),
+        };
+        iced::Image::new(iced::image::Handle::from_pixels(
+            framew as u32,
+            frameh as u32,
+            crate::u32_to_bgra(frame),
+        ))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .into()
+    }
+
+    fn subscription(&self) -> iced::Subscription<Self::Message> {
+        iced_futures::time::every(std::time::Duration::from_millis(16)).map(|_| Message::TickFrame)
+    }
+
+    fn background_color(&self) -> Color {
+        Color::BLACK
+    }
+}
+
+pub fn run(addr: String) {
+    let settings = Settings {
+        flags: addr,
+        window: window::Settings {
+            size: (160 * 2, 144 * 2),
+            min_size: Some((160, 144)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    SpectatorApp::run(settings).unwrap();
+}